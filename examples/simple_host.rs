@@ -4,6 +4,7 @@ use std::sync::{Arc, Mutex};
 use std::path::Path;
 use std::error::Error;
 
+use vst2::buffer::AudioBuffer;
 use vst2::host::{Host, PluginLoader};
 use vst2::plugin::Plugin;
 
@@ -56,6 +57,17 @@ fn main() {
     instance.init();
     println!("Initialized instance!");
 
+    // Prepare the instance for processing
+    instance.set_sample_rate(44100.0);
+    instance.set_block_size(512);
+    instance.resume();
+
+    // Process a block of silence
+    let mut inputs = vec![vec![0.0f32; 512]; info.inputs as usize];
+    let mut outputs = vec![vec![0.0f32; 512]; info.outputs as usize];
+    instance.process(AudioBuffer::from_channels(&mut inputs, &mut outputs).unwrap());
+    println!("Processed a block!");
+
     println!("Closing instance...");
     // Close the instance. This is not necessary as the instance is shut down when
     // it is dropped as it goes out of scope.
@@ -0,0 +1,50 @@
+//! Fuzz the dispatcher of a plugin with parameters, presets and chunks.
+//!
+//! Run with `cargo fuzz run dispatch` from the repository root.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate vst2;
+
+use vst2::plugin::{Info, Plugin};
+
+#[derive(Default)]
+struct FuzzPlugin {
+    parameters: [f32; 4],
+    preset: i32,
+    chunk: Vec<u8>,
+}
+
+impl Plugin for FuzzPlugin {
+    fn get_info(&self) -> Info {
+        Info {
+            name: "Fuzz Plugin".to_string(),
+            parameters: 4,
+            presets: 4,
+            preset_chunks: true,
+            ..Default::default()
+        }
+    }
+
+    fn change_preset(&mut self, preset: i32) { self.preset = preset; }
+
+    fn get_preset_num(&self) -> i32 { self.preset }
+
+    fn get_parameter(&self, index: i32) -> f32 {
+        self.parameters.get(index as usize).cloned().unwrap_or(0.0)
+    }
+
+    fn set_parameter(&mut self, index: i32, value: f32) {
+        if let Some(parameter) = self.parameters.get_mut(index as usize) {
+            *parameter = value;
+        }
+    }
+
+    fn get_preset_data(&mut self) -> Vec<u8> { self.chunk.clone() }
+
+    fn load_preset_data(&mut self, data: Vec<u8>) { self.chunk = data; }
+}
+
+fuzz_target!(|data: &[u8]| {
+    vst2::fuzz::dispatch::<FuzzPlugin>(data);
+});
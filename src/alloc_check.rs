@@ -0,0 +1,129 @@
+//! Detecting heap allocations on the audio thread.
+//!
+//! This module is only available with the `alloc-check` feature. Allocating in `process` can take
+//! arbitrarily long and cause dropouts, yet it easily happens by accident, e.g. through a `Vec`
+//! growing or a `String` being formatted. [`CheckingAllocator`](struct.CheckingAllocator.html)
+//! wraps the system allocator and reports every allocation made while the wrapper is inside
+//! `process`, `process_f64`, `process_events`, `process_var_io` or the parameter accessors called
+//! by the host. Each report is logged as an error together with a backtrace of the call site.
+//!
+//! The allocator has to be installed by the plugin crate, preferably only in debug builds:
+//!
+//! ```ignore
+//! extern crate vst2;
+//!
+//! use vst2::alloc_check::CheckingAllocator;
+//!
+//! #[cfg_attr(debug_assertions, global_allocator)]
+//! static ALLOCATOR: CheckingAllocator = CheckingAllocator;
+//! ```
+//!
+//! Plugins can mark their own real-time code, e.g. a worker thread feeding the audio thread, with
+//! [`section`](fn.section.html). Tests can check [`violations`](fn.violations.html), or make any
+//! violation abort the process with [`set_abort`](fn.set_abort.html).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use backtrace::Backtrace;
+
+thread_local! {
+    /// Name of the real-time section the thread is in.
+    static SECTION: Cell<Option<&'static str>> = Cell::new(None);
+
+    /// Whether the thread is reporting a violation, so allocations made for the report itself
+    /// are not reported again.
+    static REPORTING: Cell<bool> = Cell::new(false);
+
+    /// Number of allocations reported on the thread.
+    static THREAD_VIOLATIONS: Cell<usize> = Cell::new(0);
+}
+
+static VIOLATIONS: AtomicUsize = AtomicUsize::new(0);
+static ABORT: AtomicBool = AtomicBool::new(false);
+
+/// A global allocator reporting allocations made in real-time sections, see the module
+/// documentation.
+pub struct CheckingAllocator;
+
+unsafe impl GlobalAlloc for CheckingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        check(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        check(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        check(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// Report an allocation of `size` bytes if the thread is in a real-time section.
+fn check(size: usize) {
+    // The thread locals may already be destroyed while a thread exits
+    let section = match SECTION.try_with(|section| section.get()) {
+        Ok(Some(section)) => section,
+        _ => return,
+    };
+    match REPORTING.try_with(|reporting| reporting.replace(true)) {
+        Ok(false) => (),
+        _ => return,
+    }
+
+    VIOLATIONS.fetch_add(1, Ordering::SeqCst);
+    let _ = THREAD_VIOLATIONS.try_with(|count| count.set(count.get() + 1));
+    error!("Allocation of {} bytes in {}:\n{:?}", size, section, Backtrace::new());
+
+    if ABORT.load(Ordering::SeqCst) {
+        process::abort();
+    }
+
+    let _ = REPORTING.try_with(|reporting| reporting.set(false));
+}
+
+/// Marks the current thread as being in a real-time section until dropped. See `section`.
+pub struct Section {
+    previous: Option<&'static str>,
+}
+
+impl Drop for Section {
+    fn drop(&mut self) {
+        let previous = self.previous;
+        let _ = SECTION.try_with(|section| section.set(previous));
+    }
+}
+
+/// Report allocations on the current thread until the returned guard is dropped.
+///
+/// `name` identifies the section in reports. Sections can be nested; the innermost name is
+/// reported.
+pub fn section(name: &'static str) -> Section {
+    Section { previous: SECTION.with(|section| section.replace(Some(name))) }
+}
+
+/// Number of allocations reported so far.
+pub fn violations() -> usize {
+    VIOLATIONS.load(Ordering::SeqCst)
+}
+
+/// Number of allocations reported on the current thread so far, e.g. for tests running
+/// concurrently with other tests.
+pub fn thread_violations() -> usize {
+    THREAD_VIOLATIONS.with(|count| count.get())
+}
+
+/// Abort the process after reporting an allocation, e.g. to fail tests on the first violation.
+pub fn set_abort(abort: bool) {
+    ABORT.store(abort, Ordering::SeqCst);
+}
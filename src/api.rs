@@ -1,10 +1,11 @@
 //! Structures and types for interfacing with the VST 2.4 API.
-use std::mem;
+use std::{cmp, mem, slice};
 
-use libc::c_void;
+use libc::{c_char, c_void};
 
 use plugin::Plugin;
 use self::consts::*;
+use {read_fixed, write_fixed};
 
 /// Constant values
 #[allow(missing_docs)] // For obvious constants
@@ -15,8 +16,15 @@ pub mod consts {
     pub const MAX_PARAM_STR_LEN: size_t = 8;
     pub const MAX_LABEL: usize = 64;
     pub const MAX_SHORT_LABEL: usize = 8;
+    pub const MAX_CATEGORY_LABEL: usize = 24;
     pub const MAX_PRODUCT_STR_LEN: size_t = 64;
     pub const MAX_VENDOR_STR_LEN: size_t = 64;
+    pub const MAX_EFFECT_NAME_LEN: size_t = 32;
+    pub const MAX_NAME_LEN: usize = 64;
+    pub const MAX_FILE_NAME_LEN: usize = 100;
+
+    /// Version of the VST API implemented by this crate, VST 2.4.
+    pub const VST_VERSION: i32 = 2400;
 
     /// VST plugins are identified by a magic number. This corresponds to 0x56737450.
     pub const VST_MAGIC: i32 = ('V' as i32) << 24 |
@@ -168,6 +176,7 @@ pub struct ChannelProperties {
 /// Tells the host how the channels are intended to be used in the plugin. Only useful for some
 /// hosts.
 #[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SpeakerArrangementType {
     /// User defined arrangement.
     Custom = -2,
@@ -247,14 +256,797 @@ pub enum SpeakerArrangementType {
     Surround102,
 }
 
+impl SpeakerArrangementType {
+    /// Convert from the value used in `SpeakerArrangement`, returning `None` for unknown values.
+    pub fn from_raw(value: i32) -> Option<SpeakerArrangementType> {
+        use self::SpeakerArrangementType::*;
+
+        Some(match value {
+            -2 => Custom,
+            -1 => Empty,
+            0 => Mono,
+            1 => Stereo,
+            2 => StereoSurround,
+            3 => StereoCenter,
+            4 => StereoSide,
+            5 => StereoCLfe,
+            6 => Cinema30,
+            7 => Music30,
+            8 => Cinema31,
+            9 => Music31,
+            10 => Cinema40,
+            11 => Music40,
+            12 => Cinema41,
+            13 => Music41,
+            14 => Surround50,
+            15 => Surround51,
+            16 => Cinema60,
+            17 => Music60,
+            18 => Cinema61,
+            19 => Music61,
+            20 => Cinema70,
+            21 => Music70,
+            22 => Cinema71,
+            23 => Music71,
+            24 => Cinema80,
+            25 => Music80,
+            26 => Cinema81,
+            27 => Music81,
+            28 => Surround102,
+            _ => return None,
+        })
+    }
+}
+
+/// Properties of a single speaker. See `api::SpeakerArrangement`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpeakerProperties {
+    /// Azimuth in radians, range -pi to pi. 0 is in front of the listener.
+    pub azimuth: f32,
+    /// Elevation in radians, range -pi/2 to pi/2. 0 is at ear level.
+    pub elevation: f32,
+    /// Distance from the listener in meters.
+    pub radius: f32,
+    /// Reserved for future use. Should be 0.
+    pub _reserved: f32,
+    /// Speaker name, e.g. "L".
+    pub name: [u8; 64],
+    /// Type of the speaker, one of the `kSpeaker*` values. See `channels::SpeakerType`.
+    pub speaker_type: i32,
+    /// Reserved for future use.
+    pub future: [u8; 28],
+}
+
+/// A speaker arrangement for the inputs or outputs of a plugin.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SpeakerArrangement {
+    /// Type of the arrangement, one of the `api::SpeakerArrangementType` values. Kept as a raw
+    /// integer since hosts may send values unknown to this crate; see `arrangement_type()`.
+    pub arrangement_type: i32,
+
+    /// Number of channels in the arrangement.
+    pub num_channels: i32,
+
+    /// Variable-length array of speakers.
+    ///
+    /// The VST standard specifies a variable length array of initial size 8. If there are more
+    /// than 8 channels a larger array must be stored in this structure.
+    pub speakers: [SpeakerProperties; 8],
+}
+
+impl SpeakerArrangement {
+    /// Type of the arrangement. Unknown values are reported as `Custom`.
+    pub fn arrangement_type(&self) -> SpeakerArrangementType {
+        SpeakerArrangementType::from_raw(self.arrangement_type)
+            .unwrap_or(SpeakerArrangementType::Custom)
+    }
+
+    /// Return a slice of the speakers stored inline in this struct.
+    ///
+    /// At most 8 speakers are returned, even if `num_channels` is larger. Use
+    /// `channels::SpeakerArrangement::from_raw` to read arrangements with more channels.
+    pub fn speakers(&self) -> &[SpeakerProperties] {
+        let len = cmp::min(cmp::max(self.num_channels, 0) as usize, self.speakers.len());
+        &self.speakers[..len]
+    }
+}
+
+/// A struct which contains events.
+#[repr(C)]
+pub struct Events {
+    /// Number of events.
+    pub num_events: i32,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved: isize,
+
+    /// Variable-length array of pointers to `api::Event` objects.
+    ///
+    /// The VST standard specifies a variable length array of initial size 2. If there are more
+    /// than 2 elements a larger array must be stored in this structure.
+    pub events: [*mut Event; 2],
+}
+
+impl Events {
+    /// Return a slice of raw pointers to the events contained in this struct.
+    pub fn events_raw(&self) -> &[*const Event] {
+        unsafe {
+            slice::from_raw_parts(&self.events[0] as *const *mut _ as *const *const _,
+                                  cmp::max(self.num_events, 0) as usize)
+        }
+    }
+}
+
+/// The type of event that has occured. See `api::Event.event_type`.
+#[repr(i32)]
+#[derive(Copy, Clone, Debug)]
+pub enum EventType {
+    /// Midi event. See `api::MidiEvent`.
+    Midi = 1,
+
+    /// Deprecated.
+    _Audio,
+    /// Deprecated.
+    _Video,
+    /// Deprecated.
+    _Parameter,
+    /// Deprecated.
+    _Trigger,
+
+    /// Midi system exclusive dump. See `api::SysExEvent`.
+    SysEx,
+}
+
+impl EventType {
+    /// Convert from the value used in `Event`, returning `None` for unknown values.
+    pub fn from_raw(value: i32) -> Option<EventType> {
+        use self::EventType::*;
+
+        Some(match value {
+            1 => Midi,
+            2 => _Audio,
+            3 => _Video,
+            4 => _Parameter,
+            5 => _Trigger,
+            6 => SysEx,
+            _ => return None,
+        })
+    }
+}
+
+/// A VST event.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Event {
+    /// The type of event, one of the `api::EventType` values. This lets you know which event
+    /// this object should be casted to. Kept as a raw integer since unknown values may be sent.
+    pub event_type: i32,
+
+    /// Size of this structure; `mem::sizeof::<Event>()`.
+    pub byte_size: i32,
+
+    /// Number of samples into the current processing block that this event occurs on.
+    pub delta_frames: i32,
+
+    /// Generic flags, none defined in VST api yet.
+    pub _flags: i32,
+
+    /// The `Event` type is cast appropriately, so this acts as reserved space.
+    pub _reserved: [u8; 16],
+}
+
+/// A midi event.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct MidiEvent {
+    /// Should be `EventType::Midi`.
+    pub event_type: i32,
+
+    /// Size of this structure; `mem::sizeof::<MidiEvent>()`.
+    pub byte_size: i32,
+
+    /// Number of samples into the current processing block that this event occurs on.
+    pub delta_frames: i32,
+
+    /// Flags found in `flags::MidiEvent`.
+    pub flags: i32,
+
+    /// Length in sample frames of entire note if available, otherwise 0.
+    pub note_length: i32,
+
+    /// Offset in samples into note from start if available, otherwise 0.
+    pub note_offset: i32,
+
+    /// 1 to 3 midi bytes. 4th byte is reserved.
+    pub midi_data: [u8; 3],
+
+    /// Reserved midi byte (0).
+    pub _midi_reserved: u8,
+
+    /// Detuning between -63 and +64 cents, for scales other than 'well-tempered'. e.g.
+    /// 'microtuning'.
+    pub detune: i8,
+
+    /// Note off velocity between 0 and 127.
+    pub note_off_velocity: u8,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved1: u8,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved2: u8,
+}
+
+/// Describes the time at the start of the block currently being processed.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimeInfo {
+    /// Current position in audio samples.
+    pub sample_pos: f64,
+    /// Current sample rate in Hz.
+    pub sample_rate: f64,
+    /// System time in nanoseconds.
+    pub nanoseconds: f64,
+    /// Musical position in quarter notes (1.0 equals 1 quarter note).
+    pub ppq_pos: f64,
+    /// Current tempo in beats per minute.
+    pub tempo: f64,
+    /// Last bar start position in quarter notes.
+    pub bar_start_pos: f64,
+    /// Cycle start (left locator) in quarter notes.
+    pub cycle_start_pos: f64,
+    /// Cycle end (right locator) in quarter notes.
+    pub cycle_end_pos: f64,
+
+    /// Time signature numerator, e.g. 3 for 3/4.
+    pub time_sig_numerator: i32,
+    /// Time signature denominator, e.g. 4 for 3/4.
+    pub time_sig_denominator: i32,
+
+    /// SMPTE offset in SMPTE subframes (bits; 1/80 of a frame).
+    pub smpte_offset: i32,
+    /// SMPTE frame rate. See `SmpteFrameRate` and `TimeInfo::smpte_frame_rate`.
+    pub smpte_frame_rate: i32,
+
+    /// MIDI clock resolution (24 per quarter note), can be negative (nearest clock).
+    pub samples_to_next_clock: i32,
+
+    /// Flags found in `flags::TimeInfo`.
+    pub flags: i32,
+}
+
+impl TimeInfo {
+    /// The flags of the time info, indicating the transport state and which fields are valid.
+    pub fn flags(&self) -> flags::TimeInfo {
+        flags::TimeInfo::from_bits_truncate(self.flags)
+    }
+
+    fn valid<T>(&self, flag: flags::TimeInfo, value: T) -> Option<T> {
+        if self.flags().contains(flag) { Some(value) } else { None }
+    }
+
+    /// Whether the host sequencer is playing.
+    pub fn is_playing(&self) -> bool {
+        self.flags().contains(flags::TRANSPORT_PLAYING)
+    }
+
+    /// Whether the host sequencer is recording.
+    pub fn is_recording(&self) -> bool {
+        self.flags().contains(flags::TRANSPORT_RECORDING)
+    }
+
+    /// Whether the host sequencer is in cycle mode.
+    pub fn is_cycle_active(&self) -> bool {
+        self.flags().contains(flags::TRANSPORT_CYCLE_ACTIVE)
+    }
+
+    /// System time in nanoseconds, if provided by the host.
+    pub fn system_time(&self) -> Option<f64> {
+        self.valid(flags::NANOSECONDS_VALID, self.nanoseconds)
+    }
+
+    /// Musical position in quarter notes, if provided by the host.
+    pub fn ppq_position(&self) -> Option<f64> {
+        self.valid(flags::PPQ_POS_VALID, self.ppq_pos)
+    }
+
+    /// Tempo in beats per minute, if provided by the host.
+    pub fn bpm(&self) -> Option<f64> {
+        self.valid(flags::TEMPO_VALID, self.tempo)
+    }
+
+    /// Start position of the last bar in quarter notes, if provided by the host.
+    pub fn bar_start(&self) -> Option<f64> {
+        self.valid(flags::BARS_VALID, self.bar_start_pos)
+    }
+
+    /// Cycle start and end positions (left and right locators) in quarter notes, if provided by
+    /// the host.
+    pub fn cycle(&self) -> Option<(f64, f64)> {
+        self.valid(flags::CYCLE_POS_VALID, (self.cycle_start_pos, self.cycle_end_pos))
+    }
+
+    /// Time signature as numerator and denominator, if provided by the host.
+    pub fn time_signature(&self) -> Option<(i32, i32)> {
+        self.valid(flags::TIME_SIG_VALID, (self.time_sig_numerator, self.time_sig_denominator))
+    }
+
+    /// SMPTE offset in subframes (1/80 of a frame), if provided by the host.
+    pub fn smpte_offset(&self) -> Option<i32> {
+        self.valid(flags::SMPTE_VALID, self.smpte_offset)
+    }
+
+    /// SMPTE frame rate, if provided by the host and known.
+    pub fn smpte_frame_rate(&self) -> Option<SmpteFrameRate> {
+        self.valid(flags::SMPTE_VALID, self.smpte_frame_rate)
+            .and_then(SmpteFrameRate::from_raw)
+    }
+
+    /// Samples until the next MIDI clock (24 per quarter note), if provided by the host. Can be
+    /// negative if the nearest clock has already passed.
+    pub fn next_clock(&self) -> Option<i32> {
+        self.valid(flags::VST_CLOCK_VALID, self.samples_to_next_clock)
+    }
+}
+
+/// SMPTE frame rates used in `TimeInfo`.
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmpteFrameRate {
+    Smpte24fps = 0,
+    Smpte25fps = 1,
+    Smpte2997fps = 2,
+    Smpte30fps = 3,
+    Smpte2997dfps = 4,
+    Smpte30dfps = 5,
+
+    /// Feet and frames for 16mm film.
+    SmpteFilm16mm = 6,
+    /// Feet and frames for 35mm film.
+    SmpteFilm35mm = 7,
+
+    Smpte239fps = 10,
+    Smpte249fps = 11,
+    Smpte599fps = 12,
+    Smpte60fps = 13,
+}
+
+impl SmpteFrameRate {
+    /// Convert from the value used in `TimeInfo`, returning `None` for unknown values.
+    pub fn from_raw(value: i32) -> Option<SmpteFrameRate> {
+        use self::SmpteFrameRate::*;
+
+        Some(match value {
+            0 => Smpte24fps,
+            1 => Smpte25fps,
+            2 => Smpte2997fps,
+            3 => Smpte30fps,
+            4 => Smpte2997dfps,
+            5 => Smpte30dfps,
+            6 => SmpteFilm16mm,
+            7 => SmpteFilm35mm,
+            10 => Smpte239fps,
+            11 => Smpte249fps,
+            12 => Smpte599fps,
+            13 => Smpte60fps,
+            _ => return None,
+        })
+    }
+
+    /// Number of frames per second. Film rates are counted as 24 fps.
+    pub fn frames_per_second(&self) -> f64 {
+        use self::SmpteFrameRate::*;
+
+        match *self {
+            Smpte24fps | SmpteFilm16mm | SmpteFilm35mm => 24.0,
+            Smpte25fps => 25.0,
+            Smpte2997fps | Smpte2997dfps => 30000.0 / 1001.0,
+            Smpte30fps | Smpte30dfps => 30.0,
+            Smpte239fps => 24000.0 / 1001.0,
+            Smpte249fps => 25000.0 / 1001.0,
+            Smpte599fps => 60000.0 / 1001.0,
+            Smpte60fps => 60.0,
+        }
+    }
+
+    /// Whether drop frame timecode is used.
+    pub fn is_drop_frame(&self) -> bool {
+        match *self {
+            SmpteFrameRate::Smpte2997dfps | SmpteFrameRate::Smpte30dfps => true,
+            _ => false,
+        }
+    }
+}
+
+/// A midi system exclusive event.
+///
+/// This event only contains raw byte data, and is up to the plugin to interpret it correctly.
+/// `plugin::CanDo` has a `ReceiveSysexEvent` variant which lets the host query the plugin as to
+/// whether this event is supported.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct SysExEvent {
+    /// Should be `EventType::SysEx`.
+    pub event_type: i32,
+
+    /// Size of this structure; `mem::sizeof::<SysExEvent>()`.
+    pub byte_size: i32,
+
+    /// Number of samples into the current processing block that this event occurs on.
+    pub delta_frames: i32,
+
+    /// Generic flags, none defined in VST api yet.
+    pub _flags: i32,
+
+    /// Size of payload in bytes.
+    pub data_size: i32,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved1: isize,
+
+    /// Pointer to payload.
+    pub system_data: *mut u8,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved2: isize,
+}
+
+/// Properties of a parameter, used by hosts to display it appropriately.
+#[repr(C)]
+pub struct ParameterProperties {
+    /// Float step.
+    pub step_f32: f32,
+    /// Small float step.
+    pub small_step_f32: f32,
+    /// Large float step.
+    pub large_step_f32: f32,
+
+    /// Parameter label.
+    pub label: [u8; MAX_LABEL],
+
+    /// Flags found in `flags::Parameter`.
+    pub flags: i32,
+
+    /// Minimum integer value.
+    pub min_integer: i32,
+    /// Maximum integer value.
+    pub max_integer: i32,
+    /// Integer step.
+    pub step_integer: i32,
+    /// Large integer step.
+    pub large_step_integer: i32,
+
+    /// Short label, recommended 6 characters + delimiter.
+    pub short_label: [u8; MAX_SHORT_LABEL],
+
+    /// Index where this parameter should be displayed, starting at 0.
+    pub display_index: i16,
+
+    /// Category of this parameter, starting at 1. 0 means no category.
+    pub category: i16,
+
+    /// Number of parameters in this category.
+    pub num_parameters_in_category: i16,
+
+    /// Reserved for future use. Should be 0.
+    pub _reserved: i16,
+
+    /// Category label, e.g. "Osc 1".
+    pub category_label: [u8; MAX_CATEGORY_LABEL],
+
+    /// Reserved for future use.
+    pub future: [u8; 16],
+}
+
+/// Name of a MIDI program of a plugin. See `plugin::OpCode::GetMidiProgramName`.
+#[repr(C)]
+pub struct MidiProgramName {
+    /// Index of the program to fill this struct for, starting at 0.
+    pub this_program_index: i32,
+    /// Name of the program.
+    pub name: [u8; MAX_NAME_LEN],
+    /// MIDI program number, -1 = off, 0-127.
+    pub midi_program: i8,
+    /// MIDI bank select MSB, -1 = off, 0-127.
+    pub midi_bank_msb: i8,
+    /// MIDI bank select LSB, -1 = off, 0-127.
+    pub midi_bank_lsb: i8,
+    /// Reserved for future use. Should be 0.
+    pub _reserved: i8,
+    /// Index of the category of this program, -1 = no category.
+    pub parent_category_index: i32,
+    /// Flags found in `flags::MidiProgramName`.
+    pub flags: i32,
+}
+
+/// Category of MIDI programs of a plugin. See `plugin::OpCode::GetMidiProgramCategory`.
+#[repr(C)]
+pub struct MidiProgramCategory {
+    /// Index of the category to fill this struct for, starting at 0.
+    pub this_category_index: i32,
+    /// Name of the category.
+    pub name: [u8; MAX_NAME_LEN],
+    /// Index of the parent category, -1 = no parent category.
+    pub parent_category_index: i32,
+    /// Reserved for future use. Should be 0.
+    pub flags: i32,
+}
+
+/// Name of a key in a MIDI program of a plugin. See `plugin::OpCode::GetMidiKeyName`.
+#[repr(C)]
+pub struct MidiKeyName {
+    /// Index of the program the key belongs to.
+    pub this_program_index: i32,
+    /// MIDI key number, 0-127.
+    pub this_key_number: i32,
+    /// Name of the key.
+    pub key_name: [u8; MAX_NAME_LEN],
+    /// Reserved for future use. Should be 0.
+    pub _reserved: i32,
+    /// Reserved for future use. Should be 0.
+    pub flags: i32,
+}
+
+/// Buffers for variable I/O processing. See `plugin::OpCode::ProcessVarIo`.
+#[repr(C)]
+pub struct VariableIo {
+    /// Input channel buffers.
+    pub inputs: *mut *mut f32,
+    /// Output channel buffers.
+    pub outputs: *mut *mut f32,
+    /// Number of samples in each input buffer.
+    pub num_samples_input: i32,
+    /// Number of samples in each output buffer.
+    pub num_samples_output: i32,
+    /// Number of input samples consumed by the plugin.
+    pub num_samples_input_processed: *mut i32,
+    /// Number of output samples produced by the plugin.
+    pub num_samples_output_processed: *mut i32,
+}
+
+/// An audio file opened in an offline capable host. See `plugin::OpCode::OfflineNotify`.
+#[repr(C)]
+pub struct AudioFile {
+    /// Flags describing the file, see the VST SDK `VstAudioFileFlags`.
+    pub flags: i32,
+    /// Data private to the host.
+    pub host_owned: *mut c_void,
+    /// Data private to the plugin.
+    pub plug_owned: *mut c_void,
+    /// File title.
+    pub name: [u8; MAX_FILE_NAME_LEN],
+    /// Identifies the file during a session.
+    pub unique_id: i32,
+    /// Sample rate of the file.
+    pub sample_rate: f64,
+    /// Number of channels.
+    pub num_channels: i32,
+    /// Number of frames in the file.
+    pub num_frames: f64,
+    /// Reserved for future use.
+    pub format: i32,
+    /// Position of the edit cursor in frames, -1 if there is none.
+    pub edit_cursor_position: f64,
+    /// Index of the first selected frame, -1 if nothing is selected.
+    pub selection_start: f64,
+    /// Number of selected frames.
+    pub selection_size: f64,
+    /// Mask of the selected channels, 1 bit per channel.
+    pub selected_channels_mask: i32,
+    /// Number of markers in the file.
+    pub num_markers: i32,
+    /// Unit of the time ruler.
+    pub time_ruler_unit: i32,
+    /// Offset of the time ruler.
+    pub time_ruler_offset: f64,
+    /// Tempo in beats per minute.
+    pub tempo: f64,
+    /// Time signature numerator.
+    pub time_sig_numerator: i32,
+    /// Time signature denominator.
+    pub time_sig_denominator: i32,
+    /// Resolution in ticks per quarter note.
+    pub ticks_per_black_note: i32,
+    /// SMPTE frame rate, as in `TimeInfo`.
+    pub smpte_frame_rate: i32,
+    /// Reserved for future use.
+    pub future: [u8; 64],
+}
+
+/// A task processed by an offline plugin. See `plugin::OpCode::OfflinePrepare` and
+/// `plugin::OpCode::OfflineRun`.
+#[repr(C)]
+pub struct OfflineTask {
+    /// Name of the process, set by the plugin.
+    pub process_name: [u8; 96],
+
+    /// Read position in frames, set by the plugin or host.
+    pub read_position: f64,
+    /// Write position in frames, set by the plugin or host.
+    pub write_position: f64,
+    /// Number of frames read, set by the plugin or host.
+    pub read_count: i32,
+    /// Number of frames written, set by the plugin.
+    pub write_count: i32,
+    /// Size of the input buffer in frames, set by the host.
+    pub size_input_buffer: i32,
+    /// Size of the output buffer in frames, set by the host.
+    pub size_output_buffer: i32,
+    /// Input buffer, set by the host.
+    pub input_buffer: *mut c_void,
+    /// Output buffer, set by the host.
+    pub output_buffer: *mut c_void,
+    /// Position to start processing from in frames, set by the host.
+    pub position_to_process_from: f64,
+    /// Number of frames to process, set by the host.
+    pub num_frames_to_process: f64,
+    /// Maximum number of frames to write, set by the plugin.
+    pub max_frames_to_write: f64,
+
+    /// Buffer for other data, set by the plugin.
+    pub extra_buffer: *mut c_void,
+    /// Generic value, set by the plugin or host.
+    pub value: i32,
+    /// Generic index, set by the plugin or host.
+    pub index: i32,
+
+    /// Number of frames in the source file, set by the host.
+    pub num_frames_in_source_file: f64,
+    /// Sample rate of the source, set by the plugin or host.
+    pub source_sample_rate: f64,
+    /// Sample rate of the destination, set by the plugin or host.
+    pub destination_sample_rate: f64,
+    /// Number of source channels, set by the plugin or host.
+    pub num_source_channels: i32,
+    /// Number of destination channels, set by the plugin or host.
+    pub num_destination_channels: i32,
+    /// Format of the source, set by the host.
+    pub source_format: i32,
+    /// Format of the destination, set by the plugin.
+    pub destination_format: i32,
+    /// Text output, set by the plugin or host.
+    pub output_text: [u8; 512],
+
+    /// Progress between 0.0 and 1.0, set by the plugin.
+    pub progress: f64,
+    /// Reserved for future use.
+    pub progress_mode: i32,
+    /// Text describing the progress, set by the plugin.
+    pub progress_text: [u8; 100],
+
+    /// Flags found in `flags::OfflineTask`, set by the plugin and host.
+    pub flags: i32,
+    /// Reserved for future use.
+    pub return_value: i32,
+    /// Data private to the host.
+    pub host_owned: *mut c_void,
+    /// Data private to the plugin.
+    pub plug_owned: *mut c_void,
+
+    /// Reserved for future use.
+    pub future: [u8; 1024],
+}
+
+impl AudioFile {
+    /// Title of the file.
+    pub fn name(&self) -> String {
+        read_fixed(&self.name)
+    }
+}
+
+impl OfflineTask {
+    /// Name of the process.
+    pub fn process_name(&self) -> String {
+        read_fixed(&self.process_name)
+    }
+
+    /// Set the name of the process, limited to 95 characters.
+    pub fn set_process_name(&mut self, name: &str) {
+        write_fixed(&mut self.process_name, name);
+    }
+
+    /// Text output of the task.
+    pub fn output_text(&self) -> String {
+        read_fixed(&self.output_text)
+    }
+
+    /// Set the text output of the task, limited to 511 characters.
+    pub fn set_output_text(&mut self, text: &str) {
+        write_fixed(&mut self.output_text, text);
+    }
+
+    /// Report the progress of the task between 0.0 and 1.0 with a description.
+    pub fn set_progress(&mut self, progress: f64, text: &str) {
+        self.progress = progress;
+        write_fixed(&mut self.progress_text, text);
+    }
+
+    /// The flags of the task.
+    pub fn flags(&self) -> flags::OfflineTask {
+        flags::OfflineTask::from_bits_truncate(self.flags)
+    }
+
+    /// Set the flags of the task.
+    pub fn set_flags(&mut self, flags: flags::OfflineTask) {
+        self.flags = flags.bits();
+    }
+}
+
+/// File type description used by the host file selector. See `api::FileSelect`.
+#[repr(C)]
+pub struct FileType {
+    /// Display name, e.g. "Text File".
+    pub name: [u8; 128],
+    /// Mac file type, e.g. "TEXT".
+    pub mac_type: [u8; 8],
+    /// DOS file extension, e.g. "txt".
+    pub dos_type: [u8; 8],
+    /// Unix file extension, e.g. "txt".
+    pub unix_type: [u8; 8],
+    /// MIME type, e.g. "text/plain".
+    pub mime_type_1: [u8; 128],
+    /// Additional MIME type.
+    pub mime_type_2: [u8; 128],
+}
+
+/// Request from a plugin for the host to open a file selector.
+#[repr(C)]
+pub struct FileSelect {
+    /// 0 = load, 1 = save, 2 = load multiple files, 3 = select directory.
+    pub command: i32,
+    /// Must be 0 (file type).
+    pub select_type: i32,
+    /// Optional mac creator, 0 if unused.
+    pub mac_creator: i32,
+    /// Number of items in `file_types`.
+    pub num_file_types: i32,
+    /// List of allowed file types.
+    pub file_types: *mut FileType,
+    /// Title of the file selector.
+    pub title: [u8; 1024],
+    /// Initial path, may be null.
+    pub initial_path: *mut c_char,
+    /// Buffer for the selected path when loading, saving or selecting a directory.
+    ///
+    /// If this is null the host allocates the buffer and the plugin must close the file selector
+    /// to free it.
+    pub return_path: *mut c_char,
+    /// Size of the buffer pointed to by `return_path`.
+    pub return_path_size: i32,
+    /// Host allocated list of selected paths when loading multiple files.
+    pub return_multiple_paths: *mut *mut c_char,
+    /// Number of paths in `return_multiple_paths`.
+    pub num_return_paths: i32,
+    /// Reserved for host use.
+    pub reserved: isize,
+    /// Reserved for future use.
+    pub future: [u8; 116],
+}
+
 /// Used to specify whether functionality is supported.
 #[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Supported {
     Yes,
     Maybe,
     No
 }
 
+impl From<isize> for Supported {
+    /// Convert from the integer ordinal returned by the VST api.
+    fn from(value: isize) -> Supported {
+        use self::Supported::*;
+
+        match value {
+            v if v > 0 => Yes,
+            0 => Maybe,
+            _ => No
+        }
+    }
+}
+
 impl Into<isize> for Supported {
     /// Convert to integer ordinal for interop with VST api.
     fn into(self) -> isize {
@@ -300,6 +1092,103 @@ pub mod flags {
         }
     }
 
+    bitflags! {
+        /// Flags for VST parameters. See `api::ParameterProperties`.
+        flags Parameter: i32 {
+            /// Parameter is a switch (on/off).
+            const IS_SWITCH = 1 << 0,
+            /// `min_integer` and `max_integer` are valid.
+            const USES_INTEGER_MIN_MAX = 1 << 1,
+            /// `step_f32`, `small_step_f32` and `large_step_f32` are valid.
+            const USES_FLOAT_STEP = 1 << 2,
+            /// `step_integer` and `large_step_integer` are valid.
+            const USES_INT_STEP = 1 << 3,
+            /// `display_index` is valid.
+            const SUPPORTS_DISPLAY_INDEX = 1 << 4,
+            /// `category`, `num_parameters_in_category` and `category_label` are valid.
+            const SUPPORTS_DISPLAY_CATEGORY = 1 << 5,
+            /// Parameter value can ramp up and down.
+            const CAN_RAMP = 1 << 6
+        }
+    }
+
+    bitflags! {
+        /// Flags for `api::TimeInfo`. The `*_VALID` flags are also used as a request mask.
+        flags TimeInfo: i32 {
+            /// Indicates that play, cycle or record state has changed.
+            const TRANSPORT_CHANGED = 1,
+            /// Set if Host sequencer is currently playing.
+            const TRANSPORT_PLAYING = 1 << 1,
+            /// Set if Host sequencer is in cycle mode.
+            const TRANSPORT_CYCLE_ACTIVE = 1 << 2,
+            /// Set if Host sequencer is in record mode.
+            const TRANSPORT_RECORDING = 1 << 3,
+
+            /// Set if automation write mode active (record parameter changes).
+            const AUTOMATION_WRITING = 1 << 6,
+            /// Set if automation read mode active (play parameter changes).
+            const AUTOMATION_READING = 1 << 7,
+
+            /// `nanoseconds` field is valid.
+            const NANOSECONDS_VALID = 1 << 8,
+            /// `ppq_pos` field is valid.
+            const PPQ_POS_VALID = 1 << 9,
+            /// `tempo` field is valid.
+            const TEMPO_VALID = 1 << 10,
+            /// `bar_start_pos` field is valid.
+            const BARS_VALID = 1 << 11,
+            /// `cycle_start_pos` and `cycle_end_pos` fields are valid.
+            const CYCLE_POS_VALID = 1 << 12,
+            /// `time_sig_numerator` and `time_sig_denominator` fields are valid.
+            const TIME_SIG_VALID = 1 << 13,
+            /// `smpte_offset` and `smpte_frame_rate` fields are valid.
+            const SMPTE_VALID = 1 << 14,
+            /// `samples_to_next_clock` field is valid.
+            const VST_CLOCK_VALID = 1 << 15
+        }
+    }
+
+    bitflags! {
+        /// Flags for `api::MidiEvent`.
+        flags MidiEvent: i32 {
+            /// The event is played live, not played back from a sequencer track. This allows the
+            /// plugin to handle it with the lowest possible latency.
+            const REALTIME_EVENT = 1
+        }
+    }
+
+    bitflags! {
+        /// Flags for `api::OfflineTask`.
+        flags OfflineTask: i32 {
+            /// A parameter is invalid, set by the host.
+            const INVALID_PARAMETER = 1 << 0,
+            /// A new file is processed, set by the host.
+            const NEW_FILE = 1 << 1,
+            /// An error occured, set by the plugin.
+            const PLUGIN_ERROR = 1 << 10,
+            /// The audio buffers are interleaved, set by the plugin.
+            const INTERLEAVED_AUDIO = 1 << 11,
+            /// The output file is temporary, set by the plugin.
+            const TEMP_OUTPUT_FILE = 1 << 12,
+            /// The output file uses float samples, set by the plugin.
+            const FLOAT_OUTPUT_FILE = 1 << 13,
+            /// The plugin writes at random positions, set by the plugin.
+            const RANDOM_WRITE = 1 << 14,
+            /// The plugin changes the length of the audio, set by the plugin.
+            const STRETCH = 1 << 15,
+            /// The plugin does not need a separate processing thread, set by the plugin.
+            const NO_THREAD = 1 << 16
+        }
+    }
+
+    bitflags! {
+        /// Flags for `api::MidiProgramName`.
+        flags MidiProgramName: i32 {
+            /// The program is in omni mode, the channel is ignored.
+            const IS_OMNI = 1
+        }
+    }
+
     bitflags!{
         /// Cross platform modifier key flags.
         flags ModifierKey: u8 {
@@ -314,3 +1203,37 @@ pub mod flags {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use api::{flags, SmpteFrameRate, TimeInfo};
+
+    /// Test that the time info accessors only return fields which are flagged as valid.
+    #[test]
+    fn time_info_accessors() {
+        let mut info = TimeInfo {
+            tempo: 140.0,
+            bar_start_pos: 4.0,
+            cycle_start_pos: 1.0,
+            flags: (flags::TEMPO_VALID | flags::BARS_VALID).bits(),
+            ..Default::default()
+        };
+
+        assert_eq!(info.bpm(), Some(140.0));
+        assert_eq!(info.bar_start(), Some(4.0));
+        assert_eq!(info.cycle(), None);
+        assert_eq!(info.smpte_frame_rate(), None);
+
+        info.smpte_offset = 80;
+        info.smpte_frame_rate = SmpteFrameRate::Smpte2997dfps as i32;
+        info.flags = flags::SMPTE_VALID.bits();
+        assert_eq!(info.bpm(), None);
+        assert_eq!(info.smpte_offset(), Some(80));
+        assert_eq!(info.smpte_frame_rate(), Some(SmpteFrameRate::Smpte2997dfps));
+        assert!(SmpteFrameRate::Smpte2997dfps.is_drop_frame());
+
+        // Unknown frame rates are not reported even if flagged as valid.
+        info.smpte_frame_rate = 100;
+        assert_eq!(info.smpte_frame_rate(), None);
+    }
+}
@@ -0,0 +1,221 @@
+//! Sample accurate parameter automation.
+//!
+//! Parameters are usually set between process calls, so every change takes effect at the start of
+//! a block. [`process_automated`](fn.process_automated.html) instead splits the block at the
+//! sample offsets of queued changes and calls `Plugin::process` once per segment, applying each
+//! change exactly at its boundary.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::automation::{self, ParameterChange};
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! # #[derive(Default)]
+//! # struct GainPlugin { gain: f32 }
+//! # impl Plugin for GainPlugin {
+//! #     fn get_info(&self) -> Info { Default::default() }
+//! #     fn set_parameter(&mut self, _index: i32, value: f32) { self.gain = value; }
+//! #     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//! #         for (input, output) in buffer.zip() {
+//! #             for (i, o) in input.iter().zip(output.iter_mut()) { *o = *i * self.gain; }
+//! #         }
+//! #     }
+//! # }
+//! let mut plugin = GainPlugin::default();
+//! let mut input = vec![1.0; 4];
+//! let mut output = vec![0.0; 4];
+//!
+//! // Fade in from the second sample on.
+//! let mut changes = [ParameterChange { index: 0, value: 0.5, delta_frames: 1 },
+//!                    ParameterChange { index: 0, value: 1.0, delta_frames: 2 }];
+//!
+//! automation::process_automated(&mut plugin,
+//!                               AudioBuffer::new(vec![&mut input], vec![&mut output]),
+//!                               &mut changes);
+//!
+//! assert_eq!(output, vec![0.0, 0.5, 1.0, 1.0]);
+//! ```
+
+use std::cmp;
+
+use num::Float;
+
+use buffer::AudioBuffer;
+use plugin::Plugin;
+
+/// A parameter change which takes effect at a sample offset into the current block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParameterChange {
+    /// Index of the parameter.
+    pub index: i32,
+
+    /// New value of the parameter, between 0.0 and 1.0.
+    pub value: f32,
+
+    /// Number of samples into the current block at which the change takes effect.
+    ///
+    /// Changes at or past the end of the block are applied after the block has been processed.
+    pub delta_frames: i32,
+}
+
+/// Process an `f32` buffer, applying the parameter changes at their sample offsets.
+///
+/// `changes` is sorted by `delta_frames` in place; changes with the same offset are applied in the
+/// order they were queued.
+pub fn process_automated<P: Plugin + ?Sized>(plugin: &mut P,
+                                             buffer: AudioBuffer<f32>,
+                                             changes: &mut [ParameterChange]) {
+    process_segments(plugin, buffer, changes, |p, segment| p.process(segment));
+}
+
+/// Process an `f64` buffer, applying the parameter changes at their sample offsets.
+///
+/// See [`process_automated`](fn.process_automated.html).
+pub fn process_automated_f64<P: Plugin + ?Sized>(plugin: &mut P,
+                                                 buffer: AudioBuffer<f64>,
+                                                 changes: &mut [ParameterChange]) {
+    process_segments(plugin, buffer, changes, |p, segment| p.process_f64(segment));
+}
+
+fn process_segments<P, T, F>(plugin: &mut P,
+                             buffer: AudioBuffer<T>,
+                             changes: &mut [ParameterChange],
+                             mut process: F)
+    where P: Plugin + ?Sized,
+          T: Float,
+          F: FnMut(&mut P, AudioBuffer<T>)
+{
+    // An insertion sort like `event::sort_by_time`: stable, so changes on the same sample keep
+    // their order, and it does not allocate on the audio thread
+    for i in 1..changes.len() {
+        let mut j = i;
+        while j > 0 && changes[j - 1].delta_frames > changes[j].delta_frames {
+            changes.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+
+    let samples = buffer.samples();
+    let mut rest = buffer;
+
+    let mut start = 0;
+    let mut pending = changes.iter().peekable();
+
+    while start < samples {
+        // Apply all changes up to and including the start of this segment
+        while let Some(change) = pending.peek().cloned() {
+            if cmp::max(change.delta_frames, 0) as usize > start {
+                break;
+            }
+            plugin.set_parameter(change.index, change.value);
+            pending.next();
+        }
+
+        let end = match pending.peek() {
+            Some(change) => cmp::min(change.delta_frames as usize, samples),
+            None => samples,
+        };
+
+        let (segment, remaining) = rest.split_at(end - start);
+        process(plugin, segment);
+        rest = remaining;
+        start = end;
+    }
+
+    for change in pending {
+        plugin.set_parameter(change.index, change.value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use automation::{self, ParameterChange};
+    use buffer::AudioBuffer;
+    use plugin::{Info, Plugin};
+
+    /// Records the length and parameter value of every processed segment.
+    #[derive(Default)]
+    struct RecordPlugin {
+        value: f32,
+        segments: Vec<(usize, f32)>,
+    }
+
+    impl Plugin for RecordPlugin {
+        fn get_info(&self) -> Info { Default::default() }
+
+        fn set_parameter(&mut self, _index: i32, value: f32) {
+            self.value = value;
+        }
+
+        fn process(&mut self, buffer: AudioBuffer<f32>) {
+            self.segments.push((buffer.samples(), self.value));
+        }
+    }
+
+    fn change(value: f32, delta_frames: i32) -> ParameterChange {
+        ParameterChange { index: 0, value: value, delta_frames: delta_frames }
+    }
+
+    /// Test that the block is split at each change.
+    #[test]
+    fn segments() {
+        let mut plugin = RecordPlugin::default();
+        let mut input = vec![0.0; 16];
+        let mut output = vec![0.0; 16];
+
+        let mut changes = [change(0.75, 12), change(0.25, 0), change(0.5, 4)];
+        automation::process_automated(&mut plugin,
+                                      AudioBuffer::new(vec![&mut input], vec![&mut output]),
+                                      &mut changes);
+
+        assert_eq!(plugin.segments, vec![(4, 0.25), (8, 0.5), (4, 0.75)]);
+    }
+
+    /// Test that changes on the same sample keep their order and late changes still apply.
+    #[test]
+    fn order() {
+        let mut plugin = RecordPlugin::default();
+        let mut input = vec![0.0; 8];
+        let mut output = vec![0.0; 8];
+
+        let mut changes = [change(0.1, 4), change(0.2, 4), change(0.3, 8)];
+        automation::process_automated(&mut plugin,
+                                      AudioBuffer::new(vec![&mut input], vec![&mut output]),
+                                      &mut changes);
+
+        assert_eq!(plugin.segments, vec![(4, 0.0), (4, 0.2)]);
+        assert_eq!(plugin.value, 0.3);
+    }
+
+    /// Test that changes queued out of order are sorted, keeping the order on the same sample.
+    #[test]
+    fn unsorted() {
+        let mut plugin = RecordPlugin::default();
+        let mut input = vec![0.0; 8];
+        let mut output = vec![0.0; 8];
+
+        let mut changes = [change(0.3, 8), change(0.1, 4), change(0.2, 4)];
+        automation::process_automated(&mut plugin,
+                                      AudioBuffer::new(vec![&mut input], vec![&mut output]),
+                                      &mut changes);
+
+        assert_eq!(plugin.segments, vec![(4, 0.0), (4, 0.2)]);
+        assert_eq!(plugin.value, 0.3);
+    }
+
+    /// Test that buffers without channels are split by the sample count of the host.
+    #[test]
+    fn no_channels() {
+        let mut plugin = RecordPlugin::default();
+        let mut channels: [*mut f32; 0] = [];
+
+        let buffer = unsafe {
+            AudioBuffer::from_raw(channels.as_mut_ptr(), channels.as_mut_ptr(), 0, 0, 16)
+        };
+        let mut changes = [change(0.5, 4)];
+        automation::process_automated(&mut plugin, buffer, &mut changes);
+
+        assert_eq!(plugin.segments, vec![(4, 0.0), (12, 0.5)]);
+    }
+}
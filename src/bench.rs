@@ -0,0 +1,306 @@
+//! Measuring the processing performance of plugins.
+//!
+//! A [`Benchmark`](struct.Benchmark.html) processes a fixed number of blocks of a test signal and
+//! times every call to `Plugin::process`. Since `PluginInstance` implements `Plugin`, hosted
+//! plugins can be measured just like plugin types.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::bench::{Benchmark, Signal};
+//! # use vst2::plugin::{Info, Plugin};
+//! # #[derive(Default)]
+//! # struct MyPlugin;
+//! # impl Plugin for MyPlugin {
+//! #     fn get_info(&self) -> Info { Default::default() }
+//! # }
+//! let benchmark = Benchmark {
+//!     block_size: 128,
+//!     signal: Signal::Noise,
+//!     ..Default::default()
+//! };
+//!
+//! let result = benchmark.run(&mut MyPlugin::default());
+//! println!("{}", result);
+//! ```
+
+use std::f32::consts::PI;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use buffer::{AudioBuffer, BufferStorage};
+use plugin::Plugin;
+
+/// Input signal fed to every input channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Signal {
+    /// All zeros.
+    Silence,
+
+    /// White noise between -1 and 1.
+    Noise,
+
+    /// A sine wave of the given frequency in Hz at half scale.
+    Sine(f32),
+
+    /// A single full scale sample at the start of every block.
+    Impulse,
+}
+
+/// Settings of a benchmark run.
+#[derive(Clone, Debug)]
+pub struct Benchmark {
+    /// Sample rate passed to the plugin.
+    pub sample_rate: f32,
+
+    /// Number of samples in each block.
+    pub block_size: usize,
+
+    /// Number of blocks to measure.
+    pub blocks: usize,
+
+    /// Number of blocks processed before measuring, to warm up caches and lazy initialization.
+    pub warmup: usize,
+
+    /// Input signal.
+    pub signal: Signal,
+}
+
+impl Default for Benchmark {
+    fn default() -> Benchmark {
+        Benchmark {
+            sample_rate: 44100.0,
+            block_size: 512,
+            blocks: 1000,
+            warmup: 10,
+            signal: Signal::Sine(440.0),
+        }
+    }
+}
+
+impl Benchmark {
+    /// Process the configured blocks with `plugin` and measure the time taken for each.
+    ///
+    /// The sample rate and block size are set and the plugin is resumed before processing and
+    /// suspended afterwards. Initialize the plugin first if needed.
+    pub fn run<P: Plugin + ?Sized>(&self, plugin: &mut P) -> BenchmarkResult {
+        let info = plugin.get_info();
+        let mut inputs = vec![vec![0.0f32; self.block_size]; info.inputs.max(0) as usize];
+        let mut outputs = vec![vec![0.0f32; self.block_size]; info.outputs.max(0) as usize];
+
+        plugin.sample_rate_changed(self.sample_rate);
+        plugin.block_size_changed(self.block_size as i64);
+        plugin.on_resume();
+
+        let mut generator = Generator { position: 0, seed: 0x1234_5678 };
+        let mut block_times = Vec::with_capacity(self.blocks);
+
+        let mut storage = BufferStorage::default();
+        storage.reserve(inputs.len(), outputs.len(), self.block_size);
+        let mut input_ptrs = Vec::with_capacity(inputs.len());
+        let mut output_ptrs = Vec::with_capacity(outputs.len());
+
+        for block in 0..self.warmup + self.blocks {
+            generator.fill(self.signal, self.sample_rate, &mut inputs);
+
+            // The buffer is created before starting the clock, so only the plugin is timed
+            input_ptrs.clear();
+            input_ptrs.extend(inputs.iter_mut().map(|c| c.as_mut_ptr()));
+            output_ptrs.clear();
+            output_ptrs.extend(outputs.iter_mut().map(|c| c.as_mut_ptr()));
+            let buffer = unsafe {
+                AudioBuffer::from_raw_in_place(input_ptrs.as_mut_ptr(), output_ptrs.as_mut_ptr(),
+                                               input_ptrs.len(), output_ptrs.len(),
+                                               self.block_size, &mut storage)
+            };
+
+            let start = Instant::now();
+            plugin.process(buffer);
+            let elapsed = start.elapsed();
+
+            if block >= self.warmup {
+                block_times.push(elapsed);
+            }
+        }
+
+        plugin.on_suspend();
+
+        BenchmarkResult {
+            sample_rate: self.sample_rate,
+            block_size: self.block_size,
+            block_times: block_times,
+        }
+    }
+}
+
+/// Generates the input signal across blocks.
+struct Generator {
+    position: usize,
+    seed: u32,
+}
+
+impl Generator {
+    fn fill(&mut self, signal: Signal, sample_rate: f32, channels: &mut [Vec<f32>]) {
+        let len = channels.first().map_or(0, |c| c.len());
+
+        for i in 0..len {
+            let sample = match signal {
+                Signal::Silence => 0.0,
+                Signal::Noise => {
+                    // Linear congruential generator, good enough for test noise
+                    self.seed = self.seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                    (self.seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+                }
+                Signal::Sine(frequency) => {
+                    let time = (self.position + i) as f32 / sample_rate;
+                    (2.0 * PI * frequency * time).sin() * 0.5
+                }
+                Signal::Impulse => if i == 0 { 1.0 } else { 0.0 },
+            };
+
+            for channel in channels.iter_mut() {
+                channel[i] = sample;
+            }
+        }
+
+        self.position += len;
+    }
+}
+
+/// Timings measured by a benchmark run.
+#[derive(Clone, Debug)]
+pub struct BenchmarkResult {
+    /// Sample rate used.
+    pub sample_rate: f32,
+
+    /// Number of samples in each block.
+    pub block_size: usize,
+
+    /// Time taken to process each measured block.
+    pub block_times: Vec<Duration>,
+}
+
+impl BenchmarkResult {
+    /// Duration of the audio in a single block.
+    pub fn block_duration(&self) -> Duration {
+        duration(self.block_size as f64 / self.sample_rate as f64)
+    }
+
+    /// Mean time taken to process a block.
+    pub fn mean(&self) -> Duration {
+        if self.block_times.is_empty() {
+            return Duration::new(0, 0);
+        }
+
+        let total: f64 = self.block_times.iter().map(|&t| seconds(t)).sum();
+        duration(total / self.block_times.len() as f64)
+    }
+
+    /// Longest time taken to process a block, which decides whether dropouts occur.
+    pub fn worst(&self) -> Duration {
+        self.block_times.iter().cloned().max().unwrap_or(Duration::new(0, 0))
+    }
+
+    /// Time taken by the given fraction of blocks, e.g. `0.99` for the 99th percentile.
+    pub fn percentile(&self, fraction: f64) -> Duration {
+        if self.block_times.is_empty() {
+            return Duration::new(0, 0);
+        }
+
+        let mut sorted = self.block_times.clone();
+        sorted.sort();
+
+        let index = (fraction.max(0.0).min(1.0) * (sorted.len() - 1) as f64).round() as usize;
+        sorted[index]
+    }
+
+    /// How many times faster than real time the plugin processes on average.
+    ///
+    /// Values below 1 mean the plugin cannot run in real time with these settings.
+    pub fn real_time_ratio(&self) -> f64 {
+        ratio(self.block_duration(), self.mean())
+    }
+
+    /// The real time ratio of the slowest block.
+    pub fn worst_real_time_ratio(&self) -> f64 {
+        ratio(self.block_duration(), self.worst())
+    }
+
+    /// Share of the available time used on average, as a fraction of 1.
+    pub fn cpu_load(&self) -> f64 {
+        seconds(self.mean()) / seconds(self.block_duration())
+    }
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} blocks of {} samples at {} Hz: mean {:.1} us, 99% {:.1} us, worst {:.1} us, \
+                {:.1}x real time, {:.2}% CPU",
+               self.block_times.len(),
+               self.block_size,
+               self.sample_rate,
+               seconds(self.mean()) * 1e6,
+               seconds(self.percentile(0.99)) * 1e6,
+               seconds(self.worst()) * 1e6,
+               self.real_time_ratio(),
+               self.cpu_load() * 100.0)
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+fn duration(seconds: f64) -> Duration {
+    Duration::new(seconds as u64, (seconds.fract() * 1e9).round() as u32)
+}
+
+/// Ratio of `available` to `taken`, infinite if no time was taken.
+fn ratio(available: Duration, taken: Duration) -> f64 {
+    let taken = seconds(taken);
+    if taken > 0.0 { seconds(available) / taken } else { ::std::f64::INFINITY }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bench::{Benchmark, BenchmarkResult};
+    use plugin::{Info, Plugin};
+
+    #[derive(Default)]
+    struct Passthrough;
+
+    impl Plugin for Passthrough {
+        fn get_info(&self) -> Info { Default::default() }
+    }
+
+    /// Test that every measured block is timed.
+    #[test]
+    fn run() {
+        let benchmark = Benchmark { blocks: 20, warmup: 5, ..Default::default() };
+        let result = benchmark.run(&mut Passthrough);
+
+        assert_eq!(result.block_times.len(), 20);
+        assert!(result.worst() >= result.mean());
+    }
+
+    /// Test the statistics of known timings.
+    #[test]
+    fn statistics() {
+        let result = BenchmarkResult {
+            sample_rate: 1000.0,
+            block_size: 10,
+            block_times: vec![Duration::from_millis(1), Duration::from_millis(5),
+                              Duration::from_millis(3)],
+        };
+
+        assert_eq!(result.block_duration(), Duration::from_millis(10));
+        assert_eq!(result.worst(), Duration::from_millis(5));
+        assert_eq!(result.percentile(0.5), Duration::from_millis(3));
+        assert!((result.real_time_ratio() - 3.333).abs() < 0.01);
+        assert!((result.worst_real_time_ratio() - 2.0).abs() < 1e-6);
+        assert!((result.cpu_load() - 0.3).abs() < 1e-6);
+    }
+}
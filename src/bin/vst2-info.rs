@@ -0,0 +1,176 @@
+//! Print the info, parameters and programs of a VST plugin and optionally time its processing.
+//!
+//! Built with the `cli` feature. Run without arguments for usage.
+
+extern crate vst2;
+
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use vst2::bench::{Benchmark, Signal};
+use vst2::host::{PluginInstance, PluginLoader};
+use vst2::plugin::Plugin;
+use vst2::testing::TestHost;
+
+const USAGE: &'static str = "\
+Usage: vst2-info <plugin> [options]
+
+Options:
+    --process <signal>      Process silence, noise, sine or impulse and report timing
+    --sample-rate <hz>      Sample rate to process at (default 44100)
+    --block-size <samples>  Samples per block (default 512)
+    --blocks <count>        Number of blocks to process (default 1000)";
+
+/// Parsed command line.
+struct Options {
+    path: String,
+    signal: Option<Signal>,
+    sample_rate: f32,
+    block_size: usize,
+    blocks: usize,
+}
+
+fn parse_options(mut args: env::Args) -> Result<Options, String> {
+    args.next();
+
+    let mut options = Options {
+        path: String::new(),
+        signal: None,
+        sample_rate: 44100.0,
+        block_size: 512,
+        blocks: 1000,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with("--") {
+            if !options.path.is_empty() {
+                return Err(format!("Unexpected argument '{}'", arg));
+            }
+            options.path = arg;
+            continue;
+        }
+
+        let value = try!(args.next().ok_or_else(|| format!("Missing value for {}", arg)));
+        let invalid = format!("Invalid value '{}' for {}", value, arg);
+
+        match &arg[..] {
+            "--process" => {
+                options.signal = Some(match &value[..] {
+                    "silence" => Signal::Silence,
+                    "noise" => Signal::Noise,
+                    "sine" => Signal::Sine(440.0),
+                    "impulse" => Signal::Impulse,
+                    _ => return Err(invalid),
+                });
+            }
+            "--sample-rate" => options.sample_rate = try!(value.parse().map_err(|_| invalid)),
+            "--block-size" => options.block_size = try!(value.parse().map_err(|_| invalid)),
+            "--blocks" => options.blocks = try!(value.parse().map_err(|_| invalid)),
+            _ => return Err(format!("Unknown option {}", arg)),
+        }
+    }
+
+    if options.path.is_empty() {
+        return Err("Missing plugin path".to_string());
+    }
+    if options.block_size == 0 {
+        return Err("Block size must be positive".to_string());
+    }
+
+    Ok(options)
+}
+
+fn print_info(instance: &PluginInstance) {
+    let info = instance.get_info();
+
+    println!("Name:          {}", info.name);
+    println!("Vendor:        {}", info.vendor);
+    println!("Unique ID:     {} ({:?})", info.unique_id, unique_id_chars(info.unique_id));
+    println!("Version:       {}", info.version);
+    println!("Category:      {:?}", info.category);
+    println!("Inputs:        {}", info.inputs);
+    println!("Outputs:       {}", info.outputs);
+    println!("MIDI inputs:   {}", info.midi_inputs);
+    println!("MIDI outputs:  {}", info.midi_outputs);
+    println!("Synth:         {}", info.is_synth);
+    println!("Latency:       {} samples", info.initial_delay);
+    println!("Chunks:        {}", info.preset_chunks);
+    println!("Double:        {}", info.f64_precision);
+
+    println!("\nParameters ({}):", info.parameters);
+    for index in 0..info.parameters {
+        println!("  {:>3} {:<24} {:>8.4} {} {}",
+                 index,
+                 instance.get_parameter_name(index),
+                 instance.get_parameter(index),
+                 instance.get_parameter_text(index),
+                 instance.get_parameter_label(index));
+    }
+
+    let current = instance.get_preset_num();
+    println!("\nPrograms ({}):", info.presets);
+    for index in 0..info.presets {
+        println!("  {} {:>3} {}",
+                 if index == current { "*" } else { " " },
+                 index,
+                 instance.get_preset_name(index));
+    }
+}
+
+/// The unique ID as the four characters it is usually registered as.
+fn unique_id_chars(id: i32) -> String {
+    (0..4).rev()
+          .map(|shift| ((id >> (shift * 8)) & 0xFF) as u8)
+          .map(|byte| if byte.is_ascii_graphic() { byte as char } else { '.' })
+          .collect()
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let host = Arc::new(Mutex::new(TestHost::new(options.sample_rate, options.block_size)));
+    let mut loader = try!(PluginLoader::load(Path::new(&options.path), host).map_err(|e| {
+        format!("Could not load plugin: {}", e.description())
+    }));
+    let mut instance = try!(loader.instance().map_err(|e| {
+        format!("Could not create instance: {}", e.description())
+    }));
+    instance.init();
+
+    print_info(&instance);
+
+    if let Some(signal) = options.signal {
+        let benchmark = Benchmark {
+            sample_rate: options.sample_rate,
+            block_size: options.block_size,
+            blocks: options.blocks,
+            signal: signal,
+            ..Default::default()
+        };
+
+        println!("\nProcessing {:?}...", signal);
+        let result = benchmark.run(&mut instance);
+        println!("{}", result);
+    }
+
+    match instance.crashed() {
+        Some(crash) => Err(crash.to_string()),
+        None => Ok(()),
+    }
+}
+
+fn main() {
+    let options = match parse_options(env::args()) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    if let Err(error) = run(options) {
+        eprintln!("{}", error);
+        process::exit(1);
+    }
+}
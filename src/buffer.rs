@@ -1,15 +1,86 @@
 //! Buffers to safely work with audio samples.
 
-use std::iter::{Zip, IntoIterator};
+use std::error::Error;
+use std::fmt;
+use std::iter::IntoIterator;
+use std::marker::PhantomData;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
 use std::vec::IntoIter;
-use std::slice;
+use std::{cmp, mem, slice};
 
 use num::Float;
 
+use api;
+use interfaces::Conversion;
+
 /// A buffer containing `ChannelBuffer` buffers for each input/output.
+///
+/// The buffer is generic over the sample type, so the same DSP code can serve both
+/// `Plugin::process` and `Plugin::process_f64`.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::buffer::AudioBuffer;
+/// # use vst2::plugin::{Info, Plugin};
+/// extern crate num;
+///
+/// use num::Float;
+///
+/// struct Gain {
+///     gain: f32,
+/// }
+///
+/// impl Gain {
+///     fn process_generic<T: Float>(&self, buffer: AudioBuffer<T>) {
+///         let gain = T::from(self.gain).unwrap();
+///         for (input, output) in buffer.zip() {
+///             for (i, o) in input.iter().zip(output.iter_mut()) {
+///                 *o = *i * gain;
+///             }
+///         }
+///     }
+/// }
+///
+/// impl Plugin for Gain {
+///     fn get_info(&self) -> Info { Default::default() }
+///
+///     fn process(&mut self, buffer: AudioBuffer<f32>) { self.process_generic(buffer) }
+///     fn process_f64(&mut self, buffer: AudioBuffer<f64>) { self.process_generic(buffer) }
+/// }
+/// # fn main() {}
+/// ```
 pub struct AudioBuffer<'a, T: 'a + Float> {
-    inputs: Vec<&'a mut [T]>,
-    outputs: Vec<&'a mut [T]>,
+    inputs: Table<RawChannel<T>>,
+    outputs: Table<RawChannel<T>>,
+
+    /// Owner of the channel tables if they are not borrowed from a `BufferStorage`, shared with
+    /// the buffers created by `split_at`.
+    tables: Option<Arc<OwnedTables<T>>>,
+
+    /// Pairs of input and output indices which the host passed the same memory for. The inputs
+    /// point to scratch memory instead, which is only filled when they are accessed.
+    aliased: Table<(usize, usize)>,
+
+    /// Whether the inputs in `aliased` were filled for the samples covered by this buffer.
+    loaded: bool,
+
+    /// Range of samples covered by this buffer, clamped to the length of each channel. Buffers
+    /// from raw pointers end at the host's sample count, which is the only sample count of a
+    /// buffer without channels.
+    start: usize,
+    end: usize,
+
+    /// Index of the first sidechain input, or the number of inputs if there are none.
+    sidechain: usize,
+
+    /// Channels returned by `inputs` and `outputs`.
+    input_vec: Vec<&'a mut [T]>,
+    output_vec: Vec<&'a mut [T]>,
+
+    /// Memory reserved on resume for the `f32` copies made by the default `Plugin::process_f64`.
+    conversion: Option<&'a mut Conversion>,
 }
 
 /// Iterator over channel buffers for either inputs or outputs.
@@ -22,72 +93,1168 @@ impl<'a, T: 'a + Float> AudioBuffer<'a, T> {
     /// if inputs was a vector of size 2 containing slices of size 512, it would hold 2 inputs where
     /// each input holds 512 samples.
     pub fn new(inputs: Vec<&'a mut [T]>, outputs: Vec<&'a mut [T]>) -> AudioBuffer<'a, T> {
+        AudioBuffer::from_tables(OwnedTables {
+            inputs: inputs.into_iter().map(RawChannel::new).collect(),
+            outputs: outputs.into_iter().map(RawChannel::new).collect(),
+        })
+    }
+
+    /// Create an `AudioBuffer` over the whole of each channel in `tables`.
+    fn from_tables(tables: OwnedTables<T>) -> AudioBuffer<'a, T> {
+        let empty = tables.inputs.is_empty() && tables.outputs.is_empty();
+        let tables = Arc::new(tables);
         AudioBuffer {
-            inputs: inputs,
-            outputs: outputs,
+            inputs: Table::new(&tables.inputs),
+            outputs: Table::new(&tables.outputs),
+            sidechain: tables.inputs.len(),
+            tables: Some(tables),
+            aliased: Table::new(&[]),
+            loaded: true,
+            start: 0,
+            end: if empty { 0 } else { usize::max_value() },
+            input_vec: Vec::new(),
+            output_vec: Vec::new(),
+            conversion: None,
+        }
+    }
+
+    /// Set the number of samples covered by the buffer, limited to the length of its channels.
+    ///
+    /// Buffers created from slices take their sample count from the channels, so this is needed
+    /// to process a plugin without inputs and outputs, e.g. a MIDI effect, for a number of
+    /// samples.
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// let buffer = AudioBuffer::<f32>::new(Vec::new(), Vec::new()).with_samples(512);
+    /// assert_eq!(buffer.samples(), 512);
+    /// ```
+    pub fn with_samples(mut self, samples: usize) -> AudioBuffer<'a, T> {
+        self.end = self.start.saturating_add(samples);
+        self
+    }
+
+    /// Mark the inputs from `first` on as sidechain inputs, separating them from the main inputs
+    /// in `main_inputs`, `sidechain` and `split_sidechain`.
+    ///
+    /// Buffers passed to `Plugin::process` are marked according to the sidechain buses of
+    /// `Plugin::get_bus_layout`. Hosts can use this to pass a key signal to hosted plugins.
+    pub fn with_sidechain(mut self, first: usize) -> AudioBuffer<'a, T> {
+        self.sidechain = cmp::min(first, self.inputs.len);
+        self
+    }
+
+    /// Create an `AudioBuffer` from owned channels, e.g. `Vec<Vec<f32>>`, checking that all
+    /// channels hold the same number of samples.
+    ///
+    /// This is the safe way for hosts to build a buffer for `PluginInstance::process`; the raw
+    /// pointer arrays passed to the plugin are created from it internally. Inputs are borrowed
+    /// mutably as the plugin receives them as mutable slices.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// let mut inputs = vec![vec![0.0f32; 512]; 2];
+    /// let mut outputs = vec![vec![0.0f32; 512]; 2];
+    ///
+    /// let buffer = AudioBuffer::from_channels(&mut inputs, &mut outputs).unwrap();
+    /// assert_eq!(buffer.samples(), 512);
+    /// ```
+    pub fn from_channels<I, O>(inputs: &'a mut [I], outputs: &'a mut [O])
+                               -> Result<AudioBuffer<'a, T>, ChannelLengthError>
+        where I: AsMut<[T]>,
+              O: AsMut<[T]>
+    {
+        let inputs: Vec<&'a mut [T]> = inputs.iter_mut().map(|c| c.as_mut()).collect();
+        let outputs: Vec<&'a mut [T]> = outputs.iter_mut().map(|c| c.as_mut()).collect();
+
+        if let Some(expected) = inputs.iter().chain(outputs.iter()).map(|c| c.len()).next() {
+            if let Some(channel) = inputs.iter().chain(outputs.iter()).find(|c| c.len() != expected) {
+                return Err(ChannelLengthError { expected: expected, actual: channel.len() });
+            }
         }
+
+        Ok(AudioBuffer::new(inputs, outputs))
     }
 
     /// Create an `AudioBuffer` from raw pointers. Only really useful for interacting with the VST
     /// API.
+    ///
+    /// The inputs must not share memory with the outputs; use `from_raw_in_place` if they might.
+    /// Null channel tables are read as having no channels.
     pub unsafe fn from_raw(inputs_raw: *mut *mut T, outputs_raw: *mut *mut T, num_inputs: usize, num_outputs: usize, samples: usize) -> AudioBuffer<'a, T> {
-        let inputs =
-            // Create a slice of type &mut [*mut f32]
-            slice::from_raw_parts_mut(inputs_raw, num_inputs).iter()
-            // Convert to &mut [&mut [f32]]
-            .map(|input| slice::from_raw_parts_mut(*input, samples))
-            // Collect into Vec<&mut [f32]>
-            .collect();
-
-        let outputs =
-            // Create a slice of type &mut [*mut f32]
-            slice::from_raw_parts_mut(outputs_raw, num_outputs).iter()
-            // Convert to &mut [&mut [f32]]
-            .map(|output| slice::from_raw_parts_mut(*output, samples))
-            // Collect into Vec<&mut [f32]>
-            .collect();
-
-        // Call constructor with vectors
-        AudioBuffer::new(inputs, outputs)
+        let channels = |raw: *mut *mut T, num: usize| -> Vec<RawChannel<T>> {
+            channel_table(raw, num).iter()
+                .map(|&ptr| RawChannel { ptr: ptr, len: samples })
+                .collect()
+        };
+
+        AudioBuffer::from_tables(OwnedTables {
+            inputs: channels(inputs_raw, num_inputs),
+            outputs: channels(outputs_raw, num_outputs),
+        }).with_samples(samples)
+    }
+
+    /// Create an `AudioBuffer` from raw pointers where inputs may share memory with outputs, as
+    /// passed by hosts processing in place.
+    ///
+    /// Inputs sharing memory with an output are backed by scratch memory in `storage` instead,
+    /// and only copied there when the inputs are accessed, so the buffer never hands out
+    /// overlapping slices. Processing with `in_place` avoids the copy altogether.
+    ///
+    /// This does not allocate once `storage` is large enough, see `BufferStorage::reserve`. Null
+    /// channel tables are read as having no channels.
+    pub unsafe fn from_raw_in_place(inputs_raw: *mut *mut T,
+                                    outputs_raw: *mut *mut T,
+                                    num_inputs: usize,
+                                    num_outputs: usize,
+                                    samples: usize,
+                                    storage: &'a mut BufferStorage<T>)
+                                    -> AudioBuffer<'a, T> {
+        let input_ptrs = channel_table(inputs_raw, num_inputs);
+        let output_ptrs = channel_table(outputs_raw, num_outputs);
+
+        storage.inputs.clear();
+        storage.outputs.clear();
+        storage.aliased.clear();
+
+        for &output in output_ptrs {
+            storage.outputs.push(RawChannel { ptr: output, len: samples });
+        }
+
+        for (i, &input) in input_ptrs.iter().enumerate() {
+            match output_ptrs.iter().position(|&output| output == input) {
+                Some(o) => {
+                    let index = storage.aliased.len();
+                    if storage.scratch.len() == index {
+                        storage.scratch.push(Vec::new());
+                    }
+                    storage.scratch[index].resize(samples, T::zero());
+                    storage.inputs.push(RawChannel::new(&mut storage.scratch[index][..]));
+                    storage.aliased.push((i, o));
+                }
+                None => storage.inputs.push(RawChannel { ptr: input, len: samples }),
+            }
+        }
+
+        AudioBuffer {
+            inputs: Table::new(&storage.inputs),
+            outputs: Table::new(&storage.outputs),
+            tables: None,
+            aliased: Table::new(&storage.aliased),
+            loaded: storage.aliased.is_empty(),
+            start: 0,
+            end: samples,
+            sidechain: num_inputs,
+            input_vec: Vec::new(),
+            output_vec: Vec::new(),
+            conversion: None,
+        }
+    }
+
+    /// Attach memory for the `f32` copies made by the default `Plugin::process_f64`, so that it
+    /// does not allocate.
+    pub(crate) fn with_conversion(mut self, conversion: &'a mut Conversion) -> AudioBuffer<'a, T> {
+        self.conversion = Some(conversion);
+        self
+    }
+
+    /// Take the memory attached with `with_conversion`, if any.
+    pub(crate) fn take_conversion(&mut self) -> Option<&'a mut Conversion> {
+        self.conversion.take()
+    }
+
+    /// Whether the host passed the same memory for some inputs and outputs.
+    ///
+    /// If so, those outputs initially hold the input signal, and `in_place` is free.
+    pub fn is_in_place(&self) -> bool {
+        self.aliased.len > 0
+    }
+
+    /// Input channel `index`, limited to the samples covered by this buffer.
+    ///
+    /// The caller must not hand out overlapping slices of the channel.
+    unsafe fn input(&self, index: usize) -> &'a mut [T] {
+        self.inputs.get(index).window_mut(self.start, self.end)
+    }
+
+    /// Output channel `index`, limited to the samples covered by this buffer.
+    ///
+    /// The caller must not hand out overlapping slices of the channel.
+    unsafe fn output(&self, index: usize) -> &'a mut [T] {
+        self.outputs.get(index).window_mut(self.start, self.end)
+    }
+
+    /// Copy the input signal of inputs sharing memory with an output into their scratch memory,
+    /// before the outputs are written to.
+    fn load_aliased_inputs(&mut self) {
+        if !self.loaded {
+            for &(input, output) in unsafe { self.aliased.as_slice() } {
+                let (input, output) = unsafe { (self.input(input), self.output(output)) };
+                let len = cmp::min(input.len(), output.len());
+                input[..len].copy_from_slice(&output[..len]);
+            }
+            self.loaded = true;
+        }
+    }
+
+    /// Consume this buffer and return the outputs, each holding the input signal of the same
+    /// index, for processing in place.
+    ///
+    /// Inputs are copied to the output of the same index unless the host already passed the same
+    /// memory for both, in which case this is free. Outputs without a matching input are left
+    /// unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// # let mut in1 = vec![1.0; 512];
+    /// # let mut out1 = vec![0.0; 512];
+    /// # let buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+    /// for channel in buffer.in_place() {
+    ///     for sample in channel.iter_mut() {
+    ///         *sample *= 0.5;
+    ///     }
+    /// }
+    /// ```
+    pub fn in_place(mut self) -> Outputs<'a, T> {
+        let aliased = unsafe { self.aliased.as_slice() };
+        let same_index_loaded = self.loaded;
+
+        // Inputs sharing memory with the output of the same index already hold the signal there,
+        // but inputs sharing memory with another output could be overwritten by the copies below
+        if !self.loaded {
+            for &(input, output) in aliased.iter().filter(|&&(input, output)| input != output) {
+                let (input, output) = unsafe { (self.input(input), self.output(output)) };
+                let len = cmp::min(input.len(), output.len());
+                input[..len].copy_from_slice(&output[..len]);
+            }
+            self.loaded = true;
+        }
+
+        for index in 0..cmp::min(self.inputs.len, self.outputs.len) {
+            if same_index_loaded || !aliased.contains(&(index, index)) {
+                let (input, output) = unsafe { (self.input(index), self.output(index)) };
+                let len = cmp::min(input.len(), output.len());
+                output[..len].copy_from_slice(&input[..len]);
+            }
+        }
+
+        Outputs {
+            channels: self.output_window(),
+            tables: self.tables.take(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Number of input channels.
+    pub fn input_count(&self) -> usize {
+        self.inputs.len
+    }
+
+    /// Number of output channels.
+    pub fn output_count(&self) -> usize {
+        self.outputs.len
+    }
+
+    /// Number of main input channels, i.e. the inputs which are not sidechain inputs.
+    pub fn main_input_count(&self) -> usize {
+        self.sidechain
+    }
+
+    /// Number of sidechain input channels.
+    pub fn sidechain_count(&self) -> usize {
+        self.inputs.len - self.sidechain
+    }
+
+    /// Number of samples per channel, i.e. the shortest channel length.
+    ///
+    /// Buffers without channels report the sample count passed by the host or to `with_samples`.
+    pub fn samples(&self) -> usize {
+        let (inputs, outputs) = unsafe { (self.inputs.as_slice(), self.outputs.as_slice()) };
+        inputs.iter().chain(outputs.iter())
+            .map(|c| c.window_len(self.start, self.end))
+            .min()
+            .unwrap_or(self.end - self.start)
     }
 
     /// Return a reference to all inputs.
+    ///
+    /// This collects the channels into a vector, use `split_mut` to avoid allocating.
     pub fn inputs(&'a mut self) -> &'a mut Vec<&'a mut [T]> {
-        &mut self.inputs
+        self.load_aliased_inputs();
+        self.input_vec = (0..self.inputs.len).map(|i| unsafe { self.input(i) }).collect();
+        &mut self.input_vec
     }
 
     /// Return a reference to all outputs.
+    ///
+    /// This collects the channels into a vector, use `split_mut` to avoid allocating.
     pub fn outputs(&'a mut self) -> &'a mut Vec<&'a mut [T]> {
-        &mut self.outputs
+        self.load_aliased_inputs();
+        self.output_vec = (0..self.outputs.len).map(|i| unsafe { self.output(i) }).collect();
+        &mut self.output_vec
+    }
+
+    /// Consume this buffer and return its input and output channels.
+    ///
+    /// This collects the channels into vectors, use `split_mut` or `zip` to avoid allocating.
+    pub fn split(mut self) -> (Vec<&'a mut [T]>, Vec<&'a mut [T]>) {
+        self.load_aliased_inputs();
+        ((0..self.inputs.len).map(|i| unsafe { self.input(i) }).collect(),
+         (0..self.outputs.len).map(|i| unsafe { self.output(i) }).collect())
+    }
+
+    /// The input channels from `from` up to `to`, limited to the samples covered by this buffer.
+    fn input_window(&self, from: usize, to: usize) -> Window<T> {
+        Window { channels: self.inputs.range(from, to), start: self.start, end: self.end }
+    }
+
+    /// The output channels, limited to the samples covered by this buffer.
+    fn output_window(&self) -> Window<T> {
+        Window { channels: self.outputs, start: self.start, end: self.end }
+    }
+
+    /// Split this buffer into read-only inputs and writable outputs, which can be used at the
+    /// same time.
+    ///
+    /// Unlike `split`, this only borrows the buffer and does not allocate.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// # let mut in1 = vec![0.0; 512];
+    /// # let (mut in2, mut out1, mut out2) = (in1.clone(), in1.clone(), in1.clone());
+    /// #
+    /// # let mut buffer = AudioBuffer::new(vec![&mut in1, &mut in2],
+    /// #                                   vec![&mut out1, &mut out2]);
+    /// let (inputs, mut outputs) = buffer.split_mut();
+    ///
+    /// // Mix both inputs into the first output
+    /// for (i, sample) in outputs[0].iter_mut().enumerate() {
+    ///     *sample = inputs[0][i] + inputs[1][i];
+    /// }
+    /// ```
+    pub fn split_mut<'b>(&'b mut self) -> (Inputs<'b, T>, Outputs<'b, T>) {
+        self.load_aliased_inputs();
+        (Inputs { channels: self.input_window(0, self.inputs.len), marker: PhantomData },
+         Outputs { channels: self.output_window(), tables: None, marker: PhantomData })
+    }
+
+    /// Split this buffer into read-only main inputs, read-only sidechain inputs and writable
+    /// outputs. See `with_sidechain`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// # let mut in1 = vec![0.5f32; 512];
+    /// # let (mut key, mut out1) = (vec![1.0; 512], vec![0.0; 512]);
+    /// # let mut buffer = AudioBuffer::new(vec![&mut in1, &mut key], vec![&mut out1])
+    /// #                              .with_sidechain(1);
+    /// let (inputs, sidechain, mut outputs) = buffer.split_sidechain();
+    ///
+    /// // Duck the input while the key signal is loud
+    /// for (i, sample) in outputs[0].iter_mut().enumerate() {
+    ///     let gain = if sidechain[0][i].abs() > 0.5 { 0.25 } else { 1.0 };
+    ///     *sample = inputs[0][i] * gain;
+    /// }
+    /// # assert_eq!(outputs[0][0], 0.125);
+    /// ```
+    pub fn split_sidechain<'b>(&'b mut self) -> (Inputs<'b, T>, Inputs<'b, T>, Outputs<'b, T>) {
+        self.load_aliased_inputs();
+        let sidechain = self.input_window(self.sidechain, self.inputs.len);
+        (Inputs { channels: self.input_window(0, self.sidechain), marker: PhantomData },
+         Inputs { channels: sidechain, marker: PhantomData },
+         Outputs { channels: self.output_window(), tables: None, marker: PhantomData })
+    }
+
+    /// The main inputs, without the sidechain inputs.
+    pub fn main_inputs<'b>(&'b mut self) -> Inputs<'b, T> {
+        self.load_aliased_inputs();
+        Inputs { channels: self.input_window(0, self.sidechain), marker: PhantomData }
+    }
+
+    /// The sidechain inputs, empty if there are none.
+    pub fn sidechain<'b>(&'b mut self) -> Inputs<'b, T> {
+        self.load_aliased_inputs();
+        Inputs { channels: self.input_window(self.sidechain, self.inputs.len), marker: PhantomData }
+    }
+
+    /// Set all output samples to zero.
+    pub fn clear_outputs(&mut self) {
+        self.load_aliased_inputs();
+        for index in 0..self.outputs.len {
+            for sample in unsafe { self.output(index) }.iter_mut() {
+                *sample = T::zero();
+            }
+        }
+    }
+
+    /// Copy each input to the output of the same index, e.g. when bypassed. Outputs without a
+    /// matching input are cleared.
+    pub fn copy_inputs_to_outputs(&mut self) {
+        self.load_aliased_inputs();
+        for index in 0..self.outputs.len {
+            let output = unsafe { self.output(index) };
+            let len = if index < self.inputs.len {
+                let input = unsafe { self.input(index) };
+                let len = cmp::min(input.len(), output.len());
+                output[..len].copy_from_slice(&input[..len]);
+                len
+            } else {
+                0
+            };
+            for sample in output[len..].iter_mut() {
+                *sample = T::zero();
+            }
+        }
+    }
+
+    /// Whether all input samples are zero, e.g. to stop processing once a tail has decayed.
+    ///
+    /// This returns as soon as a non-zero sample is found.
+    pub fn inputs_are_silent(&mut self) -> bool {
+        self.load_aliased_inputs();
+        self.input_window(0, self.inputs.len).iter()
+            .all(|input| input.iter().all(|sample| sample.is_zero()))
+    }
+
+    /// Whether all channels start at an address which is a multiple of `align` bytes.
+    ///
+    /// Hosts usually allocate channels aligned to at least `SIMD_ALIGNMENT`, so this can be
+    /// checked once per block to choose between an aligned SIMD path and a fallback. Use
+    /// `split_aligned` to process unaligned channels with aligned loads anyway. An `align` of 0
+    /// is treated like 1, every channel is aligned to it.
+    pub fn is_aligned(&self, align: usize) -> bool {
+        if align == 0 {
+            return true;
+        }
+
+        self.input_window(0, self.inputs.len).iter().chain(self.output_window().iter())
+            .all(|c| c.as_ptr() as usize % align == 0)
+    }
+
+    /// Copy the inputs into `interleaved` as consecutive frames of one sample per input.
+    ///
+    /// `interleaved` must hold exactly `input_count() * samples()` samples.
+    pub fn copy_inputs_to_interleaved(&mut self, interleaved: &mut [T])
+                                      -> Result<(), InterleaveError> {
+        self.load_aliased_inputs();
+        let samples = self.samples();
+        interleave_channels(self.input_window(0, self.inputs.len).iter(), samples, interleaved)
+    }
+
+    /// Copy the outputs into `interleaved` as consecutive frames of one sample per output.
+    ///
+    /// `interleaved` must hold exactly `output_count() * samples()` samples.
+    pub fn copy_outputs_to_interleaved(&self, interleaved: &mut [T]) -> Result<(), InterleaveError> {
+        interleave_channels(self.output_window().iter(), self.samples(), interleaved)
+    }
+
+    /// Fill the inputs from consecutive frames of one sample per input in `interleaved`.
+    ///
+    /// `interleaved` must hold exactly `input_count() * samples()` samples.
+    pub fn copy_inputs_from_interleaved(&mut self, interleaved: &[T]) -> Result<(), InterleaveError> {
+        // The inputs are overwritten, so inputs sharing memory with an output need no loading
+        self.loaded = true;
+        let samples = self.samples();
+        deinterleave_channels(interleaved, samples, self.input_window(0, self.inputs.len).iter_mut())
+    }
+
+    /// Fill the outputs from consecutive frames of one sample per output in `interleaved`.
+    ///
+    /// `interleaved` must hold exactly `output_count() * samples()` samples.
+    pub fn copy_outputs_from_interleaved(&mut self, interleaved: &[T]) -> Result<(), InterleaveError> {
+        self.load_aliased_inputs();
+        let samples = self.samples();
+        deinterleave_channels(interleaved, samples, self.output_window().iter_mut())
     }
 
-    /// Consume this buffer and split it into separate inputs and outputs.
+    /// Consume this buffer and split it into two buffers over the same channels, covering the
+    /// samples before `frame` and the samples from `frame` on.
+    ///
+    /// This allows processing a block in segments, e.g. to apply events or parameter changes at
+    /// the exact sample. Channels shorter than `frame` end up entirely in the first buffer. Both
+    /// buffers share the channel tables of this buffer, so this does not allocate.
     ///
     /// # Example
     ///
     /// ```
     /// # use vst2::buffer::AudioBuffer;
     /// # let mut in1 = vec![0.0; 512];
+    /// # let mut out1 = vec![0.0; 512];
+    /// # let buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+    /// let (before, after) = buffer.split_at(100);
+    /// assert_eq!((before.samples(), after.samples()), (100, 412));
+    /// ```
+    pub fn split_at(mut self, frame: usize) -> (AudioBuffer<'a, T>, AudioBuffer<'a, T>) {
+        let middle = cmp::min(self.start.saturating_add(frame), self.end);
+        let after = AudioBuffer {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            tables: self.tables.clone(),
+            aliased: self.aliased,
+            loaded: self.loaded,
+            start: middle,
+            end: self.end,
+            sidechain: self.sidechain,
+            input_vec: Vec::new(),
+            output_vec: Vec::new(),
+            conversion: None,
+        };
+        self.end = middle;
+        (self, after)
+    }
+
+    /// Consume this buffer and pair up each input with the output of the same index.
+    ///
+    /// Inputs are yielded as shared slices, so samples can be read from the input while writing
+    /// to the output without any index juggling. Surplus inputs or outputs are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use vst2::buffer::AudioBuffer;
+    /// # let mut in1 = vec![1.0; 512];
     /// # let (mut in2, mut out1, mut out2) = (in1.clone(), in1.clone(), in1.clone());
     /// #
     /// # let buffer = AudioBuffer::new(vec![&mut in1, &mut in2],
     /// #                               vec![&mut out1, &mut out2]);
-    /// let (mut inputs, mut outputs) = buffer.split();
-    /// let input: &mut [f32] = &mut inputs[0]; // First input
+    /// for (input, output) in buffer.zip() {
+    ///     for (i, o) in input.iter().zip(output.iter_mut()) {
+    ///         *o = *i * 0.5;
+    ///     }
+    /// }
     /// ```
-    pub fn split(self) -> (Vec<&'a mut [T]>, Vec<&'a mut [T]>) {
-        (self.inputs, self.outputs)
+    pub fn zip(mut self) -> ZipIter<'a, T> {
+        self.load_aliased_inputs();
+        ZipIter {
+            inputs: self.input_window(0, self.inputs.len).iter(),
+            outputs: self.output_window().iter_mut(),
+            _tables: self.tables.take(),
+        }
+    }
+}
+
+/// Iterator over pairs of input and output channels, created by `AudioBuffer::zip`.
+pub struct ZipIter<'a, T: 'a> {
+    inputs: Channels<'a, T>,
+    outputs: ChannelsMut<'a, T>,
+    _tables: Option<Arc<OwnedTables<T>>>,
+}
+
+impl<'a, T: 'a> Iterator for ZipIter<'a, T> {
+    type Item = (&'a [T], &'a mut [T]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match (self.inputs.next(), self.outputs.next()) {
+            (Some(input), Some(output)) => Some((input, output)),
+            _ => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = cmp::min(self.inputs.len(), self.outputs.len());
+        (len, Some(len))
+    }
+}
+
+impl<'a, T: 'a> ExactSizeIterator for ZipIter<'a, T> {}
+
+/// Memory reused by `AudioBuffer::from_raw_in_place` for the channel tables and the scratch
+/// memory of inputs sharing memory with an output.
+///
+/// Plugins created with `plugin_main!` keep one of these per sample type, reserved for the
+/// channel count and block size, so their buffers are created without allocating.
+pub struct BufferStorage<T> {
+    inputs: Vec<RawChannel<T>>,
+    outputs: Vec<RawChannel<T>>,
+    aliased: Vec<(usize, usize)>,
+    scratch: Vec<Vec<T>>,
+}
+
+impl<T: Float> BufferStorage<T> {
+    /// Create empty storage, which allocates as needed when buffers are created.
+    pub fn new() -> BufferStorage<T> {
+        BufferStorage {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            aliased: Vec::new(),
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Reserve memory for buffers of up to `inputs` input and `outputs` output channels holding
+    /// up to `samples` samples each.
+    pub fn reserve(&mut self, inputs: usize, outputs: usize, samples: usize) {
+        self.inputs.clear();
+        self.inputs.reserve(inputs);
+        self.outputs.clear();
+        self.outputs.reserve(outputs);
+        self.aliased.clear();
+        self.aliased.reserve(inputs);
+
+        if self.scratch.len() < inputs {
+            self.scratch.resize(inputs, Vec::new());
+        }
+        for channel in &mut self.scratch {
+            channel.clear();
+            channel.reserve(samples);
+        }
+    }
+}
+
+impl<T: Float> Default for BufferStorage<T> {
+    fn default() -> BufferStorage<T> {
+        BufferStorage::new()
+    }
+}
+
+/// Pointer and length of a channel.
+struct RawChannel<T> {
+    ptr: *mut T,
+    len: usize,
+}
+
+// A `RawChannel` stands in for a `&mut [T]`
+unsafe impl<T: Send> Send for RawChannel<T> {}
+unsafe impl<T: Sync> Sync for RawChannel<T> {}
+
+impl<T> Clone for RawChannel<T> {
+    fn clone(&self) -> RawChannel<T> {
+        *self
+    }
+}
+
+impl<T> Copy for RawChannel<T> {}
+
+impl<T> RawChannel<T> {
+    fn new(channel: &mut [T]) -> RawChannel<T> {
+        RawChannel { ptr: channel.as_mut_ptr(), len: channel.len() }
+    }
+
+    /// Number of samples from `start` up to `end`, clamped to the length of the channel.
+    fn window_len(&self, start: usize, end: usize) -> usize {
+        cmp::min(end, self.len).saturating_sub(start)
+    }
+
+    /// The samples from `start` up to `end`, clamped to the length of the channel.
+    unsafe fn window<'a>(&self, start: usize, end: usize) -> &'a [T] {
+        let end = cmp::min(end, self.len);
+        let start = cmp::min(start, end);
+        slice::from_raw_parts(self.ptr.offset(start as isize), end - start)
+    }
+
+    /// The samples from `start` up to `end` for writing, clamped to the length of the channel.
+    unsafe fn window_mut<'a>(&self, start: usize, end: usize) -> &'a mut [T] {
+        let end = cmp::min(end, self.len);
+        let start = cmp::min(start, end);
+        slice::from_raw_parts_mut(self.ptr.offset(start as isize), end - start)
+    }
+}
+
+/// The channel pointers of a table passed by the host, which may be null if empty.
+unsafe fn channel_table<'a, T>(raw: *mut *mut T, num: usize) -> &'a [*mut T] {
+    if raw.is_null() || num == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(raw as *const *mut T, num)
+    }
+}
+
+/// Channel tables owned by buffers created from slices.
+struct OwnedTables<T> {
+    inputs: Vec<RawChannel<T>>,
+    outputs: Vec<RawChannel<T>>,
+}
+
+/// A table of channels or aliased indices, owned by `OwnedTables` or a `BufferStorage`.
+struct Table<C> {
+    ptr: *const C,
+    len: usize,
+}
+
+// A `Table` stands in for a `&[C]`
+unsafe impl<C: Sync> Send for Table<C> {}
+unsafe impl<C: Sync> Sync for Table<C> {}
+
+impl<C> Clone for Table<C> {
+    fn clone(&self) -> Table<C> {
+        *self
+    }
+}
+
+impl<C> Copy for Table<C> {}
+
+impl<C: Copy> Table<C> {
+    fn new(items: &[C]) -> Table<C> {
+        Table { ptr: items.as_ptr(), len: items.len() }
+    }
+
+    /// The items of the table, which must still be alive.
+    unsafe fn as_slice<'t>(&self) -> &'t [C] {
+        slice::from_raw_parts(self.ptr, self.len)
+    }
+
+    unsafe fn get(&self, index: usize) -> C {
+        self.as_slice()[index]
+    }
+
+    /// The items from `from` up to `to`.
+    fn range(&self, from: usize, to: usize) -> Table<C> {
+        assert!(from <= to && to <= self.len);
+        Table { ptr: unsafe { self.ptr.offset(from as isize) }, len: to - from }
+    }
+}
+
+/// Channels limited to a range of samples.
+struct Window<T> {
+    channels: Table<RawChannel<T>>,
+    start: usize,
+    end: usize,
+}
+
+impl<T> Clone for Window<T> {
+    fn clone(&self) -> Window<T> {
+        *self
+    }
+}
+
+impl<T> Copy for Window<T> {}
+
+impl<T> Window<T> {
+    /// Channel `index` for reading. The channel must not be borrowed for writing meanwhile.
+    unsafe fn channel<'a>(&self, index: usize) -> &'a [T] {
+        self.channels.get(index).window(self.start, self.end)
+    }
+
+    /// Channel `index` for writing. The channel must not be borrowed at all meanwhile.
+    unsafe fn channel_mut<'a>(&self, index: usize) -> &'a mut [T] {
+        self.channels.get(index).window_mut(self.start, self.end)
+    }
+
+    fn iter<'a>(&self) -> Channels<'a, T> {
+        Channels { window: *self, index: 0, marker: PhantomData }
+    }
+
+    fn iter_mut<'a>(&self) -> ChannelsMut<'a, T> {
+        ChannelsMut { window: *self, index: 0, _tables: None, marker: PhantomData }
+    }
+}
+
+/// Alignment in bytes required by SIMD loads of up to 256 bits, e.g. AVX.
+pub const SIMD_ALIGNMENT: usize = 32;
+
+/// Split a channel into an unaligned head, an aligned body and a tail.
+///
+/// The body starts at an address which is a multiple of `align` bytes and its length is a
+/// multiple of the number of samples fitting in `align` bytes, so it can be processed entirely
+/// with aligned SIMD loads. The head and tail hold the remaining samples. If the channel can
+/// never be aligned, all samples are in the head.
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of two multiple of the sample size.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::buffer::{self, SIMD_ALIGNMENT};
+/// let channel = vec![0.5f32; 100];
+/// let (head, body, tail) = buffer::split_aligned(&channel, SIMD_ALIGNMENT);
+///
+/// assert_eq!(body.as_ptr() as usize % SIMD_ALIGNMENT, 0);
+/// assert_eq!(body.len() % 8, 0);
+/// assert_eq!(head.len() + body.len() + tail.len(), 100);
+/// ```
+pub fn split_aligned<T>(channel: &[T], align: usize) -> (&[T], &[T], &[T]) {
+    let (head, body) = aligned_bounds(channel.as_ptr() as usize, channel.len(),
+                                      mem::size_of::<T>(), align);
+    let (head, rest) = channel.split_at(head);
+    let (body, tail) = rest.split_at(body);
+    (head, body, tail)
+}
+
+/// Split a channel into an unaligned head, an aligned body and a tail for writing.
+///
+/// See `split_aligned`.
+pub fn split_aligned_mut<T>(channel: &mut [T], align: usize) -> (&mut [T], &mut [T], &mut [T]) {
+    let (head, body) = aligned_bounds(channel.as_ptr() as usize, channel.len(),
+                                      mem::size_of::<T>(), align);
+    let (head, rest) = channel.split_at_mut(head);
+    let (body, tail) = rest.split_at_mut(body);
+    (head, body, tail)
+}
+
+/// Lengths of the head and body of a channel split by `split_aligned`.
+fn aligned_bounds(address: usize, len: usize, size: usize, align: usize) -> (usize, usize) {
+    assert!(align.is_power_of_two() && size > 0 && align % size == 0,
+            "alignment must be a power of two multiple of the sample size");
+
+    let offset = (align - address % align) % align;
+    if offset % size != 0 {
+        return (len, 0);
+    }
+
+    let head = cmp::min(offset / size, len);
+    let lanes = align / size;
+    let body = (len - head) / lanes * lanes;
+    (head, body)
+}
+
+/// Error returned when the channels of a buffer hold different numbers of samples.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLengthError {
+    /// Number of samples in the first channel.
+    pub expected: usize,
+
+    /// Number of samples in the first channel of a different length.
+    pub actual: usize,
+}
+
+impl fmt::Display for ChannelLengthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {} samples, got {}", self.description(), self.expected, self.actual)
+    }
+}
+
+impl Error for ChannelLengthError {
+    fn description(&self) -> &str {
+        "Channels hold different numbers of samples"
+    }
+}
+
+/// Error returned when an interleaved buffer does not match the channels it is copied from or to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InterleaveError {
+    /// Number of samples required, i.e. the number of channels times the number of frames.
+    pub expected: usize,
+
+    /// Number of samples in the interleaved buffer.
+    pub actual: usize,
+}
+
+impl fmt::Display for InterleaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {} samples, got {}", self.description(), self.expected, self.actual)
+    }
+}
+
+impl Error for InterleaveError {
+    fn description(&self) -> &str {
+        "Interleaved buffer length does not match the channels"
+    }
+}
+
+/// Copy the first `frames` samples of each channel into `interleaved`, one frame after another.
+///
+/// `interleaved` must hold exactly `channels.len() * frames` samples.
+pub fn interleave<T: Copy, C: AsRef<[T]>>(channels: &[C], frames: usize, interleaved: &mut [T])
+                                        -> Result<(), InterleaveError> {
+    interleave_channels(channels.iter(), frames, interleaved)
+}
+
+/// Copy `frames` frames from `interleaved` into the first `frames` samples of each channel.
+///
+/// `interleaved` must hold exactly `channels.len() * frames` samples.
+pub fn deinterleave<T: Copy, C: AsMut<[T]>>(interleaved: &[T], frames: usize, channels: &mut [C])
+                                          -> Result<(), InterleaveError> {
+    deinterleave_channels(interleaved, frames, channels.iter_mut())
+}
+
+fn interleave_channels<T, C, I>(channels: I, frames: usize, interleaved: &mut [T])
+                                -> Result<(), InterleaveError>
+    where T: Copy,
+          C: AsRef<[T]>,
+          I: ExactSizeIterator<Item = C>
+{
+    let count = channels.len();
+    try!(check_interleaved(count, frames, interleaved.len()));
+
+    for (index, channel) in channels.enumerate() {
+        for (frame, &sample) in channel.as_ref()[..frames].iter().enumerate() {
+            interleaved[frame * count + index] = sample;
+        }
+    }
+    Ok(())
+}
+
+fn deinterleave_channels<T, C, I>(interleaved: &[T], frames: usize, channels: I)
+                                  -> Result<(), InterleaveError>
+    where T: Copy,
+          C: AsMut<[T]>,
+          I: ExactSizeIterator<Item = C>
+{
+    let count = channels.len();
+    try!(check_interleaved(count, frames, interleaved.len()));
+
+    for (index, mut channel) in channels.enumerate() {
+        for (frame, sample) in channel.as_mut()[..frames].iter_mut().enumerate() {
+            *sample = interleaved[frame * count + index];
+        }
+    }
+    Ok(())
+}
+
+fn check_interleaved(channels: usize, frames: usize, len: usize) -> Result<(), InterleaveError> {
+    if channels * frames == len {
+        Ok(())
+    } else {
+        Err(InterleaveError { expected: channels * frames, actual: len })
+    }
+}
+
+/// Read-only input channels of an `AudioBuffer`, created by `AudioBuffer::split_mut`.
+pub struct Inputs<'b, T: 'b> {
+    channels: Window<T>,
+    marker: PhantomData<&'b [T]>,
+}
+
+impl<'b, T: 'b> Inputs<'b, T> {
+    /// Number of channels.
+    pub fn len(&self) -> usize {
+        self.channels.channels.len
+    }
+
+    /// Whether there are no channels.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The channel at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&'b [T]> {
+        if index < self.len() {
+            Some(unsafe { self.channels.channel(index) })
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the channels.
+    pub fn iter(&self) -> Channels<'b, T> {
+        self.channels.iter()
+    }
+}
+
+impl<'b, T: 'b> Index<usize> for Inputs<'b, T> {
+    type Output = [T];
+
+    fn index(&self, index: usize) -> &[T] {
+        unsafe { self.channels.channel(index) }
+    }
+}
+
+impl<'b, T: 'b> IntoIterator for Inputs<'b, T> {
+    type Item = &'b [T];
+    type IntoIter = Channels<'b, T>;
+
+    fn into_iter(self) -> Channels<'b, T> {
+        self.iter()
+    }
+}
+
+/// Writable output channels of an `AudioBuffer`, created by `AudioBuffer::split_mut` or
+/// `AudioBuffer::in_place`.
+pub struct Outputs<'b, T: 'b> {
+    channels: Window<T>,
+    tables: Option<Arc<OwnedTables<T>>>,
+    marker: PhantomData<&'b mut [T]>,
+}
+
+impl<'b, T: 'b> Outputs<'b, T> {
+    /// Number of channels.
+    pub fn len(&self) -> usize {
+        self.channels.channels.len
+    }
+
+    /// Whether there are no channels.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The channel at `index`, if any.
+    pub fn get(&self, index: usize) -> Option<&[T]> {
+        if index < self.len() {
+            Some(unsafe { self.channels.channel(index) })
+        } else {
+            None
+        }
+    }
+
+    /// The channel at `index` for writing, if any.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut [T]> {
+        if index < self.len() {
+            Some(unsafe { self.channels.channel_mut(index) })
+        } else {
+            None
+        }
+    }
+
+    /// Iterate over the channels.
+    pub fn iter(&self) -> Channels<T> {
+        self.channels.iter()
+    }
+
+    /// Iterate over the channels for writing.
+    pub fn iter_mut(&mut self) -> ChannelsMut<T> {
+        self.channels.iter_mut()
+    }
+}
+
+impl<'b, T: 'b> Index<usize> for Outputs<'b, T> {
+    type Output = [T];
+
+    fn index(&self, index: usize) -> &[T] {
+        unsafe { self.channels.channel(index) }
+    }
+}
+
+impl<'b, T: 'b> IndexMut<usize> for Outputs<'b, T> {
+    fn index_mut(&mut self, index: usize) -> &mut [T] {
+        unsafe { self.channels.channel_mut(index) }
+    }
+}
+
+impl<'b, T: 'b> IntoIterator for Outputs<'b, T> {
+    type Item = &'b mut [T];
+    type IntoIter = ChannelsMut<'b, T>;
+
+    fn into_iter(mut self) -> ChannelsMut<'b, T> {
+        ChannelsMut { _tables: self.tables.take(), ..self.channels.iter_mut() }
+    }
+}
+
+/// Iterator over read-only channels of `Inputs` or `Outputs`.
+pub struct Channels<'b, T: 'b> {
+    window: Window<T>,
+    index: usize,
+    marker: PhantomData<&'b [T]>,
+}
+
+impl<'b, T: 'b> Iterator for Channels<'b, T> {
+    type Item = &'b [T];
+
+    fn next(&mut self) -> Option<&'b [T]> {
+        if self.index < self.window.channels.len {
+            self.index += 1;
+            Some(unsafe { self.window.channel(self.index - 1) })
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.window.channels.len - self.index;
+        (len, Some(len))
+    }
+}
+
+impl<'b, T: 'b> ExactSizeIterator for Channels<'b, T> {}
+
+/// Iterator over writable channels of `Outputs`.
+pub struct ChannelsMut<'b, T: 'b> {
+    window: Window<T>,
+    index: usize,
+    _tables: Option<Arc<OwnedTables<T>>>,
+    marker: PhantomData<&'b mut [T]>,
+}
+
+impl<'b, T: 'b> Iterator for ChannelsMut<'b, T> {
+    type Item = &'b mut [T];
+
+    fn next(&mut self) -> Option<&'b mut [T]> {
+        if self.index < self.window.channels.len {
+            self.index += 1;
+            Some(unsafe { self.window.channel_mut(self.index - 1) })
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.window.channels.len - self.index;
+        (len, Some(len))
+    }
+}
+
+impl<'b, T: 'b> ExactSizeIterator for ChannelsMut<'b, T> {}
+
+/// Buffers for variable I/O processing, where the number of input samples consumed and output
+/// samples produced per call may differ, e.g. for time stretching.
+pub struct VariableIo<'a> {
+    /// Input channels, all holding the same number of samples.
+    pub inputs: Inputs<'a, f32>,
+
+    /// Output channels, all holding the same number of samples.
+    pub outputs: Outputs<'a, f32>,
+
+    input_processed: Option<&'a mut i32>,
+    output_processed: Option<&'a mut i32>,
+}
+
+impl<'a> VariableIo<'a> {
+    /// Create a `VariableIo` from a raw `api::VariableIo`. Only really useful for interacting with
+    /// the VST API.
+    ///
+    /// The channel tables are kept in `storage`, so this does not allocate once it is large
+    /// enough.
+    pub unsafe fn from_raw(raw: *mut api::VariableIo,
+                           num_inputs: usize,
+                           num_outputs: usize,
+                           storage: &'a mut BufferStorage<f32>)
+                           -> VariableIo<'a> {
+        unsafe fn channels(table: &mut Vec<RawChannel<f32>>,
+                           raw: *mut *mut f32,
+                           num: usize,
+                           samples: i32)
+                           -> Window<f32> {
+            table.clear();
+            if !raw.is_null() {
+                for &channel in slice::from_raw_parts(raw as *const *mut f32, num) {
+                    table.push(RawChannel { ptr: channel, len: cmp::max(samples, 0) as usize });
+                }
+            }
+            Window { channels: Table::new(table), start: 0, end: usize::max_value() }
+        }
+
+        let raw = &mut *raw;
+        VariableIo {
+            inputs: Inputs {
+                channels: channels(&mut storage.inputs, raw.inputs, num_inputs,
+                                   raw.num_samples_input),
+                marker: PhantomData,
+            },
+            outputs: Outputs {
+                channels: channels(&mut storage.outputs, raw.outputs, num_outputs,
+                                   raw.num_samples_output),
+                tables: None,
+                marker: PhantomData,
+            },
+            input_processed: raw.num_samples_input_processed.as_mut(),
+            output_processed: raw.num_samples_output_processed.as_mut(),
+        }
     }
 
-    /// Zip together buffers.
-    pub fn zip(self) -> Zip<ChannelBufferIter<'a, T>, ChannelBufferIter<'a, T>> {
-        self.inputs.into_iter().zip(self.outputs.into_iter())
+    /// Report how many input samples were consumed and output samples were produced.
+    pub fn set_processed(&mut self, inputs: usize, outputs: usize) {
+        if let Some(ref mut processed) = self.input_processed {
+            **processed = inputs as i32;
+        }
+        if let Some(ref mut processed) = self.output_processed {
+            **processed = outputs as i32;
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use buffer::AudioBuffer;
+    use num::Float;
+
+    use buffer::{self, AudioBuffer, BufferStorage, ChannelLengthError, InterleaveError};
 
     /// Size of buffers used in tests.
     const SIZE: usize = 1024;
@@ -118,6 +1285,333 @@ mod tests {
         }
     }
 
+    /// Test that zipping pairs channels by index and skips surplus channels.
+    #[test]
+    fn zip_pairs() {
+        let mut in1 = vec![1.0f32; SIZE];
+        let mut in2 = vec![2.0; SIZE];
+        let mut in3 = vec![3.0; SIZE];
+
+        let mut out1 = vec![0.0; SIZE];
+        let mut out2 = out1.clone();
+
+        {
+            let buffer = AudioBuffer::new(vec![&mut in1, &mut in2, &mut in3],
+                                          vec![&mut out1, &mut out2]);
+            let zip = buffer.zip();
+            assert_eq!(zip.len(), 2);
+
+            for (input, output) in zip {
+                for (i, o) in input.iter().zip(output.iter_mut()) {
+                    *o = *i * 2.0;
+                }
+            }
+        }
+
+        assert_eq!(out1, vec![2.0; SIZE]);
+        assert_eq!(out2, vec![4.0; SIZE]);
+    }
+
+    /// Test that inputs sharing memory with outputs keep the input signal while the outputs are
+    /// written.
+    #[test]
+    fn in_place_split() {
+        let mut in1: Vec<f32> = (0..SIZE).map(|x| x as f32).collect();
+        let mut channel2 = in1.clone();
+        let mut out1 = vec![0.0; SIZE];
+
+        let mut storage = BufferStorage::new();
+        let buffer = unsafe {
+            AudioBuffer::from_raw_in_place(vec![in1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           vec![out1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           2, 2, SIZE, &mut storage)
+        };
+        assert!(buffer.is_in_place());
+
+        for (input, output) in buffer.zip() {
+            for (i, o) in input.iter().zip(output.iter_mut()) {
+                *o = *i * 2.0;
+            }
+        }
+
+        let expected: Vec<f32> = (0..SIZE).map(|x| x as f32 * 2.0).collect();
+        assert_eq!(out1, expected);
+        assert_eq!(channel2, expected);
+    }
+
+    /// Test that writing to outputs sharing memory with an input keeps the input signal.
+    #[test]
+    fn in_place_clear() {
+        let mut in1 = vec![1.0f32; SIZE];
+        let mut channel2 = vec![2.0; SIZE];
+        let mut out1 = vec![3.0; SIZE];
+
+        let mut storage = BufferStorage::new();
+        let mut buffer = unsafe {
+            AudioBuffer::from_raw_in_place(vec![in1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           vec![out1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           2, 2, SIZE, &mut storage)
+        };
+        assert!(buffer.is_in_place());
+
+        buffer.clear_outputs();
+        {
+            let (inputs, outputs) = buffer.split_mut();
+            assert!(inputs[0].iter().all(|&s| s == 1.0));
+            assert!(inputs[1].iter().all(|&s| s == 2.0));
+            assert!(outputs.iter().all(|o| o.iter().all(|&s| s == 0.0)));
+        }
+
+        assert!(out1.iter().all(|&s| s == 0.0));
+        assert!(channel2.iter().all(|&s| s == 0.0));
+    }
+
+    /// Test that segments of a buffer processed in place keep the input signal of later segments.
+    #[test]
+    fn in_place_split_at() {
+        let mut channel: Vec<f32> = (0..SIZE).map(|x| x as f32).collect();
+
+        let mut storage = BufferStorage::new();
+        let buffer = unsafe {
+            AudioBuffer::from_raw_in_place(vec![channel.as_mut_ptr()].as_mut_ptr(),
+                                           vec![channel.as_mut_ptr()].as_mut_ptr(),
+                                           1, 1, SIZE, &mut storage)
+        };
+
+        let (before, after) = buffer.split_at(SIZE / 2);
+        for segment in vec![before, after] {
+            for (input, output) in segment.zip() {
+                assert_eq!(input.len(), SIZE / 2);
+                for (i, o) in input.iter().zip(output.iter_mut()) {
+                    *o = *i * 2.0;
+                }
+            }
+        }
+
+        let expected: Vec<f32> = (0..SIZE).map(|x| x as f32 * 2.0).collect();
+        assert_eq!(channel, expected);
+    }
+
+    /// Test that processing in place copies only inputs not shared with their output.
+    #[test]
+    fn in_place() {
+        let mut in1 = vec![1.0f32; SIZE];
+        let mut channel2 = vec![2.0; SIZE];
+        let mut out1 = vec![0.0; SIZE];
+
+        let mut storage = BufferStorage::new();
+        let buffer = unsafe {
+            AudioBuffer::from_raw_in_place(vec![in1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           vec![out1.as_mut_ptr(), channel2.as_mut_ptr()].as_mut_ptr(),
+                                           2, 2, SIZE, &mut storage)
+        };
+
+        for channel in buffer.in_place() {
+            for sample in channel.iter_mut() {
+                *sample += 1.0;
+            }
+        }
+
+        assert_eq!(out1, vec![2.0; SIZE]);
+        assert_eq!(channel2, vec![3.0; SIZE]);
+        assert!(storage.scratch.iter().all(|channel| channel.iter().all(|&s| s == 0.0)));
+    }
+
+    /// Generic processing used by the `generic` test.
+    fn half<T: Float>(buffer: AudioBuffer<T>) {
+        let half = T::from(0.5).unwrap();
+        for (input, output) in buffer.zip() {
+            for (i, o) in input.iter().zip(output.iter_mut()) {
+                *o = *i * half;
+            }
+        }
+    }
+
+    /// Test that the same code processes both sample types.
+    #[test]
+    fn generic() {
+        let mut in32 = vec![1.0f32; SIZE];
+        let mut out32 = vec![0.0f32; SIZE];
+        let buffer = AudioBuffer::new(vec![&mut in32], vec![&mut out32]);
+        assert_eq!((buffer.input_count(), buffer.output_count(), buffer.samples()), (1, 1, SIZE));
+        half(buffer);
+
+        let mut in64 = vec![1.0f64; SIZE];
+        let mut out64 = vec![0.0f64; SIZE];
+        half(AudioBuffer::new(vec![&mut in64], vec![&mut out64]));
+
+        assert_eq!(out32, vec![0.5; SIZE]);
+        assert_eq!(out64, vec![0.5; SIZE]);
+    }
+
+    /// Test that split inputs and outputs can be used at the same time.
+    #[test]
+    fn split() {
+        let mut in1 = vec![1.0f32; SIZE];
+        let mut in2 = vec![2.0; SIZE];
+        let mut out1 = vec![0.0; SIZE];
+
+        {
+            let mut buffer = AudioBuffer::new(vec![&mut in1, &mut in2], vec![&mut out1]);
+            let (inputs, mut outputs) = buffer.split_mut();
+            assert_eq!((inputs.len(), outputs.len()), (2, 1));
+            assert!(inputs.get(2).is_none());
+
+            for (i, sample) in outputs[0].iter_mut().enumerate() {
+                *sample = inputs.iter().map(|c| c[i]).sum();
+            }
+        }
+
+        assert_eq!(out1, vec![3.0; SIZE]);
+    }
+
+    /// Test that buffers round trip through interleaved samples.
+    #[test]
+    fn interleaved() {
+        let mut in1 = vec![1.0f32, 2.0, 3.0];
+        let mut in2 = vec![-1.0, -2.0, -3.0];
+        let mut out1 = vec![0.0; 3];
+        let mut out2 = vec![0.0; 3];
+
+        let mut buffer = AudioBuffer::new(vec![&mut in1, &mut in2], vec![&mut out1, &mut out2]);
+
+        let mut interleaved = vec![0.0; 6];
+        buffer.copy_inputs_to_interleaved(&mut interleaved).unwrap();
+        assert_eq!(interleaved, vec![1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+
+        buffer.copy_outputs_from_interleaved(&interleaved).unwrap();
+        let mut copy = vec![0.0; 6];
+        buffer.copy_outputs_to_interleaved(&mut copy).unwrap();
+        assert_eq!(copy, interleaved);
+
+        let error = buffer.copy_inputs_from_interleaved(&interleaved[..5]).unwrap_err();
+        assert_eq!(error, InterleaveError { expected: 6, actual: 5 });
+    }
+
+    /// Test that aligned bodies are aligned and cover all whole lanes.
+    #[test]
+    fn split_aligned() {
+        let mut channel = vec![0.0f32; SIZE + 3];
+
+        for start in 0..4 {
+            let len = channel.len() - start;
+            let (head, body, tail) = buffer::split_aligned_mut(&mut channel[start..], 16);
+
+            assert_eq!(body.as_ptr() as usize % 16, 0);
+            assert_eq!(body.len() % 4, 0);
+            assert!(head.len() < 4 && tail.len() < 4);
+            assert_eq!(head.len() + body.len() + tail.len(), len);
+        }
+
+        let mut in1 = vec![0.0f32; SIZE];
+        let mut out1 = vec![0.0f32; SIZE];
+        let buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+        assert!(buffer.is_aligned(4));
+        assert!(buffer.is_aligned(0));
+    }
+
+    /// Test that channels can be read through several shared borrows at the same time.
+    #[test]
+    fn shared_channels() {
+        let mut in1 = vec![1.0f32; 4];
+        let mut out1 = vec![2.0f32; 4];
+        let mut buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+        let (inputs, outputs) = buffer.split_mut();
+
+        let (first, second) = (outputs.get(0).unwrap(), &outputs[0]);
+        assert_eq!(first, second);
+        assert_eq!(inputs.iter().chain(outputs.iter()).map(|c| c[0]).sum::<f32>(), 3.0);
+    }
+
+    /// Test that buffers built from owned channels check the channel lengths.
+    #[test]
+    fn from_channels() {
+        let mut inputs = vec![vec![1.0f32; SIZE]; 2];
+        let mut outputs = vec![vec![0.0f32; SIZE]; 2];
+        for (input, output) in AudioBuffer::from_channels(&mut inputs, &mut outputs).unwrap().zip() {
+            output.copy_from_slice(input);
+        }
+        assert_eq!(outputs, inputs);
+
+        let mut short = vec![vec![0.0f32; SIZE - 1]];
+        let error = AudioBuffer::from_channels(&mut inputs, &mut short).err().unwrap();
+        assert_eq!(error, ChannelLengthError { expected: SIZE, actual: SIZE - 1 });
+    }
+
+    /// Test that bypass and silence helpers affect the expected channels.
+    #[test]
+    fn silence_and_passthrough() {
+        let mut in1 = vec![0.0f32; SIZE];
+        let mut out1 = vec![1.0f32; SIZE];
+        let mut out2 = vec![1.0f32; SIZE];
+
+        {
+            let mut buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1, &mut out2]);
+            assert!(buffer.inputs_are_silent());
+
+            buffer.clear_outputs();
+        }
+        assert_eq!(out1, vec![0.0; SIZE]);
+
+        in1[SIZE - 1] = 0.5;
+        out2 = vec![1.0; SIZE];
+        {
+            let mut buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1, &mut out2]);
+            assert!(!buffer.inputs_are_silent());
+
+            buffer.copy_inputs_to_outputs();
+        }
+        assert_eq!(out1, in1);
+        assert_eq!(out2, vec![0.0; SIZE]);
+    }
+
+    /// Test that both halves of a split buffer write to the original channels.
+    #[test]
+    fn split_at() {
+        let mut in1: Vec<f32> = (0..SIZE).map(|x| x as f32).collect();
+        let mut out1 = vec![0.0; SIZE];
+
+        {
+            let buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+            let (before, after) = buffer.split_at(10);
+            assert_eq!((before.samples(), after.samples()), (10, SIZE - 10));
+
+            for (input, output) in before.zip() {
+                output.copy_from_slice(input);
+            }
+            for (input, output) in after.zip() {
+                for (i, o) in input.iter().zip(output.iter_mut()) {
+                    *o = -*i;
+                }
+            }
+        }
+
+        assert_eq!(&out1[..10], &in1[..10]);
+        assert_eq!(out1[10], -10.0);
+    }
+
+    /// Test that sidechain inputs are kept apart from the main inputs.
+    #[test]
+    fn sidechain() {
+        let mut in1 = vec![1.0f32; SIZE];
+        let mut key1 = vec![2.0; SIZE];
+        let mut key2 = vec![3.0; SIZE];
+        let mut out1 = vec![0.0; SIZE];
+
+        let buffer = AudioBuffer::new(vec![&mut in1], vec![&mut out1]);
+        assert_eq!((buffer.main_input_count(), buffer.sidechain_count()), (1, 0));
+
+        let buffer = AudioBuffer::new(vec![&mut in1, &mut key1, &mut key2], vec![&mut out1])
+                                 .with_sidechain(1);
+        let (_, mut after) = buffer.split_at(10);
+        assert_eq!((after.main_input_count(), after.sidechain_count()), (1, 2));
+        assert_eq!(after.main_inputs().len(), 1);
+        assert_eq!(after.sidechain()[1][0], 3.0);
+
+        let (inputs, sidechain, outputs) = after.split_sidechain();
+        assert_eq!((inputs[0][0], sidechain[0][0], outputs.len()), (1.0, 2.0, 1));
+    }
+
     /// Test that creating buffers from raw pointers works.
     #[test]
     fn from_raw() {
@@ -0,0 +1,310 @@
+//! Named groups of input / output channels.
+//!
+//! VST 2 only knows flat lists of input and output channels. A [`BusLayout`](struct.BusLayout.html)
+//! groups them into named buses, e.g. a main stereo input and a stereo sidechain. Plugins
+//! returning a layout from `Plugin::get_bus_layout` get their channel counts, channel (pin)
+//! properties and speaker arrangements derived from it, and can split their buffers per bus.
+//!
+//! Input buses created with `Bus::sidechain` carry a key signal rather than audio to be
+//! processed. Their channels are named and short named as such for the host, the plugin reports
+//! that it is meant to be used as an insert, and the buffers passed to `Plugin::process` keep them
+//! apart from the main inputs, see `AudioBuffer::split_sidechain`. Sidechain buses must follow
+//! the main input buses.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::bus::{Bus, BusLayout};
+//! # use vst2::plugin::{Info, Plugin};
+//! struct Compressor {
+//!     buses: BusLayout,
+//! }
+//!
+//! impl Default for Compressor {
+//!     fn default() -> Compressor {
+//!         Compressor {
+//!             buses: BusLayout::new(vec![Bus::new("Main", 2), Bus::sidechain("Sidechain", 2)],
+//!                                   vec![Bus::new("Main", 2)]),
+//!         }
+//!     }
+//! }
+//!
+//! impl Plugin for Compressor {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn get_bus_layout(&self) -> Option<&BusLayout> { Some(&self.buses) }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         let (main, sidechain, mut outputs) = buffer.split_sidechain();
+//!
+//!         for (channel, output) in outputs.iter_mut().enumerate() {
+//!             for (i, sample) in output.iter_mut().enumerate() {
+//!                 let gain = if sidechain[channel][i].abs() > 0.5 { 0.5 } else { 1.0 };
+//!                 *sample = main[channel][i] * gain;
+//!             }
+//!         }
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::vec::IntoIter;
+
+use num::Float;
+
+use api;
+use buffer::AudioBuffer;
+use channels::{ChannelInfo, Speaker, SpeakerArrangement, SpeakerArrangementType, SpeakerType,
+               StereoChannel, StereoConfig};
+
+/// What the channels of a bus carry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusKind {
+    /// Audio to be processed, or the processed output.
+    Main,
+
+    /// A key signal controlling the processing, e.g. of a compressor. Only valid for inputs.
+    Sidechain,
+}
+
+/// A named group of channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bus {
+    /// Name of the bus, e.g. "Main" or "Sidechain".
+    pub name: String,
+
+    /// Number of channels in the bus.
+    pub channels: usize,
+
+    /// What the channels carry.
+    pub kind: BusKind,
+}
+
+impl Bus {
+    /// Create a main bus with the given name and number of channels.
+    pub fn new<S: Into<String>>(name: S, channels: usize) -> Bus {
+        Bus {
+            name: name.into(),
+            channels: channels,
+            kind: BusKind::Main,
+        }
+    }
+
+    /// Create a sidechain input bus with the given name and number of channels.
+    pub fn sidechain<S: Into<String>>(name: S, channels: usize) -> Bus {
+        Bus { kind: BusKind::Sidechain, ..Bus::new(name, channels) }
+    }
+
+    /// Whether this is a sidechain bus.
+    pub fn is_sidechain(&self) -> bool {
+        self.kind == BusKind::Sidechain
+    }
+
+    /// Name of the channel at `index` within this bus, e.g. "Sidechain L".
+    fn channel_name(&self, index: usize) -> String {
+        match self.channels {
+            1 => self.name.clone(),
+            2 => format!("{} {}", self.name, if index == 0 { "L" } else { "R" }),
+            _ => format!("{} {}", self.name, index + 1),
+        }
+    }
+
+    /// Short name of the channel at `index`, marking sidechain channels as such, e.g. "SC L".
+    fn short_name(&self, index: usize) -> Option<String> {
+        if !self.is_sidechain() {
+            return None;
+        }
+
+        Some(match self.channels {
+            1 => "SC".to_string(),
+            2 => format!("SC {}", if index == 0 { "L" } else { "R" }),
+            _ => format!("SC {}", index + 1),
+        })
+    }
+
+    /// Arrangement type of the channel at `index` within this bus.
+    fn arrangement_type(&self, index: usize) -> Option<SpeakerArrangementType> {
+        match self.channels {
+            1 => Some(SpeakerArrangementType::Mono),
+            2 => {
+                let side = if index == 0 { StereoChannel::Left } else { StereoChannel::Right };
+                Some(SpeakerArrangementType::Stereo(StereoConfig::L_R, side))
+            }
+            _ => None,
+        }
+    }
+
+    /// Speaker type of the channel at `index` within this bus.
+    fn speaker_type(&self, index: usize) -> SpeakerType {
+        match (self.channels, index) {
+            (1, _) => SpeakerType::Mono,
+            (2, 0) => SpeakerType::Left,
+            (2, _) => SpeakerType::Right,
+            _ => SpeakerType::Undefined,
+        }
+    }
+}
+
+/// The input and output buses of a plugin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BusLayout {
+    /// Input buses, in channel order.
+    pub inputs: Vec<Bus>,
+
+    /// Output buses, in channel order.
+    pub outputs: Vec<Bus>,
+}
+
+impl BusLayout {
+    /// Create a layout from input and output buses, given in channel order.
+    pub fn new(inputs: Vec<Bus>, outputs: Vec<Bus>) -> BusLayout {
+        BusLayout {
+            inputs: inputs,
+            outputs: outputs,
+        }
+    }
+
+    /// Total number of input channels.
+    pub fn num_inputs(&self) -> usize {
+        self.inputs.iter().map(|b| b.channels).sum()
+    }
+
+    /// Total number of output channels.
+    pub fn num_outputs(&self) -> usize {
+        self.outputs.iter().map(|b| b.channels).sum()
+    }
+
+    /// Index of the first sidechain input channel, or the number of input channels if there is
+    /// no sidechain bus.
+    pub fn first_sidechain_input(&self) -> usize {
+        self.inputs.iter().take_while(|b| !b.is_sidechain()).map(|b| b.channels).sum()
+    }
+
+    /// Whether any input bus is a sidechain.
+    pub fn has_sidechain(&self) -> bool {
+        self.inputs.iter().any(|b| b.is_sidechain())
+    }
+
+    /// Information about the input channel at `index`, named after its bus.
+    pub fn input_info(&self, index: usize) -> Option<ChannelInfo> {
+        channel_info(&self.inputs, index)
+    }
+
+    /// Information about the output channel at `index`, named after its bus.
+    pub fn output_info(&self, index: usize) -> Option<ChannelInfo> {
+        channel_info(&self.outputs, index)
+    }
+
+    /// The input and output speaker arrangements, with one speaker per channel named after its
+    /// bus.
+    pub fn speaker_arrangements(&self) -> (SpeakerArrangement, SpeakerArrangement) {
+        (speaker_arrangement(&self.inputs), speaker_arrangement(&self.outputs))
+    }
+
+    /// Split a buffer into the channels of each input and output bus.
+    ///
+    /// Buses without matching channels in the buffer are left empty.
+    pub fn split<'a, T: 'a + Float>(&self, buffer: AudioBuffer<'a, T>)
+                                   -> (Vec<Vec<&'a mut [T]>>, Vec<Vec<&'a mut [T]>>) {
+        let (inputs, outputs) = buffer.split();
+        (split_channels(&self.inputs, inputs.into_iter()),
+         split_channels(&self.outputs, outputs.into_iter()))
+    }
+}
+
+/// Find the bus containing the channel at `index` and the channel's index within it.
+fn find_channel(buses: &[Bus], mut index: usize) -> Option<(&Bus, usize)> {
+    for bus in buses {
+        if index < bus.channels {
+            return Some((bus, index));
+        }
+        index -= bus.channels;
+    }
+    None
+}
+
+fn channel_info(buses: &[Bus], index: usize) -> Option<ChannelInfo> {
+    find_channel(buses, index).map(|(bus, index)| {
+        ChannelInfo::new(bus.channel_name(index), bus.short_name(index), true,
+                         bus.arrangement_type(index))
+    })
+}
+
+fn speaker_arrangement(buses: &[Bus]) -> SpeakerArrangement {
+    let speakers: Vec<Speaker> = buses.iter().flat_map(|bus| {
+        (0..bus.channels).map(move |index| {
+            Speaker { name: bus.channel_name(index), ..Speaker::new(bus.speaker_type(index)) }
+        })
+    }).collect();
+
+    let arrangement_type = match speakers.len() {
+        0 => api::SpeakerArrangementType::Empty,
+        1 => api::SpeakerArrangementType::Mono,
+        2 => api::SpeakerArrangementType::Stereo,
+        _ => api::SpeakerArrangementType::Custom,
+    };
+
+    SpeakerArrangement {
+        arrangement_type: arrangement_type,
+        speakers: speakers,
+    }
+}
+
+fn split_channels<'a, T>(buses: &[Bus], mut channels: IntoIter<&'a mut [T]>) -> Vec<Vec<&'a mut [T]>> {
+    buses.iter().map(|bus| channels.by_ref().take(bus.channels).collect()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use api;
+    use buffer::AudioBuffer;
+    use bus::{Bus, BusLayout};
+
+    fn layout() -> BusLayout {
+        BusLayout::new(vec![Bus::new("Main", 2), Bus::sidechain("Sidechain", 1)],
+                       vec![Bus::new("Out", 2), Bus::new("Aux", 4)])
+    }
+
+    /// Test that channels are named after their bus.
+    #[test]
+    fn channel_info() {
+        let layout = layout();
+
+        assert_eq!((layout.num_inputs(), layout.num_outputs()), (3, 6));
+        assert_eq!(layout.input_info(1).unwrap().name(), "Main R");
+        assert!(layout.input_info(0).unwrap().arrangement_type().is_left_stereo());
+        assert_eq!(layout.input_info(2).unwrap().name(), "Sidechain");
+        assert_eq!(layout.input_info(2).unwrap().short_name(), "SC");
+        assert_eq!(layout.input_info(1).unwrap().short_name(), "Main R");
+        assert_eq!(layout.first_sidechain_input(), 2);
+        assert!(layout.has_sidechain());
+        assert_eq!(layout.output_info(5).unwrap().name(), "Aux 4");
+        assert!(layout.input_info(3).is_none());
+    }
+
+    /// Test that buffers are split per bus.
+    #[test]
+    fn split() {
+        let layout = layout();
+        let mut inputs = vec![vec![0.0f32; 4], vec![1.0; 4], vec![2.0; 4]];
+        let mut outputs = vec![vec![0.0f32; 4]; 6];
+        let buffer = AudioBuffer::new(inputs.iter_mut().map(|c| &mut c[..]).collect(),
+                                      outputs.iter_mut().map(|c| &mut c[..]).collect());
+
+        let (inputs, outputs) = layout.split(buffer);
+        assert_eq!(inputs.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(outputs.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![2, 4]);
+        assert_eq!(inputs[1][0][0], 2.0);
+    }
+
+    /// Test that the speaker arrangements cover all channels.
+    #[test]
+    fn speaker_arrangements() {
+        let (input, output) = layout().speaker_arrangements();
+
+        assert_eq!(input.arrangement_type, api::SpeakerArrangementType::Custom);
+        assert_eq!(input.speakers[2].name, "Sidechain");
+        assert_eq!(output.num_channels(), 6);
+    }
+}
@@ -1,11 +1,16 @@
 //! Meta data for dealing with input / output channels. Not all hosts use this so it is not
 //! necessary for plugin functionality.
 
+use std::{cmp, mem, slice};
+
 use api;
 use api::consts::{MAX_LABEL, MAX_SHORT_LABEL};
+use {read_fixed, write_fixed};
 
-/// Information about an input / output channel. This isn't necessary for a channel to function but
-/// informs the host how the channel is meant to be used.
+/// Information about an input / output channel (pin). This isn't necessary for a channel to
+/// function but informs the host how the channel is meant to be used, e.g. by naming it
+/// "Sidechain".
+#[derive(Clone, Debug, PartialEq)]
 pub struct ChannelInfo {
     name: String,
     short_name: String,
@@ -40,6 +45,26 @@ impl ChannelInfo {
             arrangement_type: arrangement_type.unwrap_or(SpeakerArrangementType::Custom)
         }
     }
+
+    /// Name of this channel.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Short name of this channel.
+    pub fn short_name(&self) -> &str {
+        &self.short_name
+    }
+
+    /// Whether this channel is active.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// How this channel is used in a speaker arrangement.
+    pub fn arrangement_type(&self) -> &SpeakerArrangementType {
+        &self.arrangement_type
+    }
 }
 
 impl Into<api::ChannelProperties> for ChannelInfo {
@@ -48,9 +73,7 @@ impl Into<api::ChannelProperties> for ChannelInfo {
         api::ChannelProperties {
             name: {
                 let mut label = [0; MAX_LABEL as usize];
-                for (b, c) in self.name.bytes().zip(label.iter_mut()) {
-                    *c = b;
-                }
+                write_fixed(&mut label, &self.name);
                 label
             },
             flags: {
@@ -65,9 +88,7 @@ impl Into<api::ChannelProperties> for ChannelInfo {
             arrangement_type: self.arrangement_type.into(),
             short_name: {
                 let mut label = [0; MAX_SHORT_LABEL as usize];
-                for (b, c) in self.short_name.bytes().zip(label.iter_mut()) {
-                    *c = b;
-                }
+                write_fixed(&mut label, &self.short_name);
                 label
             },
             future: [0; 48]
@@ -75,8 +96,68 @@ impl Into<api::ChannelProperties> for ChannelInfo {
     }
 }
 
+impl From<api::ChannelProperties> for ChannelInfo {
+    /// Convert from the VST api equivalent of this structure.
+    ///
+    /// The raw structure does not tell which side of a stereo pair the right channel is on, so
+    /// stereo channels without the stereo flag are assumed to be the right channel.
+    fn from(raw: api::ChannelProperties) -> ChannelInfo {
+        use api::flags::*;
+        use api::SpeakerArrangementType as Raw;
+
+        let flags = Channel::from_bits_truncate(raw.flags);
+        let side = if flags.contains(STEREO) { StereoChannel::Left } else { StereoChannel::Right };
+        let music = |config| SpeakerArrangementType::Surround(config, ArrangementTarget::Music);
+        let cinema = |config| SpeakerArrangementType::Surround(config, ArrangementTarget::Cinema);
+
+        let arrangement_type = match raw.arrangement_type {
+            Raw::Custom => SpeakerArrangementType::Custom,
+            Raw::Empty => SpeakerArrangementType::Empty,
+            Raw::Mono => SpeakerArrangementType::Mono,
+
+            Raw::Stereo => SpeakerArrangementType::Stereo(StereoConfig::L_R, side),
+            Raw::StereoSurround => SpeakerArrangementType::Stereo(StereoConfig::Ls_Rs, side),
+            Raw::StereoCenter => SpeakerArrangementType::Stereo(StereoConfig::Lc_Rc, side),
+            Raw::StereoSide => SpeakerArrangementType::Stereo(StereoConfig::Sl_Sr, side),
+            Raw::StereoCLfe => SpeakerArrangementType::Stereo(StereoConfig::C_Lfe, side),
+
+            Raw::Cinema30 => cinema(SurroundConfig::S3_0),
+            Raw::Music30 => music(SurroundConfig::S3_0),
+            Raw::Cinema31 => cinema(SurroundConfig::S3_1),
+            Raw::Music31 => music(SurroundConfig::S3_1),
+            Raw::Cinema40 => cinema(SurroundConfig::S4_0),
+            Raw::Music40 => music(SurroundConfig::S4_0),
+            Raw::Cinema41 => cinema(SurroundConfig::S4_1),
+            Raw::Music41 => music(SurroundConfig::S4_1),
+            Raw::Surround50 => music(SurroundConfig::S5_0),
+            Raw::Surround51 => music(SurroundConfig::S5_1),
+            Raw::Cinema60 => cinema(SurroundConfig::S6_0),
+            Raw::Music60 => music(SurroundConfig::S6_0),
+            Raw::Cinema61 => cinema(SurroundConfig::S6_1),
+            Raw::Music61 => music(SurroundConfig::S6_1),
+            Raw::Cinema70 => cinema(SurroundConfig::S7_0),
+            Raw::Music70 => music(SurroundConfig::S7_0),
+            Raw::Cinema71 => cinema(SurroundConfig::S7_1),
+            Raw::Music71 => music(SurroundConfig::S7_1),
+            Raw::Cinema80 => cinema(SurroundConfig::S8_0),
+            Raw::Music80 => music(SurroundConfig::S8_0),
+            Raw::Cinema81 => cinema(SurroundConfig::S8_1),
+            Raw::Music81 => music(SurroundConfig::S8_1),
+            Raw::Surround102 => music(SurroundConfig::S10_2),
+        };
+
+        ChannelInfo {
+            name: read_fixed(&raw.name),
+            short_name: read_fixed(&raw.short_name),
+            active: flags.contains(ACTIVE),
+            arrangement_type: arrangement_type,
+        }
+    }
+}
+
 /// Target for Speaker arrangement type. Can be a cinema configuration or music configuration. Both
 /// are technically identical but this provides extra information to the host.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ArrangementTarget {
     /// Music arrangement. Technically identical to Cinema.
     Music,
@@ -85,6 +166,7 @@ pub enum ArrangementTarget {
 }
 
 /// An enum for all channels in a stereo configuration.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum StereoChannel {
     /// Left channel.
     Left,
@@ -94,6 +176,7 @@ pub enum StereoChannel {
 
 /// Possible stereo speaker configurations.
 #[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum StereoConfig {
     /// Regular.
     L_R,
@@ -109,6 +192,7 @@ pub enum StereoConfig {
 
 /// Possible surround speaker configurations.
 #[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SurroundConfig {
     /// 3.0 surround sound.
     /// Cinema: L R C
@@ -162,6 +246,7 @@ pub enum SurroundConfig {
 }
 
 /// Type representing how a channel is used. Only useful for some hosts.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SpeakerArrangementType {
     /// Custom arrangement not specified to host.
     Custom,
@@ -265,3 +350,336 @@ impl Into<api::SpeakerArrangementType> for SpeakerArrangementType {
         }
     }
 }
+
+/// Type of a single speaker in a `SpeakerArrangement`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpeakerType {
+    /// Undefined speaker.
+    Undefined,
+    /// Mono (M).
+    Mono,
+    /// Left (L).
+    Left,
+    /// Right (R).
+    Right,
+    /// Center (C).
+    Center,
+    /// Subbass (Lfe).
+    Lfe,
+    /// Left surround (Ls).
+    LeftSurround,
+    /// Right surround (Rs).
+    RightSurround,
+    /// Left of center (Lc).
+    LeftCenter,
+    /// Right of center (Rc).
+    RightCenter,
+    /// Surround (S), also used for center surround (Cs).
+    Surround,
+    /// Side left (Sl).
+    SideLeft,
+    /// Side right (Sr).
+    SideRight,
+    /// Top middle, over the listener (Tm).
+    TopMiddle,
+    /// Top front left (Tfl).
+    TopFrontLeft,
+    /// Top front center (Tfc).
+    TopFrontCenter,
+    /// Top front right (Tfr).
+    TopFrontRight,
+    /// Top rear left (Trl).
+    TopRearLeft,
+    /// Top rear center (Trc).
+    TopRearCenter,
+    /// Top rear right (Trr).
+    TopRearRight,
+    /// Second subbass (Lfe2).
+    Lfe2,
+}
+
+impl SpeakerType {
+    /// Short name of the speaker as used in speaker arrangement descriptions, e.g. "Ls".
+    pub fn short_name(&self) -> &'static str {
+        use self::SpeakerType::*;
+
+        match *self {
+            Undefined => "",
+            Mono => "M",
+            Left => "L",
+            Right => "R",
+            Center => "C",
+            Lfe => "Lfe",
+            LeftSurround => "Ls",
+            RightSurround => "Rs",
+            LeftCenter => "Lc",
+            RightCenter => "Rc",
+            Surround => "S",
+            SideLeft => "Sl",
+            SideRight => "Sr",
+            TopMiddle => "Tm",
+            TopFrontLeft => "Tfl",
+            TopFrontCenter => "Tfc",
+            TopFrontRight => "Tfr",
+            TopRearLeft => "Trl",
+            TopRearCenter => "Trc",
+            TopRearRight => "Trr",
+            Lfe2 => "Lfe2",
+        }
+    }
+}
+
+impl From<i32> for SpeakerType {
+    fn from(raw: i32) -> SpeakerType {
+        use self::SpeakerType::*;
+
+        match raw {
+            0 => Mono,
+            1 => Left,
+            2 => Right,
+            3 => Center,
+            4 => Lfe,
+            5 => LeftSurround,
+            6 => RightSurround,
+            7 => LeftCenter,
+            8 => RightCenter,
+            9 => Surround,
+            10 => SideLeft,
+            11 => SideRight,
+            12 => TopMiddle,
+            13 => TopFrontLeft,
+            14 => TopFrontCenter,
+            15 => TopFrontRight,
+            16 => TopRearLeft,
+            17 => TopRearCenter,
+            18 => TopRearRight,
+            19 => Lfe2,
+            _ => Undefined,
+        }
+    }
+}
+
+impl Into<i32> for SpeakerType {
+    fn into(self) -> i32 {
+        use self::SpeakerType::*;
+
+        match self {
+            Undefined => 0x7fffffff,
+            Mono => 0,
+            Left => 1,
+            Right => 2,
+            Center => 3,
+            Lfe => 4,
+            LeftSurround => 5,
+            RightSurround => 6,
+            LeftCenter => 7,
+            RightCenter => 8,
+            Surround => 9,
+            SideLeft => 10,
+            SideRight => 11,
+            TopMiddle => 12,
+            TopFrontLeft => 13,
+            TopFrontCenter => 14,
+            TopFrontRight => 15,
+            TopRearLeft => 16,
+            TopRearCenter => 17,
+            TopRearRight => 18,
+            Lfe2 => 19,
+        }
+    }
+}
+
+/// A single speaker of a `SpeakerArrangement`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Speaker {
+    /// Type of the speaker.
+    pub speaker_type: SpeakerType,
+
+    /// Name of the speaker, limited to 63 characters.
+    pub name: String,
+
+    /// Azimuth in radians, range -pi to pi. 0 is in front of the listener.
+    pub azimuth: f32,
+
+    /// Elevation in radians, range -pi/2 to pi/2. 0 is at ear level.
+    pub elevation: f32,
+
+    /// Distance from the listener in meters.
+    pub radius: f32,
+}
+
+impl Speaker {
+    /// Create a speaker of the given type, named after its short name.
+    pub fn new(speaker_type: SpeakerType) -> Speaker {
+        Speaker {
+            speaker_type: speaker_type,
+            name: speaker_type.short_name().to_string(),
+            azimuth: 0.0,
+            elevation: 0.0,
+            radius: 0.0,
+        }
+    }
+}
+
+/// The speaker arrangement of the inputs or outputs of a plugin.
+///
+/// The number of speakers is the number of channels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpeakerArrangement {
+    /// Type of the arrangement.
+    pub arrangement_type: api::SpeakerArrangementType,
+
+    /// The speakers of the arrangement, one per channel.
+    pub speakers: Vec<Speaker>,
+}
+
+impl SpeakerArrangement {
+    /// Create a standard arrangement of the given type with its speakers in channel order.
+    ///
+    /// `Custom` and `Empty` arrangements have no speakers.
+    pub fn new(arrangement_type: api::SpeakerArrangementType) -> SpeakerArrangement {
+        use api::SpeakerArrangementType as Raw;
+        use self::SpeakerType::*;
+
+        let speakers: &[SpeakerType] = match arrangement_type {
+            Raw::Custom | Raw::Empty => &[],
+            Raw::Mono => &[Mono],
+
+            Raw::Stereo => &[Left, Right],
+            Raw::StereoSurround => &[LeftSurround, RightSurround],
+            Raw::StereoCenter => &[LeftCenter, RightCenter],
+            Raw::StereoSide => &[SideLeft, SideRight],
+            Raw::StereoCLfe => &[Center, Lfe],
+
+            Raw::Cinema30 => &[Left, Right, Center],
+            Raw::Music30 => &[Left, Right, Surround],
+            Raw::Cinema31 => &[Left, Right, Center, Lfe],
+            Raw::Music31 => &[Left, Right, Lfe, Surround],
+            Raw::Cinema40 => &[Left, Right, Center, Surround],
+            Raw::Music40 => &[Left, Right, LeftSurround, RightSurround],
+            Raw::Cinema41 => &[Left, Right, Center, Lfe, Surround],
+            Raw::Music41 => &[Left, Right, Lfe, LeftSurround, RightSurround],
+
+            Raw::Surround50 => &[Left, Right, Center, LeftSurround, RightSurround],
+            Raw::Surround51 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround],
+
+            Raw::Cinema60 => &[Left, Right, Center, LeftSurround, RightSurround, Surround],
+            Raw::Music60 => &[Left, Right, LeftSurround, RightSurround, SideLeft, SideRight],
+            Raw::Cinema61 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround, Surround],
+            Raw::Music61 => &[Left, Right, Lfe, LeftSurround, RightSurround, SideLeft, SideRight],
+
+            Raw::Cinema70 => &[Left, Right, Center, LeftSurround, RightSurround, LeftCenter,
+                               RightCenter],
+            Raw::Music70 => &[Left, Right, Center, LeftSurround, RightSurround, SideLeft,
+                              SideRight],
+            Raw::Cinema71 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround, LeftCenter,
+                               RightCenter],
+            Raw::Music71 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround, SideLeft,
+                              SideRight],
+
+            Raw::Cinema80 => &[Left, Right, Center, LeftSurround, RightSurround, LeftCenter,
+                               RightCenter, Surround],
+            Raw::Music80 => &[Left, Right, Center, LeftSurround, RightSurround, Surround, SideLeft,
+                              SideRight],
+            Raw::Cinema81 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround, LeftCenter,
+                               RightCenter, Surround],
+            Raw::Music81 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround, Surround,
+                              SideLeft, SideRight],
+
+            Raw::Surround102 => &[Left, Right, Center, Lfe, LeftSurround, RightSurround,
+                                  TopFrontLeft, TopFrontCenter, TopFrontRight, TopRearLeft,
+                                  TopRearRight, Lfe2],
+        };
+
+        SpeakerArrangement {
+            arrangement_type: arrangement_type,
+            speakers: speakers.iter().map(|t| Speaker::new(*t)).collect(),
+        }
+    }
+
+    /// Number of channels in this arrangement.
+    pub fn num_channels(&self) -> usize {
+        self.speakers.len()
+    }
+
+    /// Create a safe `SpeakerArrangement` from a raw `api::SpeakerArrangement` pointer.
+    ///
+    /// `raw` must be followed by storage for all `num_channels` speakers, as the VST standard
+    /// requires for arrangements of more than 8 channels. A negative channel count is read as an
+    /// empty arrangement and an unknown arrangement type as `Custom`.
+    pub unsafe fn from_raw(raw: *const api::SpeakerArrangement) -> SpeakerArrangement {
+        let num_channels = cmp::max((*raw).num_channels, 0) as usize;
+        let speakers = slice::from_raw_parts(speakers_ptr(raw), num_channels);
+
+        SpeakerArrangement {
+            arrangement_type: (*raw).arrangement_type(),
+            speakers: speakers.iter().map(|speaker| {
+                Speaker {
+                    speaker_type: SpeakerType::from(speaker.speaker_type),
+                    name: read_fixed(&speaker.name),
+                    azimuth: speaker.azimuth,
+                    elevation: speaker.elevation,
+                    radius: speaker.radius,
+                }
+            }).collect(),
+        }
+    }
+}
+
+/// An `api::SpeakerArrangement` with room for any number of speakers, owning its memory.
+pub struct RawSpeakerArrangement {
+    // Extra elements only provide the space for speakers past the first 8
+    data: Vec<api::SpeakerArrangement>,
+}
+
+impl RawSpeakerArrangement {
+    /// Get a pointer to the raw arrangement, valid as long as this struct is alive.
+    pub fn as_mut_ptr(&mut self) -> *mut api::SpeakerArrangement {
+        self.data.as_mut_ptr()
+    }
+}
+
+impl<'a> From<&'a SpeakerArrangement> for RawSpeakerArrangement {
+    fn from(arrangement: &'a SpeakerArrangement) -> RawSpeakerArrangement {
+        let mut speaker: api::SpeakerProperties = unsafe { mem::zeroed() };
+        speaker.speaker_type = SpeakerType::Undefined.into();
+
+        let template = api::SpeakerArrangement {
+            arrangement_type: arrangement.arrangement_type as i32,
+            num_channels: arrangement.speakers.len() as i32,
+            speakers: [speaker; 8],
+        };
+
+        let per_element = mem::size_of::<api::SpeakerArrangement>() /
+                          mem::size_of::<api::SpeakerProperties>();
+        let extra = arrangement.speakers.len().saturating_sub(8);
+        let mut raw = RawSpeakerArrangement {
+            data: vec![template; 1 + (extra + per_element - 1) / per_element],
+        };
+
+        unsafe {
+            let speakers = speakers_ptr(raw.as_mut_ptr()) as *mut api::SpeakerProperties;
+
+            for (i, speaker) in arrangement.speakers.iter().enumerate() {
+                let raw_speaker = &mut *speakers.offset(i as isize);
+
+                raw_speaker.speaker_type = speaker.speaker_type.into();
+                write_fixed(&mut raw_speaker.name, &speaker.name);
+                raw_speaker.azimuth = speaker.azimuth;
+                raw_speaker.elevation = speaker.elevation;
+                raw_speaker.radius = speaker.radius;
+            }
+        }
+
+        raw
+    }
+}
+
+/// Pointer to the first speaker of an arrangement.
+///
+/// The pointer is derived from `raw` rather than from its inline array of 8 speakers, so it may
+/// be used to reach speakers stored past the end of that array.
+unsafe fn speakers_ptr(raw: *const api::SpeakerArrangement) -> *const api::SpeakerProperties {
+    let offset = &(*raw).speakers as *const _ as usize - raw as usize;
+    (raw as *const u8).offset(offset as isize) as *const api::SpeakerProperties
+}
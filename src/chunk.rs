@@ -0,0 +1,355 @@
+//! Preset and bank chunks built from serializable plugin state.
+//!
+//! This module is only available with the `chunk` feature. Instead of laying out raw bytes in
+//! `Plugin::get_preset_data` and friends, a plugin implements [`State`](trait.State.html) for its
+//! state type and uses the functions below. Every chunk starts with a small header containing the
+//! chunk kind and the state version, so chunks saved by older versions of a plugin can be upgraded
+//! and foreign chunks are rejected.
+//!
+//! # Example
+//!
+//! ```
+//! #[macro_use] extern crate serde_derive;
+//! extern crate vst2;
+//!
+//! use vst2::chunk::{self, State};
+//! use vst2::plugin::{Info, Plugin};
+//!
+//! #[derive(Default, Serialize, Deserialize)]
+//! struct GainState {
+//!     gain: f32,
+//! }
+//!
+//! impl State for GainState {}
+//!
+//! #[derive(Default)]
+//! struct GainPlugin {
+//!     state: GainState,
+//! }
+//!
+//! impl Plugin for GainPlugin {
+//!     fn get_info(&self) -> Info {
+//!         Info { preset_chunks: true, ..Default::default() }
+//!     }
+//!
+//!     fn get_preset_data(&mut self) -> Vec<u8> {
+//!         chunk::save_preset(&self.state).unwrap_or(Vec::new())
+//!     }
+//!
+//!     fn load_preset_data(&mut self, data: Vec<u8>) {
+//!         if let Ok(state) = chunk::load_preset(&data) {
+//!             self.state = state;
+//!         }
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+//!
+//! Plugins with a single state can let the `chunk_state!` macro implement these methods instead.
+//! Inside `impl Plugin for GainPlugin`, `chunk_state!(state);` replaces the two methods above and
+//! makes bank chunks hold the state as well.
+
+use std::{error, fmt};
+
+use bincode;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Header magic of a preset chunk.
+const PRESET_MAGIC: &'static [u8; 4] = b"RVPS";
+/// Header magic of a bank chunk.
+const BANK_MAGIC: &'static [u8; 4] = b"RVBK";
+/// Header magic of a bank chunk including preset names.
+const NAMED_BANK_MAGIC: &'static [u8; 4] = b"RVNB";
+/// Length of the header preceding the serialized state.
+const HEADER_LEN: usize = 8;
+
+/// Implement the chunk methods of `Plugin` by storing the state in the given field.
+///
+/// Use it inside an `impl Plugin` block, with `Info::preset_chunks` set. The field's type must
+/// implement `chunk::State`. Since the plugin only has a single state, bank chunks contain the same
+/// data as preset chunks. Chunks which can not be loaded leave the state unchanged.
+#[macro_export]
+macro_rules! chunk_state {
+    ($field:ident) => {
+        fn get_preset_data(&mut self) -> Vec<u8> {
+            $crate::chunk::save_preset(&self.$field).unwrap_or(Vec::new())
+        }
+
+        fn get_bank_data(&mut self) -> Vec<u8> {
+            $crate::chunk::save_preset(&self.$field).unwrap_or(Vec::new())
+        }
+
+        fn load_preset_data(&mut self, data: Vec<u8>) {
+            if let Ok(state) = $crate::chunk::load_preset(&data) {
+                self.$field = state;
+            }
+        }
+
+        fn load_bank_data(&mut self, data: Vec<u8>) {
+            if let Ok(state) = $crate::chunk::load_preset(&data) {
+                self.$field = state;
+            }
+        }
+    }
+}
+
+/// Plugin state which can be stored in a chunk.
+pub trait State: Serialize + DeserializeOwned {
+    /// Version of the serialized format, stored in the chunk header.
+    ///
+    /// Increase this whenever the state type changes in a way that breaks deserialization.
+    fn version() -> u32 { 1 }
+
+    /// Convert the serialized state of an older `version` into the current state.
+    ///
+    /// Use `chunk::deserialize` to read the old state type. Returning `None` rejects the chunk,
+    /// which is the default.
+    #[allow(unused_variables)]
+    fn upgrade(version: u32, data: &[u8]) -> Option<Self> { None }
+}
+
+/// An error which occured while reading or writing a chunk.
+#[derive(Debug)]
+pub enum ChunkError {
+    /// The chunk does not start with the expected header.
+    InvalidHeader,
+
+    /// The chunk was saved with a version newer than the current one, or an older version
+    /// which could not be upgraded.
+    UnsupportedVersion(u32),
+
+    /// The state could not be serialized or deserialized.
+    Serialization(bincode::Error),
+}
+
+impl fmt::Display for ChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ChunkError::UnsupportedVersion(version) => {
+                write!(f, "{} ({})", error::Error::description(self), version)
+            }
+            ChunkError::Serialization(ref e) => {
+                write!(f, "{}: {}", error::Error::description(self), e)
+            }
+            _ => write!(f, "{}", error::Error::description(self)),
+        }
+    }
+}
+
+impl error::Error for ChunkError {
+    fn description(&self) -> &str {
+        match *self {
+            ChunkError::InvalidHeader => "The chunk has an invalid header",
+            ChunkError::UnsupportedVersion(_) => "The chunk has an unsupported version",
+            ChunkError::Serialization(_) => "The chunk state could not be serialized",
+        }
+    }
+}
+
+impl From<bincode::Error> for ChunkError {
+    fn from(e: bincode::Error) -> ChunkError {
+        ChunkError::Serialization(e)
+    }
+}
+
+/// Serialize the state of a single preset into a chunk.
+pub fn save_preset<S: State>(state: &S) -> Result<Vec<u8>, ChunkError> {
+    let data = try!(bincode::serialize(state));
+    Ok(with_header::<S>(PRESET_MAGIC, data))
+}
+
+/// Deserialize the state of a single preset from a chunk created by `save_preset`.
+pub fn load_preset<S: State>(chunk: &[u8]) -> Result<S, ChunkError> {
+    let (version, data) = try!(read_header(PRESET_MAGIC, chunk));
+    read_state(version, data)
+}
+
+/// Serialize the states of all presets of a bank into a chunk.
+pub fn save_bank<S: State>(presets: &[S]) -> Result<Vec<u8>, ChunkError> {
+    let mut states = Vec::with_capacity(presets.len());
+    for preset in presets {
+        states.push(try!(bincode::serialize(preset)));
+    }
+
+    let data = try!(bincode::serialize(&states));
+    Ok(with_header::<S>(BANK_MAGIC, data))
+}
+
+/// Deserialize the states of all presets of a bank from a chunk created by `save_bank`.
+pub fn load_bank<S: State>(chunk: &[u8]) -> Result<Vec<S>, ChunkError> {
+    let (version, data) = try!(read_header(BANK_MAGIC, chunk));
+
+    // Presets are stored separately so that each can be upgraded on its own
+    let states: Vec<Vec<u8>> = try!(bincode::deserialize(data));
+    states.iter().map(|state| read_state(version, state)).collect()
+}
+
+/// Serialize the names and states of all presets of a bank into a chunk.
+///
+/// Chunk based banks do not store preset names in the bank file, so plugins with named presets
+/// use this instead of `save_bank`.
+pub fn save_named_bank<S: State>(names: &[String], presets: &[S]) -> Result<Vec<u8>, ChunkError> {
+    let mut states = Vec::with_capacity(presets.len());
+    for preset in presets {
+        states.push(try!(bincode::serialize(preset)));
+    }
+
+    let data = try!(bincode::serialize(&(names, states)));
+    Ok(with_header::<S>(NAMED_BANK_MAGIC, data))
+}
+
+/// Deserialize the names and states of all presets of a bank from a chunk created by
+/// `save_named_bank`.
+pub fn load_named_bank<S: State>(chunk: &[u8]) -> Result<(Vec<String>, Vec<S>), ChunkError> {
+    let (version, data) = try!(read_header(NAMED_BANK_MAGIC, chunk));
+
+    let (names, states): (Vec<String>, Vec<Vec<u8>>) = try!(bincode::deserialize(data));
+    let presets = try!(states.iter().map(|state| read_state(version, state)).collect());
+    Ok((names, presets))
+}
+
+/// Deserialize the state data passed to `State::upgrade` as an older state type.
+pub fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T, ChunkError> {
+    Ok(try!(bincode::deserialize(data)))
+}
+
+/// Prepend the chunk header to serialized state.
+fn with_header<S: State>(magic: &[u8; 4], data: Vec<u8>) -> Vec<u8> {
+    let version = S::version();
+
+    let mut chunk = Vec::with_capacity(HEADER_LEN + data.len());
+    chunk.extend_from_slice(magic);
+    chunk.extend_from_slice(&[version as u8,
+                              (version >> 8) as u8,
+                              (version >> 16) as u8,
+                              (version >> 24) as u8]);
+    chunk.extend_from_slice(&data);
+    chunk
+}
+
+/// Check the chunk header and return the version and serialized state.
+fn read_header<'a>(magic: &[u8; 4], chunk: &'a [u8]) -> Result<(u32, &'a [u8]), ChunkError> {
+    if chunk.len() < HEADER_LEN || &chunk[..4] != magic {
+        return Err(ChunkError::InvalidHeader);
+    }
+
+    let version = chunk[4] as u32 | (chunk[5] as u32) << 8 | (chunk[6] as u32) << 16 |
+                  (chunk[7] as u32) << 24;
+
+    Ok((version, &chunk[HEADER_LEN..]))
+}
+
+/// Deserialize state of the given version, upgrading it if necessary.
+fn read_state<S: State>(version: u32, data: &[u8]) -> Result<S, ChunkError> {
+    if version == S::version() {
+        Ok(try!(bincode::deserialize(data)))
+    } else if version < S::version() {
+        S::upgrade(version, data).ok_or(ChunkError::UnsupportedVersion(version))
+    } else {
+        Err(ChunkError::UnsupportedVersion(version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chunk::{self, ChunkError, State};
+    use plugin::{Info, Plugin};
+
+    impl State for (f32, String) {}
+
+    /// Version 2 of the state, (gain, mix), which dropped the preset name.
+    impl State for (f32, f32) {
+        fn version() -> u32 { 2 }
+
+        fn upgrade(version: u32, data: &[u8]) -> Option<(f32, f32)> {
+            match version {
+                1 => chunk::deserialize::<(f32, String)>(data).ok().map(|(gain, _)| (gain, 1.0)),
+                _ => None,
+            }
+        }
+    }
+
+    /// Test that a preset survives a round trip.
+    #[test]
+    fn preset() {
+        let state = (0.5, "Preset".to_string());
+        let chunk = chunk::save_preset(&state).unwrap();
+
+        assert_eq!(chunk::load_preset::<(f32, String)>(&chunk).unwrap(), state);
+    }
+
+    /// Test that a bank survives a round trip.
+    #[test]
+    fn bank() {
+        let presets = vec![(0.5, "A".to_string()), (1.0, "B".to_string())];
+        let chunk = chunk::save_bank(&presets).unwrap();
+
+        assert_eq!(chunk::load_bank::<(f32, String)>(&chunk).unwrap(), presets);
+    }
+
+    /// Test that a bank with names survives a round trip.
+    #[test]
+    fn named_bank() {
+        let names = vec!["A".to_string(), "B".to_string()];
+        let presets = vec![(0.5, 0.25), (1.0, 0.5)];
+        let chunk = chunk::save_named_bank(&names, &presets).unwrap();
+
+        assert_eq!(chunk::load_named_bank::<(f32, f32)>(&chunk).unwrap(), (names, presets));
+        assert!(chunk::load_bank::<(f32, f32)>(&chunk).is_err());
+    }
+
+    /// Test that preset and bank chunks can not be mixed up.
+    #[test]
+    fn kind() {
+        let chunk = chunk::save_preset(&(0.5, "Preset".to_string())).unwrap();
+
+        match chunk::load_bank::<(f32, String)>(&chunk) {
+            Err(ChunkError::InvalidHeader) => (),
+            _ => panic!("Expected an invalid header."),
+        }
+    }
+
+    #[derive(Default)]
+    struct StatePlugin {
+        state: (f32, f32),
+    }
+
+    impl Plugin for StatePlugin {
+        fn get_info(&self) -> Info { Info { preset_chunks: true, ..Default::default() } }
+
+        chunk_state!(state);
+    }
+
+    /// Test the chunk methods implemented by `chunk_state!`.
+    #[test]
+    fn plugin() {
+        let mut plugin = StatePlugin { state: (0.5, 0.25) };
+        let preset = plugin.get_preset_data();
+        let bank = plugin.get_bank_data();
+
+        let mut other = StatePlugin::default();
+        other.load_preset_data(preset);
+        assert_eq!(other.state, (0.5, 0.25));
+
+        other = StatePlugin::default();
+        other.load_bank_data(bank);
+        assert_eq!(other.state, (0.5, 0.25));
+
+        other.load_preset_data(vec![1, 2, 3]);
+        assert_eq!(other.state, (0.5, 0.25));
+    }
+
+    /// Test that older versions are upgraded and newer versions are rejected.
+    #[test]
+    fn versions() {
+        let chunk = chunk::save_preset(&(0.5, "Preset".to_string())).unwrap();
+        assert_eq!(chunk::load_preset::<(f32, f32)>(&chunk).unwrap(), (0.5, 1.0));
+
+        let chunk = chunk::save_preset(&(0.5, 0.5)).unwrap();
+        match chunk::load_preset::<(f32, String)>(&chunk) {
+            Err(ChunkError::UnsupportedVersion(2)) => (),
+            _ => panic!("Expected an unsupported version."),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Protection against denormal numbers.
+//!
+//! Values decaying towards zero, e.g. in filter and reverb feedback paths, eventually become
+//! denormal numbers, which many CPUs process up to a hundred times slower than normal ones. A
+//! [`DenormalGuard`](struct.DenormalGuard.html) sets the CPU to flush denormals to zero for as long
+//! as it is alive and restores the previous mode when dropped. This is supported on x86 and x86_64
+//! with SSE (flush-to-zero and denormals-are-zero) and, with the `aarch64-denormals` feature, on
+//! AArch64 (flush-to-zero); elsewhere the guard does nothing.
+//!
+//! Setting `Info::flush_denormals` makes the wrapper hold a guard during every call to
+//! `Plugin::process` and `Plugin::process_f64`. Code running on other threads, e.g. offline
+//! rendering, can create guards itself.
+//!
+//! [`Dither`](struct.Dither.html) is the portable alternative: adding a tiny constant offset to
+//! feedback paths keeps them out of the denormal range.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::denormal::DenormalGuard;
+//!
+//! # #[derive(Default)]
+//! # struct Reverb;
+//! impl Plugin for Reverb {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//!         let _guard = DenormalGuard::new();
+//!         // Denormals are flushed to zero until the end of the function
+//!     }
+//! }
+//! ```
+
+/// Keeps denormals flushed to zero on the current thread while alive. See the module
+/// documentation.
+pub struct DenormalGuard {
+    /// Previous contents of the floating point control register, if it was changed.
+    saved: Option<usize>,
+}
+
+impl DenormalGuard {
+    /// Flush denormals to zero on the current thread until the guard is dropped.
+    pub fn new() -> DenormalGuard {
+        DenormalGuard { saved: arch::enable() }
+    }
+
+    /// Whether denormals are flushed on this architecture.
+    pub fn is_supported() -> bool {
+        arch::SUPPORTED
+    }
+}
+
+impl Default for DenormalGuard {
+    fn default() -> DenormalGuard {
+        DenormalGuard::new()
+    }
+}
+
+impl Drop for DenormalGuard {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved {
+            arch::restore(saved);
+        }
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod arch {
+    // The intrinsics are only named inside the functions allowing their deprecation
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86 as simd;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64 as simd;
+
+    /// Flush-to-zero and denormals-are-zero bits of MXCSR.
+    const FLAGS: u32 = 0x8040;
+
+    pub const SUPPORTED: bool = cfg!(target_feature = "sse");
+
+    // Newer compilers deprecate these in favour of inline assembly, which older ones lack
+    #[allow(deprecated)]
+    fn get() -> u32 {
+        unsafe { simd::_mm_getcsr() }
+    }
+
+    #[allow(deprecated)]
+    fn set(csr: u32) {
+        unsafe { simd::_mm_setcsr(csr) }
+    }
+
+    pub fn enable() -> Option<usize> {
+        if !SUPPORTED {
+            return None;
+        }
+
+        let saved = get();
+        set(saved | FLAGS);
+        Some(saved as usize)
+    }
+
+    pub fn restore(saved: usize) {
+        set(saved as u32);
+    }
+}
+
+// Reading FPCR needs inline assembly, which is only stable since Rust 1.59
+#[cfg(all(target_arch = "aarch64", feature = "aarch64-denormals"))]
+mod arch {
+    use std::arch::asm;
+
+    /// Flush-to-zero bit of FPCR.
+    const FLAGS: u64 = 1 << 24;
+
+    pub const SUPPORTED: bool = true;
+
+    fn get() -> u64 {
+        let fpcr: u64;
+        unsafe { asm!("mrs {}, fpcr", out(reg) fpcr, options(nomem, nostack)) };
+        fpcr
+    }
+
+    fn set(fpcr: u64) {
+        unsafe { asm!("msr fpcr, {}", in(reg) fpcr, options(nomem, nostack)) };
+    }
+
+    pub fn enable() -> Option<usize> {
+        let saved = get();
+        set(saved | FLAGS);
+        Some(saved as usize)
+    }
+
+    pub fn restore(saved: usize) {
+        set(saved as u64);
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64",
+              all(target_arch = "aarch64", feature = "aarch64-denormals"))))]
+mod arch {
+    pub const SUPPORTED: bool = false;
+
+    pub fn enable() -> Option<usize> { None }
+
+    pub fn restore(_: usize) {}
+}
+
+/// Offset added by `Dither`, far below audible levels but far above the denormal range.
+pub const DITHER_OFFSET: f32 = 1e-18;
+
+/// Adds a tiny constant offset to keep signals out of the denormal range.
+///
+/// The offset is a DC offset, so it passes through the lowpass filters and feedback loops it is
+/// meant to protect, where an offset alternating with every sample would be filtered out. It is
+/// far too small to be audible, but a DC blocker in the same path removes it as well.
+#[derive(Clone, Copy, Debug)]
+pub struct Dither {
+    offset: f32,
+}
+
+impl Dither {
+    /// Create a dither adding `DITHER_OFFSET`.
+    pub fn new() -> Dither {
+        Dither { offset: DITHER_OFFSET }
+    }
+
+    /// Add the offset to a single sample.
+    pub fn apply(&self, sample: f32) -> f32 {
+        sample + self.offset
+    }
+
+    /// Add the offset to every sample of a block.
+    pub fn apply_block(&self, samples: &mut [f32]) {
+        for sample in samples {
+            *sample = self.apply(*sample);
+        }
+    }
+}
+
+impl Default for Dither {
+    fn default() -> Dither {
+        Dither::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use denormal::{Dither, DenormalGuard, DITHER_OFFSET};
+
+    /// Multiply without the compiler folding the result.
+    fn multiply(a: f32, b: f32) -> f32 {
+        unsafe { ptr::read_volatile(&a) * ptr::read_volatile(&b) }
+    }
+
+    /// Test that denormals are flushed while the guard is alive and not afterwards.
+    #[test]
+    fn guard() {
+        let denormal = multiply(1e-30, 1e-10);
+        assert!(denormal != 0.0);
+
+        if DenormalGuard::is_supported() {
+            let _guard = DenormalGuard::new();
+            assert_eq!(multiply(1e-30, 1e-10), 0.0);
+        }
+
+        assert_eq!(multiply(1e-30, 1e-10), denormal);
+    }
+
+    /// Test that the dither adds a constant offset which survives a lowpass filter.
+    #[test]
+    fn dither() {
+        let dither = Dither::new();
+        let mut samples = [0.0; 4];
+        dither.apply_block(&mut samples);
+        assert_eq!(samples, [DITHER_OFFSET; 4]);
+
+        // A decaying one pole lowpass settles at the offset instead of becoming denormal
+        let mut state = 1.0f32;
+        for _ in 0..10000 {
+            state = dither.apply(state * 0.5);
+        }
+        assert!(state.is_normal());
+    }
+}
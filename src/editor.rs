@@ -1,6 +1,10 @@
 //! All VST plugin editor related functionality.
 
 use libc::c_void;
+#[cfg(feature = "window-handle")]
+use raw_window_handle::{HasRawWindowHandle, RawWindowHandle};
+
+use api::flags::ModifierKey;
 
 /// Implemented by plugin editors.
 #[allow(unused_variables)]
@@ -12,15 +16,17 @@ pub trait Editor {
     fn position(&self) -> (i32, i32);
 
 
-    /// Editor idle call. Called by host.
+    /// Editor idle call. Called by host regularly while the editor is open, e.g. to repaint
+    /// controls whose values have changed.
     fn idle(&mut self) {}
 
     /// Called when the editor window is closed.
     fn close(&mut self) {}
 
-    /// Called when the editor window is opened. `window` is a platform dependant window pointer
-    /// (e.g. `HWND` on Windows, `WindowRef` on OSX, `Window` on X11/Linux).
-    fn open(&mut self, window: *mut c_void);
+    /// Called when the editor window is opened. The editor should create its view as a child of
+    /// `parent`, which can be passed to any library accepting a `HasRawWindowHandle` with the
+    /// `window-handle` feature.
+    fn open(&mut self, parent: ParentWindow);
 
     /// Return whether the window is currently open.
     fn is_open(&mut self) -> bool;
@@ -31,15 +37,94 @@ pub trait Editor {
     /// Return true if the knob mode was set.
     fn set_knob_mode(&mut self, mode: KnobMode) -> bool { false }
 
-    /// Recieve key up event. Return true if the key was used.
+    /// Receive key up event while the editor has focus. Return true if the key was used.
+    ///
+    /// Keys which are not used are handled by the host.
     fn key_up(&mut self, keycode: KeyCode) -> bool { false }
 
-    /// Receive key down event. Return true if the key was used.
+    /// Receive key down event while the editor has focus. Return true if the key was used.
+    ///
+    /// Keys which are not used are handled by the host.
     fn key_down(&mut self, keycode: KeyCode) -> bool { false }
 }
 
+/// The window provided by the host to open an editor in.
+///
+/// This is an `HWND` on Windows, an `NSView` on macOS and an X11 `Window` on Linux. With the
+/// `window-handle` feature it implements `HasRawWindowHandle` on these platforms, so it can be
+/// handed to windowing and GUI libraries directly.
+#[derive(Clone, Copy, Debug)]
+pub struct ParentWindow {
+    ptr: *mut c_void,
+}
+
+impl ParentWindow {
+    /// Wrap the pointer passed by the host with the `EditorOpen` opcode.
+    #[doc(hidden)]
+    pub fn from_raw(ptr: *mut c_void) -> ParentWindow {
+        ParentWindow { ptr: ptr }
+    }
+
+    /// The platform specific pointer passed by the host. On Linux, this is the X11 window id cast
+    /// to a pointer.
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.ptr
+    }
+}
+
+// VST only defines parent windows on these platforms, elsewhere there is no handle to return.
+#[cfg(all(feature = "window-handle",
+          any(target_os = "windows", target_os = "macos", target_os = "linux",
+              target_os = "dragonfly", target_os = "freebsd", target_os = "netbsd",
+              target_os = "openbsd")))]
+unsafe impl HasRawWindowHandle for ParentWindow {
+    #[cfg(target_os = "windows")]
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        use raw_window_handle::windows::WindowsHandle;
+
+        RawWindowHandle::Windows(WindowsHandle { hwnd: self.ptr, ..WindowsHandle::empty() })
+    }
+
+    #[cfg(target_os = "macos")]
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        use raw_window_handle::macos::MacOSHandle;
+
+        RawWindowHandle::MacOS(MacOSHandle { ns_view: self.ptr, ..MacOSHandle::empty() })
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd",
+              target_os = "netbsd", target_os = "openbsd"))]
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        use raw_window_handle::unix::XlibHandle;
+
+        RawWindowHandle::Xlib(XlibHandle { window: self.ptr as _, ..XlibHandle::empty() })
+    }
+}
+
+/// Get the pointer to pass to a plugin with the `EditorOpen` opcode from a window handle, or
+/// `None` if VST does not support this kind of window on this platform.
+#[cfg(feature = "window-handle")]
+#[doc(hidden)]
+pub fn parent_pointer(handle: RawWindowHandle) -> Option<*mut c_void> {
+    match handle {
+        #[cfg(target_os = "windows")]
+        RawWindowHandle::Windows(handle) => Some(handle.hwnd),
+        #[cfg(target_os = "macos")]
+        RawWindowHandle::MacOS(handle) => Some(handle.ns_view),
+        #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd",
+                  target_os = "netbsd", target_os = "openbsd"))]
+        RawWindowHandle::Xlib(handle) => Some(handle.window as *mut c_void),
+        #[cfg(any(target_os = "linux", target_os = "dragonfly", target_os = "freebsd",
+                  target_os = "netbsd", target_os = "openbsd"))]
+        RawWindowHandle::Xcb(handle) => Some(handle.window as usize as *mut c_void),
+        _ => None,
+    }
+}
+
 /// Rectangle used to specify dimensions of editor window.
 #[doc(hidden)]
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Rect {
     /// Y value in pixels of top side.
     pub top: i16,
@@ -52,13 +137,33 @@ pub struct Rect {
 }
 
 /// A platform independent key code. Includes modifier keys.
+#[derive(Clone, Copy, Debug)]
 pub struct KeyCode {
     /// ASCII character for key pressed (if applicable).
     pub character: char,
-    /// Key pressed. See `enums::Key`.
-    pub key: Key,
-    /// Modifier key bitflags. See `enums::flags::modifier_key`.
-    pub modifier: u8
+    /// Virtual key pressed, if the key is not a plain character. See `Key`.
+    pub key: Option<Key>,
+    /// Modifier keys held down. See `api::flags::ModifierKey`.
+    pub modifier: ModifierKey
+}
+
+impl KeyCode {
+    /// Create a key code from the arguments of the `EditorKeyDown` and `EditorKeyUp` opcodes.
+    ///
+    /// `index` is the ASCII character, `value` the virtual key and `opt` the modifier bitmask
+    /// passed as a float.
+    #[doc(hidden)]
+    pub fn from_raw(index: i32, value: isize, opt: f32) -> KeyCode {
+        KeyCode {
+            character: index as u8 as char,
+            key: if value >= Key::Back as isize && value <= Key::Equals as isize {
+                Some(Key::from(value))
+            } else {
+                None
+            },
+            modifier: ModifierKey::from_bits_truncate(opt as u8),
+        }
+    }
 }
 
 /// Allows host to set how a parameter knob works.
@@ -75,7 +180,7 @@ impl_clike!(KnobMode);
 /// Platform independent key codes.
 #[allow(missing_docs)]
 #[repr(usize)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Key {
 	Back = 1,
 	Tab,
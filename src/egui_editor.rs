@@ -0,0 +1,197 @@
+//! Plugin editors drawn with egui.
+//!
+//! This module is only available with the `egui-editor` feature. An
+//! [`EguiEditor`](struct.EguiEditor.html) implements `editor::Editor` by opening a baseview window
+//! as a child of the window the host passes to `Editor::open` and running an egui context in it
+//! through egui-baseview. The plugin only provides the state the editor works on and a function
+//! drawing the user interface each frame.
+//!
+//! The state is cloned into the window every time the editor is opened, so it should hold handles
+//! to data shared with the plugin, like an `Arc<param_store::ParameterStore>`, rather than the
+//! data itself.
+//!
+//! Hosts call `Editor::idle` regularly while the editor is open, e.g. after automating a
+//! parameter. Every idle call requests a repaint, so the editor follows changes made outside of
+//! it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # extern crate vst2;
+//! # extern crate egui;
+//! # use std::sync::Arc;
+//! # use vst2::editor::Editor;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::egui_editor::EguiEditor;
+//! use vst2::param_store::ParameterStore;
+//! # use vst2::params::{Mapping, Parameter};
+//! # static PARAMETERS: [Parameter; 1] = [
+//! #     Parameter { name: "Gain", label: "", min: 0.0, max: 1.0, default: 0.5,
+//! #                 mapping: Mapping::Linear },
+//! # ];
+//!
+//! struct Gain {
+//!     params: Arc<ParameterStore>,
+//!     editor: EguiEditor<Arc<ParameterStore>>,
+//! }
+//!
+//! impl Default for Gain {
+//!     fn default() -> Gain {
+//!         let params = Arc::new(ParameterStore::new(&PARAMETERS));
+//!         let editor = EguiEditor::new("Gain", (300, 100), params.clone(), |ctx, params| {
+//!             egui::CentralPanel::default().show(ctx, |ui| {
+//!                 let mut value = params.get(0);
+//!                 if ui.add(egui::Slider::new(&mut value, 0.0..=1.0).text("Gain")).changed() {
+//!                     params.set(0, value);
+//!                 }
+//!             });
+//!         });
+//!
+//!         Gain { params: params, editor: editor }
+//!     }
+//! }
+//!
+//! impl Plugin for Gain {
+//!     fn get_info(&self) -> Info {
+//!         Info { parameters: self.params.count(), ..Default::default() }
+//!     }
+//!
+//!     fn get_editor(&mut self) -> Option<&mut Editor> { Some(&mut self.editor) }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use baseview::{Size, WindowHandle, WindowOpenOptions, WindowScalePolicy};
+use egui::CtxRef;
+use egui_baseview::{EguiWindow, RenderSettings, Settings};
+
+use editor::{Editor, ParentWindow};
+
+/// An editor drawing its user interface with egui. See the module documentation.
+pub struct EguiEditor<S> {
+    title: String,
+    size: (i32, i32),
+    state: S,
+    ui: Arc<Fn(&CtxRef, &mut S) + Send + Sync>,
+    repaint: Arc<AtomicBool>,
+    window: Option<WindowHandle>,
+    /// Cleared once the window is destroyed, which the host may do by destroying its parent.
+    window_open: Arc<AtomicBool>,
+}
+
+/// The state of an open window, marking the window as closed once the window drops it.
+struct WindowState<S> {
+    state: S,
+    open: Arc<AtomicBool>,
+}
+
+impl<S> Drop for WindowState<S> {
+    fn drop(&mut self) {
+        self.open.store(false, Ordering::Release);
+    }
+}
+
+impl<S: Clone + Send + 'static> EguiEditor<S> {
+    /// Create an editor of `size` pixels drawn by `ui`, which is called with a clone of `state`
+    /// every frame while the editor is open.
+    ///
+    /// `title` is only shown by hosts which open the editor in a window of its own.
+    pub fn new<F>(title: &str, size: (i32, i32), state: S, ui: F) -> EguiEditor<S>
+        where F: Fn(&CtxRef, &mut S) + Send + Sync + 'static
+    {
+        EguiEditor {
+            title: title.to_string(),
+            size: size,
+            state: state,
+            ui: Arc::new(ui),
+            repaint: Arc::new(AtomicBool::new(false)),
+            window: None,
+            window_open: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Request a repaint from any thread, e.g. after the plugin changed a value shown by the
+    /// editor outside of an idle call.
+    pub fn request_repaint(&self) {
+        self.repaint.store(true, Ordering::Relaxed);
+    }
+}
+
+impl<S: Clone + Send + 'static> Editor for EguiEditor<S> {
+    fn size(&self) -> (i32, i32) {
+        self.size
+    }
+
+    fn position(&self) -> (i32, i32) {
+        (0, 0)
+    }
+
+    fn idle(&mut self) {
+        self.request_repaint();
+    }
+
+    fn close(&mut self) {
+        if let Some(mut window) = self.window.take() {
+            if self.window_open.load(Ordering::Acquire) {
+                window.close();
+            }
+        }
+        self.window_open.store(false, Ordering::Release);
+    }
+
+    fn open(&mut self, parent: ParentWindow) {
+        // Hosts may open the editor again without closing it first
+        self.close();
+
+        let settings = Settings {
+            window: WindowOpenOptions {
+                title: self.title.clone(),
+                size: Size::new(self.size.0 as f64, self.size.1 as f64),
+                scale: WindowScalePolicy::SystemScaleFactor,
+            },
+            render_settings: RenderSettings::default(),
+        };
+
+        // A fresh flag, so a previous window dropping its state late cannot clear it
+        self.window_open = Arc::new(AtomicBool::new(true));
+        let state = WindowState { state: self.state.clone(), open: self.window_open.clone() };
+
+        let ui = self.ui.clone();
+        let repaint = self.repaint.clone();
+        let window = EguiWindow::open_parented(
+            &parent,
+            settings,
+            state,
+            |_, _, _| {},
+            move |ctx, _, state| {
+                if repaint.swap(false, Ordering::Relaxed) {
+                    ctx.request_repaint();
+                }
+                ui(ctx, &mut state.state);
+            },
+        );
+
+        self.window = Some(window);
+    }
+
+    fn is_open(&mut self) -> bool {
+        if !self.window_open.load(Ordering::Acquire) {
+            // The window was destroyed along with its parent
+            self.window = None;
+        }
+        self.window.is_some()
+    }
+}
+
+impl<S> Drop for EguiEditor<S> {
+    fn drop(&mut self) {
+        if let Some(mut window) = self.window.take() {
+            if self.window_open.load(Ordering::Acquire) {
+                window.close();
+            }
+        }
+    }
+}
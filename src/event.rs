@@ -0,0 +1,577 @@
+//! Interfaces to VST events.
+
+use std::{mem, ptr, slice};
+
+use api;
+use midi::MidiMessage;
+
+/// A VST event.
+#[derive(Copy, Clone)]
+pub enum Event<'a> {
+    /// A midi event.
+    ///
+    /// These are sent to the plugin before `Plugin::process()` or `Plugin::process_f64()` is
+    /// called.
+    Midi(MidiEvent),
+
+    /// A system exclusive event.
+    ///
+    /// This is just a block of data and it is up to the plugin to interpret this. Generally used
+    /// by midi controllers.
+    SysEx(SysExEvent<'a>),
+
+    /// An event type which is not supported by this crate.
+    ///
+    /// The raw event header is kept so that it can still be inspected.
+    Deprecated(api::Event),
+}
+
+/// A midi event.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MidiEvent {
+    /// The raw midi data associated with this event.
+    pub data: [u8; 3],
+
+    /// Number of samples into the current processing block that this event occurs on.
+    ///
+    /// E.g. if the block size is 512 and this value is 123, the event will occur on sample
+    /// `samples[123]`.
+    pub delta_frames: i32,
+
+    /// Whether the event is played live rather than played back from a sequencer track.
+    ///
+    /// Live events should be processed with the lowest possible latency.
+    pub live: bool,
+
+    /// Length of the whole note in samples, if known.
+    pub note_length: Option<i32>,
+
+    /// Offset in samples into the note from its start, if known. Set when playback starts in the
+    /// middle of a note.
+    pub note_offset: Option<i32>,
+
+    /// Detuning between -63 and +64 cents, for scales other than 'well-tempered', e.g.
+    /// 'microtuning'.
+    pub detune: i8,
+
+    /// Note off velocity between 0 and 127.
+    pub note_off_velocity: u8,
+}
+
+/// A midi system exclusive event.
+#[derive(Copy, Clone, Debug)]
+pub struct SysExEvent<'a> {
+    /// The SysEx payload.
+    pub payload: &'a [u8],
+
+    /// Number of samples into the current processing block that this event occurs on.
+    ///
+    /// E.g. if the block size is 512 and this value is 123, the event will occur on sample
+    /// `samples[123]`.
+    pub delta_frames: i32,
+}
+
+impl MidiEvent {
+    /// Create an event sending `message` at `delta_frames` samples into the block.
+    pub fn from_message(message: MidiMessage, delta_frames: i32) -> MidiEvent {
+        MidiEvent {
+            data: message.to_bytes(),
+            delta_frames: delta_frames,
+            ..Default::default()
+        }
+    }
+
+    /// Create a note on event. Channels are numbered from 0 to 15.
+    pub fn note_on(channel: u8, note: u8, velocity: u8, delta_frames: i32) -> MidiEvent {
+        let message = MidiMessage::NoteOn { channel: channel, note: note, velocity: velocity };
+        MidiEvent::from_message(message, delta_frames)
+    }
+
+    /// Create a note off event. Channels are numbered from 0 to 15.
+    pub fn note_off(channel: u8, note: u8, velocity: u8, delta_frames: i32) -> MidiEvent {
+        let message = MidiMessage::NoteOff { channel: channel, note: note, velocity: velocity };
+        MidiEvent::from_message(message, delta_frames)
+    }
+
+    /// Create a control change event. Channels are numbered from 0 to 15.
+    pub fn cc(channel: u8, controller: u8, value: u8, delta_frames: i32) -> MidiEvent {
+        let message = MidiMessage::ControlChange {
+            channel: channel,
+            controller: controller,
+            value: value,
+        };
+        MidiEvent::from_message(message, delta_frames)
+    }
+
+    /// Create a program change event. Channels are numbered from 0 to 15.
+    pub fn program_change(channel: u8, program: u8, delta_frames: i32) -> MidiEvent {
+        let message = MidiMessage::ProgramChange { channel: channel, program: program };
+        MidiEvent::from_message(message, delta_frames)
+    }
+
+    /// Create a pitch bend event with a value between -8192 and 8191, 0 being the center.
+    /// Channels are numbered from 0 to 15.
+    pub fn pitch_bend(channel: u8, value: i16, delta_frames: i32) -> MidiEvent {
+        let message = MidiMessage::PitchBend { channel: channel, value: value };
+        MidiEvent::from_message(message, delta_frames)
+    }
+
+    /// Decode the raw data of this event.
+    ///
+    /// Data without a status byte is returned as `MidiMessage::Unknown`.
+    pub fn message(&self) -> MidiMessage {
+        MidiMessage::from_bytes(&self.data).unwrap_or(MidiMessage::Unknown(self.data))
+    }
+}
+
+impl<'a> Event<'a> {
+    /// Create a safe `Event` from a raw `api::Event` pointer.
+    ///
+    /// The pointer must point to a valid event of the type given in its header, which must stay
+    /// valid for the lifetime `'a`. Returns `None` for null pointers, unknown event types and
+    /// SysEx events without a valid payload.
+    pub unsafe fn from_raw(event: *const api::Event) -> Option<Event<'a>> {
+        use api::EventType::*;
+
+        if event.is_null() {
+            return None;
+        }
+
+        Some(match api::EventType::from_raw((*event).event_type) {
+            Some(Midi) => {
+                let event = &*(event as *const api::MidiEvent);
+
+                let known = |value: i32| if value > 0 { Some(value) } else { None };
+
+                Event::Midi(MidiEvent {
+                    data: event.midi_data,
+                    delta_frames: event.delta_frames,
+                    live: api::flags::MidiEvent::from_bits_truncate(event.flags)
+                        .contains(api::flags::REALTIME_EVENT),
+                    note_length: known(event.note_length),
+                    note_offset: known(event.note_offset),
+                    detune: event.detune,
+                    note_off_velocity: event.note_off_velocity,
+                })
+            }
+
+            Some(SysEx) => {
+                let event = &*(event as *const api::SysExEvent);
+
+                if event.system_data.is_null() || event.data_size < 0 {
+                    return None;
+                }
+
+                Event::SysEx(SysExEvent {
+                    payload: slice::from_raw_parts(event.system_data, event.data_size as usize),
+                    delta_frames: event.delta_frames,
+                })
+            }
+
+            Some(_) => Event::Deprecated(*event),
+            None => return None,
+        })
+    }
+
+    /// Number of samples into the current processing block that this event occurs on.
+    pub fn delta_frames(&self) -> i32 {
+        match *self {
+            Event::Midi(ref event) => event.delta_frames,
+            Event::SysEx(ref event) => event.delta_frames,
+            Event::Deprecated(ref event) => event.delta_frames,
+        }
+    }
+
+    /// Translate all events of a raw `api::Events` block, skipping events `from_raw` rejects.
+    ///
+    /// The block and the events it points to must stay valid for the lifetime `'a`.
+    pub unsafe fn from_raw_events(events: *const api::Events) -> Vec<Event<'a>> {
+        if events.is_null() {
+            return Vec::new();
+        }
+
+        (*events).events_raw().iter().filter_map(|e| Event::from_raw(*e)).collect()
+    }
+
+    /// Translate all events of a raw `api::Events` block into `events`, replacing its contents.
+    /// Events `from_raw` rejects are skipped.
+    ///
+    /// This only allocates if `events` can not hold all events yet. The block and the events it
+    /// points to must stay valid for the lifetime `'a`.
+    pub unsafe fn read_raw_events(raw: *const api::Events, events: &mut Vec<Event<'a>>) {
+        events.clear();
+        if !raw.is_null() {
+            events.extend((*raw).events_raw().iter().filter_map(|e| Event::from_raw(*e)));
+        }
+    }
+}
+
+/// Sort events by their delta frames, keeping the order of events on the same sample.
+///
+/// Hosts are not required to send events in order. This is an insertion sort, which never
+/// allocates and only passes over events which are already sorted, as most are, once.
+pub fn sort_by_time(events: &mut [Event]) {
+    for i in 1..events.len() {
+        let mut j = i;
+        while j > 0 && events[j - 1].delta_frames() > events[j].delta_frames() {
+            events.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+impl From<MidiEvent> for api::MidiEvent {
+    fn from(event: MidiEvent) -> api::MidiEvent {
+        api::MidiEvent {
+            event_type: api::EventType::Midi as i32,
+            byte_size: mem::size_of::<api::MidiEvent>() as i32,
+            delta_frames: event.delta_frames,
+            flags: if event.live { api::flags::REALTIME_EVENT.bits() } else { 0 },
+            note_length: event.note_length.unwrap_or(0),
+            note_offset: event.note_offset.unwrap_or(0),
+            midi_data: event.data,
+            _midi_reserved: 0,
+            detune: event.detune,
+            note_off_velocity: event.note_off_velocity,
+            _reserved1: 0,
+            _reserved2: 0,
+        }
+    }
+}
+
+/// The raw event points into the payload of the `SysExEvent`, so it must not outlive it.
+impl<'a> From<SysExEvent<'a>> for api::SysExEvent {
+    fn from(event: SysExEvent<'a>) -> api::SysExEvent {
+        api::SysExEvent {
+            event_type: api::EventType::SysEx as i32,
+            byte_size: mem::size_of::<api::SysExEvent>() as i32,
+            delta_frames: event.delta_frames,
+            _flags: 0,
+            data_size: event.payload.len() as i32,
+            _reserved1: 0,
+            system_data: event.payload.as_ptr() as *mut u8,
+            _reserved2: 0,
+        }
+    }
+}
+
+/// A raw event stored in a `SendEventBuffer`.
+enum StoredEvent {
+    Midi(api::MidiEvent),
+
+    /// SysEx event with the offset of its payload in the buffer's payload storage.
+    SysEx(api::SysExEvent, usize),
+}
+
+/// A reusable buffer for sending events to the host or to a loaded plugin without allocating.
+///
+/// All memory is allocated up front in `new`, so events can be pushed and sent during
+/// `process()`. The events are laid out as a contiguous `api::Events` block, as expected by the
+/// VST API.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::buffer::AudioBuffer;
+/// # use vst2::event::{Event, MidiEvent, SendEventBuffer};
+/// # use vst2::plugin::{HostCallback, Info, Plugin};
+/// struct Arpeggiator {
+///     host: HostCallback,
+///     send_buffer: SendEventBuffer,
+/// }
+///
+/// impl Plugin for Arpeggiator {
+///     fn get_info(&self) -> Info { Default::default() }
+///
+///     fn process(&mut self, buffer: AudioBuffer<f32>) {
+///         let note = MidiEvent { data: [0x90, 60, 100], delta_frames: 0, ..Default::default() };
+///         self.send_buffer.push(Event::Midi(note));
+///
+///         self.host.send_events(&mut self.send_buffer);
+///     }
+/// }
+/// ```
+pub struct SendEventBuffer {
+    events: Vec<StoredEvent>,
+    sysex_data: Vec<u8>,
+
+    // The requested capacities, as the vectors may have allocated more than the block holds
+    capacity: usize,
+    sysex_capacity: usize,
+
+    // Extra elements only provide the space for event pointers past the first 2
+    block: Vec<api::Events>,
+}
+
+// The raw pointers only ever point into the buffer's own storage.
+unsafe impl Send for SendEventBuffer {}
+
+impl SendEventBuffer {
+    /// Create a buffer with room for `capacity` events, whose SysEx payloads may take up to
+    /// `sysex_capacity` bytes in total.
+    pub fn new(capacity: usize, sysex_capacity: usize) -> SendEventBuffer {
+        let per_element = mem::size_of::<api::Events>() / mem::size_of::<*mut api::Event>();
+        let extra = capacity.saturating_sub(2);
+
+        SendEventBuffer {
+            events: Vec::with_capacity(capacity),
+            sysex_data: Vec::with_capacity(sysex_capacity),
+            capacity: capacity,
+            sysex_capacity: sysex_capacity,
+            block: (0..1 + (extra + per_element - 1) / per_element).map(|_| {
+                api::Events {
+                    num_events: 0,
+                    _reserved: 0,
+                    events: [ptr::null_mut(); 2],
+                }
+            }).collect(),
+        }
+    }
+
+    /// Number of events in the buffer.
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether the buffer holds no events.
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Remove all events.
+    pub fn clear(&mut self) {
+        self.events.clear();
+        self.sysex_data.clear();
+    }
+
+    /// Add an event, copying the payload of SysEx events.
+    ///
+    /// Returns `false` without adding the event if the buffer is full, or if the event is an
+    /// `Event::Deprecated` event.
+    pub fn push(&mut self, event: Event) -> bool {
+        if self.events.len() >= self.capacity {
+            return false;
+        }
+
+        match event {
+            Event::Midi(event) => self.events.push(StoredEvent::Midi(event.into())),
+            Event::SysEx(event) => {
+                let offset = self.sysex_data.len();
+                if offset + event.payload.len() > self.sysex_capacity {
+                    return false;
+                }
+
+                self.sysex_data.extend_from_slice(event.payload);
+                self.events.push(StoredEvent::SysEx(event.into(), offset));
+            }
+            Event::Deprecated(_) => return false,
+        }
+        true
+    }
+
+    /// Lay out the events as a raw `api::Events` block.
+    ///
+    /// The pointer stays valid until the buffer is changed or dropped.
+    pub fn as_raw(&mut self) -> *mut api::Events {
+        let data = self.sysex_data.as_mut_ptr();
+        let block = self.block.as_mut_ptr();
+
+        unsafe {
+            (*block).num_events = self.events.len() as i32;
+
+            // Derived from the whole block, as the pointers past the first 2 are stored past the
+            // end of the inline array
+            let offset = &(*block).events as *const _ as usize - block as usize;
+            let pointers = (block as *mut u8).offset(offset as isize) as *mut *mut api::Event;
+
+            for (i, event) in self.events.iter_mut().enumerate() {
+                let raw = match *event {
+                    StoredEvent::Midi(ref mut event) => event as *mut _ as *mut api::Event,
+                    StoredEvent::SysEx(ref mut event, offset) => {
+                        event.system_data = data.offset(offset as isize);
+                        event as *mut _ as *mut api::Event
+                    }
+                };
+                *pointers.offset(i as isize) = raw;
+            }
+        }
+
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{mem, ptr};
+
+    use api;
+    use event::{self, Event, MidiEvent, SendEventBuffer, SysExEvent};
+    use midi::MidiMessage;
+
+    /// Test that raw events are translated into their safe counterparts.
+    #[test]
+    fn from_raw() {
+        let mut payload = vec![0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7];
+        let mut sysex = api::SysExEvent {
+            event_type: api::EventType::SysEx as i32,
+            byte_size: mem::size_of::<api::SysExEvent>() as i32,
+            delta_frames: 7,
+            _flags: 0,
+            data_size: payload.len() as i32,
+            _reserved1: 0,
+            system_data: payload.as_mut_ptr(),
+            _reserved2: 0,
+        };
+
+        let mut midi = api::MidiEvent {
+            event_type: api::EventType::Midi as i32,
+            byte_size: mem::size_of::<api::MidiEvent>() as i32,
+            delta_frames: 123,
+            flags: 1,
+            note_length: 4410,
+            note_offset: 0,
+            midi_data: [0x90, 60, 127],
+            _midi_reserved: 0,
+            detune: -20,
+            note_off_velocity: 64,
+            _reserved1: 0,
+            _reserved2: 0,
+        };
+
+        let events = api::Events {
+            num_events: 2,
+            _reserved: 0,
+            events: [&mut midi as *mut _ as *mut api::Event,
+                     &mut sysex as *mut _ as *mut api::Event],
+        };
+
+        let events = unsafe { Event::from_raw_events(&events) };
+
+        assert_eq!(events.len(), 2);
+        match events[0] {
+            Event::Midi(midi) => {
+                assert_eq!(midi.data, [0x90, 60, 127]);
+                assert_eq!(midi.delta_frames, 123);
+                assert!(midi.live);
+                assert_eq!((midi.note_length, midi.note_offset), (Some(4410), None));
+                assert_eq!((midi.detune, midi.note_off_velocity), (-20, 64));
+                assert_eq!(midi.message(),
+                           MidiMessage::NoteOn { channel: 0, note: 60, velocity: 127 });
+
+                // Assert that no fields are lost when converting back.
+                let raw = api::MidiEvent::from(midi);
+                let event = unsafe { Event::from_raw(&raw as *const _ as *const api::Event) };
+                match event {
+                    Some(Event::Midi(event)) => assert_eq!(event, midi),
+                    _ => panic!("Expected a midi event."),
+                }
+            }
+            _ => panic!("Expected a midi event."),
+        }
+        match events[1] {
+            Event::SysEx(sysex) => {
+                assert_eq!(sysex.payload, &[0xF0, 0x7E, 0x7F, 0x06, 0x01, 0xF7]);
+                assert_eq!(sysex.delta_frames, 7);
+            }
+            _ => panic!("Expected a sysex event."),
+        }
+    }
+
+    /// Test that unknown event types, invalid SysEx payloads and null pointers are skipped.
+    #[test]
+    fn from_raw_invalid() {
+        let mut payload = [0xF0, 0xF7];
+        let mut sysex = api::SysExEvent {
+            event_type: api::EventType::SysEx as i32,
+            byte_size: mem::size_of::<api::SysExEvent>() as i32,
+            delta_frames: 0,
+            _flags: 0,
+            data_size: -1,
+            _reserved1: 0,
+            system_data: payload.as_mut_ptr(),
+            _reserved2: 0,
+        };
+        let mut unknown = api::MidiEvent::from(MidiEvent::default());
+        unknown.event_type = 1000;
+
+        let events = api::Events {
+            num_events: 2,
+            _reserved: 0,
+            events: [&mut sysex as *mut _ as *mut api::Event,
+                     &mut unknown as *mut _ as *mut api::Event],
+        };
+        assert_eq!(unsafe { Event::from_raw_events(&events) }.len(), 0);
+
+        sysex.system_data = ptr::null_mut();
+        sysex.data_size = 2;
+        let raw = &sysex as *const _ as *const api::Event;
+        assert!(unsafe { Event::from_raw(raw) }.is_none());
+        assert!(unsafe { Event::from_raw(ptr::null()) }.is_none());
+    }
+
+    /// Test that buffered events are laid out as a raw block and respect the capacities.
+    #[test]
+    fn send_event_buffer() {
+        let mut buffer = SendEventBuffer::new(3, 4);
+
+        let payload = [0xF0, 0x01, 0xF7];
+        for i in 0..2 {
+            let midi = MidiEvent {
+                data: [0x90, 60 + i, 100],
+                delta_frames: i as i32,
+                ..Default::default()
+            };
+            assert!(buffer.push(Event::Midi(midi)));
+        }
+        assert!(!buffer.push(Event::SysEx(SysExEvent { payload: &[0; 5], delta_frames: 0 })));
+        assert!(buffer.push(Event::SysEx(SysExEvent { payload: &payload, delta_frames: 2 })));
+        assert!(!buffer.push(Event::Midi(MidiEvent::default())));
+
+        let events = unsafe { Event::from_raw_events(buffer.as_raw()) };
+        assert_eq!(events.len(), 3);
+        match events[1] {
+            Event::Midi(midi) => assert_eq!((midi.data[1], midi.delta_frames), (61, 1)),
+            _ => panic!("Expected a midi event."),
+        }
+        match events[2] {
+            Event::SysEx(sysex) => assert_eq!(sysex.payload, &payload),
+            _ => panic!("Expected a sysex event."),
+        }
+
+        buffer.clear();
+        assert!(buffer.is_empty());
+        assert_eq!(unsafe { Event::from_raw_events(buffer.as_raw()) }.len(), 0);
+    }
+
+    /// Test that events are sorted by time, keeping the order of simultaneous events.
+    #[test]
+    fn sort_by_time() {
+        let note = |note: u8, delta_frames: i32| {
+            Event::Midi(MidiEvent {
+                data: [0x90, note, 100],
+                delta_frames: delta_frames,
+                ..Default::default()
+            })
+        };
+        let mut events = vec![note(1, 10), note(2, 0), note(3, 10), note(4, 5)];
+
+        event::sort_by_time(&mut events);
+
+        let notes: Vec<u8> = events.iter().map(|e| match *e {
+            Event::Midi(midi) => midi.data[1],
+            _ => 0,
+        }).collect();
+        assert_eq!(notes, vec![2, 4, 1, 3]);
+    }
+
+    /// Test that the constructors encode the expected bytes.
+    #[test]
+    fn midi_constructors() {
+        assert_eq!(MidiEvent::note_on(1, 60, 100, 5).data, [0x91, 60, 100]);
+        assert_eq!(MidiEvent::note_on(1, 60, 100, 5).delta_frames, 5);
+        assert_eq!(MidiEvent::note_off(0, 60, 0, 0).data, [0x80, 60, 0]);
+        assert_eq!(MidiEvent::cc(15, 7, 127, 0).data, [0xBF, 7, 127]);
+        assert_eq!(MidiEvent::program_change(2, 10, 0).data, [0xC2, 10, 0]);
+        assert_eq!(MidiEvent::pitch_bend(0, 0, 0).data, [0xE0, 0x00, 0x40]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Fuzzing the plugin side opcode handling.
+//!
+//! This module is only available with the `fuzz` feature. [`dispatch`](fn.dispatch.html) creates a
+//! plugin through the exported entry point and decodes arbitrary bytes into a sequence of
+//! dispatcher calls with arbitrary opcodes, indices, values, `opt` values and pointer payloads.
+//! Run it from a fuzzer such as `cargo fuzz`, ideally with a sanitizer, to find panics and memory
+//! errors in the wrapper and in a plugin's opcode handlers. The `fuzz` directory of this
+//! repository contains a ready-made target, and `fuzz/regressions` inputs which previously
+//! reached undefined behaviour. Pass that directory as a corpus to start from them.
+//!
+//! Opcodes whose pointers must reference host owned structures containing further pointers,
+//! i.e. `EditorOpen`, `ProcessVarIo`, the offline opcodes and `VendorSpecific`, are skipped, as no
+//! host could send arbitrary data with them. `Shutdown` is only sent at the end.
+//!
+//! The payload of `ProcessEvents` is read as up to two raw events, each taking up the size of an
+//! `api::SysExEvent`. Non-null SysEx payload pointers are pointed at a second buffer and their
+//! size is limited to it. The channel count of speaker arrangements is limited to the speakers
+//! fitting into the buffer. Event types, arrangement types and negative counts are left as they
+//! are, since hosts may send any of them.
+//!
+//! Every call is encoded as:
+//!
+//! | Bytes | Content                                                                       |
+//! |-------|-------------------------------------------------------------------------------|
+//! | 1     | Opcode                                                                        |
+//! | 4     | `index`, little endian                                                        |
+//! | 4     | `value`, little endian                                                        |
+//! | 4     | `opt`, little endian `f32` bits                                               |
+//! | 1     | Payload length `n`                                                            |
+//! | `n`   | Payload copied to the start of the zero padded buffer `ptr` points to         |
+//!
+//! Missing trailing bytes are read as zeros.
+
+use std::{cmp, mem, ptr, slice};
+
+use libc::c_void;
+
+use api::{self, AEffect};
+use host::Host;
+use interfaces;
+use plugin::{OpCode, Plugin};
+
+/// Size of the buffers passed as pointers, large enough for every structure an opcode exchanges.
+const PAYLOAD_LEN: usize = 4096;
+
+/// Opcodes which are never sent, see the module documentation.
+const SKIPPED: [OpCode; 7] = [OpCode::Shutdown, OpCode::EditorOpen, OpCode::ProcessVarIo,
+                              OpCode::OfflineNotify, OpCode::OfflinePrepare, OpCode::OfflineRun,
+                              OpCode::VendorSpecific];
+
+/// Host answering the plugin's requests with defaults.
+struct FuzzHost;
+
+impl Host for FuzzHost {}
+
+fn fuzz_callback(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr: *mut c_void,
+                 opt: f32) -> isize {
+    interfaces::host_dispatch(&mut FuzzHost, effect, opcode, index, value, ptr, opt)
+}
+
+/// Reads the encoded calls.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> &'a [u8] {
+        let len = cmp::min(len, self.data.len());
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.bytes(1).first().cloned().unwrap_or(0)
+    }
+
+    fn u32(&mut self) -> u32 {
+        let mut value = 0;
+        for (i, &byte) in self.bytes(4).iter().enumerate() {
+            value |= (byte as u32) << (8 * i);
+        }
+        value
+    }
+}
+
+/// A zeroed buffer of `PAYLOAD_LEN` bytes, aligned for every structure an opcode exchanges.
+fn buffer() -> Vec<u64> {
+    vec![0; PAYLOAD_LEN / mem::size_of::<u64>()]
+}
+
+fn bytes_mut(buffer: &mut [u64]) -> &mut [u8] {
+    unsafe {
+        slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8,
+                                  buffer.len() * mem::size_of::<u64>())
+    }
+}
+
+/// Limit the channel count of the speaker arrangement in `buffer` to the speakers it can hold.
+fn limit_speakers(buffer: &mut [u64]) {
+    let max = PAYLOAD_LEN / mem::size_of::<api::SpeakerProperties>() - 1;
+    let arrangement = buffer.as_mut_ptr() as *mut api::SpeakerArrangement;
+
+    unsafe {
+        (*arrangement).num_channels = cmp::min((*arrangement).num_channels, max as i32);
+    }
+}
+
+/// Read up to two raw events from `bytes`, pointing SysEx payloads into `sysex`.
+fn read_events(bytes: &[u8], events: &mut [api::SysExEvent; 2], sysex: &mut [u64]) -> usize {
+    let record = mem::size_of::<api::SysExEvent>();
+    let count = cmp::min(bytes.len() / record, events.len());
+
+    for (i, event) in events.iter_mut().enumerate().take(count) {
+        unsafe {
+            ptr::copy_nonoverlapping(bytes[i * record..].as_ptr(),
+                                     event as *mut _ as *mut u8,
+                                     record);
+        }
+
+        if event.event_type == api::EventType::SysEx as i32 && !event.system_data.is_null() {
+            event.system_data = sysex.as_mut_ptr() as *mut u8;
+            event.data_size = cmp::min(event.data_size, PAYLOAD_LEN as i32);
+        }
+    }
+    count
+}
+
+/// Create plugin `P` and dispatch the calls encoded in `data`, then shut it down.
+///
+/// Panics and memory errors are left to the fuzzer to report.
+pub fn dispatch<P: Plugin + Default>(data: &[u8]) {
+    let effect = ::main::<P>(fuzz_callback);
+    if effect.is_null() {
+        return;
+    }
+
+    let mut reader = Reader { data: data };
+    let mut payload = buffer();
+    let mut second = buffer();
+    let mut events: [api::SysExEvent; 2] = unsafe { mem::zeroed() };
+
+    while !reader.data.is_empty() {
+        let opcode = reader.u8() as i32;
+        let index = reader.u32() as i32;
+        let mut value = reader.u32() as i32 as isize;
+        let opt = f32::from_bits(reader.u32());
+        let len = reader.u8() as usize;
+        let bytes = reader.bytes(len);
+
+        for byte in bytes_mut(&mut payload).iter_mut() {
+            *byte = 0;
+        }
+        bytes_mut(&mut payload)[..bytes.len()].copy_from_slice(bytes);
+        second.copy_from_slice(&payload);
+
+        if SKIPPED.iter().any(|&skipped| skipped as i32 == opcode) {
+            continue;
+        }
+
+        let mut ptr = payload.as_mut_ptr() as *mut c_void;
+        let mut block: api::Events = unsafe { mem::zeroed() };
+
+        if opcode == OpCode::SetData as i32 {
+            // The value is the length of the chunk at `ptr`
+            value = cmp::max(0, cmp::min(value, len as isize));
+        } else if opcode == OpCode::SetSpeakerArrangement as i32 ||
+                  opcode == OpCode::GetSpeakerArrangement as i32 {
+            // The value is a pointer to the input arrangement
+            limit_speakers(&mut payload);
+            limit_speakers(&mut second);
+            value = second.as_mut_ptr() as isize;
+        } else if opcode == OpCode::ProcessEvents as i32 {
+            let num_events = read_events(bytes, &mut events, &mut second);
+            let raw = events.as_mut_ptr();
+
+            block.num_events = num_events as i32;
+            block.events = unsafe { [raw as *mut api::Event, raw.offset(1) as *mut api::Event] };
+            ptr = &mut block as *mut _ as *mut c_void;
+        }
+
+        unsafe {
+            ((*effect).dispatcher)(effect, opcode, index, value, ptr, opt);
+        }
+    }
+
+    unsafe {
+        ((*effect).dispatcher)(effect, OpCode::Shutdown.into(), 0, 0, ptr::null_mut(), 0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fuzz;
+    use plugin::{Info, Plugin};
+
+    #[derive(Default)]
+    struct FuzzPlugin;
+
+    impl Plugin for FuzzPlugin {
+        fn get_info(&self) -> Info {
+            Info { parameters: 2, presets: 2, ..Default::default() }
+        }
+    }
+
+    /// Test every opcode and some unknown ones with zero, small and large arguments.
+    #[test]
+    fn opcodes() {
+        for opcode in 0..100u8 {
+            for &argument in &[0u8, 1, 0xFF] {
+                let mut data = vec![opcode];
+                data.extend_from_slice(&[argument; 12]);
+                data.push(4);
+                data.extend_from_slice(b"ab\0c");
+
+                fuzz::dispatch::<FuzzPlugin>(&data);
+            }
+        }
+    }
+
+    /// Test the inputs which previously reached undefined behaviour with invalid speaker
+    /// arrangements and events.
+    #[test]
+    fn regressions() {
+        let inputs: [&[u8]; 4] = [
+            include_bytes!("../fuzz/regressions/dispatch/speaker_arrangement_large_count"),
+            include_bytes!("../fuzz/regressions/dispatch/speaker_arrangement_negative_count"),
+            include_bytes!("../fuzz/regressions/dispatch/events_unknown_type_negative_sysex"),
+            include_bytes!("../fuzz/regressions/dispatch/events_sysex_size"),
+        ];
+
+        for input in inputs.iter() {
+            fuzz::dispatch::<FuzzPlugin>(input);
+        }
+    }
+}
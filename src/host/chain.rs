@@ -0,0 +1,801 @@
+//! Processing audio through several plugins in series.
+
+use std::{cmp, mem};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use api;
+use buffer::{AudioBuffer, BufferStorage};
+use host::{Host, PluginLoader, PluginLoadError, SharedTimeInfo, Transport};
+use mix::MixMatrix;
+use plugin::Plugin;
+
+/// The host of plugins loaded by a [`PluginChain`](struct.PluginChain.html).
+///
+/// It reports the sample rate, block size and transport of the chain to its plugins, and lets the
+/// chain know when the latency of a plugin may have changed.
+pub struct ChainHost {
+    sample_rate: f32,
+    block_size: i64,
+    /// Set by the chain for every block, without taking the lock of the host.
+    time_info: Arc<SharedTimeInfo>,
+    /// Set when a plugin reports an I/O change, checked by the chain without taking the lock.
+    io_changed: Arc<AtomicBool>,
+}
+
+impl Host for ChainHost {
+    fn get_sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+
+    fn get_block_size(&self) -> i64 {
+        self.block_size
+    }
+
+    fn get_time_info(&self, _: i32) -> Option<api::TimeInfo> {
+        Some(self.time_info.get())
+    }
+
+    fn io_changed(&mut self) -> bool {
+        self.io_changed.store(true, Ordering::Release);
+        true
+    }
+}
+
+/// Make sure `channels` holds at least `count` channels with room for `block_size` samples each.
+fn reserve_channels(channels: &mut Vec<Vec<f32>>, count: usize, block_size: usize) {
+    while channels.len() < count {
+        channels.push(Vec::new());
+    }
+    for channel in channels {
+        let additional = block_size.saturating_sub(channel.len());
+        channel.reserve(additional);
+    }
+}
+
+/// Delays channels by a fixed number of samples.
+struct Delay {
+    samples: usize,
+    lines: Vec<Vec<f32>>,
+    pos: usize,
+    outputs: Vec<Vec<f32>>,
+}
+
+impl Delay {
+    fn new() -> Delay {
+        Delay { samples: 0, lines: Vec::new(), pos: 0, outputs: Vec::new() }
+    }
+
+    /// Change the delay, discarding the delayed samples.
+    fn set(&mut self, samples: usize) {
+        if samples != self.samples {
+            self.samples = samples;
+            self.lines.clear();
+            self.pos = 0;
+        }
+    }
+
+    /// Make room for delaying `channels` channels in blocks of up to `block_size` samples.
+    fn reserve(&mut self, channels: usize, block_size: usize) {
+        while self.lines.len() < channels {
+            self.lines.push(vec![0.0; self.samples]);
+        }
+        reserve_channels(&mut self.outputs, channels, block_size);
+    }
+
+    /// Delay `source`, returning the delayed channels.
+    ///
+    /// This does not allocate once `reserve` made room for the channels of `source`.
+    fn process<'a>(&'a mut self, source: &'a [Vec<f32>]) -> &'a [Vec<f32>] {
+        if self.samples == 0 {
+            return source;
+        }
+
+        let len = source.iter().map(|c| c.len()).min().unwrap_or(0);
+        let block_size = cmp::max(len, self.outputs.first().map_or(0, |c| c.capacity()));
+        self.reserve(source.len(), block_size);
+
+        for ((input, line), output) in source.iter().zip(&mut self.lines).zip(&mut self.outputs) {
+            output.clear();
+            output.resize(len, 0.0);
+
+            let mut pos = self.pos;
+            for (x, y) in input[..len].iter().zip(output.iter_mut()) {
+                *y = mem::replace(&mut line[pos], *x);
+                pos = (pos + 1) % self.samples;
+            }
+        }
+        self.pos = (self.pos + len) % self.samples;
+
+        &self.outputs[..source.len()]
+    }
+}
+
+/// A parallel path mixing the outputs of an earlier plugin into a later point of the chain.
+struct Branch {
+    /// Index of the plugin whose outputs are mixed in, or `None` for the chain inputs.
+    source: Option<usize>,
+    connection: MixMatrix,
+
+    /// Compensates the latency of the plugins between the source and the destination.
+    delay: Delay,
+    mixed: Vec<Vec<f32>>,
+}
+
+impl Branch {
+    /// Add the delayed `source` to `outputs` through the connection.
+    fn add_to(&mut self, source: &[Vec<f32>], outputs: &mut [Vec<f32>]) {
+        let len = outputs.iter().map(|c| c.len()).min().unwrap_or(0);
+        reserve_channels(&mut self.mixed, outputs.len(), len);
+        for channel in &mut self.mixed {
+            channel.clear();
+            channel.resize(len, 0.0);
+        }
+
+        let delayed = self.delay.process(source);
+        self.connection.apply(delayed, &mut self.mixed[..outputs.len()]);
+
+        for (output, mixed) in outputs.iter_mut().zip(self.mixed.iter()) {
+            for (y, x) in output.iter_mut().zip(mixed.iter()) {
+                *y += *x;
+            }
+        }
+    }
+}
+
+/// Remove the branches from the plugin at `removed` and renumber the sources after it.
+fn remove_source(branches: &mut Vec<Branch>, removed: usize) {
+    branches.retain(|branch| branch.source != Some(removed));
+    for branch in branches {
+        if let Some(ref mut source) = branch.source {
+            if *source > removed {
+                *source -= 1;
+            }
+        }
+    }
+}
+
+/// A plugin of the chain with its buffers.
+struct Node {
+    plugin: Box<Plugin>,
+
+    /// Mixes the outputs of the previous node, or the chain inputs, into `inputs`.
+    connection: MixMatrix,
+    /// Parallel paths mixed into `inputs` in addition to `connection`.
+    branches: Vec<Branch>,
+
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+
+    /// Pointers to `inputs` and `outputs`, and the tables of the buffer passed to the plugin.
+    input_ptrs: Vec<*mut f32>,
+    output_ptrs: Vec<*mut f32>,
+    storage: BufferStorage<f32>,
+}
+
+/// Plugins processing audio in series, like an effects rack or a channel strip.
+///
+/// Every plugin receives the outputs of the previous plugin, by default connected channel by
+/// channel: surplus channels are dropped and missing ones are silent. `set_connection` replaces
+/// this with any `MixMatrix`, e.g. to fan a mono plugin out to a stereo one. The chain passes its
+/// sample rate and block size on to all plugins, and plugins loaded through the chain receive its
+/// [`Transport`](struct.Transport.html) as time info.
+///
+/// `add_branch` adds parallel paths, e.g. for parallel compression, which mix the chain inputs or
+/// the outputs of a plugin into the inputs of a later plugin or into the chain outputs. Branches
+/// are delayed by the `initial_delay` of the plugins they bypass, so they stay aligned with the
+/// processed signal. The delays are updated whenever a plugin loaded by the chain reports an I/O
+/// change.
+///
+/// The chain can also hold plugins running in the same process, which is useful for testing.
+/// Since hosted `PluginInstance`s must stay on the thread which loaded them, so must the chain.
+///
+/// Memory for processing is reserved whenever the plugins, branches or block size change, so
+/// `process` does not allocate. The exceptions are buffers with more channels than the first
+/// plugin's inputs or last plugin's outputs, whose memory is reserved the first time they are
+/// processed, and latency changes reported by plugins, which reallocate the branch delays.
+///
+/// # Example
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use vst2::buffer::AudioBuffer;
+/// use vst2::host::PluginChain;
+///
+/// let mut chain = PluginChain::new(44100.0, 512);
+/// chain.load(Path::new("/path/to/eq.so")).unwrap();
+/// chain.load(Path::new("/path/to/compressor.so")).unwrap();
+/// chain.transport_mut().play();
+/// chain.resume();
+///
+/// let mut inputs = vec![vec![0.0f32; 512]; 2];
+/// let mut outputs = vec![vec![0.0f32; 512]; 2];
+/// chain.process(AudioBuffer::from_channels(&mut inputs, &mut outputs).unwrap());
+/// ```
+pub struct PluginChain {
+    host: Arc<Mutex<ChainHost>>,
+    /// Shared with the host, so processing does not take its lock.
+    time_info: Arc<SharedTimeInfo>,
+    io_changed: Arc<AtomicBool>,
+    nodes: Vec<Node>,
+    /// Parallel paths mixed into the chain outputs.
+    branches: Vec<Branch>,
+    transport: Transport,
+    sample_rate: f32,
+    block_size: usize,
+    resumed: bool,
+
+    /// The chain inputs and outputs of the current block.
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+}
+
+impl PluginChain {
+    /// Create an empty chain processing at `sample_rate` in blocks of at most `block_size`
+    /// samples.
+    pub fn new(sample_rate: f32, block_size: usize) -> PluginChain {
+        let block_size = cmp::max(block_size, 1);
+        let mut transport = Transport::new(sample_rate as f64);
+        let time_info = Arc::new(SharedTimeInfo::new(transport.time_info()));
+        let io_changed = Arc::new(AtomicBool::new(false));
+
+        PluginChain {
+            host: Arc::new(Mutex::new(ChainHost {
+                sample_rate: sample_rate,
+                block_size: block_size as i64,
+                time_info: time_info.clone(),
+                io_changed: io_changed.clone(),
+            })),
+            time_info: time_info,
+            io_changed: io_changed,
+            nodes: Vec::new(),
+            branches: Vec::new(),
+            transport: transport,
+            sample_rate: sample_rate,
+            block_size: block_size,
+            resumed: false,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// The host to load plugins with, so they receive the chain's time info.
+    ///
+    /// `load` uses this host already; it is only needed to load plugins separately, e.g. to
+    /// inspect them before adding them.
+    pub fn host(&self) -> Arc<Mutex<ChainHost>> {
+        self.host.clone()
+    }
+
+    /// Load the plugin at `path`, initialize it and add it to the end of the chain.
+    pub fn load(&mut self, path: &Path) -> Result<(), PluginLoadError> {
+        let mut loader = try!(PluginLoader::load(path, self.host.clone()));
+        let mut instance = try!(loader.instance());
+        instance.init();
+
+        self.push(Box::new(instance));
+        Ok(())
+    }
+
+    /// Add an initialized plugin to the end of the chain.
+    ///
+    /// The plugin is told the sample rate and block size of the chain, and resumed if the chain
+    /// is.
+    pub fn push(&mut self, mut plugin: Box<Plugin>) {
+        plugin.sample_rate_changed(self.sample_rate);
+        plugin.block_size_changed(self.block_size as i64);
+        if self.resumed {
+            plugin.on_resume();
+        }
+
+        let info = plugin.get_info();
+        let inputs = info.inputs.max(0) as usize;
+        let outputs = info.outputs.max(0) as usize;
+        let previous = self.nodes.last().map_or(inputs, |node| node.outputs.len());
+
+        self.nodes.push(Node {
+            plugin: plugin,
+            connection: MixMatrix::identity(previous, inputs),
+            branches: Vec::new(),
+            inputs: vec![vec![0.0; self.block_size]; inputs],
+            outputs: vec![vec![0.0; self.block_size]; outputs],
+            input_ptrs: Vec::new(),
+            output_ptrs: Vec::new(),
+            storage: BufferStorage::new(),
+        });
+        self.update_latency();
+    }
+
+    /// Remove the plugin at `index` from the chain, suspending it if the chain is resumed.
+    ///
+    /// The plugin following it is connected to the previous one channel by channel. Branches from
+    /// the plugin are removed along with it.
+    pub fn remove(&mut self, index: usize) -> Option<Box<Plugin>> {
+        if index >= self.nodes.len() {
+            return None;
+        }
+
+        let mut node = self.nodes.remove(index);
+        if self.resumed {
+            node.plugin.on_suspend();
+        }
+
+        if index < self.nodes.len() {
+            let inputs = self.nodes[index].inputs.len();
+            let previous = if index > 0 { self.nodes[index - 1].outputs.len() } else { inputs };
+            self.nodes[index].connection = MixMatrix::identity(previous, inputs);
+        }
+
+        for node in &mut self.nodes[index..] {
+            remove_source(&mut node.branches, index);
+        }
+        remove_source(&mut self.branches, index);
+        self.update_latency();
+
+        Some(node.plugin)
+    }
+
+    /// Number of plugins in the chain.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether the chain holds no plugins, in which case it passes audio through unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The plugin at `index`, e.g. to send it events or change its parameters.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Box<Plugin>> {
+        self.nodes.get_mut(index).map(|node| &mut node.plugin)
+    }
+
+    /// Replace the matrix mixing the outputs of the previous plugin, or the chain inputs for the
+    /// first plugin, into the inputs of the plugin at `index`.
+    pub fn set_connection(&mut self, index: usize, connection: MixMatrix) {
+        if let Some(node) = self.nodes.get_mut(index) {
+            node.connection = connection;
+        }
+    }
+
+    /// Add a parallel path mixing the outputs of the plugin at `from`, or the chain inputs if
+    /// `from` is `None`, through `connection` into the inputs of the plugin at `to`, or into the
+    /// chain outputs if `to` is `None`.
+    ///
+    /// The branch adds to the regular connection of its destination. It is ignored unless `from`
+    /// comes before `to` in the chain.
+    pub fn add_branch(&mut self, from: Option<usize>, to: Option<usize>, connection: MixMatrix) {
+        let len = self.nodes.len();
+        let valid_to = to.map_or(true, |to| to < len);
+        let valid_from = from.map_or(true, |from| from < to.unwrap_or(len));
+        if !valid_to || !valid_from {
+            return;
+        }
+
+        let branch = Branch {
+            source: from,
+            connection: connection,
+            delay: Delay::new(),
+            mixed: Vec::new(),
+        };
+        match to {
+            Some(to) => self.nodes[to].branches.push(branch),
+            None => self.branches.push(branch),
+        }
+        self.update_latency();
+    }
+
+    /// Remove all branches into the plugin at `to`, or into the chain outputs if `to` is `None`.
+    pub fn clear_branches(&mut self, to: Option<usize>) {
+        match to {
+            Some(to) => {
+                if let Some(node) = self.nodes.get_mut(to) {
+                    node.branches.clear();
+                }
+            }
+            None => self.branches.clear(),
+        }
+    }
+
+    /// The transport reported to the plugins, e.g. to start playback or change the tempo.
+    pub fn transport_mut(&mut self) -> &mut Transport {
+        &mut self.transport
+    }
+
+    /// Total latency of the chain in samples, as reported by the plugins' `initial_delay`.
+    ///
+    /// Branches are delayed to match the plugins they bypass, so they do not add to it.
+    pub fn latency(&self) -> i32 {
+        self.nodes.iter().map(|node| node.plugin.get_info().initial_delay.max(0)).sum()
+    }
+
+    /// Delay every branch by the latency of the plugins it bypasses.
+    ///
+    /// This happens automatically when plugins or branches are added or removed, when the chain
+    /// is resumed and when a plugin loaded by the chain reports an I/O change. Plugins added with
+    /// `push` report their changes to their own host, which should call this instead.
+    ///
+    /// This also reserves the memory used by `process`.
+    pub fn update_latency(&mut self) {
+        // Latency at the outputs of every plugin
+        let mut latencies = Vec::with_capacity(self.nodes.len());
+        let mut total = 0;
+
+        for node in &mut self.nodes {
+            for branch in &mut node.branches {
+                branch.delay.set(total - branch.source.map_or(0, |source| latencies[source]));
+            }
+            total += node.plugin.get_info().initial_delay.max(0) as usize;
+            latencies.push(total);
+        }
+
+        for branch in &mut self.branches {
+            branch.delay.set(total - branch.source.map_or(0, |source| latencies[source]));
+        }
+
+        self.reserve();
+    }
+
+    /// Reserve the memory used by `process` for the current plugins, branches and block size.
+    fn reserve(&mut self) {
+        let block_size = self.block_size;
+        let num_inputs = self.nodes.first().map_or(0, |node| node.inputs.len());
+        let num_outputs = self.nodes.last().map_or(0, |node| node.outputs.len());
+        reserve_channels(&mut self.inputs, num_inputs, block_size);
+        reserve_channels(&mut self.outputs, num_outputs, block_size);
+
+        // Channels of the chain inputs and of the outputs of every plugin
+        let chain_inputs = self.inputs.len();
+        let source_channels = |nodes: &[Node], source: Option<usize>| {
+            source.map_or(chain_inputs, |source| nodes[source].outputs.len())
+        };
+
+        for index in 0..self.nodes.len() {
+            let (previous, rest) = self.nodes.split_at_mut(index);
+            let node = &mut rest[0];
+
+            reserve_channels(&mut node.inputs, 0, block_size);
+            reserve_channels(&mut node.outputs, 0, block_size);
+            node.input_ptrs.reserve(node.inputs.len());
+            node.output_ptrs.reserve(node.outputs.len());
+            node.storage.reserve(node.inputs.len(), node.outputs.len(), 0);
+
+            for branch in &mut node.branches {
+                branch.delay.reserve(source_channels(previous, branch.source), block_size);
+                reserve_channels(&mut branch.mixed, node.inputs.len(), block_size);
+            }
+        }
+
+        for branch in &mut self.branches {
+            branch.delay.reserve(source_channels(&self.nodes, branch.source), block_size);
+            reserve_channels(&mut branch.mixed, self.outputs.len(), block_size);
+        }
+    }
+
+    /// Change the sample rate of the chain and all plugins.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        self.transport.set_sample_rate(sample_rate as f64);
+        self.host.lock().unwrap().sample_rate = sample_rate;
+
+        let resumed = self.resumed;
+        for node in &mut self.nodes {
+            if resumed {
+                node.plugin.on_suspend();
+            }
+            node.plugin.sample_rate_changed(sample_rate);
+            if resumed {
+                node.plugin.on_resume();
+            }
+        }
+        self.update_latency();
+    }
+
+    /// Change the maximum block size passed to the plugins.
+    pub fn set_block_size(&mut self, block_size: usize) {
+        let block_size = cmp::max(block_size, 1);
+        self.block_size = block_size;
+        self.host.lock().unwrap().block_size = block_size as i64;
+
+        let resumed = self.resumed;
+        for node in &mut self.nodes {
+            if resumed {
+                node.plugin.on_suspend();
+            }
+            node.plugin.block_size_changed(block_size as i64);
+            if resumed {
+                node.plugin.on_resume();
+            }
+        }
+        self.reserve();
+    }
+
+    /// Resume all plugins. This must be called before processing.
+    pub fn resume(&mut self) {
+        if !self.resumed {
+            self.resumed = true;
+            for node in &mut self.nodes {
+                node.plugin.on_resume();
+            }
+            self.update_latency();
+        }
+    }
+
+    /// Suspend all plugins.
+    pub fn suspend(&mut self) {
+        if self.resumed {
+            self.resumed = false;
+            for node in &mut self.nodes {
+                node.plugin.on_suspend();
+            }
+        }
+    }
+
+    /// Process the inputs of `buffer` through all plugins into its outputs.
+    ///
+    /// Buffers longer than the block size are processed in several blocks. The transport
+    /// advances by the length of the buffer.
+    pub fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+        // Latency changes are applied between buffers, as they reallocate the delays
+        if self.io_changed.swap(false, Ordering::AcqRel) {
+            self.update_latency();
+        }
+
+        let samples = buffer.samples();
+        let (inputs, mut outputs) = buffer.split_mut();
+        let (num_inputs, num_outputs) = (inputs.len(), outputs.len());
+        if num_inputs > self.inputs.len() || num_outputs > self.outputs.len() {
+            reserve_channels(&mut self.inputs, num_inputs, self.block_size);
+            reserve_channels(&mut self.outputs, num_outputs, self.block_size);
+            self.reserve();
+        }
+
+        let mut start = 0;
+        while start < samples {
+            let len = cmp::min(self.block_size, samples - start);
+            self.time_info.set(self.transport.time_info());
+
+            for (channel, block) in self.inputs[..num_inputs].iter_mut().enumerate() {
+                block.clear();
+                if let Some(input) = inputs.get(channel) {
+                    block.extend_from_slice(&input[start..start + len]);
+                }
+            }
+
+            for index in 0..self.nodes.len() {
+                let (previous, rest) = self.nodes.split_at_mut(index);
+                let node = &mut rest[0];
+
+                for channel in node.inputs.iter_mut().chain(node.outputs.iter_mut()) {
+                    channel.resize(len, 0.0);
+                }
+
+                match previous.last() {
+                    Some(previous) => {
+                        node.connection.apply(&previous.outputs[..], &mut node.inputs[..])
+                    }
+                    None => node.connection.apply(&self.inputs[..num_inputs], &mut node.inputs[..]),
+                }
+
+                for branch in &mut node.branches {
+                    let source = match branch.source {
+                        Some(source) => &previous[source].outputs[..],
+                        None => &self.inputs[..num_inputs],
+                    };
+                    branch.add_to(source, &mut node.inputs);
+                }
+
+                node.input_ptrs.clear();
+                node.input_ptrs.extend(node.inputs.iter_mut().map(|c| c.as_mut_ptr()));
+                node.output_ptrs.clear();
+                node.output_ptrs.extend(node.outputs.iter_mut().map(|c| c.as_mut_ptr()));
+
+                let buffer = unsafe {
+                    AudioBuffer::from_raw_in_place(node.input_ptrs.as_mut_ptr(),
+                                                   node.output_ptrs.as_mut_ptr(),
+                                                   node.inputs.len(),
+                                                   node.outputs.len(),
+                                                   len,
+                                                   &mut node.storage)
+                };
+                node.plugin.process(buffer);
+            }
+
+            for (channel, block) in self.outputs[..num_outputs].iter_mut().enumerate() {
+                let source = match self.nodes.last() {
+                    Some(node) => node.outputs.get(channel),
+                    None => self.inputs[..num_inputs].get(channel),
+                };
+
+                block.clear();
+                match source {
+                    Some(source) => block.extend_from_slice(source),
+                    None => block.resize(len, 0.0),
+                }
+            }
+
+            for branch in &mut self.branches {
+                let source = match branch.source {
+                    Some(source) => &self.nodes[source].outputs[..],
+                    None => &self.inputs[..num_inputs],
+                };
+                branch.add_to(source, &mut self.outputs[..num_outputs]);
+            }
+
+            for (channel, block) in self.outputs[..num_outputs].iter().enumerate() {
+                if let Some(output) = outputs.get_mut(channel) {
+                    output[start..start + len].copy_from_slice(block);
+                }
+            }
+
+            self.transport.advance(len);
+            start += len;
+        }
+    }
+}
+
+impl Drop for PluginChain {
+    fn drop(&mut self) {
+        self.suspend();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffer::AudioBuffer;
+    use host::{Host, PluginChain};
+    use mix::MixMatrix;
+    use plugin::{Info, Plugin};
+
+    /// Delays every channel by a latency set through parameter 0.
+    #[derive(Default)]
+    struct Latency {
+        delay: usize,
+        history: Vec<Vec<f32>>,
+    }
+
+    impl Plugin for Latency {
+        fn get_info(&self) -> Info {
+            Info {
+                inputs: 2,
+                outputs: 2,
+                parameters: 1,
+                initial_delay: self.delay as i32,
+                ..Default::default()
+            }
+        }
+
+        fn set_parameter(&mut self, _: i32, value: f32) {
+            self.delay = value as usize;
+        }
+
+        fn process(&mut self, buffer: AudioBuffer<f32>) {
+            for (channel, (input, output)) in buffer.zip().enumerate() {
+                if self.history.len() <= channel {
+                    self.history.push(Vec::new());
+                }
+
+                let history = &mut self.history[channel];
+                for (x, y) in input.iter().zip(output.iter_mut()) {
+                    history.push(*x);
+                    *y = if history.len() > self.delay {
+                        history[history.len() - 1 - self.delay]
+                    } else {
+                        0.0
+                    };
+                }
+            }
+        }
+    }
+
+    /// Multiplies every input channel by a gain.
+    struct Gain {
+        gain: f32,
+        channels: i32,
+        delay: i32,
+    }
+
+    impl Plugin for Gain {
+        fn get_info(&self) -> Info {
+            Info {
+                inputs: self.channels,
+                outputs: self.channels,
+                initial_delay: self.delay,
+                ..Default::default()
+            }
+        }
+
+        fn process(&mut self, buffer: AudioBuffer<f32>) {
+            for (input, output) in buffer.zip() {
+                for (x, y) in input.iter().zip(output.iter_mut()) {
+                    *y = x * self.gain;
+                }
+            }
+        }
+    }
+
+    impl Default for Gain {
+        fn default() -> Gain {
+            Gain { gain: 1.0, channels: 2, delay: 0 }
+        }
+    }
+
+    fn process(chain: &mut PluginChain, input: Vec<f32>) -> Vec<Vec<f32>> {
+        let len = input.len();
+        let mut inputs = vec![input.clone(), input];
+        let mut outputs = vec![vec![0.0; len]; 2];
+        chain.process(AudioBuffer::from_channels(&mut inputs, &mut outputs).unwrap());
+        outputs
+    }
+
+    /// Test that audio passes through all plugins and their connections.
+    #[test]
+    fn chain() {
+        let mut chain = PluginChain::new(44100.0, 4);
+        assert_eq!(process(&mut chain, vec![1.0; 6]), vec![vec![1.0; 6]; 2]);
+
+        chain.push(Box::new(Gain { gain: 2.0, channels: 1, delay: 3 }));
+        chain.push(Box::new(Gain { gain: 3.0, ..Default::default() }));
+        chain.resume();
+        assert_eq!(chain.len(), 2);
+        assert_eq!(chain.latency(), 3);
+
+        // The second channel is dropped by the mono plugin, then silent
+        assert_eq!(process(&mut chain, vec![1.0; 6]), vec![vec![6.0; 6], vec![0.0; 6]]);
+
+        chain.set_connection(1, MixMatrix::fan_out(2));
+        assert_eq!(process(&mut chain, vec![1.0; 6]), vec![vec![6.0; 6]; 2]);
+
+        assert!(chain.remove(0).is_some());
+        assert!(chain.remove(1).is_none());
+        assert_eq!(process(&mut chain, vec![1.0; 6]), vec![vec![3.0; 6]; 2]);
+    }
+
+    /// Test that branches are delayed by the latency of the plugins they bypass.
+    #[test]
+    fn latency_compensation() {
+        let mut chain = PluginChain::new(44100.0, 4);
+        chain.push(Box::new(Latency { delay: 2, ..Default::default() }));
+        chain.push(Box::new(Gain::default()));
+
+        // Dry signal around the delaying plugin, and its output around the gain
+        chain.add_branch(None, Some(1), MixMatrix::identity(2, 2));
+        chain.add_branch(Some(0), None, MixMatrix::identity(2, 2));
+        chain.add_branch(Some(1), Some(0), MixMatrix::identity(2, 2)); // Ignored, it goes backwards
+        chain.resume();
+        assert_eq!(chain.latency(), 2);
+
+        let impulse = vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        assert_eq!(process(&mut chain, impulse.clone()),
+                   vec![vec![0.0, 0.0, 3.0, 0.0, 0.0, 0.0]; 2]);
+
+        // The plugin reports a longer latency through its host
+        chain.get_mut(0).unwrap().set_parameter(0, 3.0);
+        chain.host().lock().unwrap().io_changed();
+        assert_eq!(chain.latency(), 3);
+        assert_eq!(process(&mut chain, impulse.clone()),
+                   vec![vec![0.0, 0.0, 0.0, 3.0, 0.0, 0.0]; 2]);
+
+        // Removing the delaying plugin removes the branch from it
+        chain.remove(0);
+        assert_eq!(chain.latency(), 0);
+        assert_eq!(process(&mut chain, impulse.clone()),
+                   vec![vec![2.0, 0.0, 0.0, 0.0, 0.0, 0.0]; 2]);
+    }
+
+    /// Test that the transport advances and is reported to the host.
+    #[test]
+    fn transport() {
+        let mut chain = PluginChain::new(48000.0, 64);
+        chain.transport_mut().play();
+        chain.set_block_size(32);
+
+        process(&mut chain, vec![0.0; 100]);
+
+        let host = chain.host();
+        let host = host.lock().unwrap();
+        assert_eq!(host.get_block_size(), 32);
+        assert_eq!(host.get_time_info(0).unwrap().sample_pos, 96.0);
+    }
+}
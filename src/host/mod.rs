@@ -0,0 +1,2991 @@
+//! Host specific structures.
+
+use std::cell::UnsafeCell;
+use std::path::{Path, PathBuf};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fs::{self, File};
+use std::io::Read;
+use std::marker::PhantomData;
+use std::{cmp, fmt, ptr, mem, slice};
+
+use dylib::DynamicLibrary;
+use libc::c_void;
+use num::Float;
+#[cfg(feature = "window-handle")]
+use raw_window_handle::HasRawWindowHandle;
+
+use interfaces;
+use read_fixed;
+use plugin::{self, Plugin, Info, Category, ParameterProperties};
+use api::{self, AEffect, PluginMain, Supported};
+use api::consts::*;
+use buffer::{AudioBuffer, VariableIo};
+use channels::ChannelInfo;
+use editor::Rect;
+#[cfg(feature = "window-handle")]
+use editor::parent_pointer;
+use event::{Event, SendEventBuffer};
+
+mod chain;
+mod transport;
+
+pub use self::chain::{ChainHost, PluginChain};
+pub use self::transport::{SharedTimeInfo, Transport, TimeInfoBuilder};
+
+#[repr(usize)]
+#[derive(Clone, Copy, Debug)]
+#[doc(hidden)]
+pub enum OpCode {
+    /// [index]: parameter index
+    /// [opt]: parameter value
+    Automate = 0,
+    /// [return]: host vst version (e.g. 2400 for VST 2.4)
+    Version,
+    /// [return]: current plugin ID (useful for shell plugins to figure out which plugin to load in
+    ///           `VSTPluginMain()`).
+    CurrentId,
+    /// No arguments. Give idle time to Host application, e.g. if plug-in editor is doing mouse
+    /// tracking in a modal loop.
+    Idle,
+    /// Deprecated.
+    _PinConnected = 4,
+
+    /// Deprecated. The plugin wants to receive MIDI events, only needed by hosts predating
+    /// VST 2.4.
+    /// [value]: filter flags, usually 1.
+    WantMidi = 6, // Not a typo
+    /// [value]: request mask. see `VstTimeInfoFlags`
+    /// [return]: `VstTimeInfo` pointer or null if not supported.
+    GetTime,
+    /// Inform host that the plugin has MIDI events ready to be processed. Should be called at the
+    /// end of `Plugin::process`.
+    /// [ptr]: `VstEvents*` the events to be processed.
+    /// [return]: 1 if supported and processed OK.
+    ProcessEvents,
+    /// Deprecated.
+    _SetTime,
+    /// Deprecated.
+    _TempoAt,
+    /// Deprecated.
+    _GetNumAutomatableParameters,
+    /// Deprecated.
+    _GetParameterQuantization,
+
+    /// Notifies the host that the input/output setup has changed. This can allow the host to check
+    /// numInputs/numOutputs or call `getSpeakerArrangement()`
+    /// [return]: 1 if supported.
+    IOChanged,
+
+    /// Deprecated. The plugin wants to receive `plugin::OpCode::Idle` calls.
+    /// [return]: 1 if supported.
+    NeedIdle,
+
+    /// Request the host to resize the plugin window.
+    /// [index]: new width.
+    /// [value]: new height.
+    SizeWindow,
+    /// [return]: the current sample rate.
+    GetSampleRate,
+    /// [return]: the current block size.
+    GetBlockSize,
+    /// [return]: the input latency in samples.
+    GetInputLatency,
+    /// [return]: the output latency in samples.
+    GetOutputLatency,
+
+    /// Deprecated.
+    _GetPreviousPlug,
+    /// Deprecated.
+    _GetNextPlug,
+    /// Deprecated.
+    _WillReplaceOrAccumulate,
+
+    /// [return]: the current process level, see `VstProcessLevels`
+    GetCurrentProcessLevel,
+    /// [return]: the current automation state, see `VstAutomationStates`
+    GetAutomationState,
+
+    /// The plugin is ready to begin offline processing.
+    /// [index]: number of new audio files.
+    /// [value]: number of audio files.
+    /// [ptr]: `AudioFile*` the host audio files. Flags can be updated from plugin.
+    OfflineStart,
+    /// Called by the plugin to read data.
+    /// [index]: (bool)
+    ///    VST offline processing allows a plugin to overwrite existing files. If this value is
+    ///    true then the host will read the original file's samples, but if it is false it will
+    ///    read the samples which the plugin has written via `OfflineWrite`
+    /// [value]: see `OfflineOption`
+    /// [ptr]: `OfflineTask*` describing the task.
+    /// [return]: 1 on success
+    OfflineRead,
+    /// Called by the plugin to write data.
+    /// [value]: see `OfflineOption`
+    /// [ptr]: `OfflineTask*` describing the task.
+    OfflineWrite,
+    /// Unknown. Used in offline processing.
+    OfflineGetCurrentPass,
+    /// Unknown. Used in offline processing.
+    OfflineGetCurrentMetaPass,
+
+    /// Deprecated.
+    _SetOutputSampleRate,
+    /// Deprecated.
+    _GetOutputSpeakerArrangement,
+
+    /// Get the vendor string.
+    /// [ptr]: `char*` for vendor string, limited to `MAX_VENDOR_STR_LEN`.
+    GetVendorString,
+    /// Get the product string.
+    /// [ptr]: `char*` for vendor string, limited to `MAX_PRODUCT_STR_LEN`.
+    GetProductString,
+    /// [return]: vendor-specific version
+    GetVendorVersion,
+    /// Vendor specific handling.
+    VendorSpecific,
+
+    /// Deprecated.
+    _SetIcon,
+
+    /// Check if the host supports a feature.
+    /// [ptr]: `char*` can do string
+    /// [return]: 1 if supported
+    CanDo,
+    /// Get the language of the host.
+    /// [return]: `VstHostLanguage`
+    GetLanguage,
+
+    /// Deprecated.
+    _OpenWindow,
+    /// Deprecated.
+    _CloseWindow,
+
+    /// Get the current directory.
+    /// [return]: `FSSpec` on OS X, `char*` otherwise
+    GetDirectory,
+    /// Tell the host that the plugin's parameters have changed, refresh the UI.
+    ///
+    /// No arguments.
+    UpdateDisplay,
+    /// Tell the host that if needed, it should record automation data for a control.
+    ///
+    /// Typically called when the plugin editor begins changing a control.
+    ///
+    /// [index]: index of the control.
+    /// [return]: true on success.
+    BeginEdit,
+    /// A control is no longer being changed.
+    ///
+    /// Typically called after the plugin editor is done.
+    ///
+    /// [index]: index of the control.
+    /// [return]: true on success.
+    EndEdit,
+    /// Open the host file selector.
+    /// [ptr]: `VstFileSelect*`
+    /// [return]: true on success.
+    OpenFileSelector,
+    /// Close the host file selector.
+    /// [ptr]: `VstFileSelect*`
+    /// [return]: true on success.
+    CloseFileSelector,
+
+    /// Deprecated.
+    _EditFile,
+    /// Deprecated.
+    /// [ptr]: char[2048] or sizeof (FSSpec).
+    /// [return]: 1 if supported.
+    _GetChunkFile,
+    /// Deprecated.
+    _GetInputSpeakerArrangement,
+}
+impl_clike!(OpCode);
+
+/// Implemented by all VST hosts.
+#[allow(unused_variables)]
+pub trait Host {
+    /// Automate a parameter; the value has been changed.
+    fn automate(&mut self, index: i32, value: f32) {}
+
+    /// The user has started changing the parameter at `index`, e.g. by grabbing a knob.
+    ///
+    /// Any `automate` calls until the matching `end_edit` belong to the same gesture. Return
+    /// `true` if the gesture was handled.
+    fn begin_edit(&mut self, index: i32) -> bool { false }
+
+    /// The user has stopped changing the parameter at `index`. Return `true` if handled.
+    fn end_edit(&mut self, index: i32) -> bool { false }
+
+    /// Get the unique ID of the plugin to load from a shell, or 0 to load the shell itself.
+    ///
+    /// Shell plugins ask for this while they are being loaded, see the `shell` module.
+    fn get_plugin_id(&self) -> i32 { 0 }
+
+    /// Get the current sample rate of the host, or 0.0 if unknown.
+    fn get_sample_rate(&self) -> f32 { 0.0 }
+
+    /// Get the current maximum block size of the host, or 0 if unknown.
+    fn get_block_size(&self) -> i64 { 0 }
+
+    /// The plugin's parameters or programs have changed and any displayed values should be
+    /// refreshed. Return `true` if handled.
+    fn update_display(&mut self) -> bool { false }
+
+    /// Get the time info for the block currently being processed.
+    ///
+    /// `mask` is a bitmask of `flags::TimeInfo` values which the plugin is interested in. Return
+    /// `None` if no time info is available. See [`Transport`](struct.Transport.html) for a helper
+    /// which keeps track of the transport state.
+    fn get_time_info(&self, mask: i32) -> Option<api::TimeInfo> { None }
+
+    /// An idle call.
+    ///
+    /// This is useful when the plugin is doing something such as mouse tracking in the UI.
+    fn idle(&self) {}
+
+    /// The plugin requests regular idle calls, see `PluginInstance::idle`. Return `true` if
+    /// they will be sent.
+    fn need_idle(&mut self) -> bool { false }
+
+    /// Get the vendor name of the host, limited to `MAX_VENDOR_STR_LEN` bytes.
+    fn get_vendor_string(&self) -> String { "rust-vst2".to_string() }
+
+    /// Get the product name of the host, limited to `MAX_PRODUCT_STR_LEN` bytes.
+    fn get_product_string(&self) -> String { "rust-vst2 host".to_string() }
+
+    /// Get the vendor specific version of the host (e.g. 1000 = `v1.0.0.0`).
+    fn get_vendor_version(&self) -> i32 { 1 }
+
+    /// Return whether the host supports the given "can do" string (e.g. `"sizeWindow"`,
+    /// `"sendVstEvents"`, `"openFileSelector"`).
+    ///
+    /// Plugins may enable or disable features depending on the answer.
+    fn can_do(&self, value: &str) -> Supported {
+        info!("Plugin is asking if host can: {}.", value);
+        Supported::Maybe
+    }
+
+    /// The plugin's inputs, outputs or initial delay have changed.
+    ///
+    /// The host should call `refresh_info` on the instance and rebuild its buffers once
+    /// processing has been stopped. Return `true` if the change is supported.
+    fn io_changed(&mut self) -> bool { false }
+
+    /// The latency of the plugin has changed to `latency` samples.
+    ///
+    /// This is called when a plugin reports an I/O change and its initial delay differs from the
+    /// last known value. Hosts implementing delay compensation should react to this.
+    fn latency_changed(&mut self, latency: i32) {}
+
+    /// Resize the plugin editor window to the given size in pixels.
+    ///
+    /// Return `true` if the window was resized.
+    fn size_window(&mut self, width: i32, height: i32) -> bool { false }
+
+    /// Handle MIDI events sent by the plugin.
+    ///
+    /// Plugins which generate MIDI output call this from their process function, so it should
+    /// return quickly.
+    fn process_events(&mut self, events: &[Event]) {}
+
+    /// Present a file selector to the user.
+    ///
+    /// Return the selected paths, or `None` if the selection was cancelled or file selectors are
+    /// not supported. Only the first path is passed to the plugin unless
+    /// `FileSelectCommand::LoadMultiple` was requested.
+    fn open_file_selector(&mut self, selector: &FileSelect) -> Option<Vec<PathBuf>> { None }
+
+    /// Called when the plugin is done with a file selector opened via `open_file_selector`.
+    fn close_file_selector(&mut self, selector: &FileSelect) {}
+}
+
+/// Information about a single parameter of a loaded plugin.
+#[derive(Clone, Debug)]
+pub struct ParameterInfo {
+    /// Name of the parameter (e.g. "Release", "Gain").
+    pub name: String,
+    /// Unit label of the parameter (e.g. "dB", "ms", "%").
+    pub label: String,
+    /// Current value formatted by the plugin (e.g. "0.5", "ROOM", "Off").
+    pub display: String,
+    /// Current normalized value between 0.0 and 1.0.
+    pub value: f32,
+    /// Whether the plugin allows automating the parameter.
+    pub automatable: bool,
+}
+
+/// Iterator over the parameters of a plugin, see `PluginInstance::parameters`.
+pub struct Parameters<'a> {
+    effect: &'a Effect,
+    index: i32,
+    count: i32,
+}
+
+impl<'a> Iterator for Parameters<'a> {
+    type Item = (i32, ParameterInfo);
+
+    fn next(&mut self) -> Option<(i32, ParameterInfo)> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let index = self.index;
+        self.index += 1;
+        Some((index, self.effect.parameter_info(index)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.index).max(0) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for Parameters<'a> {}
+
+/// The kind of file selector requested by a plugin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileSelectCommand {
+    /// Select a single file to load.
+    Load,
+    /// Select a file to save to.
+    Save,
+    /// Select multiple files to load.
+    LoadMultiple,
+    /// Select a directory.
+    Directory,
+}
+
+/// A file type which a plugin accepts in a file selector.
+#[derive(Clone, Debug)]
+pub struct FileType {
+    /// Display name, e.g. "Wave File".
+    pub name: String,
+    /// File extension without the leading dot, e.g. "wav".
+    pub extension: String,
+    /// Mac file type, e.g. "WAVE".
+    pub mac_type: String,
+    /// MIME types, e.g. "audio/wav".
+    pub mime_types: Vec<String>,
+}
+
+/// A request from a plugin for the host to present a file selector.
+#[derive(Clone, Debug)]
+pub struct FileSelect {
+    /// The kind of selection requested.
+    pub command: FileSelectCommand,
+    /// Title for the file selector window.
+    pub title: String,
+    /// Path the file selector should initially show, if any.
+    pub initial_path: Option<PathBuf>,
+    /// File types the plugin accepts. Empty if any file is accepted.
+    pub file_types: Vec<FileType>,
+}
+
+impl FileSelect {
+    /// Read a `FileSelect` from the raw VST structure passed by a plugin.
+    #[doc(hidden)]
+    pub unsafe fn from_raw(raw: &api::FileSelect) -> FileSelect {
+        let file_types = if raw.file_types.is_null() {
+            Vec::new()
+        } else {
+            slice::from_raw_parts(raw.file_types, raw.num_file_types.max(0) as usize)
+                .iter()
+                .map(|t| {
+                    let unix = read_fixed(&t.unix_type);
+                    FileType {
+                        name: read_fixed(&t.name),
+                        extension: if unix.is_empty() { read_fixed(&t.dos_type) } else { unix },
+                        mac_type: read_fixed(&t.mac_type),
+                        mime_types: vec![read_fixed(&t.mime_type_1), read_fixed(&t.mime_type_2)]
+                                        .into_iter()
+                                        .filter(|m| !m.is_empty())
+                                        .collect(),
+                    }
+                })
+                .collect()
+        };
+
+        FileSelect {
+            command: match raw.command {
+                1 => FileSelectCommand::Save,
+                2 => FileSelectCommand::LoadMultiple,
+                3 => FileSelectCommand::Directory,
+                _ => FileSelectCommand::Load,
+            },
+            title: read_fixed(&raw.title),
+            initial_path: if raw.initial_path.is_null() {
+                None
+            } else {
+                Some(PathBuf::from(CStr::from_ptr(raw.initial_path).to_string_lossy().into_owned()))
+            },
+            file_types: file_types,
+        }
+    }
+}
+
+/// Locate the executable inside of a `.vst` bundle directory.
+///
+/// The executable name is read from `Contents/Info.plist`. If it is missing, the binary named after
+/// the bundle or else the only file in `Contents/MacOS` is used.
+fn bundle_binary(bundle: &Path) -> Option<PathBuf> {
+    let macos = bundle.join("Contents").join("MacOS");
+
+    if let Ok(mut file) = File::open(bundle.join("Contents").join("Info.plist")) {
+        let mut plist = String::new();
+        if file.read_to_string(&mut plist).is_ok() {
+            if let Some(name) = bundle_executable(&plist) {
+                let path = macos.join(name);
+                if path.is_file() {
+                    return Some(path);
+                }
+            }
+        }
+    }
+
+    if let Some(stem) = bundle.file_stem() {
+        let path = macos.join(stem);
+        if path.is_file() {
+            return Some(path);
+        }
+    }
+
+    let mut files = match fs::read_dir(&macos) {
+        Ok(entries) => entries.filter_map(|e| e.ok())
+                              .map(|e| e.path())
+                              .filter(|p| p.is_file())
+                              .collect::<Vec<_>>(),
+        Err(_) => return None,
+    };
+    if files.len() == 1 { files.pop() } else { None }
+}
+
+/// Read the `CFBundleExecutable` value from the contents of an `Info.plist` file.
+fn bundle_executable(plist: &str) -> Option<String> {
+    let key = plist.find("<key>CFBundleExecutable</key>")
+                   .map(|i| &plist[i + "<key>CFBundleExecutable</key>".len()..]);
+
+    key.and_then(|mut rest| {
+        // Skip whitespace and comments between the key and its value
+        loop {
+            rest = rest.trim_start();
+            if !rest.starts_with("<!--") {
+                break;
+            }
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + "-->".len()..],
+                None => return None,
+            };
+        }
+
+        if !rest.starts_with("<string>") {
+            return None;
+        }
+
+        let rest = &rest["<string>".len()..];
+        rest.find("</string>").map(|end| rest[..end].trim().to_string())
+    })
+}
+
+/// Minimum VST API version reported by plugins which can be loaded (VST 2.0).
+const MIN_VST_VERSION: isize = 2000;
+
+/// The VST API version reported by a plugin as e.g. `2400`.
+///
+/// Plugins built with the VST 2.0 SDK report only the major version, e.g. `2`.
+fn normalize_vst_version(version: isize) -> isize {
+    if version >= 1 && version <= 9 { version * 1000 } else { version }
+}
+
+/// All possible errors that can occur when loading a VST plugin.
+#[derive(Debug)]
+pub enum PluginLoadError {
+    /// Could not load given path.
+    InvalidPath,
+
+    /// Given path is not a VST plugin.
+    NotAPlugin,
+
+    /// Failed to create an instance of this plugin.
+    ///
+    /// This can happen for many reasons, such as if the plugin requires a different version of
+    /// the VST API to be used, or due to improper licensing.
+    InstanceFailed,
+
+    /// The plugin uses a version of the VST API which is not supported (VST 1.x).
+    IncompatibleVersion,
+}
+
+impl fmt::Display for PluginLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.description())
+    }
+}
+
+impl Error for PluginLoadError {
+    fn description(&self) -> &str {
+        use self::PluginLoadError::*;
+
+        match *self {
+            InvalidPath => "Could not open the requested path",
+            NotAPlugin => "The given path does not contain a VST2.4 compatible library",
+            InstanceFailed => "Failed to create a plugin instance",
+            IncompatibleVersion => "The plugin uses an unsupported version of the VST API",
+        }
+    }
+}
+
+/// Error returned by `PluginLoader::reload` when the plugin could not be loaded again.
+///
+/// The old instance is already gone at that point, so the state saved from it is returned to let
+/// the host restore it into an instance created later, e.g. once the build is fixed.
+#[derive(Debug)]
+pub struct ReloadError {
+    /// Why loading the plugin failed.
+    pub error: PluginLoadError,
+    /// The chunk saved from the old instance, if it supports preset chunks.
+    pub chunk: Option<Vec<u8>>,
+    /// The parameter values of the old instance.
+    pub parameters: Vec<f32>,
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to reload the plugin: {}", self.error)
+    }
+}
+
+impl Error for ReloadError {
+    fn description(&self) -> &str {
+        self.error.description()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.error)
+    }
+}
+
+/// Error state of a plugin which panicked or stopped responding during a call.
+///
+/// Once a plugin has crashed, no further calls are made into it and all of its handles behave as
+/// if the plugin did nothing. The host should report the error and drop the instance. Dropping the
+/// last handle of a crashed plugin still sends it `Shutdown`, guarded like any other call, so the
+/// plugin can release its memory; it is not suspended first.
+///
+/// Only Rust panics unwinding out of the plugin are caught, e.g. from plugins built with this
+/// crate. Segmentation faults, aborts and C++ exceptions still take down the host. Catching those
+/// through signal handlers or structured exception handling is deliberately not offered: a
+/// faulting plugin may have corrupted the host's memory, and jumping out of a signal handler over
+/// Rust frames is undefined behaviour. Hosts which need to survive such crashes have to run
+/// plugins in a separate process.
+#[derive(Clone, Debug)]
+pub struct PluginCrashed {
+    /// The panic message of the plugin.
+    pub message: String,
+}
+
+impl fmt::Display for PluginCrashed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.description(), self.message)
+    }
+}
+
+impl Error for PluginCrashed {
+    fn description(&self) -> &str {
+        "The plugin crashed"
+    }
+}
+
+/// Wrapper for an externally loaded VST plugin.
+///
+/// The only functionality this struct provides is loading plugins, which can be done via the
+/// [`load`](#method.load) method.
+pub struct PluginLoader<T: Host> {
+    main: PluginMain,
+    lib: Arc<DynamicLibrary>,
+    host: Arc<Mutex<T>>,
+    path: PathBuf,
+}
+
+/// An instance of an externally loaded VST plugin.
+///
+/// An instance can be used directly from the thread which loaded it, or it can be
+/// [split](#method.split) into a [`PluginProcessor`](struct.PluginProcessor.html) for the audio
+/// thread and a [`PluginController`](struct.PluginController.html) for the UI thread.
+pub struct PluginInstance {
+    effect: Arc<Effect>,
+    info: Info,
+
+    // Last values passed to the plugin, restored by `PluginLoader::reload`.
+    sample_rate: Option<f32>,
+    block_size: Option<i64>,
+
+    scratch: Scratch<f32>,
+    scratch_f64: Scratch<f64>,
+
+    // Keeps this handle `!Send`, only the processor half may move to another thread.
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// The audio thread half of a split [`PluginInstance`](struct.PluginInstance.html).
+///
+/// This handle can be sent to the audio thread and only exposes the calls which are valid to make
+/// while the plugin is processing.
+pub struct PluginProcessor {
+    effect: Arc<Effect>,
+    info: Info,
+    scratch: Scratch<f32>,
+    scratch_f64: Scratch<f64>,
+}
+
+/// The UI thread half of a split [`PluginInstance`](struct.PluginInstance.html).
+///
+/// This handle exposes the calls which must not be made from the audio thread. It cannot be sent
+/// to another thread.
+pub struct PluginController {
+    effect: Arc<Effect>,
+    info: Info,
+    // Keeps this handle `!Send` so it stays on the thread that split the instance.
+    _not_send: PhantomData<*mut ()>,
+    watchdog: Option<Watchdog>,
+}
+
+/// A call queued for the watchdog thread of a `PluginController`.
+type Job = Box<FnMut(&Effect) + Send>;
+
+/// An editor call made on the calling thread, watched by the timer thread of a
+/// `PluginController`. The call is done once the sender is used or dropped.
+type Watch = (mpsc::Receiver<()>, Duration);
+
+/// Worker thread making the calls of a `PluginController` while its watchdog is enabled, and
+/// timer thread watching its editor calls.
+///
+/// The threads exit once the controller drops the sending ends of their queues.
+struct Watchdog {
+    timeout: Duration,
+    jobs: mpsc::Sender<Job>,
+    watches: mpsc::Sender<Watch>,
+}
+
+/// Channel pointer tables and padding passed to the plugin when processing.
+///
+/// Reserved for the block size before processing starts, so that processing a block does not
+/// allocate.
+struct Scratch<T: Float> {
+    inputs: Vec<*mut T>,
+    outputs: Vec<*mut T>,
+    /// Silent inputs and discarded outputs for channels missing from the caller's buffer.
+    padding: Vec<T>,
+}
+
+// The pointers are only used during a single process call.
+unsafe impl<T: Float + Send> Send for Scratch<T> {}
+
+impl<T: Float> Scratch<T> {
+    fn new() -> Scratch<T> {
+        Scratch {
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            padding: Vec::new(),
+        }
+    }
+
+    /// Make room for the channels of a plugin processing blocks of up to `samples`.
+    fn reserve(&mut self, num_inputs: usize, num_outputs: usize, samples: usize) {
+        self.inputs.clear();
+        self.inputs.reserve(num_inputs);
+        self.outputs.clear();
+        self.outputs.reserve(num_outputs);
+        self.padding.clear();
+        self.padding.reserve((num_inputs + num_outputs) * samples);
+    }
+}
+
+/// Bit of `Effect::state` set while the plugin is resumed.
+const RESUMED: usize = 1;
+/// Bit of `Effect::state` set while the processor is between `start_process` and `stop_process`.
+const PROCESSING: usize = 2;
+/// Bit of `Effect::state` set while the controller changes the state, so that the processor can
+/// not start processing in between checking the state and calling the plugin.
+const CHANGING: usize = 4;
+
+/// Shared handle to the raw `AEffect` of a loaded plugin.
+///
+/// The plugin is suspended and shut down once every handle referencing it has been dropped.
+#[allow(dead_code)] // To keep `lib` around.
+struct Effect {
+    effect: *mut AEffect,
+    lib: Arc<DynamicLibrary>,
+
+    /// Set once the plugin has panicked. No further calls are made into a crashed plugin.
+    crashed: AtomicBool,
+    /// Message of the panic which crashed the plugin, written once by whoever set `crashed`.
+    crash_message: UnsafeCell<Option<String>>,
+    /// Set once `crash_message` has been written and may be read.
+    crash_reported: AtomicBool,
+
+    /// Combination of the `RESUMED`, `PROCESSING` and `CHANGING` bits, shared by the processor
+    /// and the controller.
+    state: AtomicUsize,
+    /// Last block size passed to the plugin, used to reserve the `Scratch` of the processor.
+    block_size: AtomicUsize,
+}
+
+// The VST API allows the dispatcher and the process functions to be called from different threads.
+// Which opcodes are sent from where is restricted by `PluginProcessor` and `PluginController`.
+unsafe impl Send for Effect {}
+unsafe impl Sync for Effect {}
+
+impl Drop for Effect {
+    fn drop(&mut self) {
+        if self.crashed.load(Ordering::Acquire) {
+            // Skip the quarantine so the plugin can still free itself, see `PluginCrashed`
+            let (effect, dispatcher) = (self.effect, unsafe { (*self.effect).dispatcher });
+            self.catch(0, || {
+                dispatcher(effect, plugin::OpCode::Shutdown.into(), 0, 0, ptr::null_mut(), 0.0)
+            });
+            return;
+        }
+
+        // Plugins expect to be turned off before they are closed.
+        self.dispatch(plugin::OpCode::StateChanged, 0, 0, ptr::null_mut(), 0.0);
+        self.dispatch(plugin::OpCode::Shutdown, 0, 0, ptr::null_mut(), 0.0);
+    }
+}
+
+impl<T: Host> PluginLoader<T> {
+    /// Load a plugin at the given path with the given host.
+    ///
+    /// Because of the possibility of multi-threading problems that can occur when using plugins,
+    /// the host must be passed in via an `Arc<Mutex<T>>` object. This makes sure that even if the
+    /// plugins are multi-threaded no data race issues can occur.
+    ///
+    /// Upon success, this method returns a [`PluginLoader`](.) object which you can use to call
+    /// [`instance`](#method.instance) to create a new instance of the plugin.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::path::Path;
+    /// # use std::sync::{Arc, Mutex};
+    /// # use vst2::host::{Host, PluginLoader};
+    /// # let path = Path::new(".");
+    /// # struct MyHost;
+    /// # impl MyHost { fn new() -> MyHost { MyHost } }
+    /// # impl Host for MyHost {
+    /// #     fn automate(&mut self, _: i32, _: f32) {}
+    /// #     fn get_plugin_id(&self) -> i32 { 0 }
+    /// # }
+    /// // ...
+    /// let host = Arc::new(Mutex::new(MyHost::new()));
+    ///
+    /// let mut plugin = PluginLoader::load(path, host.clone()).unwrap();
+    ///
+    /// let instance = plugin.instance().unwrap();
+    /// // ...
+    /// ```
+    ///
+    /// # Linux/Windows
+    ///   * This should be a path to the library, typically ending in `.so`/`.dll`.
+    ///   * Possible full path: `/home/overdrivenpotato/.vst/u-he/Zebra2.64.so`
+    ///   * Possible full path: `C:\Program Files (x86)\VSTPlugins\iZotope Ozone 5.dll`
+    ///
+    /// # OS X
+    ///   * This can point to the `.vst` bundle itself, in which case the mach-o file is found
+    ///     through the bundle's `Info.plist`.
+    ///   * Plugin: `/Library/Audio/Plug-Ins/VST/iZotope Ozone 5.vst`
+    ///   * This can also point to the mach-o file within the `.vst` bundle directly.
+    ///   * Possible full path:
+    ///     `/Library/Audio/Plug-Ins/VST/iZotope Ozone 5.vst/Contents/MacOS/PluginHooksVST`
+    pub fn load(path: &Path, host: Arc<Mutex<T>>) -> Result<PluginLoader<T>, PluginLoadError> {
+        // Find the binary inside of a bundle
+        let binary = if path.is_dir() {
+            match bundle_binary(path) {
+                Some(p) => p,
+                None => return Err(PluginLoadError::InvalidPath)
+            }
+        } else {
+            path.to_path_buf()
+        };
+
+        // Try loading the library at the given path
+        let lib = match DynamicLibrary::open(Some(&binary)) {
+            Ok(l) => l,
+            Err(_) => return Err(PluginLoadError::InvalidPath)
+        };
+
+        Ok(PluginLoader {
+            main: unsafe {
+                      // Search the library for the VSTAPI entry point
+                      match lib.symbol("VSTPluginMain") {
+                          // Use `Fn(...)` instead of `*mut Fn(...)`.
+                          Ok(s) => mem::transmute::<*mut PluginMain, PluginMain>(s),
+                          _ => return Err(PluginLoadError::NotAPlugin)
+                      }
+                  },
+            lib: Arc::new(lib),
+            host: host,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Reload the plugin library from disk and recreate `instance` from it.
+    ///
+    /// This is meant for plugin developers iterating on builds. The state of `instance` is saved,
+    /// the library is closed and opened again, and a new instance is created with the saved state,
+    /// sample rate and block size restored. The new instance is initialized and resumed if the old
+    /// one was resumed.
+    ///
+    /// The library is only unloaded from memory if no other instances created by this loader are
+    /// still alive, otherwise the old code is reused.
+    ///
+    /// If the library cannot be loaded or instantiated again, the state saved from the old
+    /// instance is returned in the error.
+    pub fn reload(self, instance: PluginInstance)
+                  -> Result<(PluginLoader<T>, PluginInstance), ReloadError> {
+        let state = instance.save_state();
+
+        let PluginLoader { lib, host, path, .. } = self;
+        // The instance must be shut down before the library is closed.
+        drop(instance);
+        drop(lib);
+
+        let result = PluginLoader::load(&path, host)
+            .and_then(|mut loader| loader.instance().map(|instance| (loader, instance)));
+
+        match result {
+            Ok((loader, mut instance)) => {
+                instance.restore_state(state);
+                Ok((loader, instance))
+            }
+            Err(error) => {
+                Err(ReloadError {
+                    error: error,
+                    chunk: state.chunk,
+                    parameters: state.parameters,
+                })
+            }
+        }
+    }
+
+    /// Call the VST entry point and retrieve a (possibly null) pointer.
+    unsafe fn call_main(&mut self) -> *mut AEffect {
+        load_pointer = mem::transmute(Box::new(self.host.clone()));
+        (self.main)(callback_wrapper::<T>)
+    }
+
+    /// Try to create an instance of this VST plugin.
+    ///
+    /// If the instance is successfully created, a [`PluginInstance`](struct.PluginInstance.html)
+    /// is returned. This struct implements the [`Plugin` trait](../plugin/trait.Plugin.html).
+    pub fn instance(&mut self) -> Result<PluginInstance, PluginLoadError> {
+        // Call the plugin main function. This also passes the plugin main function as the closure
+        // could not return an error if the symbol wasn't found
+        let effect = unsafe { self.call_main() };
+
+        if effect.is_null() {
+            return Err(PluginLoadError::InstanceFailed);
+        }
+
+        // Make sure the plugin returned an actual `AEffect` struct before touching it any further.
+        // Anything else may not even hold a function to shut it down, so it is left alone.
+        if unsafe { (*effect).magic } != VST_MAGIC {
+            return Err(PluginLoadError::NotAPlugin);
+        }
+
+        unsafe {
+            // Move the host to the heap and add it to the `AEffect` struct for future reference
+            (*effect).reserved1 = mem::transmute(Box::new(self.host.clone()));
+            // Remember the initial latency so that changes can be reported to the host
+            (*effect).reserved2 = (*effect).initialDelay as isize;
+        }
+
+        let effect = Effect::new(effect, self.lib.clone());
+
+        // VST 1.x plugins do not answer this opcode.
+        if normalize_vst_version(effect.opcode(plugin::OpCode::GetApiVersion)) < MIN_VST_VERSION {
+            // Dropping the effect sends `Shutdown`, so the plugin frees itself
+            drop(effect);
+            return Err(PluginLoadError::IncompatibleVersion);
+        }
+
+        Ok(PluginInstance::new(effect))
+    }
+}
+
+impl Effect {
+    fn new(effect: *mut AEffect, lib: Arc<DynamicLibrary>) -> Effect {
+        Effect {
+            effect: effect,
+            lib: lib,
+            crashed: AtomicBool::new(false),
+            crash_message: UnsafeCell::new(None),
+            crash_reported: AtomicBool::new(false),
+            state: AtomicUsize::new(0),
+            block_size: AtomicUsize::new(0),
+        }
+    }
+
+    /// Send a dispatch message to the plugin.
+    fn dispatch(&self,
+                opcode: plugin::OpCode,
+                index: i32,
+                value: isize,
+                ptr: *mut c_void,
+                opt: f32)
+                -> isize {
+        let dispatcher = unsafe {
+            // A fn pointer can not be null, but a broken plugin may leave it null
+            ptr::read(ptr::addr_of!((*self.effect).dispatcher) as *const Option<api::DispatcherProc>)
+        };
+        let dispatcher = dispatcher.expect("Plugin was not loaded correctly.");
+        self.guard(0, || dispatcher(self.effect, opcode.into(), index, value, ptr, opt))
+    }
+
+    /// Call into the plugin, catching Rust panics. Signals such as segmentation faults are not
+    /// handled.
+    ///
+    /// If the plugin panics, or has panicked before, `default` is returned and the plugin is
+    /// marked as crashed.
+    fn guard<F: FnOnce() -> R, R>(&self, default: R, f: F) -> R {
+        if self.crashed.load(Ordering::Acquire) {
+            return default;
+        }
+
+        self.catch(default, f)
+    }
+
+    /// Call into the plugin like `guard`, even if it has crashed before.
+    fn catch<F: FnOnce() -> R, R>(&self, default: R, f: F) -> R {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result,
+            Err(payload) => {
+                let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                    s.to_string()
+                } else if let Some(s) = payload.downcast_ref::<String>() {
+                    s.clone()
+                } else {
+                    "Unknown panic".to_string()
+                };
+
+                self.quarantine(message);
+                default
+            }
+        }
+    }
+
+    /// Mark the plugin as crashed so that no further calls are made into it.
+    ///
+    /// This takes no locks, as it may run on the audio thread. Only the first crash is reported.
+    fn quarantine(&self, message: String) {
+        error!("Plugin crashed: {}", message);
+        if self.crashed.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        // Only the thread which set `crashed` writes the message, and nobody reads it before
+        // `crash_reported` is set.
+        unsafe {
+            *self.crash_message.get() = Some(message);
+        }
+        self.crash_reported.store(true, Ordering::Release);
+    }
+
+    fn crashed(&self) -> Option<PluginCrashed> {
+        if !self.crashed.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let message = if self.crash_reported.load(Ordering::Acquire) {
+            unsafe { (*self.crash_message.get()).clone().unwrap_or_default() }
+        } else {
+            // The message is still being written by the thread which quarantined the plugin
+            String::new()
+        };
+        Some(PluginCrashed { message: message })
+    }
+
+    fn read_string(&self, opcode: plugin::OpCode, max: u64) -> String {
+        self.read_string_indexed(opcode, 0, max)
+    }
+
+    fn read_string_indexed(&self, opcode: plugin::OpCode, index: i32, max: u64) -> String {
+        let mut buf = vec![0; max as usize];
+        self.dispatch(opcode, index, 0, unsafe { mem::transmute(buf.as_mut_ptr()) }, 0.0);
+        read_fixed(&buf)
+    }
+
+    fn parameter_string(&self, opcode: plugin::OpCode, index: i32) -> String {
+        // Many plugins write past `MAX_PARAM_STR_LEN`, so a larger buffer is used.
+        self.read_string_indexed(opcode, index, MAX_LABEL as u64)
+    }
+
+    fn preset_name(&self, preset: i32) -> String {
+        // Many plugins write past `MAX_PRESET_NAME_LEN`, so a larger buffer is used.
+        self.read_string_indexed(plugin::OpCode::GetPresetName, preset, MAX_LABEL as u64)
+    }
+
+    fn set_preset_name(&self, name: &str) {
+        let name: String = name.chars().take(MAX_PRESET_NAME_LEN as usize - 1).collect();
+        if let Ok(name) = CString::new(name) {
+            self.dispatch(plugin::OpCode::SetCurrentPresetName, 0, 0,
+                          name.as_ptr() as *mut c_void, 0.0);
+        }
+    }
+
+    fn string_to_parameter(&self, index: i32, text: &str) -> bool {
+        let text = match CString::new(text) {
+            Ok(t) => t,
+            Err(_) => return false
+        };
+
+        self.dispatch(plugin::OpCode::StringToParameter, index, 0,
+                      text.as_ptr() as *mut c_void, 0.0) != 0
+    }
+
+    /// Get the chunk data of the current preset, or of the whole bank if `preset` is false.
+    fn get_chunk(&self, preset: bool) -> Vec<u8> {
+        let mut data: *mut u8 = ptr::null_mut();
+        let len = self.dispatch(plugin::OpCode::GetData, preset as i32, 0,
+                                &mut data as *mut *mut u8 as *mut c_void, 0.0);
+
+        if data.is_null() || len <= 0 {
+            return Vec::new();
+        }
+
+        // The data is owned by the plugin, so it is copied
+        unsafe { slice::from_raw_parts(data, len as usize) }.to_vec()
+    }
+
+    /// Load chunk data into the current preset, or into the whole bank if `preset` is false.
+    fn set_chunk(&self, preset: bool, data: &[u8]) {
+        self.dispatch(plugin::OpCode::SetData, preset as i32, data.len() as isize,
+                      data.as_ptr() as *mut c_void, 0.0);
+    }
+
+    fn parameter_properties(&self, index: i32) -> Option<ParameterProperties> {
+        let mut raw: api::ParameterProperties = unsafe { mem::zeroed() };
+
+        let supported = self.dispatch(plugin::OpCode::GetParameterProperties, index, 0,
+                                      &mut raw as *mut _ as *mut c_void, 0.0);
+        if supported != 0 { Some(raw.into()) } else { None }
+    }
+
+    fn parameter_info(&self, index: i32) -> ParameterInfo {
+        ParameterInfo {
+            name: self.parameter_string(plugin::OpCode::GetParameterName, index),
+            label: self.parameter_string(plugin::OpCode::GetParameterLabel, index),
+            display: self.parameter_string(plugin::OpCode::GetParameterDisplay, index),
+            value: self.get_parameter(index),
+            automatable: self.can_be_automated(index),
+        }
+    }
+
+    fn can_be_automated(&self, index: i32) -> bool {
+        self.dispatch(plugin::OpCode::CanBeAutomated, index, 0, ptr::null_mut(), 0.0) != 0
+    }
+
+    fn opcode(&self, opcode: plugin::OpCode) -> isize {
+        self.dispatch(opcode, 0, 0, ptr::null_mut(), 0.0)
+    }
+
+    fn latency(&self) -> i32 {
+        unsafe { (*self.effect).initialDelay }
+    }
+
+    #[cfg(feature = "window-handle")]
+    fn open_editor<W: HasRawWindowHandle>(&self, parent: &W) -> bool {
+        match parent_pointer(parent.raw_window_handle()) {
+            Some(ptr) => self.dispatch(plugin::OpCode::EditorOpen, 0, 0, ptr, 0.0) != 0,
+            None => false,
+        }
+    }
+
+    fn editor_size(&self) -> Option<(i32, i32)> {
+        let mut rect: *mut Rect = ptr::null_mut();
+        self.dispatch(plugin::OpCode::EditorGetRect, 0, 0,
+                      &mut rect as *mut *mut Rect as *mut c_void, 0.0);
+
+        if rect.is_null() {
+            return None;
+        }
+
+        let rect = unsafe { *rect };
+        Some(((rect.right - rect.left) as i32, (rect.bottom - rect.top) as i32))
+    }
+
+    /// Query the plugin information from the `AEffect` struct and the dispatcher.
+    fn read_info(&self) -> Info {
+        use plugin::OpCode as op;
+
+        unsafe {
+            use api::flags::*;
+
+            let raw: &AEffect = &*self.effect;
+            let flags = Plugin::from_bits_truncate(raw.flags);
+
+            Info {
+                name: self.read_string(op::GetProductName, MAX_PRODUCT_STR_LEN as u64),
+                vendor: self.read_string(op::GetVendorName, MAX_VENDOR_STR_LEN as u64),
+
+                presets: raw.numPrograms,
+                parameters: raw.numParams,
+                inputs: raw.numInputs,
+                outputs: raw.numOutputs,
+
+                unique_id: raw.uniqueId,
+                version: raw.version,
+
+                category: Category::from_raw(self.opcode(op::GetCategory)),
+
+                initial_delay: raw.initialDelay,
+
+                preset_chunks: flags.intersects(PROGRAM_CHUNKS),
+                f64_precision: flags.intersects(CAN_DOUBLE_REPLACING),
+                silent_when_stopped: flags.intersects(NO_SOUND_IN_STOP),
+                soft_bypass: self.can_do(&plugin::CanDo::Bypass) == Supported::Yes,
+                is_synth: flags.intersects(IS_SYNTH),
+                midi_inputs: self.opcode(op::GetNumMidiInputs) as i32,
+                midi_outputs: self.opcode(op::GetNumMidiOutputs) as i32,
+                // Not exposed to hosts
+                legacy_host_support: false,
+                flush_denormals: false,
+            }
+        }
+    }
+
+    fn set_sample_rate(&self, rate: f32) {
+        self.dispatch(plugin::OpCode::SetSampleRate, 0, 0, ptr::null_mut(), rate);
+    }
+
+    fn set_block_size(&self, size: i64) {
+        self.block_size.store(cmp::max(size, 0) as usize, Ordering::Release);
+        self.dispatch(plugin::OpCode::SetBlockSize, 0, size as isize, ptr::null_mut(), 0.0);
+    }
+
+    fn set_state(&self, on: bool) {
+        if on {
+            self.state.fetch_or(RESUMED, Ordering::AcqRel);
+        } else {
+            self.state.fetch_and(!RESUMED, Ordering::AcqRel);
+        }
+        self.state_changed(on);
+    }
+
+    fn state_changed(&self, on: bool) {
+        self.dispatch(plugin::OpCode::StateChanged, 0, on as isize, ptr::null_mut(), 0.0);
+    }
+
+    fn set_processing(&self, processing: bool) {
+        if processing {
+            self.state.fetch_or(PROCESSING, Ordering::AcqRel);
+            self.opcode(plugin::OpCode::StartProcess);
+        } else {
+            self.opcode(plugin::OpCode::StopProcess);
+            self.state.fetch_and(!PROCESSING, Ordering::AcqRel);
+        }
+    }
+
+    /// Call `f` if the state is exactly `from`, and leave it in `to` afterwards.
+    ///
+    /// The state is marked as changing during the call, so `start_processing` fails until it
+    /// returns. Returns `None` without calling `f` if the state differs.
+    fn change_state<F, R>(&self, from: usize, to: usize, f: F) -> Option<R>
+        where F: FnOnce() -> R
+    {
+        if self.state.compare_exchange(from, from | CHANGING, Ordering::AcqRel, Ordering::Acquire)
+               .is_err() {
+            return None;
+        }
+        let result = f();
+        self.state.store(to, Ordering::Release);
+        Some(result)
+    }
+
+    /// Start processing if the plugin is resumed and its state is not being changed.
+    fn start_processing(&self) -> bool {
+        if self.state.compare_exchange(RESUMED, RESUMED | PROCESSING,
+                                       Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return false;
+        }
+        self.opcode(plugin::OpCode::StartProcess);
+        true
+    }
+
+    /// Stop processing. The state only changes once the plugin has been told, so the controller
+    /// can not suspend the plugin before.
+    fn stop_processing(&self) {
+        if self.state.load(Ordering::Acquire) & PROCESSING != 0 {
+            self.opcode(plugin::OpCode::StopProcess);
+            self.state.fetch_and(!PROCESSING, Ordering::AcqRel);
+        }
+    }
+
+    fn is_resumed(&self) -> bool {
+        self.state.load(Ordering::Acquire) & RESUMED != 0
+    }
+
+    /// Get the properties of an input or output channel, or `None` if the plugin does not
+    /// provide them.
+    fn channel_info(&self, opcode: plugin::OpCode, index: i32) -> Option<ChannelInfo> {
+        let mut raw: api::ChannelProperties = unsafe { mem::zeroed() };
+
+        match self.dispatch(opcode, index, 0, &mut raw as *mut _ as *mut c_void, 0.0) {
+            0 => None,
+            _ => Some(ChannelInfo::from(raw)),
+        }
+    }
+
+    fn midi_programs(&self, channel: i32) -> Vec<plugin::MidiProgram> {
+        let mut programs = Vec::new();
+        let mut raw: api::MidiProgramName = unsafe { mem::zeroed() };
+
+        // Each call returns the number of programs, so the first call tells how many to request
+        loop {
+            raw.this_program_index = programs.len() as i32;
+            let count = self.dispatch(plugin::OpCode::GetMidiProgramName, channel, 0,
+                                      &mut raw as *mut _ as *mut c_void, 0.0);
+            if programs.len() as isize >= count {
+                return programs;
+            }
+            programs.push(plugin::MidiProgram::from(&raw));
+        }
+    }
+
+    fn current_midi_program(&self, channel: i32) -> Option<i32> {
+        let mut raw: api::MidiProgramName = unsafe { mem::zeroed() };
+        raw.this_program_index = -1;
+
+        let current = self.dispatch(plugin::OpCode::GetCurrentMidiProgram, channel, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0);
+
+        // Plugins without MIDI programs often return 0 without filling in the program
+        if current < 0 || raw.this_program_index < 0 { None } else { Some(current as i32) }
+    }
+
+    fn midi_program_categories(&self, channel: i32) -> Vec<plugin::MidiProgramCategory> {
+        let mut categories = Vec::new();
+        let mut raw: api::MidiProgramCategory = unsafe { mem::zeroed() };
+
+        loop {
+            raw.this_category_index = categories.len() as i32;
+            let count = self.dispatch(plugin::OpCode::GetMidiProgramCategory, channel, 0,
+                                      &mut raw as *mut _ as *mut c_void, 0.0);
+            if categories.len() as isize >= count {
+                return categories;
+            }
+            categories.push(plugin::MidiProgramCategory {
+                name: read_fixed(&raw.name),
+                parent: if raw.parent_category_index < 0 {
+                    None
+                } else {
+                    Some(raw.parent_category_index)
+                },
+            });
+        }
+    }
+
+    fn midi_key_name(&self, channel: i32, program: i32, key: i32) -> Option<String> {
+        let mut raw: api::MidiKeyName = unsafe { mem::zeroed() };
+        raw.this_program_index = program;
+        raw.this_key_number = key;
+
+        match self.dispatch(plugin::OpCode::GetMidiKeyName, channel, 0,
+                            &mut raw as *mut _ as *mut c_void, 0.0) {
+            0 => None,
+            _ => Some(read_fixed(&raw.key_name)),
+        }
+    }
+
+    /// Ask the plugin whether it supports a capability.
+    fn can_do(&self, can_do: &plugin::CanDo) -> Supported {
+        let value = match CString::new(can_do.as_str()) {
+            Ok(v) => v,
+            Err(_) => return Supported::No
+        };
+
+        Supported::from(self.dispatch(plugin::OpCode::CanDo, 0, 0,
+                                      value.as_ptr() as *mut c_void, 0.0))
+    }
+
+    fn set_bypass(&self, bypass: bool) -> bool {
+        self.dispatch(plugin::OpCode::SoftBypass, 0, bypass as isize, ptr::null_mut(), 0.0) != 0
+    }
+
+    fn get_parameter(&self, index: i32) -> f32 {
+        self.guard(0.0, || unsafe { ((*self.effect).getParameter)(self.effect, index) })
+    }
+
+    fn set_parameter(&self, index: i32, value: f32) {
+        self.guard((), || unsafe { ((*self.effect).setParameter)(self.effect, index, value) })
+    }
+
+    fn channel_counts(&self) -> (usize, usize) {
+        unsafe {
+            ((*self.effect).numInputs.max(0) as usize, (*self.effect).numOutputs.max(0) as usize)
+        }
+    }
+
+    /// Reserve `scratch` for the current channel counts and block size.
+    fn reserve<T: Float>(&self, scratch: &mut Scratch<T>) {
+        let (num_inputs, num_outputs) = self.channel_counts();
+        scratch.reserve(num_inputs, num_outputs, self.block_size.load(Ordering::Acquire));
+    }
+
+    /// Fill the pointer tables of `scratch` with the channels of a buffer, with exactly as many
+    /// inputs and outputs as the plugin expects.
+    ///
+    /// Missing channels point into the padding of `scratch`: silence for inputs and discarded
+    /// samples for outputs. Surplus channels are not passed to the plugin. Nothing is allocated
+    /// unless the buffer is longer than the reserved block size or the channel counts changed.
+    fn channel_pointers<T: Float>(&self,
+                                 buffer: &mut AudioBuffer<T>,
+                                 scratch: &mut Scratch<T>) {
+        let (num_inputs, num_outputs) = self.channel_counts();
+        let samples = buffer.samples();
+        let (inputs, mut outputs) = buffer.split_mut();
+
+        let missing = num_inputs.saturating_sub(inputs.len()) +
+                      num_outputs.saturating_sub(outputs.len());
+        scratch.padding.clear();
+        scratch.padding.resize(missing * samples, T::zero());
+
+        let base = scratch.padding.as_mut_ptr();
+        let mut padded = 0;
+        let mut pad = || {
+            padded += 1;
+            unsafe { base.offset(((padded - 1) * samples) as isize) }
+        };
+
+        // The plugin receives the inputs as mutable pointers, but must not write to them
+        scratch.inputs.clear();
+        scratch.inputs.extend((0..num_inputs).map(|i| match inputs.get(i) {
+            Some(channel) => channel.as_ptr() as *mut T,
+            None => pad(),
+        }));
+        scratch.outputs.clear();
+        scratch.outputs.extend((0..num_outputs).map(|i| match outputs.get_mut(i) {
+            Some(channel) => channel.as_mut_ptr(),
+            None => pad(),
+        }));
+    }
+
+    fn process(&self, mut buffer: AudioBuffer<f32>, scratch: &mut Scratch<f32>) {
+        let samples = buffer.samples() as i32;
+        self.channel_pointers(&mut buffer, scratch);
+
+        self.guard((), || unsafe {
+            ((*self.effect).processReplacing)(self.effect,
+                                              scratch.inputs.as_mut_ptr(),
+                                              scratch.outputs.as_mut_ptr(),
+                                              samples)
+        });
+    }
+
+    /// Process an `f64` buffer, with `f32` copies of its channels in `converted` if the plugin
+    /// has no 64 bit path.
+    fn process_f64(&self,
+                   mut buffer: AudioBuffer<f64>,
+                   scratch: &mut Scratch<f64>,
+                   converted: &mut Scratch<f32>) {
+        use api::flags::{Plugin, CAN_DOUBLE_REPLACING};
+
+        // Plugins without a 64 bit path, e.g. for VST 2.3, may leave the function null
+        let (flags, process) = unsafe {
+            ((*self.effect).flags,
+             ptr::read(ptr::addr_of!((*self.effect).processReplacingF64)
+                           as *const Option<api::ProcessProcF64>))
+        };
+        let process = match process {
+            Some(process) if Plugin::from_bits_truncate(flags).intersects(CAN_DOUBLE_REPLACING) => {
+                process
+            }
+            _ => return self.process_converted(buffer, converted),
+        };
+
+        let samples = buffer.samples() as i32;
+        self.channel_pointers(&mut buffer, scratch);
+
+        self.guard((), || {
+            process(self.effect, scratch.inputs.as_mut_ptr(), scratch.outputs.as_mut_ptr(), samples)
+        });
+    }
+
+    /// Process an `f64` buffer through `processReplacing`, with `f32` copies of the channels kept
+    /// in the padding of `scratch`, which is reserved for all channels of the plugin.
+    fn process_converted(&self, mut buffer: AudioBuffer<f64>, scratch: &mut Scratch<f32>) {
+        let (num_inputs, num_outputs) = self.channel_counts();
+        let samples = buffer.samples();
+        let (inputs, mut outputs) = buffer.split_mut();
+
+        // Missing inputs are silent, missing outputs are discarded
+        scratch.padding.clear();
+        scratch.padding.resize((num_inputs + num_outputs) * samples, 0.0);
+        for (index, input) in inputs.iter().enumerate().take(num_inputs) {
+            let copy = &mut scratch.padding[index * samples..][..samples];
+            for (y, x) in copy.iter_mut().zip(input.iter()) {
+                *y = *x as f32;
+            }
+        }
+        for (index, output) in outputs.iter_mut().enumerate().take(num_outputs) {
+            let copy = &mut scratch.padding[(num_inputs + index) * samples..][..samples];
+            for (y, x) in copy.iter_mut().zip(output.iter()) {
+                *y = *x as f32;
+            }
+        }
+
+        let base = scratch.padding.as_mut_ptr();
+        let channel = |index: usize| unsafe { base.offset((index * samples) as isize) };
+        scratch.inputs.clear();
+        scratch.inputs.extend((0..num_inputs).map(&channel));
+        scratch.outputs.clear();
+        scratch.outputs.extend((num_inputs..num_inputs + num_outputs).map(&channel));
+
+        self.guard((), || unsafe {
+            ((*self.effect).processReplacing)(self.effect,
+                                              scratch.inputs.as_mut_ptr(),
+                                              scratch.outputs.as_mut_ptr(),
+                                              samples as i32)
+        });
+
+        for (index, output) in outputs.iter_mut().enumerate().take(num_outputs) {
+            let copy = &scratch.padding[(num_inputs + index) * samples..][..samples];
+            for (y, x) in output.iter_mut().zip(copy.iter()) {
+                *y = *x as f64;
+            }
+        }
+    }
+
+    fn process_events(&self, events: &mut SendEventBuffer) {
+        if !events.is_empty() {
+            self.dispatch(plugin::OpCode::ProcessEvents,
+                          0, 0, events.as_raw() as *mut c_void, 0.0);
+        }
+        events.clear();
+    }
+
+    /// Process variable I/O with the channel pointers and sample counts of the inputs and
+    /// outputs.
+    fn process_var_io(&self,
+                      mut input_ptrs: Vec<*mut f32>,
+                      input_samples: usize,
+                      mut output_ptrs: Vec<*mut f32>,
+                      output_samples: usize)
+                      -> Option<(usize, usize)> {
+        let (mut input_processed, mut output_processed) = (0, 0);
+
+        let mut raw = api::VariableIo {
+            inputs: input_ptrs.as_mut_ptr(),
+            outputs: output_ptrs.as_mut_ptr(),
+            num_samples_input: input_samples as i32,
+            num_samples_output: output_samples as i32,
+            num_samples_input_processed: &mut input_processed,
+            num_samples_output_processed: &mut output_processed,
+        };
+
+        match self.dispatch(plugin::OpCode::ProcessVarIo, 0, 0,
+                            &mut raw as *mut _ as *mut c_void, 0.0) {
+            0 => None,
+            // Plugins may report anything, but never more than was passed
+            _ => Some((cmp::min(cmp::max(input_processed, 0) as usize, input_samples),
+                       cmp::min(cmp::max(output_processed, 0) as usize, output_samples))),
+        }
+    }
+}
+
+impl PluginInstance {
+    fn new(effect: Effect) -> PluginInstance {
+        PluginInstance {
+            info: effect.read_info(),
+            effect: Arc::new(effect),
+
+            sample_rate: None,
+            block_size: None,
+            scratch: Scratch::new(),
+            scratch_f64: Scratch::new(),
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Save everything needed to recreate this instance in the same state.
+    fn save_state(&self) -> SavedState {
+        let chunk = if self.info.preset_chunks {
+            Some(self.effect.get_chunk(false))
+        } else {
+            None
+        };
+
+        SavedState {
+            chunk: chunk,
+            parameters: (0..self.info.parameters).map(|i| self.effect.get_parameter(i)).collect(),
+            sample_rate: self.sample_rate,
+            block_size: self.block_size,
+            resumed: self.effect.is_resumed(),
+        }
+    }
+
+    /// Restore a state saved with `save_state`, possibly from a different instance.
+    fn restore_state(&mut self, state: SavedState) {
+        self.init();
+
+        if let Some(rate) = state.sample_rate {
+            self.set_sample_rate(rate);
+        }
+        if let Some(size) = state.block_size {
+            self.set_block_size(size);
+        }
+
+        match state.chunk {
+            Some(ref chunk) if self.info.preset_chunks => self.effect.set_chunk(false, chunk),
+            _ => {
+                for (i, value) in state.parameters.into_iter().enumerate() {
+                    if (i as i32) < self.info.parameters {
+                        self.effect.set_parameter(i as i32, value);
+                    }
+                }
+            }
+        }
+
+        if state.resumed {
+            self.resume();
+        }
+    }
+
+    /// Check whether the plugin has crashed.
+    ///
+    /// Calls into the plugin are guarded so that a Rust panic in the plugin does not take down the
+    /// host; see [`PluginCrashed`](struct.PluginCrashed.html) for what is not caught. After a
+    /// crash the plugin is quarantined and this returns the error.
+    pub fn crashed(&self) -> Option<PluginCrashed> {
+        self.effect.crashed()
+    }
+
+    /// Get the current latency of the plugin in samples.
+    ///
+    /// This is read from the plugin on every call and reflects changes made at runtime, like
+    /// `get_info().initial_delay`.
+    pub fn latency(&self) -> i32 {
+        self.effect.latency()
+    }
+
+    /// Re-read the plugin information from the plugin.
+    ///
+    /// This should be called when the plugin reports that its inputs, outputs or initial delay
+    /// have changed (see [`Host::io_changed`](trait.Host.html#method.io_changed)), after which
+    /// the host should rebuild its buffers from the new [`get_info`](#method.get_info) values.
+    pub fn refresh_info(&mut self) {
+        self.info = self.effect.read_info();
+    }
+
+    /// Get the name, label, display string and value of the parameter at `index`.
+    pub fn get_parameter_info(&self, index: i32) -> ParameterInfo {
+        self.effect.parameter_info(index)
+    }
+
+    /// Iterate over the index and information of every parameter of the plugin.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use vst2::host::PluginInstance;
+    /// # fn get_instance() -> PluginInstance { unimplemented!() }
+    /// let instance = get_instance();
+    ///
+    /// for (index, param) in instance.parameters() {
+    ///     println!("{}: {} = {} {}", index, param.name, param.display, param.label);
+    /// }
+    /// ```
+    pub fn parameters(&self) -> Parameters {
+        Parameters {
+            effect: &self.effect,
+            index: 0,
+            count: self.info.parameters,
+        }
+    }
+
+    /// Set the parameter at `index` from a text value (e.g. "-6.0 dB").
+    ///
+    /// Returns `false` if the plugin does not support text entry or could not parse `text`.
+    pub fn string_to_parameter(&mut self, index: i32, text: &str) -> bool {
+        self.effect.string_to_parameter(index, text)
+    }
+
+    /// Send all events of `events` to the plugin in a single block and clear it.
+    ///
+    /// Events apply to the next call to `process`. Nothing is allocated.
+    pub fn send_events(&mut self, events: &mut SendEventBuffer) {
+        self.effect.process_events(events);
+    }
+
+    /// Get the VST API version supported by the plugin (e.g. 2400 for VST 2.4).
+    ///
+    /// Plugins which do not answer this are older than VST 2, in which case 0 is returned. Hosts
+    /// can use this to avoid sending opcodes which the plugin does not know.
+    pub fn get_vst_version(&self) -> i32 {
+        self.effect.opcode(plugin::OpCode::GetApiVersion) as i32
+    }
+
+    /// Get the effect name of the plugin, which may differ from the product name in `Info`.
+    pub fn get_effect_name(&self) -> String {
+        self.effect.read_string(plugin::OpCode::GetEffectName, MAX_EFFECT_NAME_LEN as u64)
+    }
+
+    /// Open the plugin editor as a child of `parent`, e.g. a window created with winit.
+    ///
+    /// Returns `false` if the plugin has no editor or the window type is not supported by VST on
+    /// this platform. The editor must be idled regularly with `editor_idle` while it is open.
+    #[cfg(feature = "window-handle")]
+    pub fn open_editor<W: HasRawWindowHandle>(&mut self, parent: &W) -> bool {
+        self.effect.open_editor(parent)
+    }
+
+    /// Close the plugin editor.
+    pub fn close_editor(&mut self) {
+        self.effect.opcode(plugin::OpCode::EditorClose);
+    }
+
+    /// Give the open editor time to redraw, should be called regularly from the UI thread.
+    pub fn editor_idle(&mut self) {
+        self.effect.opcode(plugin::OpCode::EditorIdle);
+    }
+
+    /// Get the size of the editor window in pixels, or `None` if the plugin has no editor.
+    pub fn editor_size(&self) -> Option<(i32, i32)> {
+        self.effect.editor_size()
+    }
+
+    /// Split this instance into a processor for the audio thread and a controller for the UI
+    /// thread.
+    ///
+    /// The plugin is shut down once both halves have been dropped.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::thread;
+    /// # use vst2::host::PluginInstance;
+    /// # fn get_instance() -> PluginInstance { unimplemented!() }
+    /// let instance = get_instance();
+    /// let (mut processor, mut controller) = instance.split();
+    ///
+    /// controller.set_sample_rate(44100.0);
+    /// controller.set_block_size(512);
+    /// controller.resume();
+    ///
+    /// let audio = thread::spawn(move || {
+    ///     processor.start_process();
+    ///     // ...
+    ///     processor.stop_process();
+    /// });
+    /// # audio.join().unwrap();
+    /// ```
+    pub fn split(self) -> (PluginProcessor, PluginController) {
+        let processor = PluginProcessor {
+            effect: self.effect.clone(),
+            info: self.info.clone(),
+            scratch: self.scratch,
+            scratch_f64: self.scratch_f64,
+        };
+
+        let controller = PluginController {
+            effect: self.effect,
+            info: self.info,
+            _not_send: PhantomData,
+            watchdog: None,
+        };
+
+        (processor, controller)
+    }
+
+    /// Inform the plugin of the sample rate it will be processing at.
+    ///
+    /// This should be called before the plugin is resumed.
+    pub fn set_sample_rate(&mut self, rate: f32) {
+        self.sample_rate = Some(rate);
+        self.effect.set_sample_rate(rate);
+    }
+
+    /// Inform the plugin of the maximum number of samples it will be asked to process at once.
+    ///
+    /// This should be called before the plugin is resumed.
+    pub fn set_block_size(&mut self, size: i64) {
+        self.block_size = Some(size);
+        self.effect.set_block_size(size);
+        self.reserve();
+    }
+
+    /// Turn the plugin on. This must be called before any audio is processed.
+    pub fn resume(&mut self) {
+        self.reserve();
+        self.effect.set_state(true);
+    }
+
+    /// Reserve the channel tables used by `process` for the current block size.
+    fn reserve(&mut self) {
+        self.effect.reserve(&mut self.scratch);
+        if self.info.f64_precision {
+            self.effect.reserve(&mut self.scratch_f64);
+        }
+    }
+
+    /// Turn the plugin off. This is called automatically when the instance is dropped.
+    pub fn suspend(&mut self) {
+        self.effect.set_state(false);
+    }
+
+    /// Notify the plugin that processing is about to start.
+    ///
+    /// This should be called after the plugin is resumed and before the first process call.
+    pub fn start_process(&mut self) {
+        self.effect.set_processing(true);
+    }
+
+    /// Notify the plugin that processing has stopped.
+    ///
+    /// This should be called after the last process call and before the plugin is suspended.
+    pub fn stop_process(&mut self) {
+        self.effect.set_processing(false);
+    }
+}
+
+impl Plugin for PluginInstance {
+    fn init(&mut self) {
+        self.effect.opcode(plugin::OpCode::Initialize);
+    }
+
+    /// Give the plugin idle time. Only needed for plugins which requested it with `need_idle`.
+    fn idle(&mut self) {
+        self.effect.opcode(plugin::OpCode::Idle);
+    }
+
+    /// The information read when the plugin was loaded or last refreshed, with the current
+    /// initial delay.
+    fn get_info(&self) -> plugin::Info {
+        plugin::Info { initial_delay: self.effect.latency(), ..self.info.clone() }
+    }
+
+    /// Change the current preset, bracketed by `BeginSetPreset` and `EndSetPreset`.
+    fn change_preset(&mut self, preset: i32) {
+        self.effect.opcode(plugin::OpCode::BeginSetPreset);
+        self.effect.dispatch(plugin::OpCode::ChangePreset, 0, preset as isize, ptr::null_mut(), 0.0);
+        self.effect.opcode(plugin::OpCode::EndSetPreset);
+    }
+
+    fn get_preset_num(&self) -> i32 {
+        self.effect.opcode(plugin::OpCode::GetCurrentPresetNum) as i32
+    }
+
+    fn set_preset_name(&mut self, name: String) {
+        self.effect.set_preset_name(&name);
+    }
+
+    fn get_preset_name(&self, preset: i32) -> String {
+        self.effect.preset_name(preset)
+    }
+
+    fn begin_set_preset(&mut self) {
+        self.effect.opcode(plugin::OpCode::BeginSetPreset);
+    }
+
+    fn end_set_preset(&mut self) {
+        self.effect.opcode(plugin::OpCode::EndSetPreset);
+    }
+
+    fn get_parameter(&self, index: i32) -> f32 {
+        self.effect.get_parameter(index)
+    }
+
+    fn set_parameter(&mut self, index: i32, value: f32) {
+        self.effect.set_parameter(index, value);
+    }
+
+    fn get_parameter_name(&self, index: i32) -> String {
+        self.effect.parameter_string(plugin::OpCode::GetParameterName, index)
+    }
+
+    fn get_parameter_label(&self, index: i32) -> String {
+        self.effect.parameter_string(plugin::OpCode::GetParameterLabel, index)
+    }
+
+    fn get_parameter_text(&self, index: i32) -> String {
+        self.effect.parameter_string(plugin::OpCode::GetParameterDisplay, index)
+    }
+
+    fn string_to_parameter(&mut self, index: i32, text: String) -> bool {
+        self.effect.string_to_parameter(index, &text)
+    }
+
+    fn can_be_automated(&self, index: i32) -> bool {
+        self.effect.can_be_automated(index)
+    }
+
+    fn get_parameter_properties(&self, index: i32) -> Option<ParameterProperties> {
+        self.effect.parameter_properties(index)
+    }
+
+    fn can_do(&self, can_do: plugin::CanDo) -> Supported {
+        self.effect.can_do(&can_do)
+    }
+
+    fn vendor_specific(&mut self, index: i32, value: isize, ptr: *mut c_void, opt: f32) -> isize {
+        self.effect.dispatch(plugin::OpCode::VendorSpecific, index, value, ptr, opt)
+    }
+
+    /// Get information about an input channel.
+    ///
+    /// Plugins which don't provide channel information get a generic name, as with the default
+    /// `Plugin` implementation.
+    fn get_input_info(&self, input: i32) -> ChannelInfo {
+        self.effect.channel_info(plugin::OpCode::GetInputInfo, input).unwrap_or_else(|| {
+            ChannelInfo::new(format!("Input channel {}", input),
+                             Some(format!("In {}", input)),
+                             true, None)
+        })
+    }
+
+    /// Get information about an output channel.
+    ///
+    /// Plugins which don't provide channel information get a generic name, as with the default
+    /// `Plugin` implementation.
+    fn get_output_info(&self, output: i32) -> ChannelInfo {
+        self.effect.channel_info(plugin::OpCode::GetOutputInfo, output).unwrap_or_else(|| {
+            ChannelInfo::new(format!("Output channel {}", output),
+                             Some(format!("Out {}", output)),
+                             true, None)
+        })
+    }
+
+    fn get_midi_programs(&self, channel: i32) -> Vec<plugin::MidiProgram> {
+        self.effect.midi_programs(channel)
+    }
+
+    fn get_current_midi_program(&self, channel: i32) -> Option<i32> {
+        self.effect.current_midi_program(channel)
+    }
+
+    fn get_midi_program_categories(&self, channel: i32) -> Vec<plugin::MidiProgramCategory> {
+        self.effect.midi_program_categories(channel)
+    }
+
+    fn has_midi_programs_changed(&self, channel: i32) -> bool {
+        self.effect.dispatch(plugin::OpCode::HasMidiProgramsChanged, channel, 0,
+                             ptr::null_mut(), 0.0) != 0
+    }
+
+    fn get_midi_key_name(&self, channel: i32, program: i32, key: i32) -> Option<String> {
+        self.effect.midi_key_name(channel, program, key)
+    }
+
+    /// Enable or disable the plugin's soft bypass.
+    ///
+    /// Returns `false` if the plugin does not support soft bypass, in which case the host should
+    /// stop calling the process functions instead.
+    fn set_bypass(&mut self, bypass: bool) -> bool {
+        self.effect.set_bypass(bypass)
+    }
+
+    /// Tell the plugin which process function will be used from now on.
+    fn set_precision(&mut self, precision: plugin::ProcessPrecision) {
+        let value = (precision == plugin::ProcessPrecision::F64) as isize;
+        self.effect.dispatch(plugin::OpCode::SetPrecision, 0, value, ptr::null_mut(), 0.0);
+    }
+
+    fn get_tail_size(&self) -> isize {
+        self.effect.opcode(plugin::OpCode::GetTailSize)
+    }
+
+    fn sample_rate_changed(&mut self, rate: f32) {
+        self.set_sample_rate(rate);
+    }
+
+    fn block_size_changed(&mut self, size: i64) {
+        self.set_block_size(size);
+    }
+
+    fn on_resume(&mut self) {
+        self.resume();
+    }
+
+    fn on_suspend(&mut self) {
+        self.suspend();
+    }
+
+    /// Send events to the plugin, before the next call to `process`.
+    ///
+    /// This allocates a temporary `SendEventBuffer`; use `send_events` on the audio thread.
+    fn process_events(&mut self, events: &[Event]) {
+        let sysex: usize = events.iter().map(|e| match *e {
+            Event::SysEx(ref sysex) => sysex.payload.len(),
+            _ => 0,
+        }).sum();
+
+        let mut buffer = SendEventBuffer::new(events.len(), sysex);
+        for event in events {
+            buffer.push(*event);
+        }
+        self.effect.process_events(&mut buffer);
+    }
+
+    fn process(&mut self, buffer: AudioBuffer<f32>) {
+        self.effect.process(buffer, &mut self.scratch);
+    }
+
+    fn process_f64(&mut self, buffer: AudioBuffer<f64>) {
+        self.effect.process_f64(buffer, &mut self.scratch_f64, &mut self.scratch);
+    }
+
+    fn process_var_io(&mut self, mut io: VariableIo) -> bool {
+        // The plugin receives the inputs as mutable pointers, but must not write to them
+        let inputs = io.inputs.iter().map(|c| c.as_ptr() as *mut f32).collect();
+        let input_samples = io.inputs.iter().map(|c| c.len()).min().unwrap_or(0);
+        let outputs = io.outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let output_samples = io.outputs.iter().map(|c| c.len()).min().unwrap_or(0);
+
+        match self.effect.process_var_io(inputs, input_samples, outputs, output_samples) {
+            Some((inputs, outputs)) => {
+                io.set_processed(inputs, outputs);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_preset_data(&mut self) -> Vec<u8> {
+        self.effect.get_chunk(true)
+    }
+
+    fn get_bank_data(&mut self) -> Vec<u8> {
+        self.effect.get_chunk(false)
+    }
+
+    fn load_preset_data(&mut self, data: Vec<u8>) {
+        self.effect.set_chunk(true, &data);
+    }
+
+    fn load_bank_data(&mut self, data: Vec<u8>) {
+        self.effect.set_chunk(false, &data);
+    }
+}
+
+/// State of a `PluginInstance` saved by `PluginLoader::reload`.
+struct SavedState {
+    chunk: Option<Vec<u8>>,
+    parameters: Vec<f32>,
+    sample_rate: Option<f32>,
+    block_size: Option<i64>,
+    resumed: bool,
+}
+
+impl PluginProcessor {
+    /// Get the information of the plugin this processor belongs to.
+    pub fn get_info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Check whether the plugin has crashed. See `PluginInstance::crashed`.
+    pub fn crashed(&self) -> Option<PluginCrashed> {
+        self.effect.crashed()
+    }
+
+    /// Get the current latency of the plugin in samples.
+    pub fn latency(&self) -> i32 {
+        self.effect.latency()
+    }
+
+    /// Check whether the plugin has been resumed through the controller.
+    pub fn is_resumed(&self) -> bool {
+        self.effect.is_resumed()
+    }
+
+    /// Notify the plugin that processing is about to start.
+    ///
+    /// This reserves the channel tables used by `process` for the block size set through the
+    /// controller, so it should be called after the plugin is resumed and before the first
+    /// process call. Until `stop_process` is called, the controller can not suspend the plugin.
+    ///
+    /// Returns `false` without calling the plugin if it is not resumed, or while the controller
+    /// is changing its state.
+    pub fn start_process(&mut self) -> bool {
+        self.effect.reserve(&mut self.scratch);
+        if self.info.f64_precision {
+            self.effect.reserve(&mut self.scratch_f64);
+        }
+        self.effect.start_processing()
+    }
+
+    /// Notify the plugin that processing has stopped.
+    pub fn stop_process(&mut self) {
+        self.effect.stop_processing();
+    }
+
+    /// Enable or disable the plugin's soft bypass. Returns `false` if it is not supported.
+    pub fn set_bypass(&mut self, bypass: bool) -> bool {
+        self.effect.set_bypass(bypass)
+    }
+
+    /// Get the value of the parameter at `index`.
+    pub fn get_parameter(&self, index: i32) -> f32 {
+        self.effect.get_parameter(index)
+    }
+
+    /// Set the value of the parameter at `index`.
+    pub fn set_parameter(&mut self, index: i32, value: f32) {
+        self.effect.set_parameter(index, value);
+    }
+
+    /// Send all events of `events` to the plugin in a single block and clear it.
+    ///
+    /// Events apply to the next call to `process`. Nothing is allocated.
+    pub fn send_events(&mut self, events: &mut SendEventBuffer) {
+        self.effect.process_events(events);
+    }
+
+    /// Process an audio buffer containing `f32` values.
+    ///
+    /// The buffer should have as many channels as `get_info` reports. If it has fewer, the plugin
+    /// receives silent inputs and its surplus outputs are discarded. Surplus channels of the
+    /// buffer are left untouched. Plugins without channels process the samples set with
+    /// `AudioBuffer::with_samples`.
+    ///
+    /// Nothing is allocated as long as the buffer is no longer than the block size reserved by
+    /// `start_process`. Longer buffers, or channel counts changed by the plugin since then,
+    /// allocate.
+    pub fn process(&mut self, buffer: AudioBuffer<f32>) {
+        self.effect.process(buffer, &mut self.scratch);
+    }
+
+    /// Process an audio buffer containing `f64` values, with the channels padded as for
+    /// `process`.
+    ///
+    /// Plugins which do not report `f64_precision` in their info process `f32` copies of the
+    /// channels instead.
+    pub fn process_f64(&mut self, buffer: AudioBuffer<f64>) {
+        self.effect.process_f64(buffer, &mut self.scratch_f64, &mut self.scratch);
+    }
+
+    /// Process buffers with differing input and output lengths, e.g. for time stretching.
+    ///
+    /// Returns the number of input samples consumed and output samples produced, or `None` if
+    /// the plugin does not support variable I/O.
+    pub fn process_var_io(&mut self,
+                          inputs: &mut [&mut [f32]],
+                          outputs: &mut [&mut [f32]])
+                          -> Option<(usize, usize)> {
+        let samples = |channels: &[&mut [f32]]| channels.iter().map(|c| c.len()).min().unwrap_or(0);
+
+        self.effect.process_var_io(inputs.iter_mut().map(|c| c.as_mut_ptr()).collect(),
+                                   samples(inputs),
+                                   outputs.iter_mut().map(|c| c.as_mut_ptr()).collect(),
+                                   samples(outputs))
+    }
+}
+
+impl PluginController {
+    /// Get the information of the plugin this controller belongs to.
+    pub fn get_info(&self) -> &Info {
+        &self.info
+    }
+
+    /// Check whether the plugin has crashed. See `PluginInstance::crashed`.
+    pub fn crashed(&self) -> Option<PluginCrashed> {
+        self.effect.crashed()
+    }
+
+    /// Enable or disable the watchdog for calls made through this controller.
+    ///
+    /// With a watchdog enabled, calls made through this controller are queued to a worker thread
+    /// owned by the controller, and abandoned once one takes longer than `timeout`. A plugin which
+    /// does not respond in time is quarantined and reported through [`crashed`](#method.crashed),
+    /// so a hanging plugin cannot freeze the UI thread. Calls which time out, and all calls made
+    /// after that, return default values without reaching the plugin.
+    ///
+    /// Editor calls are not queued, as plugins create and draw their editor on the thread owning
+    /// the parent window. They are made on the calling thread and watched by a timer thread
+    /// instead, which quarantines the plugin once one takes longer than `timeout`. The calling
+    /// thread stays blocked until the plugin returns, but the processor and later calls no longer
+    /// reach the plugin.
+    ///
+    /// A call which timed out cannot be interrupted, so the worker thread stays blocked in the
+    /// plugin. It keeps its own handle to the plugin, which keeps the plugin library loaded until
+    /// the call returns; only then is the plugin shut down and the library closed. A plugin which
+    /// never returns leaks the worker thread, the instance and the library for the lifetime of
+    /// the process.
+    ///
+    /// The watchdog is disabled by default.
+    pub fn set_watchdog(&mut self, timeout: Option<Duration>) {
+        let timeout = match timeout {
+            Some(timeout) => timeout,
+            None => {
+                self.watchdog = None;
+                return;
+            }
+        };
+
+        if let Some(ref mut watchdog) = self.watchdog {
+            watchdog.timeout = timeout;
+            return;
+        }
+
+        let (jobs, queue) = mpsc::channel::<Job>();
+        // Keeps the plugin and its library alive while a call is running, even one that timed out
+        let effect = self.effect.clone();
+        thread::spawn(move || {
+            for mut job in queue {
+                job(&effect);
+            }
+        });
+
+        let (watches, queue) = mpsc::channel::<Watch>();
+        let effect = self.effect.clone();
+        thread::spawn(move || {
+            for (done, timeout) in queue {
+                if let Err(mpsc::RecvTimeoutError::Timeout) = done.recv_timeout(timeout) {
+                    effect.quarantine(format!("The plugin editor did not respond within {:?}",
+                                              timeout));
+                }
+            }
+        });
+
+        self.watchdog = Some(Watchdog {
+            timeout: timeout,
+            jobs: jobs,
+            watches: watches,
+        });
+    }
+
+    /// Make a call into the plugin, on the watchdog thread if the watchdog is enabled.
+    ///
+    /// Returns `None` if the call timed out or the plugin has crashed before.
+    fn call<F, R>(&self, f: F) -> Option<R>
+        where F: FnOnce(&Effect) -> R + Send + 'static,
+              R: Send + 'static
+    {
+        if self.effect.crashed.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let watchdog = match self.watchdog {
+            Some(ref watchdog) => watchdog,
+            None => return Some(f(&self.effect))
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let mut f = Some(f);
+        let job: Job = Box::new(move |effect| {
+            if let Some(f) = f.take() {
+                // The receiver is gone if the call timed out
+                let _ = tx.send(f(effect));
+            }
+        });
+        if watchdog.jobs.send(job).is_err() {
+            return None;
+        }
+
+        match rx.recv_timeout(watchdog.timeout) {
+            Ok(result) => Some(result),
+            Err(_) => {
+                let timeout = watchdog.timeout;
+                self.effect.quarantine(format!("The plugin did not respond within {:?}", timeout));
+                None
+            }
+        }
+    }
+
+    /// Make an editor call into the plugin on the calling thread, watched by the timer thread if
+    /// the watchdog is enabled.
+    ///
+    /// Returns `None` if the call timed out or the plugin has crashed before.
+    fn call_editor<F, R>(&self, f: F) -> Option<R>
+        where F: FnOnce(&Effect) -> R
+    {
+        if self.effect.crashed.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let watchdog = match self.watchdog {
+            Some(ref watchdog) => watchdog,
+            None => return Some(f(&self.effect))
+        };
+
+        let (done, watch) = mpsc::channel();
+        if watchdog.watches.send((watch, watchdog.timeout)).is_err() {
+            return None;
+        }
+        let result = f(&self.effect);
+        // The timer thread is gone if the call timed out
+        let _ = done.send(());
+
+        if self.effect.crashed.load(Ordering::Acquire) {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    /// Get the current latency of the plugin in samples.
+    pub fn latency(&self) -> i32 {
+        self.effect.latency()
+    }
+
+    /// Re-read the plugin information from the plugin. See `PluginInstance::refresh_info`.
+    pub fn refresh_info(&mut self) {
+        if let Some(info) = self.call(|e| e.read_info()) {
+            self.info = info;
+        }
+    }
+
+    /// Check whether the plugin is resumed.
+    pub fn is_resumed(&self) -> bool {
+        self.effect.is_resumed()
+    }
+
+    /// Inform the plugin of the sample rate it will be processing at.
+    ///
+    /// Returns `false` without calling the plugin while it is resumed, as the sample rate may
+    /// only change while the plugin is suspended.
+    pub fn set_sample_rate(&mut self, rate: f32) -> bool {
+        self.call(move |e| e.change_state(0, 0, || e.set_sample_rate(rate)))
+            .and_then(|result| result)
+            .is_some()
+    }
+
+    /// Inform the plugin of the maximum number of samples it will be asked to process at once.
+    ///
+    /// Returns `false` without calling the plugin while it is resumed, as the block size may only
+    /// change while the plugin is suspended.
+    pub fn set_block_size(&mut self, size: i64) -> bool {
+        self.call(move |e| e.change_state(0, 0, || e.set_block_size(size)))
+            .and_then(|result| result)
+            .is_some()
+    }
+
+    /// Turn the plugin on. This must be called before any audio is processed.
+    ///
+    /// Does nothing if the plugin is already resumed.
+    pub fn resume(&mut self) {
+        self.call(|e| e.change_state(0, RESUMED, || e.state_changed(true)));
+    }
+
+    /// Turn the plugin off.
+    ///
+    /// Returns `false` without calling the plugin if it is not resumed, or while the processor is
+    /// between `start_process` and `stop_process`.
+    pub fn suspend(&mut self) -> bool {
+        self.call(|e| e.change_state(RESUMED, 0, || e.state_changed(false)))
+            .and_then(|result| result)
+            .is_some()
+    }
+
+    /// Get the value of the parameter at `index`.
+    pub fn get_parameter(&self, index: i32) -> f32 {
+        self.effect.get_parameter(index)
+    }
+
+    /// Get the name, label, display string and value of the parameter at `index`.
+    pub fn get_parameter_info(&self, index: i32) -> ParameterInfo {
+        self.call(move |e| e.parameter_info(index)).unwrap_or_else(|| {
+            ParameterInfo {
+                name: String::new(),
+                label: String::new(),
+                display: String::new(),
+                value: 0.0,
+                automatable: false,
+            }
+        })
+    }
+
+    /// Get the display properties of the parameter at `index`, such as whether it is a switch or
+    /// its integer range.
+    ///
+    /// Returns `None` if the plugin does not provide parameter properties.
+    pub fn get_parameter_properties(&self, index: i32) -> Option<ParameterProperties> {
+        self.call(move |e| e.parameter_properties(index)).and_then(|p| p)
+    }
+
+    /// Set the parameter at `index` from a text value (e.g. "-6.0 dB").
+    ///
+    /// Returns `false` if the plugin does not support text entry or could not parse `text`.
+    pub fn string_to_parameter(&mut self, index: i32, text: &str) -> bool {
+        let text = text.to_string();
+        self.call(move |e| e.string_to_parameter(index, &text)).unwrap_or(false)
+    }
+
+    /// Get the chunk data of the current preset. See `Plugin::get_preset_data`.
+    pub fn get_preset_data(&mut self) -> Vec<u8> {
+        self.call(|e| e.get_chunk(true)).unwrap_or_default()
+    }
+
+    /// Get the chunk data of the whole bank. See `Plugin::get_bank_data`.
+    pub fn get_bank_data(&mut self) -> Vec<u8> {
+        self.call(|e| e.get_chunk(false)).unwrap_or_default()
+    }
+
+    /// Load chunk data into the current preset. See `Plugin::load_preset_data`.
+    pub fn load_preset_data(&mut self, data: Vec<u8>) {
+        self.call(move |e| e.set_chunk(true, &data));
+    }
+
+    /// Load chunk data into the whole bank. See `Plugin::load_bank_data`.
+    pub fn load_bank_data(&mut self, data: Vec<u8>) {
+        self.call(move |e| e.set_chunk(false, &data));
+    }
+
+    /// Open the plugin editor as a child of `parent`. See `PluginInstance::open_editor`.
+    ///
+    /// This must be called on the thread owning `parent`. With the watchdog enabled, editor calls
+    /// are still made on the calling thread, see [`set_watchdog`](#method.set_watchdog).
+    #[cfg(feature = "window-handle")]
+    pub fn open_editor<W: HasRawWindowHandle>(&mut self, parent: &W) -> bool {
+        match parent_pointer(parent.raw_window_handle()) {
+            Some(ptr) => self.open_editor_raw(ptr),
+            None => false,
+        }
+    }
+
+    #[cfg(any(feature = "window-handle", test))]
+    fn open_editor_raw(&mut self, parent: *mut c_void) -> bool {
+        self.call_editor(|e| {
+            e.dispatch(plugin::OpCode::EditorOpen, 0, 0, parent, 0.0) != 0
+        }).unwrap_or(false)
+    }
+
+    /// Close the plugin editor.
+    pub fn close_editor(&mut self) {
+        self.call_editor(|e| e.opcode(plugin::OpCode::EditorClose));
+    }
+
+    /// Give the open editor time to redraw, should be called regularly.
+    pub fn editor_idle(&mut self) {
+        self.call_editor(|e| e.opcode(plugin::OpCode::EditorIdle));
+    }
+
+    /// Get the size of the editor window in pixels, or `None` if the plugin has no editor.
+    pub fn editor_size(&self) -> Option<(i32, i32)> {
+        self.call_editor(|e| e.editor_size()).and_then(|size| size)
+    }
+}
+
+/// HACK: a pointer to store the host so that it can be accessed from the `callback_wrapper`
+/// function passed to the plugin.
+///
+/// When the plugin is being loaded, a `Box<Arc<Mutex<T>>>` is transmuted to a *mut c_void pointer
+/// and placed here. When the plugin calls the callback during initialization, the host refers to
+/// this pointer to get a handle to the Host. After initialization, this pointer is invalidated and
+/// the host pointer is placed into a [reserved field] in the instance `AEffect` struct.
+///
+/// The issue with this approach is that if 2 plugins are simultaneously loaded with 2 different
+/// host instances, this might fail as one host may receive a pointer to the other one. In practice
+/// this is a rare situation as you normally won't have 2 seperate host instances loading at once.
+///
+/// [reserved field]: ../api/struct.AEffect.html#structfield.reserved1
+static mut load_pointer: *mut c_void = 0 as *mut c_void;
+
+/// Function passed to plugin to handle dispatching host opcodes.
+fn callback_wrapper<T: Host>(effect: *mut AEffect, opcode: i32, index: i32,
+                             value: isize, ptr: *mut c_void, opt: f32) -> isize {
+    unsafe {
+        // Convert `*mut` to `&mut` for easier usage
+        let effect_ref: &mut AEffect = mem::transmute(effect);
+
+        // If the effect pointer is not null and the host pointer is not null, the plugin has
+        // already been initialized
+        if !effect.is_null() && effect_ref.reserved1 != 0 {
+            let host: &mut Arc<Mutex<T>> = mem::transmute(effect_ref.reserved1);
+
+            let host = &mut *host.lock().unwrap();
+
+            interfaces::host_dispatch(host, effect, opcode, index, value, ptr, opt)
+        // In this case, the plugin is still undergoing initialization and so `load_pointer` is
+        // dereferenced
+        } else {
+            // Used only during the plugin initialization
+            let host: &mut Arc<Mutex<T>> = mem::transmute(load_pointer);
+            let host = &mut *host.lock().unwrap();
+
+            interfaces::host_dispatch(host, effect, opcode, index, value, ptr, opt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicBool;
+
+    use dylib::DynamicLibrary;
+    use libc::c_void;
+
+    use api::AEffect;
+    use host::{self, Effect, PluginProcessor, PluginInstance, bundle_executable};
+    use plugin::Plugin;
+
+    fn assert_send<T: Send>() {}
+
+    /// Create an instance of a plugin running in the test process.
+    fn instance<P: Plugin + Default>() -> PluginInstance {
+        fn callback(_: *mut AEffect, opcode: i32, _: i32, _: isize, _: *mut c_void, _: f32)
+                    -> isize {
+            if opcode == host::OpCode::Version as i32 { 2400 } else { 0 }
+        }
+
+        PluginInstance::new(Effect::new(::main::<P>(callback),
+                                        Arc::new(DynamicLibrary::open(None).unwrap())))
+    }
+
+    /// Test that buffers with fewer or more channels than the plugin are padded and truncated.
+    #[test]
+    fn mismatched_buffer() {
+        use buffer::AudioBuffer;
+        use plugin::Info;
+
+        #[derive(Default)]
+        struct Offset;
+
+        impl Plugin for Offset {
+            fn get_info(&self) -> Info {
+                Info { name: "Offset".to_string(), inputs: 2, outputs: 2, ..Default::default() }
+            }
+
+            fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+                let (inputs, mut outputs) = buffer.split_mut();
+                assert_eq!((inputs.len(), outputs.len()), (2, 2));
+                for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                    for (x, y) in input.iter().zip(output.iter_mut()) {
+                        *y = *x + 1.0;
+                    }
+                }
+            }
+        }
+
+        let mut instance = instance::<Offset>();
+
+        let (mut input, mut output) = (vec![1.0f32; 16], vec![0.0f32; 16]);
+        instance.process(AudioBuffer::new(vec![&mut input], vec![&mut output]));
+        assert_eq!(output, vec![2.0; 16]);
+
+        let mut inputs = vec![vec![1.0f32; 16]; 3];
+        let mut outputs = vec![vec![0.0f32; 16]; 3];
+        instance.process(AudioBuffer::new(inputs.iter_mut().map(|c| &mut c[..]).collect(),
+                                          outputs.iter_mut().map(|c| &mut c[..]).collect()));
+        assert_eq!(outputs, vec![vec![2.0; 16], vec![2.0; 16], vec![0.0; 16]]);
+        assert!(instance.crashed().is_none());
+    }
+
+    /// Test that `process_f64` processes `f32` copies for plugins without a 64 bit path.
+    #[test]
+    fn process_f64_without_double() {
+        use std::ptr;
+
+        use buffer::AudioBuffer;
+        use plugin::Info;
+
+        #[derive(Default)]
+        struct Offset;
+
+        impl Plugin for Offset {
+            fn get_info(&self) -> Info {
+                Info {
+                    name: "Offset".to_string(),
+                    inputs: 1,
+                    outputs: 2,
+                    f64_precision: false,
+                    ..Default::default()
+                }
+            }
+
+            fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+                let (inputs, mut outputs) = buffer.split_mut();
+                for (x, y) in inputs.get(0).unwrap().iter().zip(outputs.get_mut(0).unwrap()) {
+                    *y = *x + 1.0;
+                }
+            }
+
+            fn process_f64(&mut self, _: AudioBuffer<f64>) {
+                panic!("The host called the 64 bit path");
+            }
+        }
+
+        let mut instance = instance::<Offset>();
+        // Like a VST 2.3 plugin, which has no 64 bit function at all
+        unsafe {
+            ptr::write(ptr::addr_of_mut!((*instance.effect.effect).processReplacingF64)
+                           as *mut Option<::api::ProcessProcF64>, None);
+        }
+
+        let (mut input, mut output) = (vec![1.0f64; 16], vec![5.0f64; 16]);
+        instance.process_f64(AudioBuffer::new(vec![&mut input], vec![&mut output]));
+        assert_eq!(output, vec![2.0; 16]);
+        assert!(instance.crashed().is_none());
+    }
+
+    /// Test that the channel tables reserved by `start_process` are reused by `process`.
+    #[test]
+    fn reserved_scratch() {
+        use buffer::AudioBuffer;
+        use plugin::Info;
+
+        #[derive(Default)]
+        struct Stereo;
+
+        impl Plugin for Stereo {
+            fn get_info(&self) -> Info {
+                Info { name: "Stereo".to_string(), inputs: 2, outputs: 2, ..Default::default() }
+            }
+        }
+
+        let mut instance = instance::<Stereo>();
+        instance.set_block_size(64);
+        instance.resume();
+
+        let (mut processor, _controller) = instance.split();
+        processor.start_process();
+        let reserved = (processor.scratch.inputs.as_ptr(),
+                        processor.scratch.outputs.as_ptr(),
+                        processor.scratch.padding.as_ptr());
+
+        let (mut input, mut output) = (vec![0.0f32; 64], vec![0.0f32; 64]);
+        processor.process(AudioBuffer::new(vec![&mut input], vec![&mut output]));
+        processor.process(AudioBuffer::new(Vec::new(), Vec::new()).with_samples(64));
+
+        assert_eq!((processor.scratch.inputs.as_ptr(),
+                    processor.scratch.outputs.as_ptr(),
+                    processor.scratch.padding.as_ptr()),
+                   reserved);
+        assert!(processor.scratch.padding.capacity() >= 4 * 64);
+    }
+
+    /// Test that plugins without inputs and outputs process the sample count of the buffer.
+    #[test]
+    fn no_channels() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use buffer::AudioBuffer;
+        use plugin::Info;
+
+        static SAMPLES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Analyser;
+
+        impl Plugin for Analyser {
+            fn get_info(&self) -> Info {
+                Info { name: "Analyser".to_string(), inputs: 0, outputs: 0, ..Default::default() }
+            }
+
+            fn process(&mut self, buffer: AudioBuffer<f32>) {
+                SAMPLES.fetch_add(buffer.samples(), Ordering::SeqCst);
+            }
+        }
+
+        let mut instance = instance::<Analyser>();
+        instance.process(AudioBuffer::new(Vec::new(), Vec::new()).with_samples(64));
+        assert_eq!(SAMPLES.load(Ordering::SeqCst), 64);
+    }
+
+    /// Test that unknown opcodes sent by a plugin are answered with 0.
+    #[test]
+    fn unknown_host_opcode() {
+        use std::ptr;
+
+        use interfaces;
+
+        struct Silent;
+        impl host::Host for Silent {}
+
+        for &opcode in &[-1, host::OpCode::_GetInputSpeakerArrangement as i32 + 1, 10000] {
+            assert_eq!(interfaces::host_dispatch(&mut Silent, ptr::null_mut(), opcode, 0, 0,
+                                                 ptr::null_mut(), 0.0),
+                       0);
+        }
+    }
+
+    /// Test that strings returned to plugins are truncated and terminated, and that requests
+    /// without a buffer are refused.
+    #[test]
+    fn host_strings() {
+        use std::ptr;
+
+        use api::consts::MAX_VENDOR_STR_LEN;
+        use interfaces;
+
+        struct Verbose;
+        impl host::Host for Verbose {
+            fn get_vendor_string(&self) -> String {
+                format!("Vendor\0{}", "x".repeat(100))
+            }
+        }
+
+        let dispatch = |opcode: host::OpCode, ptr: *mut c_void| {
+            interfaces::host_dispatch(&mut Verbose, ptr::null_mut(), opcode.into(), 0, 0, ptr, 0.0)
+        };
+
+        let mut vendor = [0xFFu8; MAX_VENDOR_STR_LEN];
+        assert_eq!(dispatch(host::OpCode::GetVendorString, vendor.as_mut_ptr() as *mut c_void),
+                   1);
+        assert_eq!(&vendor[..7], b"Vendor\0");
+        assert_eq!(vendor[MAX_VENDOR_STR_LEN - 1], 0);
+
+        assert_eq!(dispatch(host::OpCode::GetVendorString, ptr::null_mut()), 0);
+        assert_eq!(dispatch(host::OpCode::CanDo, ptr::null_mut()), 0);
+    }
+
+    /// Test that shell plugins are told the unique ID of the plugin the host wants to load.
+    #[test]
+    fn current_id() {
+        use std::ptr;
+
+        use interfaces;
+
+        struct Shell;
+        impl host::Host for Shell {
+            fn get_plugin_id(&self) -> i32 { 1002 }
+        }
+
+        assert_eq!(interfaces::host_dispatch(&mut Shell, ptr::null_mut(),
+                                             host::OpCode::CurrentId.into(), 0, 0,
+                                             ptr::null_mut(), 0.0),
+                   1002);
+    }
+
+    /// Test that plugins reporting only the major VST version, as the VST 2.0 SDK does, are
+    /// loaded, and that VST 1.x plugins are rejected.
+    #[test]
+    fn major_vst_version() {
+        use std::path::PathBuf;
+        use std::sync::Mutex;
+        use std::sync::atomic::{AtomicIsize, Ordering};
+
+        use api::HostCallbackProc;
+        use host::{PluginLoader, PluginLoadError};
+        use interfaces;
+        use plugin::{self, Info};
+
+        #[derive(Default)]
+        struct Old;
+
+        impl Plugin for Old {
+            fn get_info(&self) -> Info {
+                Info { name: "Old".to_string(), ..Default::default() }
+            }
+        }
+
+        struct Silent;
+        impl host::Host for Silent {}
+
+        static VERSION: AtomicIsize = AtomicIsize::new(0);
+
+        fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize,
+                    ptr: *mut c_void, opt: f32) -> isize {
+            if opcode == plugin::OpCode::GetApiVersion as i32 {
+                VERSION.load(Ordering::SeqCst)
+            } else {
+                interfaces::dispatch(effect, opcode, index, value, ptr, opt)
+            }
+        }
+
+        fn plugin_main(callback: HostCallbackProc) -> *mut AEffect {
+            let effect = ::main::<Old>(callback);
+            unsafe { (*effect).dispatcher = dispatch };
+            effect
+        }
+
+        let load = |version: isize| {
+            VERSION.store(version, Ordering::SeqCst);
+            let mut loader = PluginLoader {
+                main: plugin_main,
+                lib: Arc::new(DynamicLibrary::open(None).unwrap()),
+                host: Arc::new(Mutex::new(Silent)),
+                path: PathBuf::new(),
+            };
+            loader.instance()
+        };
+
+        assert!(load(2).is_ok());
+        assert!(load(2400).is_ok());
+        match load(1) {
+            Err(PluginLoadError::IncompatibleVersion) => {}
+            _ => panic!("VST 1.x plugin was loaded"),
+        }
+        match load(0) {
+            Err(PluginLoadError::IncompatibleVersion) => {}
+            _ => panic!("Plugin without a VST version was loaded"),
+        }
+    }
+
+    /// The processor half must be movable to the audio thread.
+    #[test]
+    fn processor_is_send() {
+        assert_send::<PluginProcessor>();
+    }
+
+    /// Test that the controller only changes the setup of a suspended plugin, and does not
+    /// suspend it while the processor is processing.
+    #[test]
+    fn controller_state() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use plugin::Info;
+
+        static SAMPLE_RATE: AtomicUsize = AtomicUsize::new(0);
+        static SUSPENDS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Recorder;
+
+        impl Plugin for Recorder {
+            fn get_info(&self) -> Info {
+                Info { name: "Recorder".to_string(), ..Default::default() }
+            }
+
+            fn sample_rate_changed(&mut self, rate: f32) {
+                SAMPLE_RATE.store(rate as usize, Ordering::SeqCst);
+            }
+
+            fn on_suspend(&mut self) {
+                SUSPENDS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let (mut processor, mut controller) = instance::<Recorder>().split();
+
+        assert!(controller.set_sample_rate(44100.0));
+        controller.resume();
+        assert!(processor.is_resumed());
+        assert!(!controller.set_sample_rate(48000.0));
+        assert!(!controller.set_block_size(256));
+        assert_eq!(SAMPLE_RATE.load(Ordering::SeqCst), 44100);
+
+        processor.start_process();
+        assert!(!controller.suspend());
+        assert!(controller.is_resumed());
+        processor.stop_process();
+
+        let suspends = SUSPENDS.load(Ordering::SeqCst);
+        assert!(controller.suspend());
+        assert_eq!(SUSPENDS.load(Ordering::SeqCst), suspends + 1);
+        assert!(controller.set_sample_rate(48000.0));
+        assert_eq!(SAMPLE_RATE.load(Ordering::SeqCst), 48000);
+    }
+
+    /// Test that the processor can not start while the controller changes the state.
+    #[test]
+    fn state_change_in_progress() {
+        use host::RESUMED;
+
+        #[derive(Default)]
+        struct Empty;
+
+        impl Plugin for Empty {
+            fn get_info(&self) -> ::plugin::Info {
+                ::plugin::Info { name: "Empty".to_string(), ..Default::default() }
+            }
+        }
+
+        let instance = instance::<Empty>();
+        let effect = instance.effect.clone();
+        let (mut processor, mut controller) = instance.split();
+        controller.resume();
+
+        assert_eq!(effect.change_state(RESUMED, 0, || effect.start_processing()), Some(false));
+        assert!(!processor.is_resumed());
+        assert!(!processor.start_process());
+        assert!(!controller.suspend());
+
+        controller.resume();
+        assert!(processor.start_process());
+        assert!(!controller.suspend());
+        processor.stop_process();
+        assert!(controller.suspend());
+    }
+
+    /// Test that the executable name is read from a bundle's `Info.plist`.
+    #[test]
+    fn plist_executable() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+    <key>CFBundleExecutable</key>
+    <string>PluginHooksVST</string>
+    <key>CFBundlePackageType</key>
+    <string>BNDL</string>
+</dict>
+</plist>"#;
+
+        assert_eq!(bundle_executable(plist), Some("PluginHooksVST".to_string()));
+        assert_eq!(bundle_executable("<plist></plist>"), None);
+
+        let commented = "<key>CFBundleExecutable</key>\n\t<!-- the binary -->\n<!---->\
+                         <string>Plugin</string>";
+        assert_eq!(bundle_executable(commented), Some("Plugin".to_string()));
+        assert_eq!(bundle_executable("<key>CFBundleExecutable</key> <!-- <string>"), None);
+    }
+
+    /// Test that a crashed plugin is still closed, but not suspended, when it is dropped.
+    #[test]
+    fn close_crashed() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use plugin::Info;
+
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+        static SUSPENDED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Crashing;
+
+        impl Plugin for Crashing {
+            fn get_info(&self) -> Info {
+                Info { name: "Crashing".to_string(), ..Default::default() }
+            }
+
+            fn on_suspend(&mut self) {
+                SUSPENDED.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn close(&mut self) {
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let instance = instance::<Crashing>();
+        instance.effect.quarantine("Test crash".to_string());
+        drop(instance);
+
+        assert!(CLOSED.load(Ordering::SeqCst));
+        assert_eq!(SUSPENDED.load(Ordering::SeqCst), 0);
+    }
+
+    /// Test that the watchdog quarantines a plugin hanging in a call and that no further calls
+    /// reach it.
+    #[test]
+    fn watchdog() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        use plugin::Info;
+
+        static RELEASE: AtomicBool = AtomicBool::new(false);
+        static SAMPLE_RATES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct Hanging;
+
+        impl Plugin for Hanging {
+            fn get_info(&self) -> Info {
+                Info { name: "Hanging".to_string(), ..Default::default() }
+            }
+
+            fn sample_rate_changed(&mut self, _: f32) {
+                SAMPLE_RATES.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn block_size_changed(&mut self, _: i64) {
+                while !RELEASE.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+
+        // The timeout is far longer than any call takes, even on a loaded machine
+        let (_, mut controller) = instance::<Hanging>().split();
+        controller.set_watchdog(Some(Duration::from_secs(2)));
+
+        controller.set_sample_rate(44100.0);
+        assert_eq!(SAMPLE_RATES.load(Ordering::SeqCst), 1);
+
+        let start = Instant::now();
+        assert!(!controller.set_block_size(512));
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert!(controller.crashed().is_some());
+
+        // The worker is still stuck in the plugin, later calls are rejected without queueing
+        controller.set_sample_rate(48000.0);
+        RELEASE.store(true, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(SAMPLE_RATES.load(Ordering::SeqCst), 1);
+    }
+
+    /// Test that the watchdog makes editor calls on the calling thread and quarantines a plugin
+    /// hanging in `EditorOpen`.
+    #[test]
+    fn watchdog_editor() {
+        use std::cell::Cell;
+        use std::ptr;
+        use std::sync::atomic::Ordering;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        use editor::{Editor, ParentWindow};
+        use plugin::Info;
+
+        thread_local! {
+            static CALLER: Cell<bool> = Cell::new(false);
+        }
+
+        static RELEASE: AtomicBool = AtomicBool::new(false);
+        static OPENED_ON_CALLER: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Default)]
+        struct HangingEditor;
+
+        impl Editor for HangingEditor {
+            fn size(&self) -> (i32, i32) { (100, 100) }
+            fn position(&self) -> (i32, i32) { (0, 0) }
+            fn is_open(&mut self) -> bool { false }
+
+            fn open(&mut self, _: ParentWindow) {
+                OPENED_ON_CALLER.store(CALLER.with(|caller| caller.get()), Ordering::SeqCst);
+                while !RELEASE.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+
+        #[derive(Default)]
+        struct Hanging {
+            editor: HangingEditor,
+        }
+
+        impl Plugin for Hanging {
+            fn get_info(&self) -> Info {
+                Info { name: "Hanging".to_string(), ..Default::default() }
+            }
+
+            fn get_editor(&mut self) -> Option<&mut Editor> {
+                Some(&mut self.editor)
+            }
+        }
+
+        CALLER.with(|caller| caller.set(true));
+
+        // The timeout is far longer than any call takes, even on a loaded machine
+        let (processor, mut controller) = instance::<Hanging>().split();
+        controller.set_watchdog(Some(Duration::from_secs(2)));
+
+        // The calling thread is stuck in the editor, so the plugin is released from elsewhere once
+        // the timer thread quarantined it
+        let releaser = thread::spawn(move || {
+            let start = Instant::now();
+            while processor.crashed().is_none() && start.elapsed() < Duration::from_secs(30) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            RELEASE.store(true, Ordering::SeqCst);
+        });
+
+        let start = Instant::now();
+        assert!(!controller.open_editor_raw(ptr::null_mut()));
+        assert!(start.elapsed() >= Duration::from_secs(2));
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert!(OPENED_ON_CALLER.load(Ordering::SeqCst));
+        assert!(controller.crashed().is_some());
+        releaser.join().unwrap();
+    }
+}
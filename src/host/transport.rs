@@ -0,0 +1,511 @@
+//! Host side transport tracking and time info construction.
+
+use std::cell::UnsafeCell;
+use std::ptr;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::thread;
+
+use api::{self, flags, SmpteFrameRate};
+
+/// Builds an `api::TimeInfo` with the flags matching the fields that have been set.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::host::TimeInfoBuilder;
+/// let info = TimeInfoBuilder::new(44100.0, 0.0)
+///                            .tempo(120.0)
+///                            .time_signature(4, 4)
+///                            .playing(true)
+///                            .build();
+///
+/// assert_eq!(info.tempo, 120.0);
+/// ```
+pub struct TimeInfoBuilder {
+    info: api::TimeInfo,
+    flags: flags::TimeInfo,
+}
+
+impl TimeInfoBuilder {
+    /// Start building time info for the given sample rate and sample position.
+    pub fn new(sample_rate: f64, sample_pos: f64) -> TimeInfoBuilder {
+        TimeInfoBuilder {
+            info: api::TimeInfo {
+                sample_rate: sample_rate,
+                sample_pos: sample_pos,
+                ..Default::default()
+            },
+            flags: flags::TimeInfo::empty(),
+        }
+    }
+
+    /// Set the tempo in beats per minute.
+    pub fn tempo(mut self, tempo: f64) -> TimeInfoBuilder {
+        self.info.tempo = tempo;
+        self.flags = self.flags | flags::TEMPO_VALID;
+        self
+    }
+
+    /// Set the time signature, e.g. `(3, 4)` for 3/4.
+    pub fn time_signature(mut self, numerator: i32, denominator: i32) -> TimeInfoBuilder {
+        self.info.time_sig_numerator = numerator;
+        self.info.time_sig_denominator = denominator;
+        self.flags = self.flags | flags::TIME_SIG_VALID;
+        self
+    }
+
+    /// Set the musical position in quarter notes.
+    pub fn ppq_position(mut self, ppq: f64) -> TimeInfoBuilder {
+        self.info.ppq_pos = ppq;
+        self.flags = self.flags | flags::PPQ_POS_VALID;
+        self
+    }
+
+    /// Set the position of the last bar start in quarter notes.
+    pub fn bar_start(mut self, ppq: f64) -> TimeInfoBuilder {
+        self.info.bar_start_pos = ppq;
+        self.flags = self.flags | flags::BARS_VALID;
+        self
+    }
+
+    /// Set the cycle (loop) locators in quarter notes and mark the cycle as active.
+    pub fn cycle(mut self, start: f64, end: f64) -> TimeInfoBuilder {
+        self.info.cycle_start_pos = start;
+        self.info.cycle_end_pos = end;
+        self.flags = self.flags | flags::CYCLE_POS_VALID | flags::TRANSPORT_CYCLE_ACTIVE;
+        self
+    }
+
+    /// Set the SMPTE offset in subframes (1/80 of a frame) and the frame rate.
+    pub fn smpte(mut self, offset: i32, rate: SmpteFrameRate) -> TimeInfoBuilder {
+        self.info.smpte_offset = offset;
+        self.info.smpte_frame_rate = rate as i32;
+        self.flags = self.flags | flags::SMPTE_VALID;
+        self
+    }
+
+    /// Set the system time in nanoseconds.
+    pub fn nanoseconds(mut self, nanoseconds: f64) -> TimeInfoBuilder {
+        self.info.nanoseconds = nanoseconds;
+        self.flags = self.flags | flags::NANOSECONDS_VALID;
+        self
+    }
+
+    /// Set whether the transport is playing.
+    pub fn playing(self, playing: bool) -> TimeInfoBuilder {
+        self.flag(flags::TRANSPORT_PLAYING, playing)
+    }
+
+    /// Set whether the transport is recording.
+    pub fn recording(self, recording: bool) -> TimeInfoBuilder {
+        self.flag(flags::TRANSPORT_RECORDING, recording)
+    }
+
+    /// Set whether the play, cycle or record state changed since the last block.
+    pub fn transport_changed(self, changed: bool) -> TimeInfoBuilder {
+        self.flag(flags::TRANSPORT_CHANGED, changed)
+    }
+
+    fn flag(mut self, flag: flags::TimeInfo, set: bool) -> TimeInfoBuilder {
+        if set {
+            self.flags.insert(flag);
+        } else {
+            self.flags.remove(flag);
+        }
+        self
+    }
+
+    /// Create the `api::TimeInfo`.
+    pub fn build(mut self) -> api::TimeInfo {
+        self.info.flags = self.flags.bits();
+        self.info
+    }
+}
+
+/// Time info updated by the audio thread and read by host callbacks, without locking.
+///
+/// Hosts share this between the block loop, which sets the time info of every block, and their
+/// `Host::get_time_info`, so answering `GetTime` requests does not need the host's lock. Setting
+/// never waits for readers; reading retries while the time info is being set.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::host::{SharedTimeInfo, Transport};
+/// let mut transport = Transport::new(44100.0);
+/// let shared = SharedTimeInfo::new(transport.time_info());
+///
+/// transport.play();
+/// transport.advance(512);
+/// shared.set(transport.time_info());
+/// assert_eq!(shared.get().sample_pos, 512.0);
+/// ```
+pub struct SharedTimeInfo {
+    /// Odd while the time info is being set.
+    sequence: AtomicUsize,
+    info: UnsafeCell<api::TimeInfo>,
+}
+
+// The time info is only copied in and out, guarded by `sequence`.
+unsafe impl Send for SharedTimeInfo {}
+unsafe impl Sync for SharedTimeInfo {}
+
+impl SharedTimeInfo {
+    /// Create shared time info, initially holding `info`.
+    pub fn new(info: api::TimeInfo) -> SharedTimeInfo {
+        SharedTimeInfo {
+            sequence: AtomicUsize::new(0),
+            info: UnsafeCell::new(info),
+        }
+    }
+
+    /// Replace the time info.
+    pub fn set(&self, info: api::TimeInfo) {
+        // Claim the time info, in case it is set from several threads
+        let mut sequence = self.sequence.load(Ordering::Relaxed);
+        loop {
+            if sequence % 2 == 0 {
+                match self.sequence.compare_exchange_weak(sequence, sequence.wrapping_add(1),
+                                                          Ordering::Acquire, Ordering::Relaxed) {
+                    Ok(_) => break,
+                    Err(current) => sequence = current,
+                }
+            } else {
+                thread::yield_now();
+                sequence = self.sequence.load(Ordering::Relaxed);
+            }
+        }
+        atomic::fence(Ordering::Release);
+
+        unsafe { ptr::write_volatile(self.info.get(), info) };
+        self.sequence.store(sequence.wrapping_add(2), Ordering::Release);
+    }
+
+    /// Get a copy of the time info.
+    pub fn get(&self) -> api::TimeInfo {
+        loop {
+            let before = self.sequence.load(Ordering::Acquire);
+            if before % 2 == 0 {
+                let info = unsafe { ptr::read_volatile(self.info.get()) };
+                atomic::fence(Ordering::Acquire);
+
+                // Only keep the copy if it was not set in the meantime
+                if self.sequence.load(Ordering::Relaxed) == before {
+                    return info;
+                }
+            }
+            thread::yield_now();
+        }
+    }
+}
+
+/// Tracks the transport state of a host as blocks are processed.
+///
+/// The host calls [`advance`](#method.advance) after every processed block and answers time info
+/// requests with [`time_info`](#method.time_info).
+///
+/// # Example
+///
+/// ```
+/// # use vst2::host::Transport;
+/// let mut transport = Transport::new(44100.0);
+/// transport.set_tempo(120.0);
+/// transport.play();
+///
+/// // One second at 120 bpm is 2 quarter notes.
+/// transport.advance(44100);
+/// assert_eq!(transport.ppq_position(), 2.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Transport {
+    sample_rate: f64,
+    sample_pos: f64,
+    tempo: f64,
+    time_signature: (i32, i32),
+    playing: bool,
+    recording: bool,
+    cycle: Option<(f64, f64)>,
+    changed: bool,
+}
+
+impl Transport {
+    /// Create a stopped transport at position 0, 120 bpm in 4/4.
+    pub fn new(sample_rate: f64) -> Transport {
+        Transport {
+            sample_rate: sample_rate,
+            sample_pos: 0.0,
+            tempo: 120.0,
+            time_signature: (4, 4),
+            playing: false,
+            recording: false,
+            cycle: None,
+            changed: false,
+        }
+    }
+
+    /// Set the sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: f64) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// Set the tempo in beats per minute.
+    pub fn set_tempo(&mut self, tempo: f64) {
+        self.tempo = tempo;
+    }
+
+    /// Set the time signature, e.g. `(3, 4)` for 3/4.
+    pub fn set_time_signature(&mut self, numerator: i32, denominator: i32) {
+        self.time_signature = (numerator, denominator);
+    }
+
+    /// Start playback.
+    pub fn play(&mut self) {
+        self.changed |= !self.playing;
+        self.playing = true;
+    }
+
+    /// Stop playback and recording.
+    pub fn stop(&mut self) {
+        self.changed |= self.playing || self.recording;
+        self.playing = false;
+        self.recording = false;
+    }
+
+    /// Enable or disable recording.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.changed |= self.recording != recording;
+        self.recording = recording;
+    }
+
+    /// Set the cycle (loop) range in quarter notes, or `None` to disable cycling.
+    pub fn set_cycle(&mut self, cycle: Option<(f64, f64)>) {
+        self.changed = true;
+        self.cycle = cycle;
+    }
+
+    /// Move the transport to the given sample position.
+    pub fn seek(&mut self, sample_pos: f64) {
+        self.changed = true;
+        self.sample_pos = sample_pos;
+    }
+
+    /// Whether the transport is playing.
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// The current position in samples.
+    pub fn sample_position(&self) -> f64 {
+        self.sample_pos
+    }
+
+    /// Number of samples in a quarter note at the current tempo.
+    pub fn samples_per_quarter(&self) -> f64 {
+        self.sample_rate * 60.0 / self.tempo
+    }
+
+    /// The current position in quarter notes, or 0 while the sample rate or tempo is 0.
+    pub fn ppq_position(&self) -> f64 {
+        let samples_per_quarter = self.samples_per_quarter();
+
+        if samples_per_quarter > 0.0 && samples_per_quarter.is_finite() {
+            self.sample_pos / samples_per_quarter
+        } else {
+            0.0
+        }
+    }
+
+    /// Length of a bar in quarter notes.
+    ///
+    /// Time signatures with a numerator or denominator of 0 or less are counted as 4/4.
+    pub fn quarters_per_bar(&self) -> f64 {
+        match self.time_signature {
+            (numerator, denominator) if numerator > 0 && denominator > 0 => {
+                numerator as f64 * 4.0 / denominator as f64
+            }
+            _ => 4.0,
+        }
+    }
+
+    /// Position of the start of the current bar in quarter notes.
+    pub fn bar_start(&self) -> f64 {
+        let bar = self.quarters_per_bar();
+        (self.ppq_position() / bar).floor() * bar
+    }
+
+    /// Advance the transport by a block of `samples` samples if it is playing.
+    ///
+    /// When a cycle is active, the position wraps back to the cycle start once it reaches the
+    /// cycle end.
+    pub fn advance(&mut self, samples: usize) {
+        if !self.playing {
+            return;
+        }
+
+        self.sample_pos += samples as f64;
+
+        if let Some((start, end)) = self.cycle {
+            let start = start * self.samples_per_quarter();
+            let end = end * self.samples_per_quarter();
+            let length = end - start;
+
+            // A sample rate or tempo of 0 leaves no cycle to wrap around in
+            if length > 0.0 && length.is_finite() && self.sample_pos >= end {
+                self.sample_pos = start + (self.sample_pos - start) % length;
+            }
+        }
+    }
+
+    /// Create a time info structure describing the current transport state.
+    ///
+    /// The transport changed flag is reported once after a state change and then cleared.
+    pub fn time_info(&mut self) -> api::TimeInfo {
+        let (numerator, denominator) = self.time_signature;
+
+        let mut builder = TimeInfoBuilder::new(self.sample_rate, self.sample_pos)
+                                          .tempo(self.tempo)
+                                          .time_signature(numerator, denominator)
+                                          .ppq_position(self.ppq_position())
+                                          .bar_start(self.bar_start())
+                                          .playing(self.playing)
+                                          .recording(self.recording)
+                                          .transport_changed(self.changed);
+
+        if let Some((start, end)) = self.cycle {
+            builder = builder.cycle(start, end);
+        }
+
+        self.changed = false;
+        builder.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::flags;
+    use host::{SharedTimeInfo, Transport, TimeInfoBuilder};
+
+    /// Test that only the flags for the fields that were set are reported.
+    #[test]
+    fn builder_flags() {
+        let info = TimeInfoBuilder::new(44100.0, 0.0).tempo(140.0).playing(true).build();
+        let set = flags::TimeInfo::from_bits_truncate(info.flags);
+
+        assert!(set.contains(flags::TEMPO_VALID));
+        assert!(set.contains(flags::TRANSPORT_PLAYING));
+        assert!(!set.contains(flags::TIME_SIG_VALID));
+        assert!(!set.contains(flags::PPQ_POS_VALID));
+    }
+
+    /// Test the quarter note and bar calculations.
+    #[test]
+    fn bars() {
+        let mut transport = Transport::new(48000.0);
+        transport.set_tempo(60.0);
+        transport.set_time_signature(6, 8);
+        transport.play();
+
+        // 4 seconds at 60 bpm is 4 quarter notes, a 6/8 bar is 3 quarter notes.
+        transport.advance(4 * 48000);
+        assert_eq!(transport.ppq_position(), 4.0);
+        assert_eq!(transport.bar_start(), 3.0);
+    }
+
+    /// Test that the position wraps around at the end of the cycle.
+    #[test]
+    fn cycle() {
+        let mut transport = Transport::new(100.0);
+        transport.set_tempo(60.0);
+        transport.set_cycle(Some((1.0, 3.0)));
+        transport.play();
+
+        transport.advance(350);
+        assert_eq!(transport.ppq_position(), 1.5);
+    }
+
+    /// Test that the transport changed flag is reported once.
+    #[test]
+    fn transport_changed() {
+        let mut transport = Transport::new(44100.0);
+        transport.play();
+
+        let changed = |info: ::api::TimeInfo| {
+            flags::TimeInfo::from_bits_truncate(info.flags).contains(flags::TRANSPORT_CHANGED)
+        };
+
+        assert!(changed(transport.time_info()));
+        assert!(!changed(transport.time_info()));
+    }
+
+    /// Test that a cycle does not hang the transport while the sample rate is 0.
+    #[test]
+    fn cycle_zero_sample_rate() {
+        let mut transport = Transport::new(0.0);
+        transport.set_cycle(Some((1.0, 3.0)));
+        transport.play();
+
+        transport.advance(512);
+        assert_eq!(transport.sample_position(), 512.0);
+        assert_eq!(transport.ppq_position(), 0.0);
+    }
+
+    /// Test that an invalid time signature does not produce NaN positions.
+    #[test]
+    fn zero_denominator() {
+        let mut transport = Transport::new(100.0);
+        transport.set_tempo(60.0);
+        transport.set_time_signature(3, 0);
+        transport.play();
+
+        transport.advance(500);
+        assert_eq!(transport.quarters_per_bar(), 4.0);
+        assert_eq!(transport.bar_start(), 4.0);
+    }
+
+    /// Test that a stopped transport does not move.
+    #[test]
+    fn stopped() {
+        let mut transport = Transport::new(44100.0);
+        transport.advance(512);
+        assert_eq!(transport.sample_position(), 0.0);
+    }
+
+    /// Test that time info set on one thread is read consistently on another.
+    #[test]
+    fn shared_time_info() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let mut transport = Transport::new(44100.0);
+        transport.play();
+        let mut info = transport.time_info();
+        info.nanoseconds = info.sample_pos;
+        let shared = Arc::new(SharedTimeInfo::new(info));
+
+        let reader = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                // Read until the last position is set, positions only move forward
+                let mut last = 0.0;
+                while last < 64000.0 {
+                    // Both fields are set together, so a torn read would mismatch
+                    let info = shared.get();
+                    assert_eq!(info.sample_pos, info.nanoseconds);
+                    assert_eq!(info.sample_pos % 64.0, 0.0);
+                    assert!(info.sample_pos >= last);
+                    last = info.sample_pos;
+                }
+            })
+        };
+
+        for _ in 0..1000 {
+            transport.advance(64);
+            let mut info = transport.time_info();
+            info.nanoseconds = info.sample_pos;
+            shared.set(info);
+        }
+        reader.join().unwrap();
+
+        assert_eq!(shared.get().sample_pos, 64000.0);
+    }
+}
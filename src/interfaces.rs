@@ -2,92 +2,424 @@
 
 #![doc(hidden)]
 
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
-use std::mem;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::{cmp, mem, ptr, slice};
 
 use libc::{self, size_t, c_char, c_void};
 
-use buffer::AudioBuffer;
+use buffer::{AudioBuffer, BufferStorage, VariableIo};
+use channels::{SpeakerArrangement, RawSpeakerArrangement};
+use denormal::DenormalGuard;
 use api::consts::*;
-use api::{AEffect, ChannelProperties};
-use editor::{Rect, KeyCode, Key, KnobMode};
-use host::Host;
+use api::{self, AEffect, ChannelProperties};
+use editor::{Rect, KeyCode, KnobMode, ParentWindow};
+use event::{self, Event};
+use host::{Host, FileSelect, FileSelectCommand};
+use plugin::{HostCallback, Plugin};
+use write_fixed;
+
+/// Plugin side data which must stay valid after a dispatch call returns.
+///
+/// This is kept in the `user` pointer of the `AEffect` and freed on shutdown.
+#[derive(Default)]
+pub struct EffectData {
+    /// The editor rect returned for `EditorGetRect`.
+    pub editor_rect: Rect,
+
+    /// Host callback for requests made by the wrapper itself.
+    pub host: HostCallback,
+
+    /// Previous output contents for `process_accumulating`, one output after another.
+    pub accumulate: Vec<f32>,
+
+    /// Channel tables and scratch memory for the buffers of `process_replacing`.
+    pub buffers: BufferStorage<f32>,
+
+    /// Channel tables and scratch memory for the buffers of `process_replacing_f64`.
+    pub buffers_f64: BufferStorage<f64>,
+
+    /// Channel tables for the buffers of `ProcessVarIo`.
+    pub var_io: BufferStorage<f32>,
+
+    /// Events of the current `ProcessEvents` call. They borrow the block passed by the host, so
+    /// the lifetime is a placeholder and the vector is emptied before the call returns.
+    pub events: Vec<Event<'static>>,
+
+    /// Block size set by the host, to reserve the buffer storage on resume.
+    pub block_size: usize,
+
+    /// Editor size requested with `HostCallback::resize_editor`, reported for `EditorGetRect`
+    /// until the editor itself reports it. Only accessed through `editor_size`.
+    pub editor_size: EditorSize,
+
+    /// The last chunk returned for `GetData`, valid until the next request.
+    pub chunk: Vec<u8>,
+
+    /// The last input and output arrangements returned for `GetSpeakerArrangement`.
+    pub speaker_arrangements: Option<(RawSpeakerArrangement, RawSpeakerArrangement)>,
+
+    /// VST version negotiated with the host, see `HostCallback::api_version`. Opcodes added in
+    /// later versions are not answered.
+    pub api_version: i32,
+
+    /// Copy of `Info::flush_denormals`, to avoid querying the info while processing.
+    pub flush_denormals: bool,
+
+    /// Copy of `Info::f64_precision`. Without it, `process_replacing_f64` processes `f32` copies
+    /// of the channels instead of calling `Plugin::process_f64`.
+    pub f64_precision: bool,
+
+    /// `f32` copies of `f64` channels, reserved on resume.
+    pub conversion: Conversion,
+}
+
+/// `f32` copies of the channels of an `f64` buffer, for plugins without a 64 bit path.
+#[derive(Default)]
+pub struct Conversion {
+    /// Samples of the copies, the inputs followed by the outputs.
+    samples: Vec<f32>,
+
+    /// Pointers to the channels in `samples`.
+    ptrs: Vec<*mut f32>,
+
+    /// Channel tables for the `f32` buffer.
+    buffers: BufferStorage<f32>,
+}
+
+// The pointers only point into `samples`
+unsafe impl Send for Conversion {}
+unsafe impl Sync for Conversion {}
+
+impl Conversion {
+    /// Reserve memory for up to `num_inputs` and `num_outputs` channels of `samples` each.
+    pub fn reserve(&mut self, num_inputs: usize, num_outputs: usize, samples: usize) {
+        self.samples.clear();
+        self.samples.reserve((num_inputs + num_outputs) * samples);
+        self.ptrs.clear();
+        self.ptrs.reserve(num_inputs + num_outputs);
+        self.buffers.reserve(num_inputs, num_outputs, samples);
+    }
+
+    /// Prepare silent copies of `num_channels` channels of `samples` each.
+    fn prepare(&mut self, num_channels: usize, samples: usize) {
+        self.samples.clear();
+        self.samples.resize(num_channels * samples, 0.0);
+
+        let base = self.samples.as_mut_ptr();
+        self.ptrs.clear();
+        self.ptrs.extend((0..num_channels).map(|channel| unsafe {
+            base.offset((channel * samples) as isize)
+        }));
+    }
+
+    /// The copy of the channel at `index`, inputs first, as prepared by `prepare`.
+    fn channel(&mut self, index: usize, samples: usize) -> &mut [f32] {
+        &mut self.samples[index * samples..][..samples]
+    }
+
+    /// Process the copies with `Plugin::process`.
+    fn process<P: Plugin + ?Sized>(&mut self,
+                                   plugin: &mut P,
+                                   num_inputs: usize,
+                                   num_outputs: usize,
+                                   samples: usize,
+                                   sidechain: usize) {
+        let (input_ptrs, output_ptrs) = self.ptrs.split_at_mut(num_inputs);
+        let buffer = unsafe {
+            AudioBuffer::from_raw_in_place(input_ptrs.as_mut_ptr(), output_ptrs.as_mut_ptr(),
+                                           num_inputs, num_outputs, samples, &mut self.buffers)
+        }.with_sidechain(sidechain);
+
+        plugin.process(buffer);
+    }
+}
+
+/// Process an `f64` buffer with `f32` copies of its channels. This is the default
+/// `Plugin::process_f64`.
+///
+/// The copies are kept in the memory attached to the buffer by `process_replacing_f64`, which is
+/// reserved on resume. Buffers without it allocate the copies.
+pub fn process_converted<P: Plugin + ?Sized>(plugin: &mut P, mut buffer: AudioBuffer<f64>) {
+    let mut allocated = Conversion::default();
+    let conversion = match buffer.take_conversion() {
+        Some(reserved) => reserved,
+        None => &mut allocated,
+    };
+
+    let samples = buffer.samples();
+    let sidechain = buffer.main_input_count();
+    let (inputs, mut outputs) = buffer.split_mut();
+    let (num_inputs, num_outputs) = (inputs.len(), outputs.len());
+
+    conversion.prepare(num_inputs + num_outputs, samples);
+    for (index, input) in inputs.iter().enumerate() {
+        for (x, y) in input.iter().zip(conversion.channel(index, samples).iter_mut()) {
+            *y = *x as f32;
+        }
+    }
+
+    conversion.process(plugin, num_inputs, num_outputs, samples, sidechain);
+
+    for (index, output) in outputs.iter_mut().enumerate() {
+        for (y, x) in output.iter_mut().zip(conversion.channel(num_inputs + index, samples).iter()) {
+            *y = *x as f64;
+        }
+    }
+}
+
+/// Number of events a `ProcessEvents` call can pass without allocating.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// Get the `EffectData` of an effect created by this library.
+pub unsafe fn effect_data<'a>(effect: *mut AEffect) -> &'a mut EffectData {
+    &mut *((*effect).user as *mut EffectData)
+}
+
+/// Get the requested editor size of an effect created by this library.
+///
+/// The host may re-enter the dispatcher while the size is requested, so this only borrows the
+/// field and not the whole `EffectData`.
+pub unsafe fn editor_size<'a>(effect: *mut AEffect) -> &'a EditorSize {
+    &(*((*effect).user as *const EffectData)).editor_size
+}
+
+/// An editor size requested by the plugin, which may be updated while it is being read.
+#[derive(Default)]
+pub struct EditorSize {
+    pending: AtomicBool,
+    /// Width and height, packed into the upper and lower half.
+    size: AtomicU64,
+}
+
+impl EditorSize {
+    /// Get the requested size, if any.
+    pub fn get(&self) -> Option<(i32, i32)> {
+        if self.pending.load(Ordering::Acquire) {
+            let size = self.size.load(Ordering::Acquire);
+            Some(((size >> 32) as u32 as i32, size as u32 as i32))
+        } else {
+            None
+        }
+    }
+
+    /// Request a size, or clear the request with `None`.
+    pub fn set(&self, size: Option<(i32, i32)>) {
+        if let Some((width, height)) = size {
+            self.size.store((width as u32 as u64) << 32 | height as u32 as u64, Ordering::Release);
+        }
+        self.pending.store(size.is_some(), Ordering::Release);
+    }
+}
+
+/// Create a mutable slice from an array passed by the host, which may be null if empty.
+unsafe fn raw_slice<'a, T>(ptr: *mut T, len: isize) -> &'a mut [T] {
+    if ptr.is_null() || len <= 0 {
+        &mut []
+    } else {
+        slice::from_raw_parts_mut(ptr, len as usize)
+    }
+}
+
+/// Number of input and output channels, taken from the bus layout if the plugin declares one.
+pub fn num_channels<P: Plugin + ?Sized>(plugin: &P) -> (i32, i32) {
+    match plugin.get_bus_layout() {
+        Some(layout) => (layout.num_inputs() as i32, layout.num_outputs() as i32),
+        None => {
+            let info = plugin.get_info();
+            (info.inputs, info.outputs)
+        }
+    }
+}
+
+/// Index of the first sidechain input channel, taken from the bus layout if the plugin declares
+/// one.
+fn first_sidechain_input<P: Plugin + ?Sized>(plugin: &P, num_inputs: i32) -> usize {
+    plugin.get_bus_layout().map_or(num_inputs as usize, |layout| layout.first_sidechain_input())
+}
 
 /// Deprecated process function.
 pub fn process_deprecated(_effect: *mut AEffect, _inputs_raw: *mut *mut f32, _outputs_raw: *mut *mut f32, _samples: i32) { }
 
+/// Deprecated accumulating process function, used if `Info::legacy_host_support` is set.
+///
+/// The output of `Plugin::process` is added to the previous contents of the output buffers.
+pub fn process_accumulating(effect: *mut AEffect, inputs_raw: *mut *mut f32, outputs_raw: *mut *mut f32, samples: i32) {
+    #[cfg(feature = "alloc-check")]
+    let _section = ::alloc_check::section("process_accumulating");
+
+    if samples <= 0 {
+        return;
+    }
+
+    let num_outputs = unsafe { (*effect).numOutputs };
+    let outputs = unsafe { raw_slice(outputs_raw, cmp::max(num_outputs, 0) as isize) };
+    let samples = samples as usize;
+
+    // Taken out of the effect data while processing, which uses it as well. This does not
+    // allocate, the storage is reserved on resume. Null outputs keep their place as silence.
+    let mut saved = mem::replace(unsafe { &mut effect_data(effect).accumulate }, Vec::new());
+    saved.clear();
+    for &output in outputs.iter() {
+        if output.is_null() {
+            let len = saved.len();
+            saved.resize(len + samples, 0.0);
+        } else {
+            saved.extend_from_slice(unsafe { slice::from_raw_parts(output, samples) });
+        }
+    }
+
+    process_replacing(effect, inputs_raw, outputs_raw, samples as i32);
+
+    for (saved, &output) in saved.chunks(samples).zip(outputs.iter()) {
+        if output.is_null() {
+            continue;
+        }
+        let output = unsafe { slice::from_raw_parts_mut(output, samples) };
+        for (o, s) in output.iter_mut().zip(saved.iter()) {
+            *o += *s;
+        }
+    }
+
+    unsafe { effect_data(effect) }.accumulate = saved;
+}
+
 /// VST2.4 replacing function.
 pub fn process_replacing(effect: *mut AEffect, inputs_raw: *mut *mut f32, outputs_raw: *mut *mut f32, samples: i32) {
+    #[cfg(feature = "alloc-check")]
+    let _section = ::alloc_check::section("process");
+
     // Handle to the vst
-    let mut plugin = unsafe { (*effect).get_plugin() };
+    let plugin = unsafe { (*effect).get_plugin() };
 
+    let (num_inputs, num_outputs) = unsafe { ((*effect).numInputs, (*effect).numOutputs) };
     let buffer = unsafe {
-        AudioBuffer::from_raw(inputs_raw,
-                              outputs_raw,
-                              plugin.get_info().inputs as usize,
-                              plugin.get_info().outputs as usize,
-                              samples as usize)
-    };
+        AudioBuffer::from_raw_in_place(inputs_raw,
+                                       outputs_raw,
+                                       cmp::max(num_inputs, 0) as usize,
+                                       cmp::max(num_outputs, 0) as usize,
+                                       cmp::max(samples, 0) as usize,
+                                       &mut effect_data(effect).buffers)
+    }.with_sidechain(first_sidechain_input(&**plugin, num_inputs));
+
+    let flush_denormals = unsafe { effect_data(effect).flush_denormals };
+    let _guard = if flush_denormals { Some(DenormalGuard::new()) } else { None };
 
     plugin.process(buffer);
 }
 
 /// VST2.4 replacing function with `f64` values.
 pub fn process_replacing_f64(effect: *mut AEffect, inputs_raw: *mut *mut f64, outputs_raw: *mut *mut f64, samples: i32) {
-    let mut plugin = unsafe { (*effect).get_plugin() };
+    #[cfg(feature = "alloc-check")]
+    let _section = ::alloc_check::section("process_f64");
+
+    let plugin = unsafe { (*effect).get_plugin() };
+    let data = unsafe { effect_data(effect) };
+
+    let (num_inputs, num_outputs) = unsafe { ((*effect).numInputs, (*effect).numOutputs) };
+    let sidechain = first_sidechain_input(&**plugin, num_inputs);
 
-    if plugin.get_info().f64_precision {
+    // Channel tables the host did not pass are processed as having no channels
+    let num_inputs = if inputs_raw.is_null() { 0 } else { cmp::max(num_inputs, 0) as usize };
+    let num_outputs = if outputs_raw.is_null() { 0 } else { cmp::max(num_outputs, 0) as usize };
+    let samples = cmp::max(samples, 0) as usize;
+
+    let _guard = if data.flush_denormals { Some(DenormalGuard::new()) } else { None };
+
+    if data.f64_precision {
         let buffer = unsafe {
-            AudioBuffer::from_raw(inputs_raw,
-                                  outputs_raw,
-                                  plugin.get_info().inputs as usize,
-                                  plugin.get_info().outputs as usize,
-                                  samples as usize)
-        };
+            AudioBuffer::from_raw_in_place(inputs_raw, outputs_raw, num_inputs, num_outputs,
+                                           samples, &mut data.buffers_f64)
+        }.with_sidechain(sidechain).with_conversion(&mut data.conversion);
 
         plugin.process_f64(buffer);
+        return;
+    }
+
+    // Without a 64 bit path the plugin processes `f32` copies, which are preallocated on resume
+    let inputs = unsafe { raw_slice(inputs_raw, num_inputs as isize) };
+    let outputs = unsafe { raw_slice(outputs_raw, num_outputs as isize) };
+    let conversion = &mut data.conversion;
+
+    conversion.prepare(num_inputs + num_outputs, samples);
+    for (index, &input) in inputs.iter().enumerate().filter(|&(_, &input)| !input.is_null()) {
+        let input = unsafe { slice::from_raw_parts(input, samples) };
+        for (x, y) in input.iter().zip(conversion.channel(index, samples).iter_mut()) {
+            *y = *x as f32;
+        }
+    }
+
+    conversion.process(&mut **plugin, num_inputs, num_outputs, samples, sidechain);
+
+    for (index, &output) in outputs.iter().enumerate().filter(|&(_, &output)| !output.is_null()) {
+        let output = unsafe { slice::from_raw_parts_mut(output, samples) };
+        for (y, x) in output.iter_mut().zip(conversion.channel(num_inputs + index, samples).iter()) {
+            *y = *x as f64;
+        }
     }
 }
 
 /// VST2.4 set parameter function.
 pub fn set_parameter(effect: *mut AEffect, index: i32, value: f32) {
+    #[cfg(feature = "alloc-check")]
+    let _section = ::alloc_check::section("set_parameter");
     unsafe { (*effect).get_plugin() }.set_parameter(index, value);
 }
 
 /// VST2.4 get parameter function.
 pub fn get_parameter(effect: *mut AEffect, index: i32) -> f32 {
+    #[cfg(feature = "alloc-check")]
+    let _section = ::alloc_check::section("get_parameter");
     unsafe { (*effect).get_plugin() }.get_parameter(index)
 }
 
 /// VST2.4 dispatch function. This function handles dispatching all opcodes to the vst plugin.
 pub fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr: *mut c_void, opt: f32) -> isize {
-    use plugin::{CanDo, OpCode, Plugin};
+    use plugin::{CanDo, OpCode};
+
+    // Unknown opcodes can not be converted to the enum
+    if opcode < 0 || opcode > OpCode::GetNumMidiOutputs as i32 {
+        warn!("Unknown opcode ({})", opcode);
+        return 0;
+    }
 
     // Convert passed in opcode to enum
     let opcode = OpCode::from(opcode);
+
+    // Hosts only send opcodes of the VST version they implement, anything newer is not answered
+    if opcode.api_version() > unsafe { effect_data(effect) }.api_version {
+        warn!("Opcode {:?} is not supported by the host's VST version", opcode);
+        return 0;
+    }
+
     // Plugin handle
-    let mut plugin = unsafe { (*effect).get_plugin() };
+    let plugin = unsafe { (*effect).get_plugin() };
 
-    // Copy a string into the `ptr` buffer
+    // Copy a string into the `ptr` buffer of `max` bytes, truncated and always terminated
     let copy_string = |string: &String, max: size_t| {
-        unsafe {
-            libc::strncpy(ptr as *mut c_char,
-                          CString::new(string.clone()).unwrap().as_ptr(),
-                          max);
+        if !ptr.is_null() {
+            write_fixed(unsafe { slice::from_raw_parts_mut(ptr as *mut u8, max) }, string);
         }
     };
 
-    // Read a string from the `ptr` buffer
+    // Read a string from the `ptr` buffer, empty if the host passed none
     let read_string = || -> String {
+        if ptr.is_null() {
+            return String::new();
+        }
         String::from_utf8_lossy(
             unsafe { CStr::from_ptr(ptr as *mut c_char).to_bytes() }
         ).into_owned()
     };
 
     match opcode {
-        OpCode::Initialize => plugin.init(),
+        OpCode::Initialize => plugin.open(),
         OpCode::Shutdown => unsafe {
+            plugin.close();
             (*effect).drop_plugin();
+            drop(mem::transmute::<*mut c_void, Box<EffectData>>((*effect).user));
             drop(mem::transmute::<*mut AEffect, Box<AEffect>>(effect));
         },
 
@@ -104,9 +436,31 @@ pub fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr
         OpCode::GetParameterName => copy_string(&plugin.get_parameter_name(index), MAX_PARAM_STR_LEN),
 
         OpCode::SetSampleRate => plugin.sample_rate_changed(opt),
-        OpCode::SetBlockSize => plugin.block_size_changed(value as i64),
+        OpCode::SetBlockSize => {
+            unsafe { effect_data(effect) }.block_size = cmp::max(value, 0) as usize;
+            plugin.block_size_changed(value as i64);
+        }
         OpCode::StateChanged => {
             if value == 1 {
+                let info = plugin.get_info();
+                if info.legacy_host_support && (info.midi_inputs > 0 || info.is_instrument()) {
+                    unsafe { effect_data(effect) }.host.want_midi();
+                }
+
+                // Processing does not allocate as long as the host sticks to the block size
+                let (num_inputs, num_outputs) = num_channels(&**plugin);
+                let (num_inputs, num_outputs) = (num_inputs as usize, num_outputs as usize);
+                let data = unsafe { effect_data(effect) };
+                data.buffers.reserve(num_inputs, num_outputs, data.block_size);
+                data.buffers_f64.reserve(num_inputs, num_outputs, data.block_size);
+                data.var_io.reserve(num_inputs, num_outputs, 0);
+                data.events.reserve(EVENT_CAPACITY);
+                data.conversion.reserve(num_inputs, num_outputs, data.block_size);
+                if info.legacy_host_support {
+                    data.accumulate.clear();
+                    data.accumulate.reserve(num_outputs * data.block_size);
+                }
+
                 plugin.on_resume();
             } else {
                 plugin.on_suspend();
@@ -115,32 +469,48 @@ pub fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr
 
         OpCode::EditorGetRect => {
             if let Some(editor) = plugin.get_editor() {
-                let size = editor.size();
+                let mut size = editor.size();
                 let pos = editor.position();
 
+                // A size requested by the plugin is reported until the editor has caught up
+                let requested = unsafe { editor_size(effect) };
+                match requested.get() {
+                    Some(pending) if pending != size => size = pending,
+                    _ => requested.set(None),
+                }
+
                 unsafe {
+                    // The rect must stay valid after returning, so it is kept with the `AEffect`.
+                    // Only the field is borrowed, see `editor_size`.
+                    let rect = &mut (*((*effect).user as *mut EffectData)).editor_rect as *mut Rect;
+                    *rect = Rect {
+                        left: pos.0 as i16, //x coord of position
+                        top: pos.1 as i16, //y coord of position
+                        right: (pos.0 + size.0) as i16, //x coord of pos + x coord of size
+                        bottom: (pos.1 + size.1) as i16 //y coord of pos + y coord of size
+                    };
+
                     //given a Rect** structure
-                    *(ptr as *mut *mut c_void) =
-                        mem::transmute(Box::new(Rect {
-                            left: pos.0 as i16, //x coord of position
-                            top: pos.1 as i16, //y coord of position
-                            right: (pos.0 + size.0) as i16, //x coord of pos + x coord of size
-                            bottom: (pos.1 + size.1) as i16 //y coord of pos + y coord of size
-                        }));
+                    *(ptr as *mut *mut c_void) = rect as *mut c_void;
                 }
+                return 1;
             }
         }
         OpCode::EditorOpen => {
             if let Some(editor) = plugin.get_editor() {
-                editor.open(ptr); //ptr is raw window handle, eg HWND* on windows
+                editor.open(ParentWindow::from_raw(ptr));
+                return 1;
             }
         }
         OpCode::EditorClose => {
             if let Some(editor) = plugin.get_editor() {
                 editor.close();
+                return 1;
             }
         }
 
+        OpCode::Idle => plugin.idle(),
+
         OpCode::EditorIdle => {
             if let Some(editor) = plugin.get_editor() {
                 editor.idle();
@@ -156,16 +526,24 @@ pub fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr
 
             let len = chunks.len() as isize;
 
-            // u8 array to **void ptr.
+            // The host does not free the chunk, so it is kept until the next request or shutdown
             unsafe {
-                *(ptr as *mut *mut c_void) =
-                    chunks.into_boxed_slice().as_ptr() as *mut c_void;
+                let data = effect_data(effect);
+                data.chunk = chunks;
+
+                // u8 array to **void ptr.
+                *(ptr as *mut *mut c_void) = data.chunk.as_mut_ptr() as *mut c_void;
             }
 
             return len;
         }
         OpCode::SetData => {
-            let chunks = unsafe { Vec::from_raw_parts(ptr as *mut u8, value as usize, value as usize) };
+            if ptr.is_null() || value < 0 {
+                return 0;
+            }
+
+            // The chunk is owned by the host, so it is copied
+            let chunks = unsafe { slice::from_raw_parts(ptr as *const u8, value as usize) }.to_vec();
             if index == 0 {
                 plugin.load_bank_data(chunks);
             } else {
@@ -173,67 +551,267 @@ pub fn dispatch(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr
             }
         }
 
+        OpCode::ProcessEvents => {
+            #[cfg(feature = "alloc-check")]
+            let _section = ::alloc_check::section("process_events");
+
+            // Only the lifetime differs, and the events are removed again before returning
+            let data = unsafe { effect_data(effect) };
+            let events: &mut Vec<Event> = unsafe { mem::transmute(&mut data.events) };
+            unsafe { Event::read_raw_events(ptr as *const api::Events, events) };
+            event::sort_by_time(events);
+
+            plugin.process_events(events);
+            events.clear();
+            return 1;
+        }
+
         OpCode::CanBeAutomated => return plugin.can_be_automated(index) as isize,
-        OpCode::StringToParameter => return plugin.string_to_parameter(index, read_string()) as isize,
+        OpCode::StringToParameter => {
+            // A null string is only a query whether text input is supported.
+            if ptr.is_null() {
+                return 0;
+            }
+            return plugin.string_to_parameter(index, read_string()) as isize;
+        }
 
-        OpCode::GetPresetName => copy_string(&plugin.get_preset_name(index), MAX_PRESET_NAME_LEN),
+        OpCode::GetPresetName => {
+            if index < 0 || index >= plugin.get_info().presets {
+                return 0;
+            }
+            copy_string(&plugin.get_preset_name(index), MAX_PRESET_NAME_LEN);
+            return 1;
+        }
+        OpCode::BeginSetPreset => plugin.begin_set_preset(),
+        OpCode::EndSetPreset => plugin.end_set_preset(),
 
         OpCode::GetInputInfo => {
-            if index >= 0 && index < plugin.get_info().inputs {
+            if !ptr.is_null() && index >= 0 && index < num_channels(&**plugin).0 {
                 unsafe {
                     let ptr = mem::transmute::<_, *mut ChannelProperties>(ptr);
                     *ptr = plugin.get_input_info(index).into();
                 }
+                return 1;
             }
         }
         OpCode::GetOutputInfo => {
-            if index >= 0 && index < plugin.get_info().outputs {
+            if !ptr.is_null() && index >= 0 && index < num_channels(&**plugin).1 {
                 unsafe {
                     let ptr = mem::transmute::<_, *mut ChannelProperties>(ptr);
                     *ptr = plugin.get_output_info(index).into();
                 }
+                return 1;
             }
         }
         OpCode::GetCategory => {
             return plugin.get_info().category.into();
         }
+        OpCode::ShellGetNextPlugin => {
+            return match plugin.next_shell_plugin() {
+                Some((unique_id, name)) => {
+                    copy_string(&name, MAX_PRODUCT_STR_LEN);
+                    unique_id as isize
+                }
+                None => 0,
+            };
+        }
 
         OpCode::GetVendorName => copy_string(&plugin.get_info().vendor, MAX_VENDOR_STR_LEN),
         OpCode::GetProductName => copy_string(&plugin.get_info().name, MAX_PRODUCT_STR_LEN),
         OpCode::GetVendorVersion => return plugin.get_info().version as isize,
-        OpCode::VendorSpecific => plugin.vendor_specific(index, value, ptr, opt),
+        OpCode::VendorSpecific => return plugin.vendor_specific(index, value, ptr, opt),
         OpCode::CanDo => {
             let can_do: CanDo = match read_string().parse() {
                 Ok(c) => c,
                 Err(e) => { warn!("{}", e); return 0; }
             };
-            return plugin.can_do(can_do).into();
+            return match can_do.answer(&plugin.get_info(), plugin.get_bus_layout()) {
+                Some(supported) => supported,
+                None => plugin.can_do(can_do),
+            }.into();
+        }
+        OpCode::GetNumMidiInputs => return plugin.get_info().midi_inputs as isize,
+        OpCode::GetNumMidiOutputs => return plugin.get_info().midi_outputs as isize,
+
+        OpCode::GetTailSize => return plugin.get_tail_size(),
+
+        OpCode::GetParameterProperties => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            if let Some(properties) = plugin.get_parameter_properties(index) {
+                unsafe {
+                    *(ptr as *mut api::ParameterProperties) = properties.into();
+                }
+                return 1;
+            }
+        }
+
+        OpCode::GetApiVersion => return VST_VERSION as isize,
+
+        OpCode::SetPrecision => {
+            use plugin::ProcessPrecision;
+
+            let precision = if value == 0 { ProcessPrecision::F32 } else { ProcessPrecision::F64 };
+            if precision == ProcessPrecision::F64 && !plugin.get_info().f64_precision {
+                return 0;
+            }
+
+            plugin.set_precision(precision);
+            return 1;
+        }
+
+        OpCode::SoftBypass => return plugin.set_bypass(value != 0) as isize,
+
+        OpCode::GetMidiProgramName => {
+            let programs = plugin.get_midi_programs(index);
+            if ptr.is_null() {
+                return programs.len() as isize;
+            }
+
+            let raw = unsafe { &mut *(ptr as *mut api::MidiProgramName) };
+            let program = raw.this_program_index;
+
+            if program >= 0 && (program as usize) < programs.len() {
+                programs[program as usize].write_raw(program, raw);
+            }
+            return programs.len() as isize;
+        }
+        OpCode::GetCurrentMidiProgram => {
+            let programs = plugin.get_midi_programs(index);
+
+            if let Some(current) = plugin.get_current_midi_program(index) {
+                if current >= 0 && (current as usize) < programs.len() {
+                    if !ptr.is_null() {
+                        let raw = unsafe { &mut *(ptr as *mut api::MidiProgramName) };
+                        programs[current as usize].write_raw(current, raw);
+                    }
+                    return current as isize;
+                }
+            }
+            return -1;
+        }
+        OpCode::GetMidiProgramCategory => {
+            let categories = plugin.get_midi_program_categories(index);
+            if ptr.is_null() {
+                return categories.len() as isize;
+            }
+
+            let raw = unsafe { &mut *(ptr as *mut api::MidiProgramCategory) };
+            let category = raw.this_category_index;
+
+            if category >= 0 && (category as usize) < categories.len() {
+                let category = &categories[category as usize];
+
+                write_fixed(&mut raw.name, &category.name);
+                raw.parent_category_index = category.parent.unwrap_or(-1);
+                raw.flags = 0;
+            }
+            return categories.len() as isize;
+        }
+        OpCode::HasMidiProgramsChanged => return plugin.has_midi_programs_changed(index) as isize,
+        OpCode::GetMidiKeyName => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            let raw = unsafe { &mut *(ptr as *mut api::MidiKeyName) };
+
+            match plugin.get_midi_key_name(index, raw.this_program_index, raw.this_key_number) {
+                Some(name) => {
+                    write_fixed(&mut raw.key_name, &name);
+                    return 1;
+                }
+                None => return 0,
+            }
+        }
+
+        OpCode::OfflineNotify => {
+            let files = unsafe { raw_slice(ptr as *mut api::AudioFile, value) };
+            return plugin.offline_notify(files, index != 0) as isize;
+        }
+        OpCode::OfflinePrepare => {
+            let tasks = unsafe { raw_slice(ptr as *mut api::OfflineTask, value) };
+            return plugin.offline_prepare(tasks) as isize;
+        }
+        OpCode::OfflineRun => {
+            let tasks = unsafe { raw_slice(ptr as *mut api::OfflineTask, value) };
+            return plugin.offline_run(tasks) as isize;
+        }
+
+        OpCode::ProcessVarIo => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            #[cfg(feature = "alloc-check")]
+            let _section = ::alloc_check::section("process_var_io");
+
+            let (num_inputs, num_outputs) = unsafe { ((*effect).numInputs, (*effect).numOutputs) };
+            let io = unsafe {
+                VariableIo::from_raw(ptr as *mut api::VariableIo,
+                                     num_inputs as usize,
+                                     num_outputs as usize,
+                                     &mut effect_data(effect).var_io)
+            };
+            return plugin.process_var_io(io) as isize;
         }
-        OpCode::GetTailSize => if plugin.get_tail_size() == 0 { return 1; } else { return plugin.get_tail_size() },
 
-        //OpCode::GetParamInfo => { /*TODO*/ }
+        OpCode::SetSpeakerArrangement => {
+            if value == 0 || ptr.is_null() {
+                return 0;
+            }
 
-        OpCode::GetApiVersion => return 2400,
+            let (input, output) = unsafe {
+                (SpeakerArrangement::from_raw(value as *const api::SpeakerArrangement),
+                 SpeakerArrangement::from_raw(ptr as *const api::SpeakerArrangement))
+            };
+
+            if plugin.set_speaker_arrangement(input, output) {
+                // The plugin reports the new channel counts through its info or bus layout
+                let (num_inputs, num_outputs) = num_channels(&**plugin);
+                unsafe {
+                    (*effect).numInputs = num_inputs;
+                    (*effect).numOutputs = num_outputs;
+                }
+                return 1;
+            }
+        }
+        OpCode::GetSpeakerArrangement => {
+            if value == 0 || ptr.is_null() {
+                return 0;
+            }
+
+            if let Some((input, output)) = plugin.get_speaker_arrangement() {
+                unsafe {
+                    // The arrangements must stay valid after returning
+                    let data = effect_data(effect);
+                    data.speaker_arrangements = Some(((&input).into(), (&output).into()));
+
+                    if let Some((ref mut input, ref mut output)) = data.speaker_arrangements {
+                        *(value as *mut *mut api::SpeakerArrangement) = input.as_mut_ptr();
+                        *(ptr as *mut *mut api::SpeakerArrangement) = output.as_mut_ptr();
+                    }
+                }
+                return 1;
+            }
+        }
 
         OpCode::EditorKeyDown => {
             if let Some(editor) = plugin.get_editor() {
-                editor.key_down(KeyCode {
-                    character: index as u8 as char,
-                    key: Key::from(value),
-                    modifier: unsafe { mem::transmute::<f32, i32>(opt) } as u8
-                });
+                return editor.key_down(KeyCode::from_raw(index, value, opt)) as isize;
             }
         }
         OpCode::EditorKeyUp => {
             if let Some(editor) = plugin.get_editor() {
-                editor.key_up(KeyCode {
-                    character: index as u8 as char,
-                    key: Key::from(value),
-                    modifier: unsafe { mem::transmute::<f32, i32>(opt) } as u8
-                });
+                return editor.key_up(KeyCode::from_raw(index, value, opt)) as isize;
             }
         }
         OpCode::EditorSetKnobMode => {
+            if value < 0 || value > KnobMode::Linear as isize {
+                return 0;
+            }
             if let Some(editor) = plugin.get_editor() {
                 editor.set_knob_mode(KnobMode::from(value));
             }
@@ -257,14 +835,171 @@ pub fn host_dispatch(host: &mut Host,
                      opt: f32) -> isize {
     use host::OpCode;
 
+    // Unknown opcodes can not be converted to the enum
+    if opcode < 0 || opcode > OpCode::_GetInputSpeakerArrangement as i32 {
+        warn!("Unknown host opcode ({})", opcode);
+        return 0;
+    }
+
+    // Copy a string into the `ptr` buffer of `max` bytes, truncated and always terminated
+    let copy_string = |string: &String, max: size_t| {
+        if ptr.is_null() {
+            return 0;
+        }
+        write_fixed(unsafe { slice::from_raw_parts_mut(ptr as *mut u8, max) }, string);
+        1
+    };
+
     match OpCode::from(opcode) {
-        OpCode::Version => return 2400,
+        OpCode::Version => return VST_VERSION as isize,
+        OpCode::GetVendorString => {
+            return copy_string(&host.get_vendor_string(), MAX_VENDOR_STR_LEN);
+        }
+        OpCode::GetProductString => {
+            return copy_string(&host.get_product_string(), MAX_PRODUCT_STR_LEN);
+        }
+        OpCode::GetVendorVersion => return host.get_vendor_version() as isize,
+        OpCode::CanDo => {
+            if ptr.is_null() {
+                return 0;
+            }
+            let value = unsafe { CStr::from_ptr(ptr as *mut c_char) }.to_string_lossy();
+            return host.can_do(&value).into();
+        }
         OpCode::Automate => host.automate(index, opt),
+        OpCode::CurrentId => return host.get_plugin_id() as isize,
+        OpCode::NeedIdle => return host.need_idle() as isize,
+        OpCode::IOChanged => {
+            let supported = host.io_changed();
+
+            // `reserved2` holds the last latency reported to the host
+            if !effect.is_null() {
+                let effect = unsafe { &mut *effect };
+                if effect.initialDelay as isize != effect.reserved2 {
+                    effect.reserved2 = effect.initialDelay as isize;
+                    host.latency_changed(effect.initialDelay);
+                }
+            }
+
+            return supported as isize;
+        }
+        OpCode::GetTime => {
+            return match host.get_time_info(value as i32) {
+                Some(info) => TIME_INFO.with(|t| {
+                    *t.borrow_mut() = info;
+                    // The pointer stays valid until the next request on this thread
+                    t.as_ptr() as isize
+                }),
+                None => 0,
+            };
+        }
+        OpCode::GetSampleRate => return host.get_sample_rate() as isize,
+        OpCode::GetBlockSize => return host.get_block_size() as isize,
+        OpCode::UpdateDisplay => return host.update_display() as isize,
+        OpCode::BeginEdit => return host.begin_edit(index) as isize,
+        OpCode::EndEdit => return host.end_edit(index) as isize,
+        OpCode::SizeWindow => return host.size_window(index, value as i32) as isize,
+        OpCode::ProcessEvents => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            let events = unsafe { Event::from_raw_events(ptr as *const api::Events) };
+
+            host.process_events(&events);
+            return 1;
+        }
+        OpCode::OpenFileSelector => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            let raw = unsafe { &mut *(ptr as *mut api::FileSelect) };
+            let selector = unsafe { FileSelect::from_raw(raw) };
+
+            if let Some(paths) = host.open_file_selector(&selector) {
+                if !paths.is_empty() {
+                    unsafe { write_file_select(raw, selector.command, &paths) };
+                    return 1;
+                }
+            }
+            return 0;
+        }
+        OpCode::CloseFileSelector => {
+            if ptr.is_null() {
+                return 0;
+            }
+
+            let raw = unsafe { &mut *(ptr as *mut api::FileSelect) };
+            host.close_file_selector(&unsafe { FileSelect::from_raw(raw) });
+            unsafe { free_file_select(raw) };
+            return 1;
+        }
         unimplemented => {
-            println!("VST: Got unimplemented host opcode ({:?})", unimplemented);
+            warn!("Unimplemented host opcode ({:?})", unimplemented);
             trace!("Arguments; effect: {:?}, index: {}, value: {}, ptr: {:?}, opt: {}",
                     effect, index, value, ptr, opt);
         }
     }
     0
 }
+
+thread_local! {
+    /// Storage for the time info returned to plugins by `host_dispatch`.
+    static TIME_INFO: RefCell<api::TimeInfo> = RefCell::new(Default::default())
+}
+
+/// Marks a `FileSelect::return_path` buffer as allocated by the host.
+const HOST_RETURN_PATH: isize = 1;
+
+/// Write the paths selected by the host into the plugin's `FileSelect` structure.
+///
+/// Any memory allocated here is released in `free_file_select` when the plugin closes the file
+/// selector.
+unsafe fn write_file_select(raw: &mut api::FileSelect, command: FileSelectCommand, paths: &[PathBuf]) {
+    let to_cstring = |path: &PathBuf| {
+        CString::new(path.to_string_lossy().into_owned()).unwrap_or_else(|_| CString::new("").unwrap())
+    };
+
+    if let FileSelectCommand::LoadMultiple = command {
+        let list = libc::malloc(paths.len() * mem::size_of::<*mut c_char>()) as *mut *mut c_char;
+        for (i, path) in paths.iter().enumerate() {
+            *list.offset(i as isize) = libc::strdup(to_cstring(path).as_ptr());
+        }
+
+        raw.return_multiple_paths = list;
+        raw.num_return_paths = paths.len() as i32;
+    } else {
+        let path = to_cstring(&paths[0]);
+
+        if raw.return_path.is_null() {
+            // The plugin asked the host to allocate the buffer.
+            raw.return_path = libc::strdup(path.as_ptr());
+            raw.return_path_size = path.as_bytes().len() as i32 + 1;
+            raw.reserved = HOST_RETURN_PATH;
+        } else if raw.return_path_size > 0 {
+            libc::strncpy(raw.return_path, path.as_ptr(), raw.return_path_size as size_t - 1);
+            *raw.return_path.offset(raw.return_path_size as isize - 1) = 0;
+        }
+
+        raw.num_return_paths = 1;
+    }
+}
+
+/// Free any memory allocated by `write_file_select`.
+unsafe fn free_file_select(raw: &mut api::FileSelect) {
+    if raw.reserved == HOST_RETURN_PATH && !raw.return_path.is_null() {
+        libc::free(raw.return_path as *mut c_void);
+        raw.return_path = ptr::null_mut();
+        raw.reserved = 0;
+    }
+
+    if !raw.return_multiple_paths.is_null() {
+        for i in 0..raw.num_return_paths as isize {
+            libc::free(*raw.return_multiple_paths.offset(i) as *mut c_void);
+        }
+        libc::free(raw.return_multiple_paths as *mut c_void);
+        raw.return_multiple_paths = ptr::null_mut();
+        raw.num_return_paths = 0;
+    }
+}
@@ -95,6 +95,18 @@ extern crate num;
 extern crate dylib;
 #[macro_use] extern crate log;
 #[macro_use] extern crate bitflags;
+#[cfg(feature = "window-handle")] extern crate raw_window_handle;
+#[cfg(feature = "chunk")] extern crate serde;
+#[cfg(feature = "chunk")] extern crate bincode;
+#[cfg(feature = "text-presets")] extern crate serde_json;
+#[cfg(feature = "text-presets")] extern crate toml;
+#[cfg(feature = "text-presets")] extern crate base64;
+#[cfg(feature = "standalone")] extern crate cpal;
+#[cfg(feature = "standalone")] extern crate midir;
+#[cfg(feature = "alloc-check")] extern crate backtrace;
+#[cfg(feature = "egui-editor")] extern crate egui;
+#[cfg(feature = "egui-editor")] extern crate baseview;
+#[cfg(feature = "egui-editor")] extern crate egui_baseview;
 
 use std::{ptr, mem};
 
@@ -123,18 +135,74 @@ macro_rules! impl_clike {
     }
 }
 
+#[cfg(feature = "alloc-check")]
+pub mod alloc_check;
+pub mod automation;
+pub mod bench;
 pub mod buffer;
+pub mod bus;
 pub mod api;
+pub mod denormal;
 pub mod editor;
+#[cfg(feature = "egui-editor")]
+pub mod egui_editor;
 pub mod channels;
+#[cfg(feature = "chunk")]
+#[macro_use]
+pub mod chunk;
+pub mod event;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
 pub mod host;
+pub mod meter;
+pub mod midi;
+pub mod midi_file;
+pub mod midi_learn;
+pub mod mix;
+pub mod oversample;
+pub mod param_store;
+#[macro_use]
+pub mod params;
 pub mod plugin;
+pub mod preset;
+pub mod program;
+pub mod render;
+#[macro_use]
+pub mod rt_log;
+pub mod shell;
+pub mod smooth;
+pub mod spsc;
+#[cfg(feature = "standalone")]
+pub mod standalone;
+pub mod testing;
+#[cfg(feature = "text-presets")]
+pub mod text_preset;
+pub mod time;
+pub mod validator;
+pub mod voice;
 mod interfaces;
 
+use std::cmp;
+
 use api::{HostCallbackProc, AEffect};
-use api::consts::VST_MAGIC;
+use api::consts::{VST_MAGIC, VST_VERSION};
 use plugin::{HostCallback, Plugin};
 
+/// Read a null terminated string from a fixed size buffer.
+fn read_fixed(buf: &[u8]) -> String {
+    String::from_utf8_lossy(buf).chars().take_while(|c| *c != '\0').collect()
+}
+
+/// Write a string into a fixed size buffer, truncating it to leave room for a null terminator.
+fn write_fixed(buf: &mut [u8], string: &str) {
+    let len = cmp::min(string.len(), buf.len().saturating_sub(1));
+
+    buf[..len].copy_from_slice(&string.as_bytes()[..len]);
+    for byte in &mut buf[len..] {
+        *byte = 0;
+    }
+}
+
 /// Exports the necessary symbols for the plugin to be used by a VST host.
 ///
 /// This macro takes a type which must implement the traits `plugin::Plugin` and
@@ -166,41 +234,55 @@ macro_rules! plugin_main {
 /// Initializes a VST plugin and returns a raw pointer to an AEffect struct.
 #[doc(hidden)]
 pub fn main<T: Plugin + Default>(callback: HostCallbackProc) -> *mut AEffect {
+    create_effect(callback, T::new)
+}
+
+/// Initializes the plugin returned by `create` and returns a raw pointer to an AEffect struct.
+fn create_effect<T: Plugin, F: FnOnce(HostCallback) -> T>(callback: HostCallbackProc,
+                                                           create: F)
+                                                           -> *mut AEffect {
     // Create a Box containing a zeroed AEffect. This is transmuted into a *mut pointer so that it
     // can be passed into the HostCallback `wrap` method. The AEffect is then updated after the vst
     // object is created so that the host still contains a raw pointer to the AEffect struct.
     let effect = unsafe { mem::transmute(Box::new(mem::zeroed::<AEffect>())) };
 
     let host = HostCallback::wrap(callback, effect);
-    if host.vst_version() == 0 { // TODO: Better criteria would probably be useful here...
+    let version = host.api_version();
+    if version == 0 { // TODO: Better criteria would probably be useful here...
         return ptr::null_mut();
     }
 
     trace!("Creating VST plugin instance...");
-    let mut plugin = T::new(host);
+    let mut plugin = create(host);
     let info = plugin.get_info().clone();
+    let (num_inputs, num_outputs) = interfaces::num_channels(&plugin);
 
     // Update AEffect in place
     unsafe { *effect = AEffect {
         magic: VST_MAGIC,
         dispatcher: interfaces::dispatch, // fn pointer
 
-        _process: interfaces::process_deprecated, // fn pointer
+        _process: if info.legacy_host_support {
+            interfaces::process_accumulating
+        } else {
+            interfaces::process_deprecated
+        }, // fn pointer
 
         setParameter: interfaces::set_parameter, // fn pointer
         getParameter: interfaces::get_parameter, // fn pointer
 
         numPrograms: info.presets,
         numParams: info.parameters,
-        numInputs: info.inputs,
-        numOutputs: info.outputs,
+        numInputs: num_inputs,
+        numOutputs: num_outputs,
 
         flags: {
             use api::flags::*;
 
             let mut flag = CAN_REPLACING;
 
-            if info.f64_precision {
+            // Double precision processing was added in VST 2.4
+            if info.f64_precision && version >= VST_VERSION {
                 flag = flag | CAN_DOUBLE_REPLACING;
             }
 
@@ -212,11 +294,12 @@ pub fn main<T: Plugin + Default>(callback: HostCallbackProc) -> *mut AEffect {
                 flag = flag | PROGRAM_CHUNKS;
             }
 
-            if let plugin::Category::Synth = info.category {
+            if info.is_instrument() {
                 flag = flag | IS_SYNTH;
             }
 
-            if info.silent_when_stopped {
+            // Added in VST 2.3
+            if info.silent_when_stopped && version >= 2300 {
                 flag = flag | NO_SOUND_IN_STOP;
             }
 
@@ -233,7 +316,14 @@ pub fn main<T: Plugin + Default>(callback: HostCallbackProc) -> *mut AEffect {
         _ioRatio: 0.0,
 
         object: mem::transmute(Box::new(Box::new(plugin) as Box<Plugin>)),
-        user: ptr::null_mut(),
+        // Storage for data returned to the host, freed on shutdown.
+        user: mem::transmute(Box::new(interfaces::EffectData {
+            host: HostCallback::wrap(callback, effect),
+            api_version: version,
+            flush_denormals: info.flush_denormals,
+            f64_precision: info.f64_precision,
+            ..Default::default()
+        })),
 
         uniqueId: info.unique_id,
         version: info.version,
@@ -256,7 +346,8 @@ mod tests {
     use interfaces;
     use api::AEffect;
     use api::consts::VST_MAGIC;
-    use plugin::{Info, Plugin};
+    use editor::{Editor, ParentWindow, Rect};
+    use plugin::{self, Info, Plugin};
 
     #[derive(Default)]
     struct TestPlugin;
@@ -282,8 +373,8 @@ mod tests {
 
     plugin_main!(TestPlugin);
 
-    fn pass_callback(_effect: *mut AEffect, _opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
-        1
+    fn pass_callback(_effect: *mut AEffect, opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
+        if opcode == ::host::OpCode::Version as i32 { 2400 } else { 1 }
     }
 
     fn fail_callback(_effect: *mut AEffect, _opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
@@ -351,6 +442,804 @@ mod tests {
         assert!(plugin.get_info().name == "Test Plugin");
     }
 
+    #[test]
+    fn old_host_version() {
+        use api::{flags, HostCallbackProc};
+
+        #[derive(Default)]
+        struct DoublePlugin;
+
+        impl Plugin for DoublePlugin {
+            fn get_info(&self) -> Info {
+                Info { f64_precision: true, midi_inputs: 1, ..Default::default() }
+            }
+        }
+
+        fn vst23_callback(_effect: *mut AEffect, opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
+            match ::host::OpCode::from(opcode) {
+                ::host::OpCode::Version => 2300,
+                _ => 1,
+            }
+        }
+
+        fn vst24_callback(_effect: *mut AEffect, opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
+            match ::host::OpCode::from(opcode) {
+                ::host::OpCode::Version => 2400,
+                _ => 1,
+            }
+        }
+
+        // Assert that double precision is only announced to VST 2.4 hosts.
+        let double = |callback: HostCallbackProc| {
+            let aeffect = ::main::<DoublePlugin>(callback);
+            flags::Plugin::from_bits_truncate(unsafe { (*aeffect).flags })
+                .contains(flags::CAN_DOUBLE_REPLACING)
+        };
+        assert!(!double(vst23_callback));
+        assert!(double(vst24_callback));
+
+        // Assert that opcodes added in VST 2.4 are not answered for older hosts.
+        let midi_inputs = |callback: HostCallbackProc| {
+            let aeffect = ::main::<DoublePlugin>(callback);
+            unsafe {
+                ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetNumMidiInputs.into(), 0, 0,
+                                        ptr::null_mut(), 0.0)
+            }
+        };
+        assert_eq!(midi_inputs(vst23_callback), 0);
+        assert_eq!(midi_inputs(vst24_callback), 1);
+    }
+
+    #[test]
+    fn open_and_close() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static STAGE: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct LifecyclePlugin;
+
+        impl Plugin for LifecyclePlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn open(&mut self) {
+                assert_eq!(STAGE.swap(1, Ordering::SeqCst), 0);
+            }
+
+            fn close(&mut self) {
+                assert_eq!(STAGE.swap(2, Ordering::SeqCst), 1);
+            }
+        }
+
+        impl Drop for LifecyclePlugin {
+            fn drop(&mut self) {
+                assert_eq!(STAGE.swap(3, Ordering::SeqCst), 2);
+            }
+        }
+
+        let aeffect = ::main::<LifecyclePlugin>(pass_callback);
+        assert_eq!(STAGE.load(Ordering::SeqCst), 0);
+
+        unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::Initialize.into(), 0, 0,
+                                    ptr::null_mut(), 0.0);
+            assert_eq!(STAGE.load(Ordering::SeqCst), 1);
+
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::Shutdown.into(), 0, 0,
+                                    ptr::null_mut(), 0.0);
+        }
+        assert_eq!(STAGE.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn bus_layout() {
+        use buffer::AudioBuffer;
+        use bus::{Bus, BusLayout};
+
+        struct BusPlugin {
+            buses: BusLayout,
+        }
+
+        impl Default for BusPlugin {
+            fn default() -> BusPlugin {
+                BusPlugin {
+                    buses: BusLayout::new(vec![Bus::new("Main", 2), Bus::sidechain("Key", 2)],
+                                          vec![Bus::new("Main", 2)]),
+                }
+            }
+        }
+
+        impl Plugin for BusPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+            fn get_bus_layout(&self) -> Option<&BusLayout> { Some(&self.buses) }
+
+            fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+                let (inputs, sidechain, mut outputs) = buffer.split_sidechain();
+                assert_eq!((inputs.len(), sidechain.len()), (2, 2));
+                for (channel, output) in outputs.iter_mut().enumerate() {
+                    output[0] = sidechain[channel][0];
+                }
+            }
+        }
+
+        // Assert that the channel counts are taken from the layout rather than the info.
+        let aeffect = ::main::<BusPlugin>(pass_callback);
+        assert_eq!(unsafe { ((*aeffect).numInputs, (*aeffect).numOutputs) }, (4, 2));
+
+        // Assert that the sidechain inputs are passed apart from the main inputs.
+        let mut channels = vec![[0.0f32; 1], [0.0], [1.0], [2.0], [0.0], [0.0]];
+        let mut pointers: Vec<*mut f32> = channels.iter_mut().map(|c| c.as_mut_ptr()).collect();
+        let (inputs, outputs) = pointers.split_at_mut(4);
+        unsafe {
+            ((*aeffect).processReplacing)(aeffect, inputs.as_mut_ptr(), outputs.as_mut_ptr(), 1)
+        };
+        assert_eq!((channels[4][0], channels[5][0]), (1.0, 2.0));
+    }
+
+    #[test]
+    fn legacy_process() {
+        use buffer::AudioBuffer;
+
+        #[derive(Default)]
+        struct LegacyPlugin;
+
+        impl Plugin for LegacyPlugin {
+            fn get_info(&self) -> Info {
+                Info { inputs: 0, outputs: 1, legacy_host_support: true, ..Default::default() }
+            }
+
+            fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+                let (_, mut outputs) = buffer.split_mut();
+                for sample in outputs[0].iter_mut() {
+                    *sample = 1.0;
+                }
+            }
+        }
+
+        let aeffect = ::main::<LegacyPlugin>(pass_callback);
+        assert_eq!(unsafe { (*aeffect)._process } as usize,
+                   interfaces::process_accumulating as usize);
+
+        // Assert that the output is added to the previous buffer contents.
+        let mut output = [0.5f32; 4];
+        let mut inputs: [*mut f32; 0] = [];
+        let mut outputs = [output.as_mut_ptr()];
+        unsafe { ((*aeffect)._process)(aeffect, inputs.as_mut_ptr(), outputs.as_mut_ptr(), 4) };
+        assert_eq!(output, [1.5; 4]);
+    }
+
+    #[test]
+    fn editor_rect() {
+        use api::flags;
+
+        #[derive(Default)]
+        struct TestEditor;
+
+        impl Editor for TestEditor {
+            fn size(&self) -> (i32, i32) { (300, 200) }
+            fn position(&self) -> (i32, i32) { (10, 20) }
+            fn open(&mut self, _: ParentWindow) {}
+            fn is_open(&mut self) -> bool { false }
+        }
+
+        #[derive(Default)]
+        struct EditorPlugin {
+            editor: TestEditor,
+        }
+
+        impl Plugin for EditorPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+            fn get_editor(&mut self) -> Option<&mut Editor> { Some(&mut self.editor) }
+        }
+
+        let aeffect = ::main::<EditorPlugin>(pass_callback);
+        assert!(flags::Plugin::from_bits_truncate(unsafe { (*aeffect).flags })
+                              .contains(flags::HAS_EDITOR));
+
+        let mut rect: *mut Rect = ptr::null_mut();
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::EditorGetRect.into(), 0, 0,
+                                    &mut rect as *mut *mut Rect as *mut c_void, 0.0)
+        };
+
+        // Assert that the rect is returned with position and size combined.
+        assert_eq!(result, 1);
+        let rect = unsafe { *rect };
+        assert_eq!((rect.left, rect.top, rect.right, rect.bottom), (10, 20, 310, 220));
+    }
+
+    #[test]
+    fn editor_resize() {
+        use plugin::HostCallback;
+
+        #[derive(Default)]
+        struct TestEditor;
+
+        impl Editor for TestEditor {
+            fn size(&self) -> (i32, i32) { (300, 200) }
+            fn position(&self) -> (i32, i32) { (0, 0) }
+            fn open(&mut self, _: ParentWindow) {}
+            fn is_open(&mut self) -> bool { true }
+        }
+
+        #[derive(Default)]
+        struct ResizePlugin {
+            host: HostCallback,
+            editor: TestEditor,
+        }
+
+        impl Plugin for ResizePlugin {
+            fn new(host: HostCallback) -> ResizePlugin {
+                ResizePlugin { host: host, editor: TestEditor }
+            }
+
+            fn get_info(&self) -> Info { Default::default() }
+            fn get_editor(&mut self) -> Option<&mut Editor> { Some(&mut self.editor) }
+
+            fn init(&mut self) {
+                assert!(self.host.resize_editor(400, 250));
+            }
+        }
+
+        fn editor_size(effect: *mut AEffect) -> (i16, i16) {
+            let mut rect: *mut Rect = ptr::null_mut();
+            unsafe {
+                ((*effect).dispatcher)(effect, plugin::OpCode::EditorGetRect.into(), 0, 0,
+                                       &mut rect as *mut *mut Rect as *mut c_void, 0.0);
+                ((*rect).right - (*rect).left, (*rect).bottom - (*rect).top)
+            }
+        }
+
+        // Assert that the host sees the requested size while resizing.
+        fn resize_callback(effect: *mut AEffect, opcode: i32, index: i32, value: isize,
+                           _ptr: *mut c_void, _opt: f32) -> isize {
+            match ::host::OpCode::from(opcode) {
+                ::host::OpCode::SizeWindow => {
+                    assert_eq!(editor_size(effect), (index as i16, value as i16));
+                    1
+                }
+                _ => 1,
+            }
+        }
+
+        let aeffect = ::main::<ResizePlugin>(resize_callback);
+        assert_eq!(editor_size(aeffect), (300, 200));
+        unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::Initialize.into(), 0, 0,
+                                    ptr::null_mut(), 0.0);
+        }
+        assert_eq!(editor_size(aeffect), (400, 250));
+    }
+
+    #[test]
+    fn editor_keys() {
+        use api::flags;
+        use editor::{Key, KeyCode};
+
+        #[derive(Default)]
+        struct KeyEditor;
+
+        impl Editor for KeyEditor {
+            fn size(&self) -> (i32, i32) { (0, 0) }
+            fn position(&self) -> (i32, i32) { (0, 0) }
+            fn open(&mut self, _: ParentWindow) {}
+            fn is_open(&mut self) -> bool { true }
+
+            fn key_down(&mut self, keycode: KeyCode) -> bool {
+                assert_eq!(keycode.character, 'a');
+                assert_eq!(keycode.key, None);
+                assert_eq!(keycode.modifier, flags::SHIFT | flags::CONTROL);
+                true
+            }
+
+            fn key_up(&mut self, keycode: KeyCode) -> bool {
+                assert_eq!(keycode.key, Some(Key::Escape));
+                false
+            }
+        }
+
+        #[derive(Default)]
+        struct KeyPlugin {
+            editor: KeyEditor,
+        }
+
+        impl Plugin for KeyPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+            fn get_editor(&mut self) -> Option<&mut Editor> { Some(&mut self.editor) }
+        }
+
+        let aeffect = ::main::<KeyPlugin>(pass_callback);
+        let dispatch = |opcode: plugin::OpCode, index, value, opt| unsafe {
+            ((*aeffect).dispatcher)(aeffect, opcode.into(), index, value, ptr::null_mut(), opt)
+        };
+
+        // Modifiers are passed as a float value, not as the bits of one.
+        assert_eq!(dispatch(plugin::OpCode::EditorKeyDown, 'a' as i32, 0, 9.0), 1);
+        assert_eq!(dispatch(plugin::OpCode::EditorKeyUp, 0, Key::Escape as isize, 0.0), 0);
+    }
+
+    #[test]
+    fn channel_info() {
+        use api::ChannelProperties;
+        use channels::{ChannelInfo, SpeakerArrangementType, StereoChannel, StereoConfig};
+
+        #[derive(Default)]
+        struct SidechainPlugin;
+
+        impl Plugin for SidechainPlugin {
+            fn get_info(&self) -> Info { Info { inputs: 4, ..Default::default() } }
+
+            fn get_input_info(&self, input: i32) -> ChannelInfo {
+                let side = if input % 2 == 0 { StereoChannel::Left } else { StereoChannel::Right };
+                let name = if input < 2 { "Main" } else { "Sidechain" };
+
+                ChannelInfo::new(name.to_string(), Some("SC".to_string()), true,
+                                 Some(SpeakerArrangementType::Stereo(StereoConfig::L_R, side)))
+            }
+        }
+
+        let aeffect = ::main::<SidechainPlugin>(pass_callback);
+        let mut raw: ChannelProperties = unsafe { mem::zeroed() };
+
+        // Assert that the channel info survives the round trip through the raw properties.
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetInputInfo.into(), 2, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(result, 1);
+
+        let info = ChannelInfo::from(raw);
+        assert_eq!(info.name(), "Sidechain");
+        assert_eq!(info.short_name(), "SC");
+        assert!(info.is_active());
+        assert_eq!(*info.arrangement_type(),
+                   SpeakerArrangementType::Stereo(StereoConfig::L_R, StereoChannel::Left));
+
+        // Assert that channels past the number of inputs are not supported.
+        let mut raw: ChannelProperties = unsafe { mem::zeroed() };
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetInputInfo.into(), 4, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn offline_tasks() {
+        use api::{self, flags};
+
+        #[derive(Default)]
+        struct OfflinePlugin;
+
+        impl Plugin for OfflinePlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn offline_prepare(&mut self, tasks: &mut [api::OfflineTask]) -> bool {
+                for task in tasks {
+                    task.set_process_name("Time Stretch");
+                    let flags = task.flags() | flags::STRETCH;
+                    task.set_flags(flags);
+                }
+                true
+            }
+        }
+
+        let aeffect = ::main::<OfflinePlugin>(pass_callback);
+        let mut tasks: Vec<api::OfflineTask> = (0..2).map(|_| unsafe { mem::zeroed() }).collect();
+
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::OfflinePrepare.into(), 0, 2,
+                                    tasks.as_mut_ptr() as *mut c_void, 0.0)
+        };
+
+        // Assert that every task was prepared by the plugin.
+        assert_eq!(result, 1);
+        for task in &tasks {
+            assert_eq!(task.process_name(), "Time Stretch");
+            assert!(task.flags().contains(flags::STRETCH));
+        }
+    }
+
+    #[test]
+    fn midi_programs() {
+        use api;
+        use plugin::MidiProgram;
+
+        #[derive(Default)]
+        struct ProgramPlugin;
+
+        impl Plugin for ProgramPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn get_midi_programs(&self, _channel: i32) -> Vec<MidiProgram> {
+                vec![MidiProgram { name: "Piano".to_string(), program: Some(0), ..Default::default() },
+                     MidiProgram { name: "Organ".to_string(), program: Some(16), omni: true,
+                                   ..Default::default() }]
+            }
+
+            fn get_current_midi_program(&self, _channel: i32) -> Option<i32> { Some(1) }
+        }
+
+        let aeffect = ::main::<ProgramPlugin>(pass_callback);
+        let mut raw: api::MidiProgramName = unsafe { mem::zeroed() };
+        raw.this_program_index = 1;
+
+        // Assert that the requested program is filled in and the program count is returned.
+        let count = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetMidiProgramName.into(), 0, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(count, 2);
+        assert_eq!(MidiProgram::from(&raw),
+                   MidiProgram { name: "Organ".to_string(), program: Some(16), omni: true,
+                                 ..Default::default() });
+
+        let current = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetCurrentMidiProgram.into(), 0, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(current, 1);
+        assert_eq!(raw.this_program_index, 1);
+    }
+
+    #[test]
+    fn speaker_arrangement() {
+        use api::{self, SpeakerArrangementType};
+        use channels::{RawSpeakerArrangement, SpeakerArrangement};
+
+        #[derive(Default)]
+        struct SurroundPlugin {
+            outputs: Option<SpeakerArrangement>,
+        }
+
+        impl Plugin for SurroundPlugin {
+            fn get_info(&self) -> Info {
+                let outputs = self.outputs.as_ref().map_or(2, |o| o.num_channels() as i32);
+                Info { inputs: 1, outputs: outputs, ..Default::default() }
+            }
+
+            fn set_speaker_arrangement(&mut self,
+                                       _input: SpeakerArrangement,
+                                       output: SpeakerArrangement)
+                                       -> bool {
+                self.outputs = Some(output);
+                true
+            }
+
+            fn get_speaker_arrangement(&self) -> Option<(SpeakerArrangement, SpeakerArrangement)> {
+                self.outputs.clone().map(|o| (SpeakerArrangement::new(SpeakerArrangementType::Mono), o))
+            }
+        }
+
+        let aeffect = ::main::<SurroundPlugin>(pass_callback);
+        let mut input = RawSpeakerArrangement::from(&SpeakerArrangement::new(SpeakerArrangementType::Mono));
+        let mut output = RawSpeakerArrangement::from(&SpeakerArrangement::new(SpeakerArrangementType::Surround102));
+
+        // Assert that accepting an arrangement updates the channel counts.
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::SetSpeakerArrangement.into(), 0,
+                                    input.as_mut_ptr() as isize,
+                                    output.as_mut_ptr() as *mut c_void, 0.0)
+        };
+        assert_eq!(result, 1);
+        assert_eq!(unsafe { (*aeffect).numOutputs }, 12);
+
+        // Assert that the arrangement is reported back, including the speakers past the first 8.
+        let mut input: *mut api::SpeakerArrangement = ptr::null_mut();
+        let mut output: *mut api::SpeakerArrangement = ptr::null_mut();
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetSpeakerArrangement.into(), 0,
+                                    &mut input as *mut _ as isize,
+                                    &mut output as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(result, 1);
+
+        let input = unsafe { SpeakerArrangement::from_raw(input) };
+        let output = unsafe { SpeakerArrangement::from_raw(output) };
+        assert_eq!(input, SpeakerArrangement::new(SpeakerArrangementType::Mono));
+        assert_eq!(output, SpeakerArrangement::new(SpeakerArrangementType::Surround102));
+        assert_eq!(output.speakers[11].name, "Lfe2");
+
+        // Assert that missing pointers are refused rather than written through.
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetSpeakerArrangement.into(), 0, 0,
+                                    ptr::null_mut(), 0.0)
+        };
+        assert_eq!(result, 0);
+    }
+
+    #[test]
+    fn speaker_arrangement_out_of_range() {
+        use api::SpeakerArrangementType;
+        use channels::{RawSpeakerArrangement, SpeakerArrangement};
+
+        let mut raw = RawSpeakerArrangement::from(&SpeakerArrangement::new(SpeakerArrangementType::Stereo));
+        let raw = unsafe { &mut *raw.as_mut_ptr() };
+
+        // Assert that channel counts outside of the inline array are clamped.
+        raw.num_channels = 100;
+        assert_eq!(raw.speakers().len(), 8);
+        raw.num_channels = -1;
+        assert_eq!(raw.speakers().len(), 0);
+        assert_eq!(unsafe { SpeakerArrangement::from_raw(raw) }.num_channels(), 0);
+
+        // Assert that unknown arrangement types are read as custom arrangements.
+        raw.arrangement_type = 1000;
+        assert_eq!(raw.arrangement_type(), SpeakerArrangementType::Custom);
+        assert_eq!(unsafe { SpeakerArrangement::from_raw(raw) }.arrangement_type,
+                   SpeakerArrangementType::Custom);
+    }
+
+    #[test]
+    fn string_to_parameter() {
+        use std::ffi::CString;
+
+        #[derive(Default)]
+        struct GainPlugin {
+            gain: f32,
+        }
+
+        impl Plugin for GainPlugin {
+            fn get_info(&self) -> Info { Info { parameters: 1, ..Default::default() } }
+
+            fn get_parameter(&self, _: i32) -> f32 { self.gain }
+
+            fn string_to_parameter(&mut self, _: i32, text: String) -> bool {
+                match text.trim_end_matches("dB").trim().parse::<f32>() {
+                    Ok(db) => { self.gain = (db + 24.0) / 24.0; true }
+                    Err(_) => false,
+                }
+            }
+        }
+
+        let aeffect = ::main::<GainPlugin>(pass_callback);
+        let string_to_parameter = |text: &str| unsafe {
+            let text = CString::new(text).unwrap();
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::StringToParameter.into(), 0, 0,
+                                    text.as_ptr() as *mut c_void, 0.0)
+        };
+
+        // Assert that the parsed value is applied and invalid text is rejected.
+        assert_eq!(string_to_parameter("-12 dB"), 1);
+        assert_eq!(unsafe { ((*aeffect).getParameter)(aeffect, 0) }, 0.5);
+        assert_eq!(string_to_parameter("loud"), 0);
+        assert_eq!(unsafe { ((*aeffect).getParameter)(aeffect, 0) }, 0.5);
+    }
+
+    #[test]
+    fn null_pointers() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use plugin::ParameterProperties;
+
+        static PROCESSED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Default)]
+        struct NullPlugin;
+
+        impl Plugin for NullPlugin {
+            fn get_info(&self) -> Info {
+                Info { inputs: 2, outputs: 2, parameters: 1, preset_chunks: true, ..Default::default() }
+            }
+
+            fn get_parameter_properties(&self, _: i32) -> Option<ParameterProperties> {
+                Some(Default::default())
+            }
+
+            fn load_bank_data(&mut self, _: Vec<u8>) {
+                panic!("bank loaded from an invalid chunk");
+            }
+
+            fn process(&mut self, buffer: ::buffer::AudioBuffer<f32>) {
+                assert_eq!(buffer.input_count() + buffer.output_count(), 0);
+                PROCESSED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let aeffect = ::main::<NullPlugin>(pass_callback);
+        let dispatch = |opcode: plugin::OpCode, value: isize| unsafe {
+            ((*aeffect).dispatcher)(aeffect, opcode.into(), 0, value, ptr::null_mut(), 0.0)
+        };
+
+        // Assert that requests without a buffer are refused instead of dereferenced.
+        assert_eq!(dispatch(plugin::OpCode::SetData, 16), 0);
+        assert_eq!(dispatch(plugin::OpCode::GetParameterProperties, 0), 0);
+        assert_eq!(dispatch(plugin::OpCode::GetInputInfo, 0), 0);
+        assert_eq!(dispatch(plugin::OpCode::GetOutputInfo, 0), 0);
+
+        // Assert that a negative chunk size is refused.
+        let mut chunk = [0u8; 4];
+        assert_eq!(unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::SetData.into(), 0, -1,
+                                    chunk.as_mut_ptr() as *mut c_void, 0.0)
+        }, 0);
+
+        // Assert that missing channel tables are processed as buffers without channels.
+        unsafe {
+            ((*aeffect).processReplacingF64)(aeffect, ptr::null_mut(), ptr::null_mut(), 64);
+        }
+        assert_eq!(PROCESSED.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn presets() {
+        use api::consts::MAX_PRESET_NAME_LEN;
+
+        #[derive(Default)]
+        struct PresetPlugin {
+            current: i32,
+            names: Vec<String>,
+            loading: bool,
+        }
+
+        impl Plugin for PresetPlugin {
+            fn get_info(&self) -> Info { Info { presets: 2, ..Default::default() } }
+
+            fn init(&mut self) {
+                self.names = vec!["Init".to_string(), "Pad".to_string()];
+            }
+
+            fn change_preset(&mut self, preset: i32) {
+                assert!(self.loading);
+                self.current = preset;
+            }
+
+            fn get_preset_num(&self) -> i32 { self.current }
+
+            fn set_preset_name(&mut self, name: String) {
+                self.names[self.current as usize] = name;
+            }
+
+            fn get_preset_name(&self, preset: i32) -> String {
+                self.names[preset as usize].clone()
+            }
+
+            fn begin_set_preset(&mut self) { self.loading = true; }
+
+            fn end_set_preset(&mut self) { self.loading = false; }
+        }
+
+        let aeffect = ::main::<PresetPlugin>(pass_callback);
+        let dispatch = |opcode: plugin::OpCode, index: i32, value: isize, ptr: *mut c_void| unsafe {
+            ((*aeffect).dispatcher)(aeffect, opcode.into(), index, value, ptr, 0.0)
+        };
+        let mut name = [0u8; MAX_PRESET_NAME_LEN];
+
+        dispatch(plugin::OpCode::Initialize, 0, 0, ptr::null_mut());
+        dispatch(plugin::OpCode::BeginSetPreset, 0, 0, ptr::null_mut());
+        dispatch(plugin::OpCode::ChangePreset, 0, 1, ptr::null_mut());
+        dispatch(plugin::OpCode::EndSetPreset, 0, 0, ptr::null_mut());
+        assert_eq!(dispatch(plugin::OpCode::GetCurrentPresetNum, 0, 0, ptr::null_mut()), 1);
+
+        dispatch(plugin::OpCode::SetCurrentPresetName, 0, 0, b"Strings\0".as_ptr() as *mut c_void);
+        dispatch(plugin::OpCode::GetCurrentPresetName, 0, 0, name.as_mut_ptr() as *mut c_void);
+        assert_eq!(::read_fixed(&name), "Strings");
+
+        // Assert that indexed names are only returned for existing presets.
+        assert_eq!(dispatch(plugin::OpCode::GetPresetName, 0, 0, name.as_mut_ptr() as *mut c_void), 1);
+        assert_eq!(::read_fixed(&name), "Init");
+        assert_eq!(dispatch(plugin::OpCode::GetPresetName, 2, 0, name.as_mut_ptr() as *mut c_void), 0);
+    }
+
+    #[test]
+    fn can_be_automated() {
+        #[derive(Default)]
+        struct MeterPlugin;
+
+        impl Plugin for MeterPlugin {
+            fn get_info(&self) -> Info { Info { parameters: 2, ..Default::default() } }
+
+            // The second parameter is an output meter.
+            fn can_be_automated(&self, index: i32) -> bool { index != 1 }
+        }
+
+        let aeffect = ::main::<MeterPlugin>(pass_callback);
+        let can_be_automated = |index| unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::CanBeAutomated.into(), index, 0,
+                                    ptr::null_mut(), 0.0)
+        };
+
+        assert_eq!(can_be_automated(0), 1);
+        assert_eq!(can_be_automated(1), 0);
+    }
+
+    #[test]
+    fn vendor_specific() {
+        #[derive(Default)]
+        struct ExtensionPlugin;
+
+        impl Plugin for ExtensionPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn vendor_specific(&mut self, index: i32, value: isize, _: *mut c_void, opt: f32) -> isize {
+                match (index, value) {
+                    (0x7374, 0x1234) => (opt * 2.0) as isize,
+                    _ => 0,
+                }
+            }
+        }
+
+        // Assert that the plugin's answer is passed back to the host.
+        let aeffect = ::main::<ExtensionPlugin>(pass_callback);
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::VendorSpecific.into(), 0x7374, 0x1234,
+                                    ptr::null_mut(), 21.0)
+        };
+        assert_eq!(result, 42);
+    }
+
+    #[test]
+    fn tail_size() {
+        #[derive(Default)]
+        struct DelayPlugin;
+
+        impl Plugin for DelayPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn get_tail_size(&self) -> isize { 44100 }
+        }
+
+        let tail_size = |aeffect: *mut AEffect| unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::GetTailSize.into(), 0, 0,
+                                    ptr::null_mut(), 0.0)
+        };
+
+        // Assert that plugins which do not override the tail size leave it to the host.
+        assert_eq!(tail_size(::main::<TestPlugin>(pass_callback)), 0);
+        assert_eq!(tail_size(::main::<DelayPlugin>(pass_callback)), 44100);
+    }
+
+    #[test]
+    fn variable_io() {
+        use api;
+        use buffer::VariableIo;
+
+        #[derive(Default)]
+        struct StretchPlugin;
+
+        impl Plugin for StretchPlugin {
+            fn get_info(&self) -> Info {
+                Info { inputs: 1, outputs: 1, ..Default::default() }
+            }
+
+            // Double the length of the input by repeating each sample.
+            fn process_var_io(&mut self, mut io: VariableIo) -> bool {
+                let consumed = io.inputs[0].len().min(io.outputs[0].len() / 2);
+                for i in 0..consumed {
+                    let sample = io.inputs[0][i];
+                    io.outputs[0][2 * i] = sample;
+                    io.outputs[0][2 * i + 1] = sample;
+                }
+                io.set_processed(consumed, consumed * 2);
+                true
+            }
+        }
+
+        let aeffect = ::main::<StretchPlugin>(pass_callback);
+        let mut input = [1.0f32, 2.0, 3.0];
+        let mut output = [0.0f32; 4];
+        let mut inputs = [input.as_mut_ptr()];
+        let mut outputs = [output.as_mut_ptr()];
+        let (mut input_processed, mut output_processed) = (0, 0);
+        let mut raw = api::VariableIo {
+            inputs: inputs.as_mut_ptr(),
+            outputs: outputs.as_mut_ptr(),
+            num_samples_input: 3,
+            num_samples_output: 4,
+            num_samples_input_processed: &mut input_processed,
+            num_samples_output_processed: &mut output_processed,
+        };
+
+        // Assert that differing numbers of samples are consumed and produced.
+        let result = unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::ProcessVarIo.into(), 0, 0,
+                                    &mut raw as *mut _ as *mut c_void, 0.0)
+        };
+        assert_eq!(result, 1);
+        assert_eq!((input_processed, output_processed), (2, 4));
+        assert_eq!(output, [1.0, 1.0, 2.0, 2.0]);
+    }
+
     #[test]
     fn aeffect_params() {
         // Assert that 2 function pointers are equal.
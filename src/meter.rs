@@ -0,0 +1,186 @@
+//! Peak and RMS metering.
+//!
+//! `levels` measures a single block of samples. A [`Meter`](struct.Meter.html) keeps smoothed
+//! levels per channel across blocks, so meters fall back smoothly instead of jumping with every
+//! block. Neither allocates once the meter has the right number of channels, so both can be used
+//! on the audio thread.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::meter::Meter;
+//! # use vst2::plugin::{Info, Plugin};
+//! struct Gain {
+//!     meter: Meter,
+//! }
+//!
+//! impl Plugin for Gain {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         buffer.copy_inputs_to_outputs();
+//!         self.meter.process_outputs(&mut buffer);
+//!
+//!         let peak = self.meter.levels()[0].peak; // Send this to the editor
+//!     }
+//! }
+//! ```
+
+use num::Float;
+
+use buffer::AudioBuffer;
+
+/// Peak and RMS level of a channel, as linear amplitudes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Levels {
+    /// Largest absolute sample value.
+    pub peak: f32,
+
+    /// Root mean square of the samples.
+    pub rms: f32,
+}
+
+/// Measure the peak and RMS level of a block of samples.
+pub fn levels<T: Float>(samples: &[T]) -> Levels {
+    let (peak, sum) = samples.iter().fold((0.0f32, 0.0f32), |(peak, sum), sample| {
+        let sample = sample.to_f32().unwrap_or(0.0);
+        (peak.max(sample.abs()), sum + sample * sample)
+    });
+
+    Levels {
+        peak: peak,
+        rms: if samples.is_empty() { 0.0 } else { (sum / samples.len() as f32).sqrt() },
+    }
+}
+
+/// Smoothed levels of several channels.
+///
+/// Peaks are held and then decay exponentially, RMS levels are averaged exponentially, both with
+/// the time constants given to `new`.
+#[derive(Clone, Debug)]
+pub struct Meter {
+    channels: Vec<Levels>,
+
+    /// Mean squares backing the RMS levels.
+    mean_squares: Vec<f32>,
+
+    sample_rate: f32,
+    peak_release: f32,
+    rms_window: f32,
+}
+
+impl Meter {
+    /// Create a meter for `channels` channels.
+    ///
+    /// Peaks fall to about a third within `peak_release` seconds, and RMS levels average over
+    /// about `rms_window` seconds. Times of zero disable the smoothing.
+    pub fn new(channels: usize, sample_rate: f32, peak_release: f32, rms_window: f32) -> Meter {
+        Meter {
+            channels: vec![Levels::default(); channels],
+            mean_squares: vec![0.0; channels],
+            sample_rate: sample_rate,
+            peak_release: peak_release,
+            rms_window: rms_window,
+        }
+    }
+
+    /// Change the sample rate used to convert the time constants.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+    }
+
+    /// The current levels, one per channel.
+    pub fn levels(&self) -> &[Levels] {
+        &self.channels
+    }
+
+    /// Reset all levels to silence.
+    pub fn reset(&mut self) {
+        for levels in &mut self.channels {
+            *levels = Levels::default();
+        }
+        for mean_square in &mut self.mean_squares {
+            *mean_square = 0.0;
+        }
+    }
+
+    /// Update the levels with the next block of each channel.
+    ///
+    /// The number of channels is adjusted to the number of blocks given, which only allocates
+    /// when it grows.
+    pub fn process<T: Float, C: AsRef<[T]>>(&mut self, channels: &[C]) {
+        self.channels.resize(channels.len(), Levels::default());
+        self.mean_squares.resize(channels.len(), 0.0);
+
+        for (index, channel) in channels.iter().enumerate() {
+            self.update(index, channel.as_ref());
+        }
+    }
+
+    /// Update the levels with the outputs of `buffer`.
+    pub fn process_outputs<T: Float>(&mut self, buffer: &mut AudioBuffer<T>) {
+        let (_, outputs) = buffer.split_mut();
+
+        self.channels.resize(outputs.len(), Levels::default());
+        self.mean_squares.resize(outputs.len(), 0.0);
+
+        for (index, output) in outputs.iter().enumerate() {
+            self.update(index, output);
+        }
+    }
+
+    fn update<T: Float>(&mut self, index: usize, samples: &[T]) {
+        let block = levels(samples);
+        let len = samples.len() as f32;
+
+        let peak_decay = decay(self.peak_release, self.sample_rate, len);
+        let rms_decay = decay(self.rms_window, self.sample_rate, len);
+
+        let mean_square = &mut self.mean_squares[index];
+        *mean_square = *mean_square * rms_decay + block.rms * block.rms * (1.0 - rms_decay);
+
+        let levels = &mut self.channels[index];
+        levels.peak = block.peak.max(levels.peak * peak_decay);
+        levels.rms = mean_square.sqrt();
+    }
+}
+
+/// Factor an exponentially smoothed value keeps over `samples` samples with time constant `time`.
+fn decay(time: f32, sample_rate: f32, samples: f32) -> f32 {
+    if time <= 0.0 || sample_rate <= 0.0 {
+        0.0
+    } else {
+        (-samples / (time * sample_rate)).exp()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use meter::{self, Levels, Meter};
+
+    /// Test levels of a single block.
+    #[test]
+    fn levels() {
+        assert_eq!(meter::levels(&[0.5f32, -1.0, 0.5, -1.0][..]).peak, 1.0);
+        assert!((meter::levels(&[0.5f64, -0.5, 0.5, -0.5][..]).rms - 0.5).abs() < 1e-6);
+        assert_eq!(meter::levels::<f32>(&[]), Levels::default());
+    }
+
+    /// Test that peaks are held and decay over following blocks.
+    #[test]
+    fn smoothing() {
+        let mut meter = Meter::new(1, 1000.0, 0.01, 0.0);
+
+        meter.process(&[vec![1.0f32; 10]]);
+        assert_eq!(meter.levels()[0].peak, 1.0);
+
+        meter.process(&[vec![0.0f32; 10]]);
+        let peak = meter.levels()[0].peak;
+        assert!(peak > 0.3 && peak < 0.4);
+        assert_eq!(meter.levels()[0].rms, 0.0);
+
+        meter.reset();
+        assert_eq!(meter.levels()[0], Levels::default());
+    }
+}
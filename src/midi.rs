@@ -0,0 +1,263 @@
+//! Typed MIDI messages.
+//!
+//! [`MidiMessage`](enum.MidiMessage.html) decodes the raw bytes of a `MidiEvent` so plugins do
+//! not have to decode status bytes by hand.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::event::MidiEvent;
+//! # use vst2::midi::MidiMessage;
+//! # let event = MidiEvent { data: [0x91, 60, 100], ..Default::default() };
+//! match event.message() {
+//!     MidiMessage::NoteOn { channel, note, velocity } => {
+//!         println!("Note {} on channel {} with velocity {}", note, channel, velocity);
+//!     }
+//!     MidiMessage::NoteOff { note, .. } => println!("Note {} released", note),
+//!     _ => {}
+//! }
+//! ```
+
+/// A decoded MIDI message. Channels are numbered from 0 to 15.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    /// A note was released.
+    NoteOff {
+        /// Channel of the note.
+        channel: u8,
+        /// Note number, 60 being middle C.
+        note: u8,
+        /// Release velocity.
+        velocity: u8,
+    },
+
+    /// A note was pressed.
+    NoteOn {
+        /// Channel of the note.
+        channel: u8,
+        /// Note number, 60 being middle C.
+        note: u8,
+        /// Velocity between 1 and 127.
+        velocity: u8,
+    },
+
+    /// Pressure of a single held note changed (polyphonic aftertouch).
+    PolyPressure {
+        /// Channel of the note.
+        channel: u8,
+        /// Note number.
+        note: u8,
+        /// New pressure.
+        pressure: u8,
+    },
+
+    /// A controller changed.
+    ControlChange {
+        /// Channel of the controller.
+        channel: u8,
+        /// Controller number, e.g. 1 for the modulation wheel.
+        controller: u8,
+        /// New value.
+        value: u8,
+    },
+
+    /// A program was selected.
+    ProgramChange {
+        /// Channel of the program change.
+        channel: u8,
+        /// Program number.
+        program: u8,
+    },
+
+    /// Pressure of all held notes of a channel changed (channel aftertouch).
+    Aftertouch {
+        /// Channel of the notes.
+        channel: u8,
+        /// New pressure.
+        pressure: u8,
+    },
+
+    /// The pitch wheel moved.
+    PitchBend {
+        /// Channel of the pitch wheel.
+        channel: u8,
+        /// New position between -8192 and 8191, 0 being the center.
+        value: i16,
+    },
+
+    /// MIDI time code quarter frame.
+    QuarterFrame(u8),
+
+    /// Song position in MIDI beats (sixteenth notes).
+    SongPosition(u16),
+
+    /// A song was selected.
+    SongSelect(u8),
+
+    /// Analog oscillators should be tuned.
+    TuneRequest,
+
+    /// Timing clock, sent 24 times per quarter note.
+    TimingClock,
+
+    /// Playback starts from the beginning.
+    Start,
+
+    /// Playback continues from the current position.
+    Continue,
+
+    /// Playback stops.
+    Stop,
+
+    /// Keep alive message.
+    ActiveSensing,
+
+    /// All receivers should reset.
+    SystemReset,
+
+    /// Any other message, e.g. undefined status bytes or parts of system exclusive messages.
+    Unknown([u8; 3]),
+}
+
+impl MidiMessage {
+    /// Decode a message from its raw bytes, or `None` if the bytes do not start with a status
+    /// byte.
+    ///
+    /// Missing data bytes are read as zero. A note on with a velocity of 0 is decoded as a note
+    /// off, as is common practice.
+    pub fn from_bytes(bytes: &[u8]) -> Option<MidiMessage> {
+        use self::MidiMessage::*;
+
+        let status = match bytes.first() {
+            Some(&status) if status & 0x80 != 0 => status,
+            _ => return None,
+        };
+        let data = |index: usize| bytes.get(index).map_or(0, |b| b & 0x7F);
+        let channel = status & 0x0F;
+
+        Some(match status & 0xF0 {
+            0x80 => NoteOff { channel: channel, note: data(1), velocity: data(2) },
+            0x90 if data(2) == 0 => NoteOff { channel: channel, note: data(1), velocity: 0 },
+            0x90 => NoteOn { channel: channel, note: data(1), velocity: data(2) },
+            0xA0 => PolyPressure { channel: channel, note: data(1), pressure: data(2) },
+            0xB0 => ControlChange { channel: channel, controller: data(1), value: data(2) },
+            0xC0 => ProgramChange { channel: channel, program: data(1) },
+            0xD0 => Aftertouch { channel: channel, pressure: data(1) },
+            0xE0 => {
+                let value = ((data(2) as i16) << 7 | data(1) as i16) - 8192;
+                PitchBend { channel: channel, value: value }
+            }
+            _ => {
+                match status {
+                    0xF1 => QuarterFrame(data(1)),
+                    0xF2 => SongPosition((data(2) as u16) << 7 | data(1) as u16),
+                    0xF3 => SongSelect(data(1)),
+                    0xF6 => TuneRequest,
+                    0xF8 => TimingClock,
+                    0xFA => Start,
+                    0xFB => Continue,
+                    0xFC => Stop,
+                    0xFE => ActiveSensing,
+                    0xFF => SystemReset,
+                    _ => Unknown([status, data(1), data(2)]),
+                }
+            }
+        })
+    }
+
+    /// Encode this message into the three bytes of a `MidiEvent`, padded with zeros.
+    ///
+    /// Channels and data values are masked to their valid ranges and pitch bend values are
+    /// clamped.
+    pub fn to_bytes(&self) -> [u8; 3] {
+        use self::MidiMessage::*;
+
+        let status = |kind: u8, channel: u8| kind | (channel & 0x0F);
+
+        match *self {
+            NoteOff { channel, note, velocity } => {
+                [status(0x80, channel), note & 0x7F, velocity & 0x7F]
+            }
+            NoteOn { channel, note, velocity } => {
+                [status(0x90, channel), note & 0x7F, velocity & 0x7F]
+            }
+            PolyPressure { channel, note, pressure } => {
+                [status(0xA0, channel), note & 0x7F, pressure & 0x7F]
+            }
+            ControlChange { channel, controller, value } => {
+                [status(0xB0, channel), controller & 0x7F, value & 0x7F]
+            }
+            ProgramChange { channel, program } => [status(0xC0, channel), program & 0x7F, 0],
+            Aftertouch { channel, pressure } => [status(0xD0, channel), pressure & 0x7F, 0],
+            PitchBend { channel, value } => {
+                let value = (value.max(-8192).min(8191) + 8192) as u16;
+                [status(0xE0, channel), (value & 0x7F) as u8, (value >> 7) as u8]
+            }
+            QuarterFrame(value) => [0xF1, value & 0x7F, 0],
+            SongPosition(position) => {
+                [0xF2, (position & 0x7F) as u8, ((position >> 7) & 0x7F) as u8]
+            }
+            SongSelect(song) => [0xF3, song & 0x7F, 0],
+            TuneRequest => [0xF6, 0, 0],
+            TimingClock => [0xF8, 0, 0],
+            Start => [0xFA, 0, 0],
+            Continue => [0xFB, 0, 0],
+            Stop => [0xFC, 0, 0],
+            ActiveSensing => [0xFE, 0, 0],
+            SystemReset => [0xFF, 0, 0],
+            Unknown(bytes) => bytes,
+        }
+    }
+
+    /// The channel of a channel message, or `None` for system messages.
+    pub fn channel(&self) -> Option<u8> {
+        use self::MidiMessage::*;
+
+        match *self {
+            NoteOff { channel, .. } | NoteOn { channel, .. } | PolyPressure { channel, .. } |
+            ControlChange { channel, .. } | ProgramChange { channel, .. } |
+            Aftertouch { channel, .. } | PitchBend { channel, .. } => Some(channel),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi::MidiMessage;
+
+    /// Test that messages survive encoding and decoding.
+    #[test]
+    fn round_trip() {
+        let messages = [
+            MidiMessage::NoteOff { channel: 0, note: 60, velocity: 64 },
+            MidiMessage::NoteOn { channel: 15, note: 127, velocity: 1 },
+            MidiMessage::PolyPressure { channel: 3, note: 61, pressure: 20 },
+            MidiMessage::ControlChange { channel: 1, controller: 7, value: 100 },
+            MidiMessage::ProgramChange { channel: 2, program: 5 },
+            MidiMessage::Aftertouch { channel: 4, pressure: 90 },
+            MidiMessage::PitchBend { channel: 5, value: -8192 },
+            MidiMessage::PitchBend { channel: 5, value: 8191 },
+            MidiMessage::SongPosition(1000),
+            MidiMessage::TimingClock,
+            MidiMessage::Unknown([0xF4, 0, 0]),
+        ];
+
+        for message in messages.iter() {
+            assert_eq!(MidiMessage::from_bytes(&message.to_bytes()), Some(*message));
+        }
+    }
+
+    /// Test decoding of raw bytes.
+    #[test]
+    fn from_bytes() {
+        assert_eq!(MidiMessage::from_bytes(&[0xE0, 0x00, 0x40]),
+                   Some(MidiMessage::PitchBend { channel: 0, value: 0 }));
+        assert_eq!(MidiMessage::from_bytes(&[0x93, 60, 0]),
+                   Some(MidiMessage::NoteOff { channel: 3, note: 60, velocity: 0 }));
+        assert_eq!(MidiMessage::from_bytes(&[0xC1, 12]),
+                   Some(MidiMessage::ProgramChange { channel: 1, program: 12 }));
+        assert_eq!(MidiMessage::from_bytes(&[60, 100]), None);
+        assert_eq!(MidiMessage::from_bytes(&[]), None);
+    }
+}
@@ -0,0 +1,466 @@
+//! Reading Standard MIDI Files.
+//!
+//! A [`MidiFile`](struct.MidiFile.html) holds the channel messages of all tracks of a `.mid` file,
+//! merged in time order, with their positions converted from ticks to seconds through the
+//! file's tempo map. This allows instruments to be tested end to end with real note data: the
+//! events can be scheduled for an offline `render::Render` or played into a
+//! `testing::TestInstance`, where they arrive with the correct delta frames.
+//!
+//! System exclusive and meta events other than tempo changes are skipped.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use vst2::plugin::Plugin;
+//! use vst2::midi_file::MidiFile;
+//! use vst2::render::{Audio, Render};
+//!
+//! # fn example(synth: &mut Plugin) {
+//! let file = MidiFile::read(&mut File::open("song.mid").unwrap()).unwrap();
+//!
+//! let silence = Audio { sample_rate: 44100, channels: vec![vec![0.0; file.frames(44100.0)]] };
+//! let render = Render {
+//!     tail: 44100,
+//!     events: file.timed_events(0, 44100.0),
+//!     ..Default::default()
+//! };
+//!
+//! let output = render.run(&mut [synth], &silence);
+//! output.write_wav(&mut File::create("song.wav").unwrap()).unwrap();
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+
+use render::{RenderEvent, TimedEvent};
+
+/// Tempo of files without tempo events, in microseconds per quarter note (120 bpm).
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// Error reading a MIDI file.
+#[derive(Debug)]
+pub enum MidiFileError {
+    /// Reading from the underlying reader failed.
+    Io(io::Error),
+
+    /// The data is not a Standard MIDI File.
+    InvalidFormat,
+
+    /// The data ended in the middle of a chunk or event.
+    Truncated,
+}
+
+impl From<io::Error> for MidiFileError {
+    fn from(error: io::Error) -> MidiFileError {
+        MidiFileError::Io(error)
+    }
+}
+
+impl fmt::Display for MidiFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MidiFileError::Io(ref error) => write!(f, "{}: {}", self.description(), error),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for MidiFileError {
+    fn description(&self) -> &str {
+        use self::MidiFileError::*;
+
+        match *self {
+            Io(_) => "Could not read the file",
+            InvalidFormat => "Not a Standard MIDI File",
+            Truncated => "The file is truncated",
+        }
+    }
+}
+
+/// A channel message of a MIDI file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileEvent {
+    /// Position in seconds from the start of the file.
+    pub time: f64,
+
+    /// Position in ticks from the start of the file.
+    pub tick: u64,
+
+    /// Index of the track containing the event.
+    pub track: usize,
+
+    /// The MIDI message. Messages with a single data byte have a zero as the third byte.
+    pub data: [u8; 3],
+}
+
+/// A tempo change of a MIDI file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TempoChange {
+    /// Position in seconds from the start of the file.
+    pub time: f64,
+
+    /// Position in ticks from the start of the file.
+    pub tick: u64,
+
+    /// The new tempo in beats (quarter notes) per minute.
+    pub tempo: f64,
+}
+
+/// The contents of a Standard MIDI File. See the module documentation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MidiFile {
+    /// Format of the file: 0 for a single track, 1 for simultaneous tracks and 2 for independent
+    /// sequences. All tracks are played at once regardless of the format.
+    pub format: u16,
+
+    /// Number of tracks in the file.
+    pub tracks: usize,
+
+    /// Channel messages of all tracks, in time order.
+    pub events: Vec<FileEvent>,
+
+    /// Tempo changes, in time order. Empty for files without tempo events, which play at 120 bpm,
+    /// and for files timed in SMPTE frames.
+    pub tempo_changes: Vec<TempoChange>,
+
+    /// Length of the file in seconds, up to the last event including the ends of the tracks.
+    pub duration: f64,
+}
+
+/// An event while parsing, before its time is known.
+enum RawEvent {
+    Message([u8; 3]),
+    Tempo(u32),
+    End,
+}
+
+/// How ticks relate to time.
+#[derive(Clone, Copy)]
+enum Division {
+    /// Ticks per quarter note, with the duration of a quarter note set by the tempo.
+    Metrical(u16),
+
+    /// Ticks per second, independent of the tempo.
+    Timecode(f64),
+}
+
+impl MidiFile {
+    /// Read a MIDI file.
+    pub fn read<R: Read>(reader: &mut R) -> Result<MidiFile, MidiFileError> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data));
+        MidiFile::from_bytes(&data)
+    }
+
+    /// Parse the contents of a MIDI file.
+    pub fn from_bytes(data: &[u8]) -> Result<MidiFile, MidiFileError> {
+        let mut cursor = Cursor { data: data, pos: 0 };
+
+        if try!(cursor.bytes(4)) != b"MThd" {
+            return Err(MidiFileError::InvalidFormat);
+        }
+        let mut header = Cursor { data: try!(cursor.chunk()), pos: 0 };
+        let format = try!(header.u16());
+        let tracks = try!(header.u16()) as usize;
+        let division = match try!(header.u16()) {
+            0 => return Err(MidiFileError::InvalidFormat),
+            division if division & 0x8000 == 0 => Division::Metrical(division),
+            division => {
+                // The upper byte holds the negated SMPTE frame rate
+                let frames = match ((division >> 8) as u8 as i8).checked_neg() {
+                    Some(24) => 24.0,
+                    Some(25) => 25.0,
+                    Some(29) => 29.97,
+                    Some(30) => 30.0,
+                    _ => return Err(MidiFileError::InvalidFormat),
+                };
+                let resolution = division & 0xFF;
+                if resolution == 0 {
+                    return Err(MidiFileError::InvalidFormat);
+                }
+                Division::Timecode(frames * resolution as f64)
+            }
+        };
+
+        // Events of all tracks with their tick, track and index within the track
+        let mut raw = Vec::new();
+        let mut track = 0;
+        while track < tracks && cursor.pos < data.len() {
+            let id = try!(cursor.bytes(4));
+            let chunk = try!(cursor.chunk());
+            if id == b"MTrk" {
+                try!(parse_track(chunk, track, &mut raw));
+                track += 1;
+            }
+        }
+
+        // Stable, so events at the same tick keep their track and file order
+        raw.sort_by_key(|&(tick, _, _)| tick);
+
+        let mut file = MidiFile {
+            format: format,
+            tracks: tracks,
+            events: Vec::new(),
+            tempo_changes: Vec::new(),
+            duration: 0.0,
+        };
+
+        let mut tempo = DEFAULT_TEMPO;
+        let (mut last_tick, mut last_time) = (0, 0.0);
+        for (tick, track, event) in raw {
+            let ticks = (tick - last_tick) as f64;
+            let time = last_time + match division {
+                Division::Metrical(per_quarter) => {
+                    ticks * tempo as f64 / 1_000_000.0 / per_quarter as f64
+                }
+                Division::Timecode(per_second) => ticks / per_second,
+            };
+            last_tick = tick;
+            last_time = time;
+
+            match event {
+                RawEvent::Message(data) => {
+                    file.events.push(FileEvent {
+                        time: time,
+                        tick: tick,
+                        track: track,
+                        data: data,
+                    });
+                }
+                RawEvent::Tempo(new_tempo) => {
+                    if let Division::Metrical(_) = division {
+                        tempo = new_tempo;
+                        file.tempo_changes.push(TempoChange {
+                            time: time,
+                            tick: tick,
+                            tempo: 60_000_000.0 / new_tempo as f64,
+                        });
+                    }
+                }
+                RawEvent::End => {}
+            }
+            file.duration = time;
+        }
+
+        Ok(file)
+    }
+
+    /// Tempo at the start of the file in beats per minute, e.g. to set up a host's transport.
+    pub fn initial_tempo(&self) -> f64 {
+        match self.tempo_changes.first() {
+            Some(change) if change.tick == 0 => change.tempo,
+            _ => 60_000_000.0 / DEFAULT_TEMPO as f64,
+        }
+    }
+
+    /// Length of the file in samples at `sample_rate`.
+    pub fn frames(&self, sample_rate: f64) -> usize {
+        (self.duration * sample_rate).ceil() as usize
+    }
+
+    /// The messages as events for plugin `plugin` of an offline render at `sample_rate`.
+    pub fn timed_events(&self, plugin: usize, sample_rate: f64) -> Vec<TimedEvent> {
+        self.events.iter().map(|event| {
+            TimedEvent {
+                frame: (event.time * sample_rate).round() as usize,
+                plugin: plugin,
+                event: RenderEvent::Midi(event.data),
+            }
+        }).collect()
+    }
+}
+
+/// Parse the events of a track chunk into `events`.
+fn parse_track(data: &[u8], track: usize, events: &mut Vec<(u64, usize, RawEvent)>)
+               -> Result<(), MidiFileError> {
+    let mut cursor = Cursor { data: data, pos: 0 };
+    let mut tick = 0;
+    let mut running = None;
+
+    while cursor.pos < data.len() {
+        tick += try!(cursor.varint()) as u64;
+
+        let first = try!(cursor.u8());
+        let (status, first_data) = if first & 0x80 != 0 {
+            (first, None)
+        } else {
+            // Running status: the status byte of the previous message is omitted
+            (try!(running.ok_or(MidiFileError::InvalidFormat)), Some(first))
+        };
+
+        match status {
+            0xFF => {
+                let kind = try!(cursor.u8());
+                let len = try!(cursor.varint()) as usize;
+                let meta = try!(cursor.bytes(len));
+                match kind {
+                    0x51 if len == 3 => {
+                        let tempo = (meta[0] as u32) << 16 | (meta[1] as u32) << 8 | meta[2] as u32;
+                        // A quarter note can not take no time at all
+                        if tempo == 0 {
+                            return Err(MidiFileError::InvalidFormat);
+                        }
+                        events.push((tick, track, RawEvent::Tempo(tempo)));
+                    }
+                    0x2F => {
+                        events.push((tick, track, RawEvent::End));
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = try!(cursor.varint()) as usize;
+                try!(cursor.bytes(len));
+                running = None;
+            }
+            _ if status < 0xF0 => {
+                let first = match first_data {
+                    Some(byte) => byte,
+                    None => try!(cursor.u8()),
+                };
+                let second = match status & 0xF0 {
+                    0xC0 | 0xD0 => 0,
+                    _ => try!(cursor.u8()),
+                };
+                running = Some(status);
+                events.push((tick, track, RawEvent::Message([status, first, second])));
+            }
+            _ => return Err(MidiFileError::InvalidFormat),
+        }
+    }
+
+    Ok(())
+}
+
+/// Big endian reader over the bytes of a file.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], MidiFileError> {
+        if self.data.len() - self.pos < len {
+            return Err(MidiFileError::Truncated);
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiFileError> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiFileError> {
+        self.bytes(2).map(|b| (b[0] as u16) << 8 | b[1] as u16)
+    }
+
+    /// Read the length of a chunk and return its contents.
+    fn chunk(&mut self) -> Result<&'a [u8], MidiFileError> {
+        let b = try!(self.bytes(4));
+        let len = (b[0] as usize) << 24 | (b[1] as usize) << 16 | (b[2] as usize) << 8 |
+                  b[3] as usize;
+        self.bytes(len)
+    }
+
+    /// Read a variable length quantity of up to four bytes.
+    fn varint(&mut self) -> Result<u32, MidiFileError> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = try!(self.u8());
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MidiFileError::InvalidFormat)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi_file::{MidiFile, MidiFileError};
+    use render::RenderEvent;
+
+    /// A format 1 file with a tempo track and a note track, at 96 ticks per quarter note.
+    fn file() -> Vec<u8> {
+        let mut data = b"MThd\0\0\0\x06\0\x01\0\x02\0\x60".to_vec();
+
+        let tempo = [
+            0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20, // 120 bpm
+            0x60, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40, // 60 bpm after a quarter note
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let notes = [
+            0x00, 0xFF, 0x03, 0x04, b'L', b'e', b'a', b'd', // Track name
+            0x30, 0x90, 0x3C, 0x64, // Note on after an eighth note
+            0x60, 0x3C, 0x00, // Note off with running status a quarter note later
+            0x00, 0xF0, 0x02, 0x7E, 0xF7, // Sysex
+            0x00, 0xC1, 0x05, // Program change
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+
+        for track in &[&tempo[..], &notes[..]] {
+            data.extend_from_slice(b"MTrk\0\0\0");
+            data.push(track.len() as u8);
+            data.extend_from_slice(track);
+        }
+        data
+    }
+
+    /// Test that events are merged and timed through the tempo map.
+    #[test]
+    fn tempo_map() {
+        let file = MidiFile::from_bytes(&file()).unwrap();
+        assert_eq!((file.format, file.tracks, file.initial_tempo()), (1, 2, 120.0));
+        assert_eq!(file.tempo_changes[1].time, 0.5);
+
+        let events: Vec<_> = file.events.iter().map(|e| (e.time, e.track, e.data)).collect();
+        assert_eq!(events, vec![(0.25, 1, [0x90, 0x3C, 0x64]),
+                                (1.0, 1, [0x90, 0x3C, 0x00]),
+                                (1.0, 1, [0xC1, 0x05, 0x00])]);
+        assert_eq!(file.duration, 1.0);
+        assert_eq!(file.frames(1000.0), 1000);
+
+        let timed = file.timed_events(2, 1000.0);
+        assert_eq!((timed[1].frame, timed[1].plugin), (1000, 2));
+        assert_eq!(timed[1].event, RenderEvent::Midi([0x90, 0x3C, 0x00]));
+    }
+
+    /// Test that broken files are rejected.
+    #[test]
+    fn invalid() {
+        let data = file();
+        assert!(match MidiFile::from_bytes(&data[..data.len() - 2]) {
+            Err(MidiFileError::Truncated) => true,
+            _ => false,
+        });
+        assert!(match MidiFile::from_bytes(b"RIFF\0\0\0\x06\0\x01\0\x02\0\x60") {
+            Err(MidiFileError::InvalidFormat) => true,
+            _ => false,
+        });
+
+        // A frame rate of -128 and an SMPTE division without ticks per frame
+        for header in &[b"MThd\0\0\0\x06\0\x00\0\x01\x80\x50",
+                        b"MThd\0\0\0\x06\0\x00\0\x01\xE8\x00"] {
+            assert!(match MidiFile::from_bytes(&header[..]) {
+                Err(MidiFileError::InvalidFormat) => true,
+                _ => false,
+            });
+        }
+
+        // A tempo of 0 microseconds per quarter note
+        let mut data = b"MThd\0\0\0\x06\0\x00\0\x01\0\x60MTrk\0\0\0\x0B".to_vec();
+        data.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x2F, 0x00]);
+        assert!(match MidiFile::from_bytes(&data) {
+            Err(MidiFileError::InvalidFormat) => true,
+            _ => false,
+        });
+    }
+}
@@ -0,0 +1,382 @@
+//! Controlling parameters from MIDI controllers.
+//!
+//! [`MidiLearn`](struct.MidiLearn.html) binds MIDI controllers, the pitch wheel and channel
+//! pressure to parameters. The user arms a parameter, e.g. from a "learn" entry in the editor, and
+//! the next suitable message received binds its source to that parameter. From then on messages
+//! from that source move the parameter. The new values are smoothed to avoid zipper noise, as most
+//! controllers only have 128 steps.
+//!
+//! Bindings are plain values which can be stored in the plugin state. With the `chunk` feature,
+//! [`Binding`](struct.Binding.html) implements `Serialize` and `Deserialize`, so a `Vec<Binding>`
+//! can be part of a `chunk::State`.
+//!
+//! Nothing is allocated after `MidiLearn::new`, so all methods can be used on the audio thread.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::event::Event;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::midi_learn::MidiLearn;
+//! use vst2::smooth::SmoothingStyle;
+//!
+//! struct Filter {
+//!     learn: MidiLearn,
+//!     params: [f32; 2],
+//! }
+//!
+//! impl Plugin for Filter {
+//!     fn get_info(&self) -> Info { Info { parameters: 2, ..Default::default() } }
+//!
+//!     fn process_events(&mut self, events: &[Event]) {
+//!         self.learn.process_events(events);
+//!     }
+//!
+//!     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//!         let params = &mut self.params;
+//!         self.learn.update(buffer.samples() as u32, |index, value| {
+//!             params[index as usize] = value;
+//!         });
+//!         // ...
+//!     }
+//! }
+//! # impl Default for Filter {
+//! #     fn default() -> Filter {
+//! #         Filter { learn: MidiLearn::new(2, SmoothingStyle::Linear(0.02)), params: [0.0; 2] }
+//! #     }
+//! # }
+//! ```
+//!
+//! The editor arms a parameter with `learn.learn(index)`, e.g. through a message queue to the
+//! audio thread.
+
+use event::Event;
+use midi::MidiMessage;
+use smooth::{Smoother, SmoothingStyle};
+
+/// A source of continuous values in incoming MIDI.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MidiSource {
+    /// A controller on a channel.
+    Controller {
+        /// Channel between 0 and 15.
+        channel: u8,
+        /// Controller number.
+        controller: u8,
+    },
+
+    /// The pitch wheel of a channel.
+    PitchBend {
+        /// Channel between 0 and 15.
+        channel: u8,
+    },
+
+    /// Channel pressure (aftertouch).
+    Aftertouch {
+        /// Channel between 0 and 15.
+        channel: u8,
+    },
+}
+
+impl MidiSource {
+    /// The source of `message` and its value between 0.0 and 1.0, or `None` if the message has
+    /// no continuous value.
+    pub fn from_message(message: MidiMessage) -> Option<(MidiSource, f32)> {
+        match message {
+            MidiMessage::ControlChange { channel, controller, value } => {
+                Some((MidiSource::Controller { channel: channel, controller: controller },
+                      value as f32 / 127.0))
+            }
+            MidiMessage::PitchBend { channel, value } => {
+                Some((MidiSource::PitchBend { channel: channel },
+                      (value as f32 + 8192.0) / 16383.0))
+            }
+            MidiMessage::Aftertouch { channel, pressure } => {
+                Some((MidiSource::Aftertouch { channel: channel }, pressure as f32 / 127.0))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A source bound to a parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    /// The MIDI source controlling the parameter.
+    pub source: MidiSource,
+
+    /// Index of the parameter.
+    pub parameter: i32,
+}
+
+/// Binds MIDI sources to parameters. See the module documentation.
+///
+/// Every parameter is bound to at most one source and every source to at most one parameter.
+#[derive(Clone, Debug)]
+pub struct MidiLearn {
+    bindings: Vec<Binding>,
+    learning: Option<i32>,
+
+    /// Smoothing state of every parameter.
+    targets: Vec<Target>,
+}
+
+/// Smoothing state of a parameter.
+#[derive(Clone, Debug)]
+struct Target {
+    smoother: Smoother,
+
+    /// Whether a value was received, so the smoother starts from the current value.
+    started: bool,
+
+    /// Whether a value was received since the last `update`.
+    pending: bool,
+}
+
+impl MidiLearn {
+    /// Create an engine for `parameters` parameters, smoothing changes with `style`.
+    pub fn new(parameters: usize, style: SmoothingStyle) -> MidiLearn {
+        MidiLearn {
+            bindings: Vec::with_capacity(parameters),
+            learning: None,
+            targets: (0..parameters).map(|_| {
+                Target { smoother: Smoother::new(style, 0.0), started: false, pending: false }
+            }).collect(),
+        }
+    }
+
+    /// Set the sample rate used for smoothing.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        for target in &mut self.targets {
+            target.smoother.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Arm learn mode: the next controller, pitch bend or channel pressure message is bound to
+    /// the parameter at `index`, replacing its previous binding.
+    pub fn learn(&mut self, index: i32) {
+        if index >= 0 && (index as usize) < self.targets.len() {
+            self.learning = Some(index);
+        }
+    }
+
+    /// Leave learn mode without binding anything.
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    /// The parameter waiting for a source, if learn mode is armed.
+    pub fn learning(&self) -> Option<i32> {
+        self.learning
+    }
+
+    /// All current bindings, e.g. to store them in the plugin state.
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    /// Replace all bindings, e.g. after loading the plugin state.
+    ///
+    /// Bindings to parameters which do not exist are dropped, as are later bindings of a source
+    /// or parameter which is already bound.
+    pub fn set_bindings(&mut self, bindings: &[Binding]) {
+        self.bindings.clear();
+        for binding in bindings {
+            if binding.parameter >= 0 && (binding.parameter as usize) < self.targets.len() &&
+               !self.bindings.iter().any(|b| b.source == binding.source ||
+                                             b.parameter == binding.parameter) {
+                self.bindings.push(*binding);
+            }
+        }
+    }
+
+    /// Bind `source` to the parameter at `index`, replacing the previous bindings of both.
+    pub fn bind(&mut self, source: MidiSource, index: i32) {
+        if index < 0 || index as usize >= self.targets.len() {
+            return;
+        }
+
+        self.bindings.retain(|b| b.source != source && b.parameter != index);
+        self.bindings.push(Binding { source: source, parameter: index });
+    }
+
+    /// Remove the binding of the parameter at `index`.
+    pub fn unbind(&mut self, index: i32) {
+        self.bindings.retain(|b| b.parameter != index);
+    }
+
+    /// Remove all bindings.
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+    }
+
+    /// Handle an incoming message, binding its source if learn mode is armed.
+    ///
+    /// Returns `true` if the message moved a parameter.
+    pub fn process_message(&mut self, message: MidiMessage) -> bool {
+        let (source, value) = match MidiSource::from_message(message) {
+            Some(source) => source,
+            None => return false,
+        };
+
+        if let Some(index) = self.learning.take() {
+            self.bind(source, index);
+        }
+
+        let index = match self.bindings.iter().find(|b| b.source == source) {
+            Some(binding) => binding.parameter as usize,
+            None => return false,
+        };
+
+        // The first value jumps, as the smoother does not know where the parameter was
+        let target = &mut self.targets[index];
+        if target.started {
+            target.smoother.set_target(value);
+        } else {
+            target.smoother.reset(value);
+            target.started = true;
+        }
+        target.pending = true;
+        true
+    }
+
+    /// Handle all MIDI events of a block, e.g. from `Plugin::process_events`.
+    pub fn process_events(&mut self, events: &[Event]) {
+        for event in events {
+            if let Event::Midi(ref midi) = *event {
+                self.process_message(midi.message());
+            }
+        }
+    }
+
+    /// Advance the smoothing by `samples` samples and call `set` with the index and new value
+    /// between 0.0 and 1.0 of every parameter which changed through MIDI.
+    ///
+    /// Call this once per block in `Plugin::process`. For sample accurate smoothing, call it with
+    /// shorter spans.
+    pub fn update<F: FnMut(i32, f32)>(&mut self, samples: u32, mut set: F) {
+        for (index, target) in self.targets.iter_mut().enumerate() {
+            if target.smoother.is_smoothing() {
+                set(index as i32, target.smoother.advance(samples));
+            } else if target.pending {
+                // Values which jumped are reported once
+                set(index as i32, target.smoother.value());
+            }
+            target.pending = false;
+        }
+    }
+}
+
+#[cfg(feature = "chunk")]
+mod serialization {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use serde::de::Error;
+
+    use midi_learn::{Binding, MidiSource};
+
+    /// Stored form of a binding: source kind, channel, controller and parameter.
+    type Raw = (u8, u8, u8, i32);
+
+    impl Serialize for Binding {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let raw: Raw = match self.source {
+                MidiSource::Controller { channel, controller } => {
+                    (0, channel, controller, self.parameter)
+                }
+                MidiSource::PitchBend { channel } => (1, channel, 0, self.parameter),
+                MidiSource::Aftertouch { channel } => (2, channel, 0, self.parameter),
+            };
+            raw.serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Binding {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Binding, D::Error> {
+            let (kind, channel, controller, parameter) = try!(Raw::deserialize(deserializer));
+            let source = match kind {
+                0 => MidiSource::Controller { channel: channel, controller: controller },
+                1 => MidiSource::PitchBend { channel: channel },
+                2 => MidiSource::Aftertouch { channel: channel },
+                _ => return Err(D::Error::custom("unknown MIDI source")),
+            };
+
+            Ok(Binding { source: source, parameter: parameter })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi::MidiMessage;
+    use midi_learn::{Binding, MidiLearn, MidiSource};
+    use smooth::SmoothingStyle;
+
+    fn cc(controller: u8, value: u8) -> MidiMessage {
+        MidiMessage::ControlChange { channel: 0, controller: controller, value: value }
+    }
+
+    /// Test learning, rebinding and ignoring unbound sources.
+    #[test]
+    fn learn() {
+        let mut learn = MidiLearn::new(2, SmoothingStyle::Linear(0.0));
+        assert!(!learn.process_message(cc(1, 127)));
+
+        learn.learn(1);
+        assert_eq!(learn.learning(), Some(1));
+        assert!(learn.process_message(cc(1, 127)));
+        assert_eq!(learn.learning(), None);
+
+        let source = MidiSource::Controller { channel: 0, controller: 1 };
+        assert_eq!(learn.bindings(), &[Binding { source: source, parameter: 1 }]);
+
+        // Binding the same source elsewhere moves it
+        learn.learn(0);
+        learn.process_message(cc(1, 0));
+        assert_eq!(learn.bindings(), &[Binding { source: source, parameter: 0 }]);
+
+        assert!(!learn.process_message(MidiMessage::NoteOn { channel: 0, note: 60, velocity: 1 }));
+        assert!(!learn.process_message(cc(2, 64)));
+
+        learn.set_bindings(&[Binding { source: source, parameter: 1 },
+                             Binding { source: source, parameter: 0 },
+                             Binding { source: MidiSource::PitchBend { channel: 0 },
+                                       parameter: 5 }]);
+        assert_eq!(learn.bindings(), &[Binding { source: source, parameter: 1 }]);
+    }
+
+    /// Test that values jump on the first message and are smoothed afterwards.
+    #[test]
+    fn smoothing() {
+        let mut learn = MidiLearn::new(1, SmoothingStyle::Linear(1.0));
+        learn.set_sample_rate(100.0);
+        learn.bind(MidiSource::PitchBend { channel: 0 }, 0);
+
+        let mut values = Vec::new();
+        learn.update(10, |index, value| values.push((index, value)));
+        assert!(values.is_empty());
+
+        learn.process_message(MidiMessage::PitchBend { channel: 0, value: 8191 });
+        learn.update(10, |index, value| values.push((index, value)));
+        assert_eq!(values, vec![(0, 1.0)]);
+
+        learn.process_message(MidiMessage::PitchBend { channel: 0, value: -8192 });
+        learn.update(50, |_, value| values.push((0, value)));
+        assert!((values[1].1 - 0.5).abs() < 1e-4);
+    }
+
+    /// Test that bindings survive serialization.
+    #[cfg(feature = "chunk")]
+    #[test]
+    fn serialization() {
+        use bincode;
+
+        let bindings = vec![
+            Binding { source: MidiSource::Controller { channel: 1, controller: 74 }, parameter: 0 },
+            Binding { source: MidiSource::Aftertouch { channel: 15 }, parameter: 3 },
+        ];
+        let data = bincode::serialize(&bindings).unwrap();
+
+        assert_eq!(bincode::deserialize::<Vec<Binding>>(&data).unwrap(), bindings);
+    }
+}
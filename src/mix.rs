@@ -0,0 +1,261 @@
+//! Remapping and downmixing channels between buffers of different channel counts.
+//!
+//! A [`MixMatrix`](struct.MixMatrix.html) holds the gain of every input channel in every output
+//! channel. It can be built to remap, duplicate, sum or drop channels, or derived from a pair of
+//! speaker arrangements, e.g. for a standard 5.1 to stereo downmix.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::api::SpeakerArrangementType;
+//! # use vst2::channels::SpeakerArrangement;
+//! # use vst2::mix::MixMatrix;
+//! let surround = SpeakerArrangement::new(SpeakerArrangementType::Surround51);
+//! let stereo = SpeakerArrangement::new(SpeakerArrangementType::Stereo);
+//! let downmix = MixMatrix::between(&surround, &stereo);
+//!
+//! // L R C Lfe Ls Rs
+//! let inputs = vec![vec![0.5f32; 64], vec![0.0; 64], vec![0.5; 64],
+//!                   vec![1.0; 64], vec![0.0; 64], vec![0.0; 64]];
+//! let mut outputs = vec![vec![0.0f32; 64]; 2];
+//! downmix.apply(&inputs, &mut outputs);
+//! ```
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use num::Float;
+
+use buffer::AudioBuffer;
+use channels::{SpeakerArrangement, SpeakerType};
+
+/// Gains from every input channel to every output channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MixMatrix {
+    inputs: usize,
+    outputs: usize,
+
+    /// Gains in output major order.
+    gains: Vec<f32>,
+}
+
+impl MixMatrix {
+    /// Create a matrix which silences all outputs.
+    pub fn new(inputs: usize, outputs: usize) -> MixMatrix {
+        MixMatrix {
+            inputs: inputs,
+            outputs: outputs,
+            gains: vec![0.0; inputs * outputs],
+        }
+    }
+
+    /// Create a matrix passing each input to the output of the same index. Surplus inputs are
+    /// dropped and surplus outputs are silent.
+    pub fn identity(inputs: usize, outputs: usize) -> MixMatrix {
+        let map: Vec<Option<usize>> = (0..outputs).map(|o| if o < inputs { Some(o) } else { None })
+                                                  .collect();
+        MixMatrix::remap(inputs, &map)
+    }
+
+    /// Create a matrix where output `i` receives the input `map[i]`, or silence for `None`.
+    ///
+    /// Inputs may be used by several outputs, or by none.
+    pub fn remap(inputs: usize, map: &[Option<usize>]) -> MixMatrix {
+        let mut matrix = MixMatrix::new(inputs, map.len());
+        for (output, input) in map.iter().enumerate() {
+            if let Some(input) = *input {
+                matrix.set(output, input, 1.0);
+            }
+        }
+        matrix
+    }
+
+    /// Create a matrix copying a single input to all outputs, e.g. mono to stereo.
+    pub fn fan_out(outputs: usize) -> MixMatrix {
+        MixMatrix::remap(1, &vec![Some(0); outputs])
+    }
+
+    /// Create a matrix summing all inputs into a single output, each scaled by `gain`.
+    pub fn sum(inputs: usize, gain: f32) -> MixMatrix {
+        MixMatrix {
+            inputs: inputs,
+            outputs: 1,
+            gains: vec![gain; inputs],
+        }
+    }
+
+    /// Create a matrix mixing one speaker arrangement into another.
+    ///
+    /// Speakers present in both arrangements are passed through. Other speakers are folded into
+    /// the left and right outputs with the ITU-R BS.775 coefficients, e.g. center and surrounds at
+    /// -3 dB, or into a single mono or center output. LFE channels are dropped. Speakers of
+    /// undefined type are matched by index.
+    pub fn between(input: &SpeakerArrangement, output: &SpeakerArrangement) -> MixMatrix {
+        let mut matrix = MixMatrix::new(input.num_channels(), output.num_channels());
+
+        let find = |speaker_type: SpeakerType| {
+            output.speakers.iter().position(|s| s.speaker_type == speaker_type)
+        };
+        let left = find(SpeakerType::Left);
+        let right = find(SpeakerType::Right);
+        let single = find(SpeakerType::Mono).or_else(|| find(SpeakerType::Center));
+
+        for (index, speaker) in input.speakers.iter().enumerate() {
+            if speaker.speaker_type == SpeakerType::Undefined {
+                if index < matrix.outputs {
+                    matrix.set(index, index, 1.0);
+                }
+                continue;
+            }
+
+            if let Some(out) = find(speaker.speaker_type) {
+                matrix.set(out, index, 1.0);
+                continue;
+            }
+
+            if let Some((l, r)) = stereo_gains(speaker.speaker_type) {
+                if left.is_some() || right.is_some() {
+                    if let Some(left) = left {
+                        matrix.set(left, index, l);
+                    }
+                    if let Some(right) = right {
+                        matrix.set(right, index, r);
+                    }
+                } else if let Some(single) = single {
+                    matrix.set(single, index, (l + r) * 0.5);
+                }
+            }
+        }
+
+        matrix
+    }
+
+    /// Number of input channels.
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Number of output channels.
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// Gain of `input` in `output`.
+    pub fn gain(&self, output: usize, input: usize) -> f32 {
+        self.gains[output * self.inputs + input]
+    }
+
+    /// Set the gain of `input` in `output`.
+    pub fn set(&mut self, output: usize, input: usize, gain: f32) {
+        self.gains[output * self.inputs + input] = gain;
+    }
+
+    /// Mix `inputs` into `outputs`, overwriting the outputs.
+    ///
+    /// Missing channels are treated as silent and surplus channels are ignored. Only the samples
+    /// present in all channels are mixed.
+    pub fn apply<T, I, O>(&self, inputs: &[I], outputs: &mut [O])
+        where T: Float,
+              I: AsRef<[T]>,
+              O: AsMut<[T]>
+    {
+        let samples = inputs.iter().map(|c| c.as_ref().len())
+            .chain(outputs.iter_mut().map(|c| c.as_mut().len()))
+            .min().unwrap_or(0);
+
+        for (o, output) in outputs.iter_mut().enumerate() {
+            let output = &mut output.as_mut()[..samples];
+            for sample in output.iter_mut() {
+                *sample = T::zero();
+            }
+
+            if o >= self.outputs {
+                continue;
+            }
+
+            for (i, input) in inputs.iter().enumerate().take(self.inputs) {
+                let gain = self.gain(o, i);
+                if gain == 0.0 {
+                    continue;
+                }
+
+                let gain = T::from(gain).unwrap();
+                for (sample, &x) in output.iter_mut().zip(input.as_ref()[..samples].iter()) {
+                    *sample = *sample + x * gain;
+                }
+            }
+        }
+    }
+
+    /// Mix the inputs of `buffer` into its outputs.
+    pub fn process<T: Float>(&self, buffer: AudioBuffer<T>) {
+        let (inputs, mut outputs) = buffer.split();
+        self.apply(&inputs, &mut outputs);
+    }
+}
+
+/// Gains of a speaker in the left and right channels of a stereo downmix, or `None` if it is
+/// dropped.
+fn stereo_gains(speaker_type: SpeakerType) -> Option<(f32, f32)> {
+    use channels::SpeakerType::*;
+
+    match speaker_type {
+        Mono => Some((1.0, 1.0)),
+        Left => Some((1.0, 0.0)),
+        Right => Some((0.0, 1.0)),
+        LeftSurround | LeftCenter | SideLeft | TopFrontLeft | TopRearLeft => {
+            Some((FRAC_1_SQRT_2, 0.0))
+        }
+        RightSurround | RightCenter | SideRight | TopFrontRight | TopRearRight => {
+            Some((0.0, FRAC_1_SQRT_2))
+        }
+        Center | Surround | TopMiddle | TopFrontCenter | TopRearCenter => {
+            Some((FRAC_1_SQRT_2, FRAC_1_SQRT_2))
+        }
+        Lfe | Lfe2 | Undefined => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_1_SQRT_2;
+
+    use api::SpeakerArrangementType;
+    use channels::SpeakerArrangement;
+    use mix::MixMatrix;
+
+    /// Test that simple matrices remap, duplicate and sum channels.
+    #[test]
+    fn remap() {
+        let inputs = vec![vec![1.0f32; 4], vec![2.0; 4]];
+        let mut outputs = vec![vec![9.0f32; 4]; 3];
+
+        MixMatrix::remap(2, &[Some(1), Some(1), None]).apply(&inputs, &mut outputs);
+        assert_eq!(outputs, vec![vec![2.0; 4], vec![2.0; 4], vec![0.0; 4]]);
+
+        MixMatrix::fan_out(3).apply(&inputs[..1], &mut outputs);
+        assert_eq!(outputs, vec![vec![1.0; 4]; 3]);
+
+        let mut mono = vec![vec![0.0f32; 4]];
+        MixMatrix::sum(2, 0.5).apply(&inputs, &mut mono);
+        assert_eq!(mono, vec![vec![1.5; 4]]);
+    }
+
+    /// Test the standard 5.1 to stereo downmix.
+    #[test]
+    fn downmix() {
+        let surround = SpeakerArrangement::new(SpeakerArrangementType::Surround51);
+        let stereo = SpeakerArrangement::new(SpeakerArrangementType::Stereo);
+        let matrix = MixMatrix::between(&surround, &stereo);
+
+        // L R C Lfe Ls Rs
+        assert_eq!((0..6).map(|i| matrix.gain(0, i)).collect::<Vec<_>>(),
+                   vec![1.0, 0.0, FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2, 0.0]);
+        assert_eq!((0..6).map(|i| matrix.gain(1, i)).collect::<Vec<_>>(),
+                   vec![0.0, 1.0, FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2]);
+
+        // Mono is fanned out to both sides
+        let mono = SpeakerArrangement::new(SpeakerArrangementType::Mono);
+        let matrix = MixMatrix::between(&mono, &stereo);
+        assert_eq!((matrix.gain(0, 0), matrix.gain(1, 0)), (1.0, 1.0));
+    }
+}
@@ -0,0 +1,306 @@
+//! Oversampling for nonlinear processing.
+//!
+//! Nonlinear processing such as saturation or clipping creates harmonics above the Nyquist
+//! frequency, which fold back into the audible range as aliasing. An
+//! [`Oversampler`](struct.Oversampler.html) upsamples a block by 2 or 4, hands the oversampled
+//! block to a closure and filters and downsamples the result again, so most of these harmonics are
+//! removed before they can fold back.
+//!
+//! Each stage doubles the rate with a linear phase halfband filter implemented in polyphase form.
+//! The filters delay the signal by [`latency`](struct.Oversampler.html#method.latency) samples,
+//! which the plugin should report through `Info::initial_delay`, or `HostCallback::io_changed`
+//! after changing the factor. An oversampler keeps filter state, so use one per channel.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::oversample::{Factor, Oversampler};
+//!
+//! struct Saturator {
+//!     oversamplers: Vec<Oversampler>,
+//! }
+//!
+//! impl Plugin for Saturator {
+//!     fn get_info(&self) -> Info {
+//!         Info { initial_delay: self.oversamplers[0].latency() as i32, ..Default::default() }
+//!     }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         let (inputs, mut outputs) = buffer.split_mut();
+//!
+//!         for ((input, output), oversampler) in inputs.iter()
+//!                                                     .zip(outputs.iter_mut())
+//!                                                     .zip(self.oversamplers.iter_mut()) {
+//!             output.copy_from_slice(input);
+//!             oversampler.process_samples(output, |sample| sample.tanh());
+//!         }
+//!     }
+//! }
+//! # impl Default for Saturator {
+//! #     fn default() -> Saturator {
+//! #         Saturator { oversamplers: vec![Oversampler::new(Factor::X4, 512); 2] }
+//! #     }
+//! # }
+//! ```
+
+use std::f64::consts::PI;
+use std::mem;
+
+/// Number of taps of the halfband filter of each stage.
+const TAPS: usize = 33;
+/// Delay of each filter in samples at the rate it runs at.
+const FILTER_DELAY: usize = (TAPS - 1) / 2;
+
+/// Oversampling factor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Factor {
+    /// Process at twice the sample rate.
+    X2,
+
+    /// Process at four times the sample rate.
+    X4,
+}
+
+impl Factor {
+    /// The factor as a number.
+    pub fn value(&self) -> usize {
+        match *self {
+            Factor::X2 => 2,
+            Factor::X4 => 4,
+        }
+    }
+
+    fn stages(&self) -> usize {
+        match *self {
+            Factor::X2 => 1,
+            Factor::X4 => 2,
+        }
+    }
+}
+
+/// Filter state of a stage doubling the rate.
+#[derive(Clone, Debug)]
+struct Stage {
+    /// Recent input samples of the upsampler, most recent at `up_pos`.
+    up: Vec<f32>,
+    up_pos: usize,
+
+    /// Recent input samples of the downsampler, most recent at `down_pos`.
+    down: Vec<f32>,
+    down_pos: usize,
+}
+
+impl Stage {
+    fn new() -> Stage {
+        Stage {
+            up: vec![0.0; (TAPS + 1) / 2],
+            up_pos: 0,
+            down: vec![0.0; TAPS],
+            down_pos: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        for sample in self.up.iter_mut().chain(self.down.iter_mut()) {
+            *sample = 0.0;
+        }
+    }
+
+    /// Write two output samples for every input sample.
+    fn upsample(&mut self, coefficients: &[f32], input: &[f32], output: &mut [f32]) {
+        let len = self.up.len();
+
+        for (sample, pair) in input.iter().zip(output.chunks_mut(2)) {
+            self.up_pos = (self.up_pos + 1) % len;
+            self.up[self.up_pos] = *sample;
+
+            // Each output phase uses every other coefficient
+            let mut even = 0.0;
+            let mut odd = 0.0;
+            for j in 0..len {
+                let history = self.up[(self.up_pos + len - j) % len];
+                even += coefficients[2 * j] * history;
+                if 2 * j + 1 < TAPS {
+                    odd += coefficients[2 * j + 1] * history;
+                }
+            }
+
+            // Compensate for the zeros inserted between the input samples
+            pair[0] = 2.0 * even;
+            pair[1] = 2.0 * odd;
+        }
+    }
+
+    /// Write one output sample for every two input samples.
+    fn downsample(&mut self, coefficients: &[f32], input: &[f32], output: &mut [f32]) {
+        let len = self.down.len();
+
+        for (pair, sample) in input.chunks(2).zip(output.iter_mut()) {
+            self.down_pos = (self.down_pos + 1) % len;
+            self.down[self.down_pos] = pair[0];
+
+            let mut sum = 0.0;
+            for (n, coefficient) in coefficients.iter().enumerate() {
+                sum += coefficient * self.down[(self.down_pos + len - n) % len];
+            }
+            *sample = sum;
+
+            // The odd samples are only needed as history
+            self.down_pos = (self.down_pos + 1) % len;
+            self.down[self.down_pos] = pair[1];
+        }
+    }
+}
+
+/// Runs processing at a multiple of the sample rate. See the module documentation.
+#[derive(Clone, Debug)]
+pub struct Oversampler {
+    factor: Factor,
+    max_block_size: usize,
+    coefficients: Vec<f32>,
+    stages: Vec<Stage>,
+
+    /// Buffers for the intermediate and oversampled signals.
+    buffer: Vec<f32>,
+    scratch: Vec<f32>,
+}
+
+impl Oversampler {
+    /// Create an oversampler for blocks of up to `max_block_size` samples.
+    ///
+    /// Larger blocks are processed in several parts. All memory is allocated here, so processing
+    /// never allocates.
+    pub fn new(factor: Factor, max_block_size: usize) -> Oversampler {
+        let max_block_size = max_block_size.max(1);
+
+        Oversampler {
+            factor: factor,
+            max_block_size: max_block_size,
+            coefficients: halfband(),
+            stages: (0..factor.stages()).map(|_| Stage::new()).collect(),
+            buffer: vec![0.0; max_block_size * factor.value()],
+            scratch: vec![0.0; max_block_size * factor.value()],
+        }
+    }
+
+    /// The oversampling factor.
+    pub fn factor(&self) -> Factor {
+        self.factor
+    }
+
+    /// Delay added by the filters, in samples at the original rate.
+    pub fn latency(&self) -> usize {
+        // Every stage delays by its filter delay on the way up and down at its own rate
+        (0..self.stages.len()).map(|stage| 2 * FILTER_DELAY >> (stage + 1)).sum()
+    }
+
+    /// Clear the filter state, e.g. when the plugin is resumed.
+    pub fn reset(&mut self) {
+        for stage in &mut self.stages {
+            stage.reset();
+        }
+    }
+
+    /// Upsample `samples`, call `process` with the oversampled block and write the downsampled
+    /// result back to `samples`.
+    pub fn process<F: FnMut(&mut [f32])>(&mut self, samples: &mut [f32], mut process: F) {
+        for block in samples.chunks_mut(self.max_block_size) {
+            let mut len = block.len() * 2;
+            self.stages[0].upsample(&self.coefficients, block, &mut self.buffer[..len]);
+
+            for stage in &mut self.stages[1..] {
+                stage.upsample(&self.coefficients, &self.buffer[..len],
+                               &mut self.scratch[..len * 2]);
+                mem::swap(&mut self.buffer, &mut self.scratch);
+                len *= 2;
+            }
+
+            process(&mut self.buffer[..len]);
+
+            for stage in self.stages[1..].iter_mut().rev() {
+                stage.downsample(&self.coefficients, &self.buffer[..len],
+                                 &mut self.scratch[..len / 2]);
+                mem::swap(&mut self.buffer, &mut self.scratch);
+                len /= 2;
+            }
+
+            self.stages[0].downsample(&self.coefficients, &self.buffer[..len], block);
+        }
+    }
+
+    /// Like `process`, but calls `process` for every oversampled sample.
+    pub fn process_samples<F: FnMut(f32) -> f32>(&mut self, samples: &mut [f32], mut process: F) {
+        self.process(samples, |block| {
+            for sample in block {
+                *sample = process(*sample);
+            }
+        });
+    }
+}
+
+/// Coefficients of a windowed sinc lowpass filter at a quarter of the sample rate, with unity gain.
+fn halfband() -> Vec<f32> {
+    let mut coefficients: Vec<f64> = (0..TAPS).map(|n| {
+        let x = n as f64 - FILTER_DELAY as f64;
+        let sinc = if x == 0.0 { 1.0 } else { (PI * x / 2.0).sin() / (PI * x / 2.0) };
+
+        // Blackman window
+        let phase = 2.0 * PI * n as f64 / (TAPS - 1) as f64;
+        let window = 0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+
+        sinc * window
+    }).collect();
+
+    let sum: f64 = coefficients.iter().sum();
+    for coefficient in &mut coefficients {
+        *coefficient /= sum;
+    }
+
+    coefficients.iter().map(|&c| c as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use oversample::{Factor, Oversampler};
+
+    /// Test that an impulse comes out delayed by the reported latency.
+    #[test]
+    fn latency() {
+        for &factor in &[Factor::X2, Factor::X4] {
+            let mut oversampler = Oversampler::new(factor, 16);
+            let mut samples = vec![0.0; 64];
+            samples[0] = 1.0;
+
+            let mut oversampled = 0;
+            oversampler.process(&mut samples, |block| oversampled += block.len());
+            assert_eq!(oversampled, 64 * factor.value());
+
+            let peak = (0..samples.len()).max_by(|&a, &b| {
+                samples[a].abs().partial_cmp(&samples[b].abs()).unwrap()
+            }).unwrap();
+            assert_eq!(peak, oversampler.latency());
+        }
+
+        assert_eq!(Oversampler::new(Factor::X2, 1).latency(), 16);
+        assert_eq!(Oversampler::new(Factor::X4, 1).latency(), 24);
+    }
+
+    /// Test that a low frequency sine passes unchanged apart from the delay.
+    #[test]
+    fn passband() {
+        let mut oversampler = Oversampler::new(Factor::X4, 100);
+        let sine: Vec<f32> = (0..1000).map(|i| (2.0 * PI * i as f32 / 50.0).sin()).collect();
+
+        let mut samples = sine.clone();
+        oversampler.process_samples(&mut samples, |sample| sample);
+
+        let latency = oversampler.latency();
+        for i in 100..1000 {
+            assert!((samples[i] - sine[i - latency]).abs() < 0.01);
+        }
+    }
+}
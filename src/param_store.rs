@@ -0,0 +1,306 @@
+//! Sharing parameter values between threads.
+//!
+//! Hosts call `Plugin::set_parameter` from the GUI or automation thread while `Plugin::process`
+//! runs on the audio thread, and an editor usually needs the values too. Keeping the values in
+//! plain fields of the plugin invites data races once the editor gets a pointer to them, while a
+//! `Mutex` can block the audio thread. A [`ParameterStore`](struct.ParameterStore.html) keeps
+//! every value in an [`AtomicFloat`](struct.AtomicFloat.html) instead, so it can be shared through
+//! an `Arc` and read and written from any thread without locking.
+//!
+//! Values are stored normalized, i.e. between 0.0 and 1.0, and described by the same
+//! `params::Parameter` descriptions the `parameters!` macro generates. Every write raises a change
+//! flag for the parameter, which the audio thread can poll to update derived state like filter
+//! coefficients only when needed.
+//!
+//! # Example
+//!
+//! ```
+//! # use std::sync::Arc;
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::param_store::ParameterStore;
+//! use vst2::params::{Mapping, Parameter};
+//!
+//! static PARAMETERS: [Parameter; 1] = [
+//!     Parameter { name: "Gain", label: "dB", min: -60.0, max: 6.0, default: 0.0,
+//!                 mapping: Mapping::Decibels },
+//! ];
+//!
+//! struct Gain {
+//!     params: Arc<ParameterStore>, // Cloned into the editor
+//!     gain: f32,
+//! }
+//!
+//! impl Plugin for Gain {
+//!     fn get_info(&self) -> Info {
+//!         Info { parameters: self.params.count(), ..Default::default() }
+//!     }
+//!
+//!     fn get_parameter(&self, index: i32) -> f32 { self.params.get(index) }
+//!     fn set_parameter(&mut self, index: i32, value: f32) { self.params.set(index, value) }
+//!     fn get_parameter_text(&self, index: i32) -> String { self.params.text(index) }
+//!
+//!     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//!         if self.params.take_changed(0) {
+//!             self.gain = vst2::params::decibels_to_gain(self.params.get_plain(0));
+//!         }
+//!         // ...
+//!     }
+//! }
+//! # impl Default for Gain {
+//! #     fn default() -> Gain {
+//! #         Gain { params: Arc::new(ParameterStore::new(&PARAMETERS)), gain: 1.0 }
+//! #     }
+//! # }
+//! ```
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use host::Host;
+use params::Parameter;
+
+/// An `f32` which can be shared between threads, stored as its bits in an `AtomicU32`.
+pub struct AtomicFloat {
+    bits: AtomicU32,
+}
+
+impl AtomicFloat {
+    /// Create a new atomic holding `value`.
+    pub fn new(value: f32) -> AtomicFloat {
+        AtomicFloat { bits: AtomicU32::new(value.to_bits()) }
+    }
+
+    /// Get the current value.
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    /// Replace the current value.
+    pub fn set(&self, value: f32) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Replace the current value, returning the previous one.
+    pub fn swap(&self, value: f32) -> f32 {
+        f32::from_bits(self.bits.swap(value.to_bits(), Ordering::Relaxed))
+    }
+}
+
+impl Default for AtomicFloat {
+    fn default() -> AtomicFloat {
+        AtomicFloat::new(0.0)
+    }
+}
+
+impl fmt::Debug for AtomicFloat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.get(), f)
+    }
+}
+
+/// Normalized parameter values shared between threads. See the module documentation.
+///
+/// Out of range indices are ignored by setters and yield empty or zero values from getters, like
+/// `params::Parameters`.
+pub struct ParameterStore {
+    parameters: &'static [Parameter],
+    values: Vec<AtomicFloat>,
+    changed: Vec<AtomicBool>,
+}
+
+impl ParameterStore {
+    /// Create a store for `parameters`, initialized to their default values.
+    ///
+    /// All change flags start raised, so the first poll picks up the initial values.
+    pub fn new(parameters: &'static [Parameter]) -> ParameterStore {
+        ParameterStore {
+            parameters: parameters,
+            values: parameters.iter()
+                              .map(|p| AtomicFloat::new(p.normalize(p.default)))
+                              .collect(),
+            changed: parameters.iter().map(|_| AtomicBool::new(true)).collect(),
+        }
+    }
+
+    /// Number of parameters, for `Info::parameters`.
+    pub fn count(&self) -> i32 {
+        self.parameters.len() as i32
+    }
+
+    /// Description of the parameter at `index`.
+    pub fn parameter(&self, index: i32) -> Option<&'static Parameter> {
+        if index < 0 {
+            return None;
+        }
+        self.parameters.get(index as usize)
+    }
+
+    /// Value of the parameter at `index` between 0.0 and 1.0, for `Plugin::get_parameter`.
+    pub fn get(&self, index: i32) -> f32 {
+        self.value(index).map_or(0.0, |value| value.get())
+    }
+
+    /// Set the parameter at `index` from a value between 0.0 and 1.0 and raise its change flag,
+    /// for `Plugin::set_parameter`.
+    pub fn set(&self, index: i32, value: f32) {
+        if let Some(stored) = self.value(index) {
+            stored.set(value.max(0.0).min(1.0));
+            self.changed[index as usize].store(true, Ordering::Release);
+        }
+    }
+
+    /// Plain value of the parameter at `index`.
+    pub fn get_plain(&self, index: i32) -> f32 {
+        self.parameter(index).map_or(0.0, |p| p.denormalize(self.get(index)))
+    }
+
+    /// Set the plain value of the parameter at `index` and raise its change flag.
+    pub fn set_plain(&self, index: i32, value: f32) {
+        if let Some(parameter) = self.parameter(index) {
+            self.set(index, parameter.normalize(value));
+        }
+    }
+
+    /// Name of the parameter at `index`, for `Plugin::get_parameter_name`.
+    pub fn name(&self, index: i32) -> String {
+        self.parameter(index).map_or(String::new(), |p| p.name.to_string())
+    }
+
+    /// Label of the parameter at `index`, for `Plugin::get_parameter_label`.
+    pub fn label(&self, index: i32) -> String {
+        self.parameter(index).map_or(String::new(), |p| p.label.to_string())
+    }
+
+    /// Plain value of the parameter at `index` as text, for `Plugin::get_parameter_text`.
+    pub fn text(&self, index: i32) -> String {
+        self.parameter(index).map_or(String::new(), |p| p.format(self.get_plain(index)))
+    }
+
+    /// Lower the change flag of the parameter at `index`, returning whether it was raised.
+    ///
+    /// Every flag is meant to be polled by a single thread, usually the audio thread.
+    pub fn take_changed(&self, index: i32) -> bool {
+        if index < 0 {
+            return false;
+        }
+        self.changed.get(index as usize)
+            .map_or(false, |changed| changed.swap(false, Ordering::Acquire))
+    }
+
+    /// Iterate over the indices of the parameters whose change flags are raised, lowering the
+    /// flags along the way.
+    ///
+    /// Nothing is allocated, so this can be used in `process`.
+    pub fn take_changes(&self) -> Changes {
+        Changes { store: self, index: 0 }
+    }
+
+    /// Set the parameter at `index` on behalf of the user, e.g. from the editor, and notify the
+    /// host so it can record automation.
+    ///
+    /// Wrap calls during a gesture such as a knob drag in `Host::begin_edit` and
+    /// `Host::end_edit`.
+    pub fn automate<H: Host>(&self, host: &mut H, index: i32, value: f32) {
+        if self.value(index).is_some() {
+            self.set(index, value);
+            host.automate(index, self.get(index));
+        }
+    }
+
+    /// Set all parameters to their default values.
+    pub fn reset(&self) {
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            self.set_plain(index as i32, parameter.default);
+        }
+    }
+
+    fn value(&self, index: i32) -> Option<&AtomicFloat> {
+        if index < 0 {
+            return None;
+        }
+        self.values.get(index as usize)
+    }
+}
+
+impl fmt::Debug for ParameterStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+         .entries(self.parameters.iter().map(|p| p.name).zip(self.values.iter()))
+         .finish()
+    }
+}
+
+/// Iterator over changed parameters, see `ParameterStore::take_changes`.
+pub struct Changes<'a> {
+    store: &'a ParameterStore,
+    index: i32,
+}
+
+impl<'a> Iterator for Changes<'a> {
+    type Item = i32;
+
+    fn next(&mut self) -> Option<i32> {
+        while self.index < self.store.count() {
+            self.index += 1;
+            if self.store.take_changed(self.index - 1) {
+                return Some(self.index - 1);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use param_store::ParameterStore;
+    use params::{Mapping, Parameter};
+    use testing::TestHost;
+
+    static PARAMETERS: [Parameter; 2] = [
+        Parameter { name: "Gain", label: "dB", min: -48.0, max: 12.0, default: 0.0,
+                    mapping: Mapping::Linear },
+        Parameter { name: "Mode", label: "", min: 0.0, max: 3.0, default: 1.0,
+                    mapping: Mapping::Stepped },
+    ];
+
+    /// Test values and change flags across threads.
+    #[test]
+    fn shared() {
+        let store = Arc::new(ParameterStore::new(&PARAMETERS));
+        assert_eq!(store.get(0), 0.8);
+        assert_eq!(store.get_plain(1), 1.0);
+        assert_eq!(store.take_changes().collect::<Vec<_>>(), vec![0, 1]);
+        assert!(!store.take_changed(0));
+
+        let writer = store.clone();
+        thread::spawn(move || writer.set_plain(1, 3.0)).join().unwrap();
+
+        assert_eq!(store.take_changes().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(store.get(1), 1.0);
+        assert_eq!(store.text(1), "3");
+
+        store.set(2, 1.0);
+        store.set(-1, 1.0);
+        assert_eq!(store.get(2), 0.0);
+        assert!(!store.take_changed(2));
+
+        store.reset();
+        assert_eq!(store.get_plain(1), 1.0);
+    }
+
+    /// Test that edits are reported to the host.
+    #[test]
+    fn automate() {
+        let store = ParameterStore::new(&PARAMETERS);
+        let mut host = TestHost::default();
+
+        store.automate(&mut host, 0, 1.5);
+        store.automate(&mut host, 5, 0.5);
+        assert_eq!(host.automated, vec![(0, 1.0)]);
+        assert!(store.take_changed(0));
+    }
+}
@@ -0,0 +1,444 @@
+//! Declaring plugin parameters.
+//!
+//! VST parameters are addressed by index and exchanged as values between 0.0 and 1.0, so plugins
+//! usually end up matching on the index in `get_parameter_name`, `get_parameter`, `set_parameter`
+//! and friends. The `parameters!` macro instead declares a struct holding the plain value of every
+//! parameter together with its name, label, range and default value, and implements
+//! [`Parameters`](trait.Parameters.html) for it. The `Plugin` methods then become one-liners.
+//!
+//! Indices follow the order of declaration. By default plain values are spread linearly between
+//! `min` and `max`; an optional `mapping` selects one of the other [`Mapping`](enum.Mapping.html)
+//! shapes, e.g. a logarithmic frequency or a gain in decibels that reaches silence at 0.0. The
+//! mapping also determines how `Parameters::text` displays the value.
+//!
+//! # Example
+//!
+//! ```
+//! #[macro_use] extern crate vst2;
+//!
+//! use vst2::params::{Mapping, Parameters};
+//! use vst2::plugin::{Info, Plugin};
+//!
+//! parameters! {
+//!     /// Parameters of the filter.
+//!     pub struct FilterParameters {
+//!         /// Cutoff frequency in Hz.
+//!         cutoff {
+//!             name: "Cutoff", label: "Hz", min: 20.0, max: 20000.0, default: 1000.0,
+//!             mapping: Mapping::Logarithmic,
+//!         },
+//!         resonance { name: "Resonance", label: "", min: 0.0, max: 1.0, default: 0.5 },
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! struct Filter {
+//!     params: FilterParameters,
+//! }
+//!
+//! impl Plugin for Filter {
+//!     fn get_info(&self) -> Info {
+//!         Info { parameters: self.params.count(), ..Default::default() }
+//!     }
+//!
+//!     fn get_parameter_name(&self, index: i32) -> String { self.params.name(index) }
+//!     fn get_parameter_label(&self, index: i32) -> String { self.params.label(index) }
+//!     fn get_parameter_text(&self, index: i32) -> String { self.params.text(index) }
+//!     fn get_parameter(&self, index: i32) -> f32 { self.params.get_normalized(index) }
+//!     fn set_parameter(&mut self, index: i32, value: f32) {
+//!         self.params.set_normalized(index, value);
+//!     }
+//!
+//!     // Processing reads the plain values, e.g. `self.params.cutoff`
+//! }
+//! # fn main() {
+//! #     let mut filter = Filter::default();
+//! #     filter.set_parameter(0, 0.0);
+//! #     assert_eq!(filter.params.cutoff, 20.0);
+//! # }
+//! ```
+
+use std::f32;
+
+/// Declare a struct of plugin parameters and implement `params::Parameters` for it.
+///
+/// Every parameter becomes a public `f32` field holding its plain value, initialized to its
+/// default value by the generated `Default` implementation. The `mapping` of a parameter is
+/// optional and defaults to `Mapping::Linear`. See the [module documentation](params/index.html)
+/// for an example.
+#[macro_export]
+macro_rules! parameters {
+    (@mapping) => { $crate::params::Mapping::Linear };
+    (@mapping $mapping:expr) => { $mapping };
+
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                $field:ident {
+                    name: $param_name:expr,
+                    label: $label:expr,
+                    min: $min:expr,
+                    max: $max:expr,
+                    default: $default:expr
+                    $(, mapping: $mapping:expr)* $(,)*
+                }
+            ),+ $(,)*
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[$field_attr])*
+                pub $field: f32,
+            )+
+        }
+
+        impl Default for $name {
+            fn default() -> $name {
+                $name {
+                    $($field: $default,)+
+                }
+            }
+        }
+
+        impl $crate::params::Parameters for $name {
+            fn parameters(&self) -> &'static [$crate::params::Parameter] {
+                const PARAMETERS: &'static [$crate::params::Parameter] = &[
+                    $(
+                        $crate::params::Parameter {
+                            name: $param_name,
+                            label: $label,
+                            min: $min,
+                            max: $max,
+                            default: $default,
+                            mapping: parameters!(@mapping $($mapping)*),
+                        },
+                    )+
+                ];
+
+                PARAMETERS
+            }
+
+            fn get_plain(&self, index: i32) -> Option<f32> {
+                if index < 0 {
+                    return None;
+                }
+                [$(self.$field),+].get(index as usize).cloned()
+            }
+
+            fn set_plain(&mut self, index: i32, value: f32) {
+                if index < 0 {
+                    return;
+                }
+                if let Some(field) = [$(&mut self.$field),+].iter_mut().nth(index as usize) {
+                    **field = value;
+                }
+            }
+        }
+    }
+}
+
+/// Description of a single parameter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Parameter {
+    /// Name shown by the host, e.g. "Cutoff".
+    pub name: &'static str,
+
+    /// Unit label shown after the value, e.g. "Hz".
+    pub label: &'static str,
+
+    /// Plain value corresponding to 0.0.
+    pub min: f32,
+
+    /// Plain value corresponding to 1.0.
+    pub max: f32,
+
+    /// Plain value the parameter starts with.
+    pub default: f32,
+
+    /// How values between 0.0 and 1.0 are spread between `min` and `max`.
+    pub mapping: Mapping,
+}
+
+impl Parameter {
+    /// Convert a plain value to a value between 0.0 and 1.0.
+    pub fn normalize(&self, plain: f32) -> f32 {
+        self.mapping.normalize(plain, self.min, self.max)
+    }
+
+    /// Convert a value between 0.0 and 1.0 to a plain value.
+    pub fn denormalize(&self, normalized: f32) -> f32 {
+        self.mapping.denormalize(normalized, self.min, self.max)
+    }
+
+    /// Format a plain value for display.
+    pub fn format(&self, plain: f32) -> String {
+        self.mapping.format(plain)
+    }
+}
+
+/// Shape of the conversion between values between 0.0 and 1.0 and plain values.
+///
+/// The conversions can also be used on their own, e.g. for parameters not declared through
+/// `parameters!`. Values outside of the range are clamped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    /// Plain values are spread evenly between `min` and `max`.
+    Linear,
+
+    /// Every step changes the plain value by the same ratio, e.g. for frequencies between
+    /// `FREQUENCY_MIN` and `FREQUENCY_MAX`. Both `min` and `max` have to be positive.
+    Logarithmic,
+
+    /// A gain in decibels, spread evenly between `min` and `max` except at 0.0, which maps to
+    /// negative infinity, i.e. silence. Plain values at or below `min` map to 0.0. Use
+    /// `decibels_to_gain` to get the factor to multiply samples with.
+    Decibels,
+
+    /// Integer values between `min` and `max`, e.g. for a filter slope or a voice count.
+    Stepped,
+
+    /// Off (0.0) below 0.5 and on (1.0) from 0.5. `min` and `max` are ignored.
+    Boolean,
+}
+
+/// Lowest frequency usually offered by frequency parameters, in Hz.
+pub const FREQUENCY_MIN: f32 = 20.0;
+
+/// Highest frequency usually offered by frequency parameters, in Hz.
+pub const FREQUENCY_MAX: f32 = 20000.0;
+
+impl Mapping {
+    /// Convert a plain value between `min` and `max` to a value between 0.0 and 1.0.
+    pub fn normalize(&self, plain: f32, min: f32, max: f32) -> f32 {
+        let normalized = match *self {
+            Mapping::Boolean => return if plain >= 0.5 { 1.0 } else { 0.0 },
+            _ if max == min => return 0.0,
+
+            Mapping::Linear => (plain - min) / (max - min),
+            Mapping::Logarithmic => (plain / min).ln() / (max / min).ln(),
+            Mapping::Decibels => {
+                if plain <= min {
+                    return 0.0;
+                }
+                (plain - min) / (max - min)
+            }
+            Mapping::Stepped => (plain.round() - min) / (max - min),
+        };
+
+        // NaN, e.g. from the logarithm of a negative value, ends up at 0.0
+        normalized.max(0.0).min(1.0)
+    }
+
+    /// Convert a value between 0.0 and 1.0 to a plain value between `min` and `max`.
+    pub fn denormalize(&self, normalized: f32, min: f32, max: f32) -> f32 {
+        let normalized = normalized.max(0.0).min(1.0);
+
+        match *self {
+            Mapping::Linear => min + normalized * (max - min),
+            Mapping::Logarithmic => min * (max / min).powf(normalized),
+            Mapping::Decibels => {
+                if normalized == 0.0 {
+                    return f32::NEG_INFINITY;
+                }
+                min + normalized * (max - min)
+            }
+            Mapping::Stepped => (min + normalized * (max - min)).round(),
+            Mapping::Boolean => if normalized >= 0.5 { 1.0 } else { 0.0 },
+        }
+    }
+
+    /// Format a plain value for display, without the unit label.
+    pub fn format(&self, plain: f32) -> String {
+        match *self {
+            Mapping::Linear => format!("{:.2}", plain),
+            Mapping::Logarithmic => {
+                // Keep the number of significant digits roughly constant
+                if plain.abs() >= 1000.0 {
+                    format!("{:.0}", plain)
+                } else if plain.abs() >= 100.0 {
+                    format!("{:.1}", plain)
+                } else {
+                    format!("{:.2}", plain)
+                }
+            }
+            Mapping::Decibels => {
+                if plain == f32::NEG_INFINITY {
+                    "-inf".to_string()
+                } else {
+                    format!("{:.1}", plain)
+                }
+            }
+            Mapping::Stepped => format!("{:.0}", plain),
+            Mapping::Boolean => if plain >= 0.5 { "On" } else { "Off" }.to_string(),
+        }
+    }
+}
+
+impl Default for Mapping {
+    fn default() -> Mapping {
+        Mapping::Linear
+    }
+}
+
+/// Convert a gain in decibels to a linear factor. Negative infinity yields 0.0.
+pub fn decibels_to_gain(decibels: f32) -> f32 {
+    10f32.powf(decibels / 20.0)
+}
+
+/// Convert a linear factor to a gain in decibels. 0.0 yields negative infinity.
+pub fn gain_to_decibels(gain: f32) -> f32 {
+    20.0 * gain.abs().log10()
+}
+
+/// A set of parameters, usually implemented through the `parameters!` macro.
+///
+/// Only `parameters`, `get_plain` and `set_plain` need to be implemented. Out of range indices
+/// are ignored by setters and yield empty or zero values from getters.
+pub trait Parameters {
+    /// Descriptions of all parameters in index order.
+    fn parameters(&self) -> &'static [Parameter];
+
+    /// Get the plain value of the parameter at `index`.
+    fn get_plain(&self, index: i32) -> Option<f32>;
+
+    /// Set the plain value of the parameter at `index`.
+    fn set_plain(&mut self, index: i32, value: f32);
+
+    /// Number of parameters, for `Info::parameters`.
+    fn count(&self) -> i32 {
+        self.parameters().len() as i32
+    }
+
+    /// Description of the parameter at `index`.
+    fn parameter(&self, index: i32) -> Option<&'static Parameter> {
+        if index < 0 {
+            return None;
+        }
+        self.parameters().get(index as usize)
+    }
+
+    /// Name of the parameter at `index`, for `Plugin::get_parameter_name`.
+    fn name(&self, index: i32) -> String {
+        self.parameter(index).map_or(String::new(), |p| p.name.to_string())
+    }
+
+    /// Label of the parameter at `index`, for `Plugin::get_parameter_label`.
+    fn label(&self, index: i32) -> String {
+        self.parameter(index).map_or(String::new(), |p| p.label.to_string())
+    }
+
+    /// Plain value of the parameter at `index` as text, for `Plugin::get_parameter_text`.
+    fn text(&self, index: i32) -> String {
+        match (self.parameter(index), self.get_plain(index)) {
+            (Some(parameter), Some(value)) => parameter.format(value),
+            _ => String::new(),
+        }
+    }
+
+    /// Value of the parameter at `index` between 0.0 and 1.0, for `Plugin::get_parameter`.
+    fn get_normalized(&self, index: i32) -> f32 {
+        match (self.parameter(index), self.get_plain(index)) {
+            (Some(parameter), Some(value)) => parameter.normalize(value),
+            _ => 0.0,
+        }
+    }
+
+    /// Set the parameter at `index` from a value between 0.0 and 1.0, for
+    /// `Plugin::set_parameter`.
+    fn set_normalized(&mut self, index: i32, value: f32) {
+        if let Some(parameter) = self.parameter(index) {
+            self.set_plain(index, parameter.denormalize(value));
+        }
+    }
+
+    /// Set all parameters to their default values.
+    fn reset(&mut self) {
+        for (index, parameter) in self.parameters().iter().enumerate() {
+            self.set_plain(index as i32, parameter.default);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32;
+
+    use params::{self, Mapping, Parameters};
+
+    parameters! {
+        pub struct TestParameters {
+            gain { name: "Gain", label: "dB", min: -48.0, max: 12.0, default: 0.0 },
+            pan { name: "Pan", label: "", min: -1.0, max: 1.0, default: 0.0 },
+            bypass {
+                name: "Bypass", label: "", min: 0.0, max: 1.0, default: 0.0,
+                mapping: Mapping::Boolean,
+            },
+        }
+    }
+
+    /// Test the generated accessors.
+    #[test]
+    fn generated() {
+        let mut params = TestParameters::default();
+
+        assert_eq!(params.count(), 3);
+        assert_eq!(params.name(0), "Gain");
+        assert_eq!(params.label(0), "dB");
+        assert_eq!(params.name(3), "");
+        assert_eq!(params.get_normalized(0), 0.8);
+        assert_eq!(params.text(0), "0.00");
+
+        params.set_normalized(1, 1.0);
+        assert_eq!(params.pan, 1.0);
+        assert_eq!(params.get_plain(1), Some(1.0));
+
+        params.set_normalized(0, 2.0);
+        assert_eq!(params.gain, 12.0);
+
+        params.set_normalized(2, 0.7);
+        assert_eq!(params.bypass, 1.0);
+        assert_eq!(params.text(2), "On");
+
+        params.set_plain(-1, 5.0);
+        params.set_plain(3, 5.0);
+        assert_eq!(params.get_plain(3), None);
+
+        params.reset();
+        assert_eq!((params.gain, params.pan, params.bypass), (0.0, 0.0, 0.0));
+    }
+
+    /// Test the conversions and formatting of every mapping.
+    #[test]
+    fn mappings() {
+        let (min, max) = (params::FREQUENCY_MIN, params::FREQUENCY_MAX);
+        let log = Mapping::Logarithmic;
+        assert_eq!(log.denormalize(0.0, min, max), 20.0);
+        assert!((log.denormalize(0.5, min, max) - 632.46).abs() < 0.01);
+        assert!((log.normalize(632.46, min, max) - 0.5).abs() < 1e-5);
+        assert_eq!(log.normalize(-1.0, min, max), 0.0);
+        assert_eq!(log.format(1234.6), "1235");
+        assert_eq!(log.format(440.0), "440.0");
+
+        let db = Mapping::Decibels;
+        assert_eq!(db.denormalize(0.0, -60.0, 6.0), f32::NEG_INFINITY);
+        assert!(db.denormalize(0.01, -60.0, 6.0) > -60.0);
+        assert_eq!(db.normalize(f32::NEG_INFINITY, -60.0, 6.0), 0.0);
+        assert_eq!(db.normalize(6.0, -60.0, 6.0), 1.0);
+        assert_eq!(db.format(f32::NEG_INFINITY), "-inf");
+        assert_eq!(db.format(-6.02), "-6.0");
+        assert_eq!(params::decibels_to_gain(f32::NEG_INFINITY), 0.0);
+        assert!((params::decibels_to_gain(-6.0206) - 0.5).abs() < 1e-4);
+        assert!((params::gain_to_decibels(2.0) - 6.0206).abs() < 1e-4);
+
+        let stepped = Mapping::Stepped;
+        assert_eq!(stepped.denormalize(0.4, 1.0, 4.0), 2.0);
+        assert_eq!(stepped.normalize(2.2, 1.0, 4.0), 1.0 / 3.0);
+        assert_eq!(stepped.format(3.0), "3");
+
+        assert_eq!(Mapping::Boolean.denormalize(0.49, 0.0, 1.0), 0.0);
+        assert_eq!(Mapping::Boolean.format(0.0), "Off");
+    }
+}
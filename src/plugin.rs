@@ -1,15 +1,19 @@
 //! Plugin specific structures.
 
-use std::{mem, ptr};
+use std::{cmp, fmt, mem, ptr};
 
 use libc::c_void;
 
-use channels::ChannelInfo;
+use channels::{ChannelInfo, SpeakerArrangement};
 use host::{self, Host};
-use api::{AEffect, HostCallbackProc, Supported};
-use api::consts::VST_MAGIC;
-use buffer::AudioBuffer;
+use api::{self, AEffect, HostCallbackProc, Supported};
+use api::consts::{VST_MAGIC, VST_VERSION};
+use buffer::{AudioBuffer, VariableIo};
+use bus::BusLayout;
 use editor::Editor;
+use event::{Event, MidiEvent, SendEventBuffer, SysExEvent};
+use interfaces;
+use {read_fixed, write_fixed};
 
 /// Plugin type. Generally either Effect or Synth.
 ///
@@ -45,6 +49,28 @@ pub enum Category {
 }
 impl_clike!(Category);
 
+impl Category {
+    /// Convert from the value returned for `GetCategory`, mapping unknown values to `Unknown`.
+    pub fn from_raw(value: isize) -> Category {
+        use self::Category::*;
+
+        match value {
+            1 => Effect,
+            2 => Synth,
+            3 => Analysis,
+            4 => Mastering,
+            5 => Spacializer,
+            6 => RoomFx,
+            7 => SurroundFx,
+            8 => Restoration,
+            9 => OfflineProcess,
+            10 => Shell,
+            11 => Generator,
+            _ => Unknown,
+        }
+    }
+}
+
 #[repr(usize)]
 #[derive(Clone, Copy, Debug)]
 #[doc(hidden)]
@@ -112,7 +138,7 @@ pub enum OpCode {
     /// [index]: 0 for bank, 1 for program
     SetData,
 
-    /// [ptr]: VstEvents* TODO: Events
+    /// [ptr]: `*mut api::Events`.
     ProcessEvents,
     /// [index]: param index
     /// [return]: 1=true, 0=false
@@ -153,23 +179,23 @@ pub enum OpCode {
     /// Deprecated.
     _GetDestinationBuffer,
 
-    /// [ptr]: `VstAudioFile` array
+    /// [ptr]: `api::AudioFile` array
     /// [value]: count
     /// [index]: start flag
     OfflineNotify,
-    /// [ptr]: `VstOfflineTask` array
+    /// [ptr]: `api::OfflineTask` array
     /// [value]: count
     OfflinePrepare,
-    /// [ptr]: `VstOfflineTask` array
+    /// [ptr]: `api::OfflineTask` array
     /// [value]: count
     OfflineRun,
 
-    /// [ptr]: `VstVariableIo`
+    /// [ptr]: `*mut api::VariableIo`
     /// [use]: used for variable I/O processing (offline e.g. timestretching)
+    /// [return]: 1 if supported.
     ProcessVarIo,
-    /// TODO: implement
-    /// [value]: input `*mut VstSpeakerArrangement`.
-    /// [ptr]: output `*mut VstSpeakerArrangement`.
+    /// [value]: input `*mut api::SpeakerArrangement`.
+    /// [ptr]: output `*mut api::SpeakerArrangement`.
     SetSpeakerArrangement,
 
     /// Deprecated.
@@ -178,7 +204,7 @@ pub enum OpCode {
     /// Soft bypass (automatable).
     /// [value]: 1 = bypass, 0 = nobypass.
     SoftBypass,
-    // [ptr]: buffer for effect name, limited to `kVstMaxEffectNameLen`
+    /// [ptr]: buffer for effect name, limited to `consts::MAX_EFFECT_NAME_LEN`.
     GetEffectName,
 
     /// Deprecated.
@@ -195,20 +221,21 @@ pub enum OpCode {
     /// [ptr]: "Can do" string.
     /// [return]: 1 = yes, 0 = maybe, -1 = no.
     CanDo,
-    /// [return]: tail size (e.g. reverb time). 0 is defualt, 1 means no tail.
+    /// [return]: tail size (e.g. reverb time). 0 is default, 1 means no tail.
     GetTailSize,
 
-    /// Deprecated.
-    _Idle,
+    /// Deprecated. Called regularly by hosts which still support it, after the plugin requested
+    /// idle calls with `host::OpCode::NeedIdle`.
+    Idle,
     /// Deprecated.
     _GetIcon,
     /// Deprecated.
     _SetVewPosition,
 
     /// [index]: param index
-    /// [ptr]: `*mut VstParamInfo` //TODO: Implement
+    /// [ptr]: `*mut api::ParameterProperties`
     /// [return]: 1 if supported
-    GetParamInfo,
+    GetParameterProperties,
 
     /// Deprecated.
     _KeysRequired,
@@ -218,34 +245,34 @@ pub enum OpCode {
 
     /// [index]: ASCII char.
     /// [value]: `Key` keycode.
-    /// [opt]: `flags::modifier_key` bitmask.
+    /// [opt]: `flags::ModifierKey` bitmask.
     /// [return]: 1 if used.
     EditorKeyDown,
     /// [index]: ASCII char.
     /// [value]: `Key` keycode.
-    /// [opt]: `flags::modifier_key` bitmask.
+    /// [opt]: `flags::ModifierKey` bitmask.
     /// [return]: 1 if used.
     EditorKeyUp,
     /// [value]: 0 = circular, 1 = circular relative, 2 = linear.
     EditorSetKnobMode,
 
     /// [index]: MIDI channel.
-    /// [ptr]: `*mut MidiProgramName`. //TODO: Implement
+    /// [ptr]: `*mut api::MidiProgramName`.
     /// [return]: number of used programs, 0 = unsupported.
     GetMidiProgramName,
     /// [index]: MIDI channel.
-    /// [ptr]: `*mut MidiProgramName`. //TODO: Implement
+    /// [ptr]: `*mut api::MidiProgramName`.
     /// [return]: index of current program.
     GetCurrentMidiProgram,
     /// [index]: MIDI channel.
-    /// [ptr]: `*mut MidiProgramCategory`. //TODO: Implement
+    /// [ptr]: `*mut api::MidiProgramCategory`.
     /// [return]: number of used categories.
     GetMidiProgramCategory,
     /// [index]: MIDI channel.
-    /// [return]: 1 if `MidiProgramName` or `MidiKeyName` has changed. //TODO: Implement
+    /// [return]: 1 if `MidiProgramName` or `MidiKeyName` has changed.
     HasMidiProgramsChanged,
     /// [index]: MIDI channel.
-    /// [ptr]: `*mut MidiKeyName`. //TODO: Implement
+    /// [ptr]: `*mut api::MidiKeyName`.
     /// [return]: 1 = supported 0 = not.
     GetMidiKeyName,
 
@@ -254,8 +281,8 @@ pub enum OpCode {
     /// Called after a preset is loaded.
     EndSetPreset,
 
-    /// [value]: inputs `*mut VstSpeakerArrangement` //TODO: Implement
-    /// [ptr]: Outputs `*mut VstSpeakerArrangement`
+    /// [value]: inputs `*mut *mut api::SpeakerArrangement`.
+    /// [ptr]: outputs `*mut *mut api::SpeakerArrangement`.
     GetSpeakerArrangement,
     /// [ptr]: buffer for plugin name, limited to `consts::MAX_PRODUCT_STR_LEN`.
     /// [return]: next plugin's uniqueID.
@@ -281,6 +308,7 @@ pub enum OpCode {
     BeginLoadPreset,
 
     /// [value]: 0 if 32 bit, anything else if 64 bit.
+    /// [return]: 1 if the precision is supported.
     SetPrecision,
 
     /// [return]: number of used MIDI Inputs (1-15).
@@ -290,6 +318,27 @@ pub enum OpCode {
 }
 impl_clike!(OpCode);
 
+impl OpCode {
+    /// The VST version which introduced this opcode, e.g. 2400 for `SetPrecision`, or 0 for
+    /// opcodes of VST 2.0 and earlier.
+    pub fn api_version(&self) -> i32 {
+        use self::OpCode::*;
+
+        match *self {
+            EditorKeyDown | EditorKeyUp | EditorSetKnobMode | GetMidiProgramName |
+            GetCurrentMidiProgram | GetMidiProgramCategory | HasMidiProgramsChanged |
+            GetMidiKeyName | BeginSetPreset | EndSetPreset => 2100,
+
+            GetSpeakerArrangement | ShellGetNextPlugin | StartProcess | StopProcess |
+            SetTotalSampleToProcess | SetPanLaw | BeginLoadBank | BeginLoadPreset => 2300,
+
+            SetPrecision | GetNumMidiInputs | GetNumMidiOutputs => 2400,
+
+            _ => 0,
+        }
+    }
+}
+
 /// A structure representing static plugin information.
 #[derive(Clone, Debug)]
 pub struct Info {
@@ -337,15 +386,66 @@ pub struct Info {
     /// `{get, load}_{preset, bank}_chunks()` methods. Default is `false`.
     pub preset_chunks: bool,
 
-    /// Indicates whether this plugin can process f64 based `AudioBuffer` buffers.
+    /// Indicates whether this plugin processes f64 based `AudioBuffer` buffers natively.
+    ///
+    /// If this is true, the host is told that the plugin can process double precision buffers.
     ///
-    /// Default is `true`.
+    /// Plugins which do not override `Plugin::process_f64` process `f32` copies of the buffers.
+    /// Clear this to have the host do the conversion instead. Default is `true`.
     pub f64_precision: bool,
 
     /// If this is true, the plugin will not produce sound when the input is silence.
     ///
     /// Default is `false`.
     pub silent_when_stopped: bool,
+
+    /// Indicates that the plugin implements `Plugin::set_bypass`.
+    ///
+    /// The `CanDo::Bypass` query is answered with `Supported::Yes` automatically if this is set.
+    /// Default is `false`.
+    pub soft_bypass: bool,
+
+    /// Indicates that the plugin is an instrument.
+    ///
+    /// Hosts use the resulting `IS_SYNTH` flag to list the plugin as an instrument. This is implied
+    /// by `Category::Synth`. Default is `false`.
+    pub is_synth: bool,
+
+    /// Number of MIDI inputs (1-15) used by the plugin.
+    ///
+    /// If this is non-zero, or the plugin is a synth, the `CanDo::ReceiveEvents` and
+    /// `CanDo::ReceiveMidiEvent` queries are answered with `Supported::Yes` automatically.
+    /// Default is `0`.
+    pub midi_inputs: i32,
+
+    /// Number of MIDI outputs (1-15) used by the plugin.
+    ///
+    /// If this is non-zero, the `CanDo::SendEvents` and `CanDo::SendMidiEvent` queries are
+    /// answered with `Supported::Yes` automatically. Default is `0`.
+    pub midi_outputs: i32,
+
+    /// Enables compatibility with hosts predating VST 2.4.
+    ///
+    /// If set, the deprecated accumulating process function adds the output of `Plugin::process`
+    /// to the host's output buffers, and MIDI is requested with `HostCallback::want_midi` when the
+    /// plugin is resumed if it receives MIDI. Default is `false`.
+    pub legacy_host_support: bool,
+
+    /// Flush denormals to zero while processing.
+    ///
+    /// If set, a `denormal::DenormalGuard` is held during every call to `Plugin::process` and
+    /// `Plugin::process_f64`. Default is `false`.
+    pub flush_denormals: bool,
+}
+
+impl Info {
+    /// Whether the plugin is an instrument, either by category or by `is_synth`.
+    pub fn is_instrument(&self) -> bool {
+        match self.category {
+            Category::Synth => true,
+            _ => self.is_synth,
+        }
+    }
 }
 
 impl Default for Info {
@@ -369,12 +469,241 @@ impl Default for Info {
             preset_chunks: false,
             f64_precision: true,
             silent_when_stopped: false,
+            soft_bypass: false,
+            is_synth: false,
+            midi_inputs: 0,
+            midi_outputs: 0,
+            legacy_host_support: false,
+            flush_denormals: false,
+        }
+    }
+}
+
+/// Sample precision used in process calls. See `Plugin::set_precision`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProcessPrecision {
+    /// `f32` samples, passed to `Plugin::process`.
+    F32,
+    /// `f64` samples, passed to `Plugin::process_f64`.
+    F64,
+}
+
+/// Display properties of a parameter.
+///
+/// Hosts use this to choose an appropriate control for a parameter, e.g. a switch instead of a
+/// knob.
+#[derive(Clone, Debug, Default)]
+pub struct ParameterProperties {
+    /// Long label of the parameter.
+    pub label: String,
+
+    /// Short label of the parameter, limited to `MAX_SHORT_LABEL` characters.
+    pub short_label: String,
+
+    /// Parameter is a switch (on/off).
+    pub is_switch: bool,
+
+    /// Minimum and maximum integer values of the parameter, if it is integer based.
+    pub integer_range: Option<(i32, i32)>,
+
+    /// Step, small step and large step for float parameters.
+    pub float_steps: Option<(f32, f32, f32)>,
+
+    /// Step and large step for integer parameters.
+    pub integer_steps: Option<(i32, i32)>,
+
+    /// Index at which the parameter should be displayed, starting at 0.
+    pub display_index: Option<i16>,
+
+    /// Category this parameter belongs to.
+    pub category: Option<ParameterCategory>,
+
+    /// The parameter value can ramp up and down instead of jumping from one value to another.
+    pub can_ramp: bool,
+}
+
+/// A category grouping parameters together, see `ParameterProperties`.
+#[derive(Clone, Debug)]
+pub struct ParameterCategory {
+    /// Index of the category, starting at 1.
+    pub index: i16,
+
+    /// Number of parameters in this category.
+    pub num_parameters: i16,
+
+    /// Display label of the category, limited to `MAX_CATEGORY_LABEL` characters.
+    pub label: String,
+}
+
+impl From<api::ParameterProperties> for ParameterProperties {
+    fn from(raw: api::ParameterProperties) -> ParameterProperties {
+        use api::flags::*;
+
+        let flags = Parameter::from_bits_truncate(raw.flags);
+
+        ParameterProperties {
+            label: read_fixed(&raw.label),
+            short_label: read_fixed(&raw.short_label),
+
+            is_switch: flags.contains(IS_SWITCH),
+
+            integer_range: if flags.contains(USES_INTEGER_MIN_MAX) {
+                Some((raw.min_integer, raw.max_integer))
+            } else {
+                None
+            },
+            float_steps: if flags.contains(USES_FLOAT_STEP) {
+                Some((raw.step_f32, raw.small_step_f32, raw.large_step_f32))
+            } else {
+                None
+            },
+            integer_steps: if flags.contains(USES_INT_STEP) {
+                Some((raw.step_integer, raw.large_step_integer))
+            } else {
+                None
+            },
+
+            display_index: if flags.contains(SUPPORTS_DISPLAY_INDEX) {
+                Some(raw.display_index)
+            } else {
+                None
+            },
+            category: if flags.contains(SUPPORTS_DISPLAY_CATEGORY) {
+                Some(ParameterCategory {
+                    index: raw.category,
+                    num_parameters: raw.num_parameters_in_category,
+                    label: read_fixed(&raw.category_label),
+                })
+            } else {
+                None
+            },
+
+            can_ramp: flags.contains(CAN_RAMP),
+        }
+    }
+}
+
+impl From<ParameterProperties> for api::ParameterProperties {
+    fn from(properties: ParameterProperties) -> api::ParameterProperties {
+        use api::flags::*;
+
+        let mut raw: api::ParameterProperties = unsafe { mem::zeroed() };
+        let mut flags = Parameter::empty();
+
+        write_fixed(&mut raw.label, &properties.label);
+        write_fixed(&mut raw.short_label, &properties.short_label);
+
+        if properties.is_switch {
+            flags.insert(IS_SWITCH);
+        }
+        if let Some((min, max)) = properties.integer_range {
+            raw.min_integer = min;
+            raw.max_integer = max;
+            flags.insert(USES_INTEGER_MIN_MAX);
+        }
+        if let Some((step, small_step, large_step)) = properties.float_steps {
+            raw.step_f32 = step;
+            raw.small_step_f32 = small_step;
+            raw.large_step_f32 = large_step;
+            flags.insert(USES_FLOAT_STEP);
+        }
+        if let Some((step, large_step)) = properties.integer_steps {
+            raw.step_integer = step;
+            raw.large_step_integer = large_step;
+            flags.insert(USES_INT_STEP);
         }
+        if let Some(index) = properties.display_index {
+            raw.display_index = index;
+            flags.insert(SUPPORTS_DISPLAY_INDEX);
+        }
+        if let Some(category) = properties.category {
+            raw.category = category.index;
+            raw.num_parameters_in_category = category.num_parameters;
+            write_fixed(&mut raw.category_label, &category.label);
+            flags.insert(SUPPORTS_DISPLAY_CATEGORY);
+        }
+        if properties.can_ramp {
+            flags.insert(CAN_RAMP);
+        }
+
+        raw.flags = flags.bits();
+        raw
+    }
+}
+
+/// A MIDI program (patch) of an instrument plugin.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MidiProgram {
+    /// Name of the program, limited to `MAX_NAME_LEN` characters.
+    pub name: String,
+
+    /// MIDI program number (0-127) which selects this program.
+    pub program: Option<u8>,
+
+    /// MIDI bank select MSB (0-127) which selects the bank of this program.
+    pub bank_msb: Option<u8>,
+
+    /// MIDI bank select LSB (0-127) which selects the bank of this program.
+    pub bank_lsb: Option<u8>,
+
+    /// Index of the `MidiProgramCategory` this program belongs to.
+    pub category: Option<i32>,
+
+    /// The program ignores the MIDI channel.
+    pub omni: bool,
+}
+
+impl MidiProgram {
+    /// Fill a raw `api::MidiProgramName` for the program at `index`.
+    #[doc(hidden)]
+    pub fn write_raw(&self, index: i32, raw: &mut api::MidiProgramName) {
+        let byte = |value: Option<u8>| value.map_or(-1, |v| (v & 0x7F) as i8);
+
+        raw.this_program_index = index;
+        write_fixed(&mut raw.name, &self.name);
+        raw.midi_program = byte(self.program);
+        raw.midi_bank_msb = byte(self.bank_msb);
+        raw.midi_bank_lsb = byte(self.bank_lsb);
+        raw._reserved = 0;
+        raw.parent_category_index = self.category.unwrap_or(-1);
+        raw.flags = if self.omni { api::flags::IS_OMNI.bits() } else { 0 };
     }
 }
 
+impl<'a> From<&'a api::MidiProgramName> for MidiProgram {
+    fn from(raw: &'a api::MidiProgramName) -> MidiProgram {
+        let byte = |value: i8| if value < 0 { None } else { Some(value as u8) };
+
+        MidiProgram {
+            name: read_fixed(&raw.name),
+            program: byte(raw.midi_program),
+            bank_msb: byte(raw.midi_bank_msb),
+            bank_lsb: byte(raw.midi_bank_lsb),
+            category: if raw.parent_category_index < 0 {
+                None
+            } else {
+                Some(raw.parent_category_index)
+            },
+            omni: api::flags::MidiProgramName::from_bits_truncate(raw.flags)
+                                                .contains(api::flags::IS_OMNI),
+        }
+    }
+}
+
+/// A category grouping MIDI programs together, see `MidiProgram`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MidiProgramCategory {
+    /// Name of the category, limited to `MAX_NAME_LEN` characters.
+    pub name: String,
+
+    /// Index of the parent category, if this is a sub category.
+    pub parent: Option<i32>,
+}
+
 /// Features which are optionally supported by a plugin. These are queried by the host at run time.
-#[derive(Debug)]
+///
+/// Some of these are answered automatically from the plugin's `Info`, see `Plugin::can_do`.
+#[derive(Clone, Debug, PartialEq)]
 #[allow(missing_docs)]
 pub enum CanDo {
     SendEvents,
@@ -386,6 +715,14 @@ pub enum CanDo {
     MidiProgramNames,
     Bypass,
 
+    PlugAsChannelInsert,
+    PlugAsSend,
+    MixDryWet,
+    NoRealTime,
+    Multipass,
+    Metapass,
+    ConformsToWindowRules,
+
     //Bitwig specific?
     ReceiveSysexEvent,
     MidiSingleNoteTuningChange,
@@ -394,6 +731,63 @@ pub enum CanDo {
     Other(String)
 }
 
+impl CanDo {
+    /// The string used for this feature by the VST api, e.g. `"receiveVstMidiEvent"`.
+    pub fn as_str(&self) -> &str {
+        use self::CanDo::*;
+
+        match *self {
+            SendEvents => "sendVstEvents",
+            SendMidiEvent => "sendVstMidiEvent",
+            ReceiveEvents => "receiveVstEvents",
+            ReceiveMidiEvent => "receiveVstMidiEvent",
+            ReceiveTimeInfo => "receiveVstTimeInfo",
+            Offline => "offline",
+            MidiProgramNames => "midiProgramNames",
+            Bypass => "bypass",
+
+            PlugAsChannelInsert => "plugAsChannelInsert",
+            PlugAsSend => "plugAsSend",
+            MixDryWet => "mixDryWet",
+            NoRealTime => "noRealTime",
+            Multipass => "multipass",
+            Metapass => "metapass",
+            ConformsToWindowRules => "conformsToWindowRules",
+
+            ReceiveSysexEvent => "receiveVstSysexEvent",
+            MidiSingleNoteTuningChange => "midiSingleNoteTuningChange",
+            MidiKeyBasedInstrumentControl => "midiKeyBasedInstrumentControl",
+            Other(ref other) => other,
+        }
+    }
+
+    /// Answer this query from the plugin's `Info` and bus layout, or return `None` if it must be
+    /// asked.
+    #[doc(hidden)]
+    pub fn answer(&self, info: &Info, layout: Option<&BusLayout>) -> Option<Supported> {
+        use self::CanDo::*;
+
+        let sidechain = layout.map_or(false, |layout| layout.has_sidechain());
+
+        match *self {
+            Bypass if info.soft_bypass => Some(Supported::Yes),
+            ReceiveEvents | ReceiveMidiEvent if info.midi_inputs > 0 || info.is_instrument() => {
+                Some(Supported::Yes)
+            }
+            SendEvents | SendMidiEvent if info.midi_outputs > 0 => Some(Supported::Yes),
+            PlugAsChannelInsert if sidechain => Some(Supported::Yes),
+            PlugAsSend if sidechain => Some(Supported::No),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CanDo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 use std::str::FromStr;
 impl FromStr for CanDo {
     type Err = String;
@@ -411,6 +805,14 @@ impl FromStr for CanDo {
             "midiProgramNames" => MidiProgramNames,
             "bypass" => Bypass,
 
+            "plugAsChannelInsert" => PlugAsChannelInsert,
+            "plugAsSend" => PlugAsSend,
+            "mixDryWet" => MixDryWet,
+            "noRealTime" => NoRealTime,
+            "multipass" => Multipass,
+            "metapass" => Metapass,
+            "conformsToWindowRules" => ConformsToWindowRules,
+
             "receiveVstSysexEvent" => ReceiveSysexEvent,
             "midiSingleNoteTuningChange" => MidiSingleNoteTuningChange,
             "midiKeyBasedInstrumentControl" => MidiKeyBasedInstrumentControl,
@@ -472,9 +874,30 @@ pub trait Plugin {
         Default::default()
     }
 
+    /// Called when the host opens the plugin, which may be a while after it was created, e.g.
+    /// when a host only scans the plugin's info. Heavy allocations and resource loading should be
+    /// deferred to this point.
+    ///
+    /// The default implementation calls `init`.
+    fn open(&mut self) { self.init(); }
+
     /// Called when plugin is fully initialized.
+    ///
+    /// This is called by the default implementation of `open`, so plugins can use either.
     fn init(&mut self) { trace!("Initialized vst plugin."); }
 
+    /// Called when the host closes the plugin, right before it is dropped.
+    ///
+    /// Unlike `Drop`, the host can still be called here, e.g. to stop receiving events.
+    fn close(&mut self) { trace!("Closing vst plugin."); }
+
+    /// Idle call from the host, for low priority work outside the audio thread (e.g. loading
+    /// samples in the background).
+    ///
+    /// Only called by hosts supporting the deprecated idle opcodes, and only after
+    /// `HostCallback::need_idle` was called. Idle calls for the editor are sent to `Editor::idle`.
+    fn idle(&mut self) { }
+
 
     /// Set the current preset to the index specified by `preset`.
     fn change_preset(&mut self, preset: i32) { }
@@ -483,11 +906,20 @@ pub trait Plugin {
     fn get_preset_num(&self) -> i32 { 0 }
 
     /// Set the current preset name.
-    fn set_preset_name(&self, name: String) { }
+    fn set_preset_name(&mut self, name: String) { }
 
     /// Get the name of the preset at the index specified by `preset`.
     fn get_preset_name(&self, preset: i32) -> String { "".to_string() }
 
+    /// Called before the host changes the current preset or loads preset data.
+    ///
+    /// Together with `end_set_preset` this brackets all parameter changes belonging to the preset
+    /// change, e.g. to avoid recalculating internal state for every parameter.
+    fn begin_set_preset(&mut self) { }
+
+    /// Called after the host has changed the current preset or loaded preset data.
+    fn end_set_preset(&mut self) { }
+
 
     /// Get parameter label for parameter at `index` (e.g. "db", "sec", "ms", "%").
     fn get_parameter_label(&self, index: i32) -> String { "".to_string() }
@@ -507,12 +939,25 @@ pub trait Plugin {
     fn set_parameter(&mut self, index: i32, value: f32) { }
 
     /// Return whether parameter at `index` can be automated.
-    fn can_be_automated(&self, index: i32) -> bool { false }
+    ///
+    /// All parameters can be automated by default. Override this to hide meta-parameters, e.g. a
+    /// preset selector or an output meter, from the host's automation lists.
+    fn can_be_automated(&self, index: i32) -> bool { true }
+
+    /// Return the display properties of the parameter at `index`, or `None` if the plugin doesn't
+    /// provide any for it.
+    ///
+    /// Hosts use these to build better generic editors, e.g. showing a switch for an on/off
+    /// parameter or grouping parameters by category.
+    fn get_parameter_properties(&self, index: i32) -> Option<ParameterProperties> { None }
 
     /// Use String as input for parameter value. Used by host to provide an editable field to
     /// adjust a parameter value. E.g. "100" may be interpreted as 100hz for parameter. Returns if
     /// the input string was used.
-    fn string_to_parameter(&self, index: i32, text: String) -> bool { false }
+    ///
+    /// The plugin is expected to parse `text` (e.g. "440 Hz" or "-12 dB") and set the parameter at
+    /// `index` accordingly.
+    fn string_to_parameter(&mut self, index: i32, text: String) -> bool { false }
 
 
     /// Called when sample rate is changed by host.
@@ -530,21 +975,46 @@ pub trait Plugin {
 
 
     /// Vendor specific handling.
-    fn vendor_specific(&mut self, index: i32, value: isize, ptr: *mut c_void, opt: f32) { }
+    ///
+    /// Hosts use this for extensions outside the VST API, e.g. Cockos extensions in Reaper. The
+    /// meaning of the arguments and of the returned value depend on the extension. Return `0` for
+    /// unknown requests.
+    fn vendor_specific(&mut self, index: i32, value: isize, ptr: *mut c_void, opt: f32) -> isize { 0 }
 
 
+    /// Enable or disable the soft bypass of the plugin.
+    ///
+    /// While bypassed, `process` is still called and the plugin should pass its input through
+    /// with the same latency, fading in and out to avoid clicks. Return `true` if the bypass is
+    /// handled by the plugin, and set `Info::soft_bypass` so the host knows before asking.
+    fn set_bypass(&mut self, bypass: bool) -> bool { false }
+
     /// Return whether plugin supports specified action.
+    ///
+    /// `CanDo::Bypass` is answered with `Supported::Yes` if `Info::soft_bypass` is set, and
+    /// `CanDo::ReceiveEvents` and `CanDo::ReceiveMidiEvent` if the plugin is a synth. Plugins with
+    /// a sidechain bus in `get_bus_layout` answer that they can be used as an insert but not on a
+    /// send. This method is only called for the remaining queries.
     fn can_do(&self, can_do: CanDo) -> Supported {
         info!("Host is asking if plugin can: {:?}.", can_do);
         Supported::Maybe
     }
 
-    /// Get the tail size of plugin when it is stopped. Used in offline processing as well.
+    /// Get the length of the tail in samples, i.e. how long the plugin keeps producing output
+    /// after its input has become silent. Used in offline processing as well.
+    ///
+    /// Hosts use this to extend render lengths and to decide when processing can be suspended.
+    /// Reverbs and delays should override this. 0 is the default and leaves the tail to the host,
+    /// 1 means no tail.
     fn get_tail_size(&self) -> isize { 0 }
 
 
     /// Process an audio buffer containing `f32` values.
     ///
+    /// The outputs are passed exactly as the host provides them and are never cleared by this
+    /// library, so they may hold stale data. Every output sample must be written; use
+    /// `AudioBuffer::clear_outputs` first if the plugin only writes some of them.
+    ///
     /// # Example
     /// ```no_run
     /// # use vst2::plugin::{Info, Plugin};
@@ -555,8 +1025,8 @@ pub trait Plugin {
     /// #     fn get_info(&self) -> Info { Default::default() }
     /// #
     /// // Processor that clips samples above 0.4 or below -0.4:
-    /// fn process(&mut self, buffer: AudioBuffer<f32>){
-    ///     let (inputs, mut outputs) = buffer.split();
+    /// fn process(&mut self, mut buffer: AudioBuffer<f32>){
+    ///     let (inputs, mut outputs) = buffer.split_mut();
     ///
     ///     for (channel, ibuf) in inputs.iter().enumerate() {
     ///         for (i, sample) in ibuf.iter().enumerate() {
@@ -584,6 +1054,8 @@ pub trait Plugin {
 
     /// Process an audio buffer containing `f64` values.
     ///
+    /// As for `process`, the outputs are not cleared before this is called.
+    ///
     /// # Example
     /// ```no_run
     /// # use vst2::plugin::{Info, Plugin};
@@ -594,8 +1066,8 @@ pub trait Plugin {
     /// #     fn get_info(&self) -> Info { Default::default() }
     /// #
     /// // Processor that clips samples above 0.4 or below -0.4:
-    /// fn process_f64(&mut self, buffer: AudioBuffer<f64>){
-    ///     let (inputs, mut outputs) = buffer.split();
+    /// fn process_f64(&mut self, mut buffer: AudioBuffer<f64>){
+    ///     let (inputs, mut outputs) = buffer.split_mut();
     ///
     ///     for (channel, ibuf) in inputs.iter().enumerate() {
     ///         for (i, sample) in ibuf.iter().enumerate() {
@@ -611,19 +1083,100 @@ pub trait Plugin {
     /// }
     /// # }
     /// ```
+    ///
+    /// The default implementation converts the buffer to `f32`, calls `process` and converts the
+    /// result back. Buffers passed by the host carry memory for the conversion which is allocated
+    /// on resume, so it does not allocate while processing. Buffers created otherwise, e.g. in
+    /// tests, do not, and the conversion allocates.
     fn process_f64(&mut self, buffer: AudioBuffer<f64>) {
-        // For each input and output
-        for (input, output) in buffer.zip() {
-            // For each input sample and output sample in buffer
-            for (in_frame, out_frame) in input.into_iter().zip(output.into_iter()) {
-                *out_frame = *in_frame;
-            }
-        }
+        interfaces::process_converted(self, buffer);
+    }
+
+    /// Called when the host selects the precision of the following process calls.
+    ///
+    /// `ProcessPrecision::F64` is only selected if `Info::f64_precision` is set.
+    fn set_precision(&mut self, precision: ProcessPrecision) {}
+
+    /// Handle incoming events sent from the host.
+    ///
+    /// This is always called before the start of `process` or `process_f64`. The events are only
+    /// valid for the duration of this call.
+    ///
+    /// Events are sorted by their delta frames, even if the host sent them out of order. Events on
+    /// the same sample keep the order they were sent in.
+    fn process_events(&mut self, events: &[Event]) {}
+
+    /// Return the MIDI programs available on MIDI `channel` (0-15).
+    ///
+    /// Instruments with a bank or patch list return it here so the host can show program names
+    /// instead of numbers. An empty list means MIDI program names are not supported.
+    fn get_midi_programs(&self, channel: i32) -> Vec<MidiProgram> { Vec::new() }
+
+    /// Return the index of the current MIDI program on MIDI `channel` in the list returned by
+    /// `get_midi_programs`.
+    fn get_current_midi_program(&self, channel: i32) -> Option<i32> { None }
+
+    /// Return the categories referenced by the MIDI programs on MIDI `channel`.
+    fn get_midi_program_categories(&self, channel: i32) -> Vec<MidiProgramCategory> { Vec::new() }
+
+    /// Return whether the MIDI programs or key names on MIDI `channel` changed since they were
+    /// last requested.
+    fn has_midi_programs_changed(&self, channel: i32) -> bool { false }
+
+    /// Return the name of MIDI `key` (0-127) in the MIDI program at `program` on `channel`, e.g.
+    /// "Snare" for a drum kit.
+    fn get_midi_key_name(&self, channel: i32, program: i32, key: i32) -> Option<String> { None }
+
+    /// Called by offline hosts when the audio files available to the plugin change.
+    ///
+    /// `start` is true if the plugin should start processing them, e.g. because the user selected
+    /// it from a menu. Return `true` if the files were handled. Offline plugins should answer
+    /// `CanDo::Offline` with `Supported::Yes`.
+    fn offline_notify(&mut self, files: &mut [api::AudioFile], start: bool) -> bool { false }
+
+    /// Prepare offline processing of `tasks`, e.g. by setting their process names, output sample
+    /// rates and channel counts. Return `true` if the tasks can be run.
+    fn offline_prepare(&mut self, tasks: &mut [api::OfflineTask]) -> bool { false }
+
+    /// Run offline processing of `tasks`, reading from and writing to their buffers. Return `true`
+    /// on success.
+    fn offline_run(&mut self, tasks: &mut [api::OfflineTask]) -> bool { false }
+
+    /// Process buffers where the number of input samples consumed and output samples produced
+    /// may differ, e.g. for time stretching.
+    ///
+    /// Report the consumed and produced sample counts with `VariableIo::set_processed`. Return
+    /// `true` if variable I/O is supported.
+    fn process_var_io(&mut self, io: VariableIo) -> bool { false }
+
+    /// Called when the host proposes speaker arrangements for the inputs and outputs.
+    ///
+    /// Return `true` to accept them, in which case `get_info` must report the new number of
+    /// inputs and outputs from then on. Plugins which change their arrangement on their own should
+    /// call `Host::io_changed` instead.
+    fn set_speaker_arrangement(&mut self,
+                               input: SpeakerArrangement,
+                               output: SpeakerArrangement)
+                               -> bool {
+        false
+    }
+
+    /// Return the current input and output speaker arrangements, or `None` if not supported.
+    ///
+    /// By default these are derived from `get_bus_layout`.
+    fn get_speaker_arrangement(&self) -> Option<(SpeakerArrangement, SpeakerArrangement)> {
+        self.get_bus_layout().map(|l| l.speaker_arrangements())
     }
 
     /// Return handle to plugin editor if supported.
     fn get_editor(&mut self) -> Option<&mut Editor> { None }
 
+    /// Return the unique ID and name of the next plugin contained in this shell plugin, or `None`
+    /// if all were reported.
+    ///
+    /// This is only used by shell plugins, which are best created with `plugin_shell_main!`.
+    fn next_shell_plugin(&mut self) -> Option<(i32, String)> { None }
+
 
     /// If `preset_chunks` is set to true in plugin info, this should return the raw chunk data for
     /// the current preset.
@@ -641,18 +1194,31 @@ pub trait Plugin {
     /// given chunk data.
     fn load_bank_data(&mut self, data: Vec<u8>) {}
 
+    /// Get the named input and output buses of the plugin, if it declares any.
+    ///
+    /// If a layout is returned, the number of channels reported to the host is taken from it
+    /// instead of `Info`, and the default channel info and speaker arrangements are derived from
+    /// it.
+    fn get_bus_layout(&self) -> Option<&BusLayout> { None }
+
     /// Get information about an input channel. Only used by some hosts.
     fn get_input_info(&self, input: i32) -> ChannelInfo {
-        ChannelInfo::new(format!("Input channel {}", input),
-                         Some(format!("In {}", input)),
-                         true, None)
+        match self.get_bus_layout().and_then(|l| l.input_info(input as usize)) {
+            Some(info) => info,
+            None => ChannelInfo::new(format!("Input channel {}", input),
+                                     Some(format!("In {}", input)),
+                                     true, None),
+        }
     }
 
     /// Get information about an output channel. Only used by some hosts.
     fn get_output_info(&self, output: i32) -> ChannelInfo {
-        ChannelInfo::new(format!("Output channel {}", output),
-                         Some(format!("Out {}", output)),
-                         true, None)
+        match self.get_bus_layout().and_then(|l| l.output_info(output as usize)) {
+            Some(info) => info,
+            None => ChannelInfo::new(format!("Output channel {}", output),
+                                     Some(format!("Out {}", output)),
+                                     true, None),
+        }
     }
 }
 
@@ -720,6 +1286,7 @@ impl HostCallback {
     #[doc(hidden)]
     fn is_effect_valid(&self) -> bool {
         // Check whether `effect` points to a valid AEffect struct
+        !self.effect.is_null() &&
         unsafe { *mem::transmute::<*mut AEffect, *mut i32>(self.effect) == VST_MAGIC }
     }
 
@@ -732,11 +1299,71 @@ impl HostCallback {
         }
     }
 
+    /// Ask the host to resize the editor window to `width` x `height`, e.g. for a resizable
+    /// editor.
+    ///
+    /// Until `Editor::size` reports the new size, it is also returned when the host queries the
+    /// editor rect, so hosts querying it while resizing get a consistent answer. Returns `false` if
+    /// the host refused to resize the window.
+    pub fn resize_editor(&mut self, width: i32, height: i32) -> bool {
+        if !self.is_effect_valid() {
+            return false;
+        }
+
+        // No reference into the effect data is held while calling the host, as hosts query the
+        // editor rect while resizing
+        unsafe { interfaces::editor_size(self.effect) }.set(Some((width, height)));
+
+        let resized = self.size_window(width, height);
+        if !resized {
+            unsafe { interfaces::editor_size(self.effect) }.set(None);
+        }
+        resized
+    }
+
+    /// Ask the host to send MIDI events to the plugin.
+    ///
+    /// Only hosts predating VST 2.4 need this, and it is called automatically if
+    /// `Info::legacy_host_support` is set.
+    pub fn want_midi(&mut self) -> bool {
+        self.callback(self.effect, host::OpCode::WantMidi,
+                      0, 1, ptr::null_mut(), 0.0) != 0
+    }
+
+    /// Alias of [`update_display`](../host/trait.Host.html#method.update_display), to call after
+    /// the plugin changed its current preset itself, e.g. from a preset menu in the editor. It
+    /// sends the same request, after which the host refreshes its preset and parameter displays.
+    ///
+    /// Returns `false` if the host did not handle the request.
+    pub fn preset_changed(&mut self) -> bool {
+        self.update_display()
+    }
+
     /// Get the VST API version supported by the host e.g. `2400 = VST 2.4`.
     pub fn vst_version(&self) -> i32 {
         self.callback(self.effect, host::OpCode::Version,
                       0, 0, ptr::null_mut(), 0.0) as i32
     }
+
+    /// Get the VST API version used between the host and the plugin, which is the lower of
+    /// `vst_version` and `consts::VST_VERSION`.
+    ///
+    /// Plugins can use this to adapt to older hosts, e.g. VST 2.3 hosts which never call
+    /// `Plugin::process_f64` or `Plugin::set_precision`.
+    pub fn api_version(&self) -> i32 {
+        cmp::min(self.vst_version(), VST_VERSION)
+    }
+
+    /// Send all events of `events` to the host in a single block and clear it.
+    ///
+    /// Nothing is allocated, so this is safe to call from `process`.
+    pub fn send_events(&self, events: &mut SendEventBuffer) {
+        if !events.is_empty() {
+            self.callback(self.effect, host::OpCode::ProcessEvents,
+                          0, 0, events.as_raw() as *mut c_void, 0.0);
+        }
+        events.clear();
+    }
 }
 
 impl Host for HostCallback {
@@ -747,6 +1374,23 @@ impl Host for HostCallback {
         }
     }
 
+    /// Signal the start of a user gesture on the parameter at `index`, e.g. when a knob in the
+    /// editor is grabbed.
+    ///
+    /// Calls to `automate` until the matching `end_edit` are recorded as a single gesture, which
+    /// hosts need for touch and latch automation modes.
+    fn begin_edit(&mut self, index: i32) -> bool {
+        self.callback(self.effect, host::OpCode::BeginEdit,
+                      index, 0, ptr::null_mut(), 0.0) != 0
+    }
+
+    /// Signal the end of a user gesture on the parameter at `index`, e.g. when a knob in the
+    /// editor is released.
+    fn end_edit(&mut self, index: i32) -> bool {
+        self.callback(self.effect, host::OpCode::EndEdit,
+                      index, 0, ptr::null_mut(), 0.0) != 0
+    }
+
     fn get_plugin_id(&self) -> i32 {
         self.callback(self.effect, host::OpCode::CurrentId,
                       0, 0, ptr::null_mut(), 0.0) as i32
@@ -756,6 +1400,111 @@ impl Host for HostCallback {
         self.callback(self.effect, host::OpCode::Idle,
                       0, 0, ptr::null_mut(), 0.0);
     }
+
+    fn io_changed(&mut self) -> bool {
+        self.callback(self.effect, host::OpCode::IOChanged,
+                      0, 0, ptr::null_mut(), 0.0) != 0
+    }
+
+    /// Request regular calls to `Plugin::idle` from the host.
+    ///
+    /// This is deprecated in VST 2.4, so not all hosts support it.
+    fn need_idle(&mut self) -> bool {
+        self.callback(self.effect, host::OpCode::NeedIdle,
+                      0, 0, ptr::null_mut(), 0.0) != 0
+    }
+
+    /// Request time information from the host.
+    ///
+    /// `mask` is a bitmask of `flags::TimeInfo` values requested by the plugin, e.g.
+    /// `TEMPO_VALID | PPQ_POS_VALID`. Returns `None` if the host does not provide time info. The
+    /// flags of the returned value indicate which fields are valid.
+    fn get_time_info(&self, mask: i32) -> Option<api::TimeInfo> {
+        let ptr = self.callback(self.effect, host::OpCode::GetTime,
+                                0, mask as isize, ptr::null_mut(), 0.0) as *const api::TimeInfo;
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { *ptr })
+        }
+    }
+
+    fn get_sample_rate(&self) -> f32 {
+        self.callback(self.effect, host::OpCode::GetSampleRate,
+                      0, 0, ptr::null_mut(), 0.0) as f32
+    }
+
+    fn get_block_size(&self) -> i64 {
+        self.callback(self.effect, host::OpCode::GetBlockSize,
+                      0, 0, ptr::null_mut(), 0.0) as i64
+    }
+
+    fn update_display(&mut self) -> bool {
+        self.callback(self.effect, host::OpCode::UpdateDisplay,
+                      0, 0, ptr::null_mut(), 0.0) != 0
+    }
+
+    fn size_window(&mut self, width: i32, height: i32) -> bool {
+        self.callback(self.effect, host::OpCode::SizeWindow,
+                      width, height as isize, ptr::null_mut(), 0.0) != 0
+    }
+
+    /// Send MIDI and SysEx events to the host.
+    ///
+    /// This is safe to call from `process`: the raw `VstEvents` block is built on the stack, so
+    /// nothing is allocated. More than `SEND_EVENTS_CHUNK` events are sent in several consecutive
+    /// blocks. `Event::Deprecated` events are skipped.
+    ///
+    /// Plugins sending events should report `CanDo::SendEvents` and `CanDo::SendMidiEvent` as
+    /// supported.
+    fn process_events(&mut self, events: &[Event]) {
+        for chunk in events.chunks(SEND_EVENTS_CHUNK) {
+            let empty = MidiEvent::default();
+            let mut midi = [api::MidiEvent::from(empty); SEND_EVENTS_CHUNK];
+            let mut sysex = [api::SysExEvent::from(SysExEvent { payload: &[], delta_frames: 0 });
+                             SEND_EVENTS_CHUNK];
+            let mut block = RawSendEvents {
+                num_events: 0,
+                _reserved: 0,
+                events: [ptr::null_mut(); SEND_EVENTS_CHUNK],
+            };
+
+            for (i, event) in chunk.iter().enumerate() {
+                let raw = match *event {
+                    Event::Midi(event) => {
+                        midi[i] = event.into();
+                        &mut midi[i] as *mut _ as *mut api::Event
+                    }
+                    Event::SysEx(event) => {
+                        sysex[i] = event.into();
+                        &mut sysex[i] as *mut _ as *mut api::Event
+                    }
+                    Event::Deprecated(_) => continue,
+                };
+
+                block.events[block.num_events as usize] = raw;
+                block.num_events += 1;
+            }
+
+            if block.num_events > 0 {
+                self.callback(self.effect, host::OpCode::ProcessEvents,
+                              0, 0, &mut block as *mut _ as *mut c_void, 0.0);
+            }
+        }
+    }
+}
+
+/// Maximum number of events sent to the host in a single `ProcessEvents` call by
+/// `HostCallback::process_events`.
+pub const SEND_EVENTS_CHUNK: usize = 64;
+
+/// Layout compatible version of `api::Events` with room for `SEND_EVENTS_CHUNK` events.
+#[repr(C)]
+struct RawSendEvents {
+    num_events: i32,
+    _reserved: isize,
+    events: [*mut api::Event; SEND_EVENTS_CHUNK],
 }
 
 #[cfg(test)]
@@ -772,7 +1521,8 @@ mod tests {
             use libc::c_void;
 
             use main;
-            use api::AEffect;
+            use api::{self, AEffect, TimeInfo};
+            use event::{Event, MidiEvent, SysExEvent};
             use host::{Host, OpCode};
             use plugin::{HostCallback, Info, Plugin};
 
@@ -799,9 +1549,21 @@ mod tests {
                     info!("Loaded with host vst version: {}", self.host.vst_version());
                     assert_eq!(2400, self.host.vst_version());
                     assert_eq!(9876, self.host.get_plugin_id());
+                    assert_eq!(44100.0, self.host.get_sample_rate());
+                    assert_eq!(512, self.host.get_block_size());
+                    assert!(self.host.update_display());
+                    assert_eq!(128.0, self.host.get_time_info(0).unwrap().tempo);
                     // Callback will assert these.
+                    assert!(self.host.begin_edit(123));
                     self.host.automate(123, 12.3);
+                    assert!(self.host.end_edit(123));
                     self.host.idle();
+                    assert!(self.host.need_idle());
+                    self.host.process_events(&[
+                        Event::Midi(MidiEvent { data: [0x90, 60, 100], delta_frames: 10,
+                                               ..Default::default() }),
+                        Event::SysEx(SysExEvent { payload: &[0xF0, 0x01, 0xF7], delta_frames: 20 }),
+                    ]);
                 }
             }
 
@@ -811,7 +1573,7 @@ mod tests {
                                  opcode: i32,
                                  index: i32,
                                  _value: isize,
-                                 _ptr: *mut c_void,
+                                 ptr: *mut c_void,
                                  opt: f32)
                                  -> isize {
                     let opcode = OpCode::from(opcode);
@@ -821,9 +1583,41 @@ mod tests {
                             assert_eq!(opt, 12.3);
                             0
                         }
+                        OpCode::BeginEdit | OpCode::EndEdit => {
+                            assert_eq!(index, 123);
+                            1
+                        }
                         OpCode::Version => 2400,
                         OpCode::CurrentId => 9876,
                         OpCode::Idle => 0,
+                        OpCode::NeedIdle => 1,
+                        OpCode::ProcessEvents => {
+                            let events = unsafe {
+                                Event::from_raw_events(ptr as *const api::Events)
+                            };
+                            assert_eq!(events.len(), 2);
+                            match (events[0], events[1]) {
+                                (Event::Midi(midi), Event::SysEx(sysex)) => {
+                                    assert_eq!(midi.data, [0x90, 60, 100]);
+                                    assert_eq!(midi.delta_frames, 10);
+                                    assert_eq!(sysex.payload, &[0xF0, 0x01, 0xF7]);
+                                    assert_eq!(sysex.delta_frames, 20);
+                                }
+                                _ => panic!("Unexpected events."),
+                            }
+                            1
+                        }
+                        OpCode::GetSampleRate => 44100,
+                        OpCode::GetBlockSize => 512,
+                        OpCode::UpdateDisplay => 1,
+                        OpCode::GetTime => {
+                            // Stays valid after returning, like the time info of a real host
+                            thread_local! {
+                                static TIME_INFO: TimeInfo =
+                                    TimeInfo { tempo: 128.0, ..Default::default() };
+                            }
+                            TIME_INFO.with(|info| info as *const TimeInfo as isize)
+                        }
                         _ => 0
                     }
                 }
@@ -855,6 +1649,119 @@ mod tests {
         TestPlugin::default();
     }
 
+    /// Test that a host callback which was never initialized refuses requests instead of
+    /// dereferencing its null effect.
+    #[test]
+    fn default_host_callback() {
+        use host::Host;
+        use plugin::HostCallback;
+
+        let mut host = HostCallback::default();
+        assert!(!host.resize_editor(640, 480));
+        host.automate(0, 0.5);
+    }
+
+    /// Test that `CanDo` strings are parsed and written back unchanged.
+    #[test]
+    fn can_do_strings() {
+        use plugin::CanDo;
+
+        for s in &["receiveVstMidiEvent", "bypass", "plugAsSend", "somethingElse"] {
+            let can_do: CanDo = s.parse().unwrap();
+            assert_eq!(can_do.as_str(), *s);
+        }
+        assert_eq!("offline".parse::<CanDo>().unwrap(), CanDo::Offline);
+    }
+
+    /// Test that queries are answered from the plugin info where possible.
+    #[test]
+    fn can_do_answers() {
+        use api::Supported;
+        use bus::{Bus, BusLayout};
+        use plugin::{CanDo, Category, Info};
+
+        let synth = Info { category: Category::Synth, soft_bypass: true, ..Default::default() };
+        assert_eq!(CanDo::ReceiveMidiEvent.answer(&synth, None), Some(Supported::Yes));
+        assert_eq!(CanDo::Bypass.answer(&synth, None), Some(Supported::Yes));
+        assert_eq!(CanDo::Offline.answer(&synth, None), None);
+
+        let effect = Info::default();
+        assert_eq!(CanDo::ReceiveMidiEvent.answer(&effect, None), None);
+        assert_eq!(CanDo::Bypass.answer(&effect, None), None);
+
+        let arpeggiator = Info { is_synth: true, midi_outputs: 1, ..Default::default() };
+        assert_eq!(CanDo::ReceiveEvents.answer(&arpeggiator, None), Some(Supported::Yes));
+        assert_eq!(CanDo::SendMidiEvent.answer(&arpeggiator, None), Some(Supported::Yes));
+        assert_eq!(CanDo::SendMidiEvent.answer(&synth, None), None);
+
+        let layout = BusLayout::new(vec![Bus::new("Main", 2), Bus::sidechain("Key", 1)],
+                                    vec![Bus::new("Main", 2)]);
+        assert_eq!(CanDo::PlugAsChannelInsert.answer(&effect, Some(&layout)),
+                   Some(Supported::Yes));
+        assert_eq!(CanDo::PlugAsSend.answer(&effect, Some(&layout)), Some(Supported::No));
+        assert_eq!(CanDo::PlugAsSend.answer(&effect, None), None);
+    }
+
+    /// Test that `process_f64` falls back to `process` by default.
+    #[test]
+    fn process_f64_fallback() {
+        use buffer::AudioBuffer;
+        use plugin::{Info, Plugin};
+
+        struct HalfPlugin;
+
+        impl Plugin for HalfPlugin {
+            fn get_info(&self) -> Info { Default::default() }
+
+            fn process(&mut self, buffer: AudioBuffer<f32>) {
+                for (input, output) in buffer.zip() {
+                    for (i, o) in input.iter().zip(output.iter_mut()) {
+                        *o = *i * 0.5;
+                    }
+                }
+            }
+        }
+
+        let mut input = vec![1.0, -0.5, 0.25];
+        let mut output = vec![0.0; 3];
+        HalfPlugin.process_f64(AudioBuffer::new(vec![&mut input], vec![&mut output]));
+
+        assert_eq!(output, vec![0.5, -0.25, 0.125]);
+    }
+
+    /// Test that parameter properties survive the conversion to and from the raw struct.
+    #[test]
+    fn parameter_properties() {
+        use api;
+        use plugin::{ParameterCategory, ParameterProperties};
+
+        let raw: api::ParameterProperties = ParameterProperties {
+            label: "Waveform".to_string(),
+            short_label: "Wave".to_string(),
+            integer_range: Some((0, 3)),
+            display_index: Some(2),
+            category: Some(ParameterCategory {
+                index: 1,
+                num_parameters: 4,
+                label: "Osc 1".to_string(),
+            }),
+            ..Default::default()
+        }.into();
+
+        let properties = ParameterProperties::from(raw);
+        assert_eq!(properties.label, "Waveform");
+        assert_eq!(properties.short_label, "Wave");
+        assert!(!properties.is_switch);
+        assert_eq!(properties.integer_range, Some((0, 3)));
+        assert!(properties.float_steps.is_none());
+        assert_eq!(properties.display_index, Some(2));
+
+        let category = properties.category.unwrap();
+        assert_eq!(category.index, 1);
+        assert_eq!(category.num_parameters, 4);
+        assert_eq!(category.label, "Osc 1");
+    }
+
     #[test]
     fn host_callbacks() {
         let aeffect = instance();
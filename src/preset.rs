@@ -0,0 +1,624 @@
+//! Reading and writing `.fxp` preset and `.fxb` bank files.
+//!
+//! These are the standard file formats hosts use to exchange presets of VST plugins. A file either
+//! stores a list of parameter values or an opaque chunk, depending on whether the plugin sets
+//! `preset_chunks` in its `Info`. Both variants are supported.
+//!
+//! A [`Preset`](struct.Preset.html) or [`Bank`](struct.Bank.html) can be taken from any `Plugin`,
+//! including a hosted `PluginInstance`, and applied to it again.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use vst2::plugin::Plugin;
+//! # use vst2::preset::Preset;
+//! # fn example<P: Plugin>(plugin: &mut P) {
+//! let preset = Preset::from_plugin(plugin);
+//! preset.write(&mut File::create("preset.fxp").unwrap()).unwrap();
+//!
+//! let preset = Preset::read(&mut File::open("preset.fxp").unwrap()).unwrap();
+//! preset.apply(plugin).unwrap();
+//! # }
+//! ```
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::fmt;
+
+use plugin::Plugin;
+
+/// Magic of every preset and bank file.
+const CHUNK_MAGIC: &'static [u8; 4] = b"CcnK";
+/// Magic of a preset storing parameter values.
+const PRESET_PARAMETERS_MAGIC: &'static [u8; 4] = b"FxCk";
+/// Magic of a preset storing an opaque chunk.
+const PRESET_CHUNK_MAGIC: &'static [u8; 4] = b"FPCh";
+/// Magic of a bank storing presets.
+const BANK_PRESETS_MAGIC: &'static [u8; 4] = b"FxBk";
+/// Magic of a bank storing an opaque chunk.
+const BANK_CHUNK_MAGIC: &'static [u8; 4] = b"FBCh";
+
+/// Length of the preset name field.
+const NAME_LEN: usize = 28;
+/// Length of the reserved space in a bank header.
+const BANK_RESERVED_LEN: usize = 128;
+
+/// Format version written to preset files.
+const PRESET_VERSION: i32 = 1;
+/// Format version written to bank files. Version 2 adds the current preset.
+const BANK_VERSION: i32 = 2;
+
+/// Error reading or applying a preset or bank file.
+#[derive(Debug)]
+pub enum PresetError {
+    /// Reading from the underlying reader failed.
+    Io(io::Error),
+
+    /// The data is not a preset or bank file.
+    InvalidFormat,
+
+    /// The data ended before the end of the file.
+    Truncated,
+
+    /// The file belongs to a different plugin.
+    WrongPlugin {
+        /// Unique id of the plugin the file was applied to.
+        expected: i32,
+        /// Unique id stored in the file.
+        actual: i32,
+    },
+}
+
+impl From<io::Error> for PresetError {
+    fn from(error: io::Error) -> PresetError {
+        PresetError::Io(error)
+    }
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PresetError::Io(ref error) => write!(f, "{}: {}", self.description(), error),
+            PresetError::WrongPlugin { expected, actual } => {
+                write!(f, "{} (expected {}, got {})", self.description(), expected, actual)
+            }
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for PresetError {
+    fn description(&self) -> &str {
+        use self::PresetError::*;
+
+        match *self {
+            Io(_) => "Could not read the file",
+            InvalidFormat => "Not a preset or bank file",
+            Truncated => "The file is truncated",
+            WrongPlugin { .. } => "The file belongs to a different plugin",
+        }
+    }
+}
+
+/// Contents of a preset.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PresetData {
+    /// Values of all parameters.
+    Parameters(Vec<f32>),
+
+    /// An opaque chunk as returned by `Plugin::get_preset_data`.
+    Chunk {
+        /// Number of parameters of the plugin, for information only.
+        parameters: i32,
+        /// The chunk data.
+        data: Vec<u8>,
+    },
+}
+
+/// A single preset, as stored in an `.fxp` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preset {
+    /// Unique id of the plugin the preset belongs to.
+    pub plugin_id: i32,
+
+    /// Version of the plugin which saved the preset.
+    pub plugin_version: i32,
+
+    /// Name of the preset, at most 27 bytes long.
+    pub name: String,
+
+    /// Parameter values or chunk of the preset.
+    pub data: PresetData,
+}
+
+impl Preset {
+    /// Take the current preset of `plugin`.
+    ///
+    /// The preset stores a chunk if the plugin uses `preset_chunks`, and the parameter values
+    /// otherwise.
+    pub fn from_plugin<P: Plugin + ?Sized>(plugin: &mut P) -> Preset {
+        let info = plugin.get_info();
+
+        let data = if info.preset_chunks {
+            PresetData::Chunk {
+                parameters: info.parameters,
+                data: plugin.get_preset_data(),
+            }
+        } else {
+            PresetData::Parameters((0..info.parameters).map(|i| plugin.get_parameter(i)).collect())
+        };
+
+        Preset {
+            plugin_id: info.unique_id,
+            plugin_version: info.version,
+            name: plugin.get_preset_name(plugin.get_preset_num()),
+            data: data,
+        }
+    }
+
+    /// Load this preset into the current preset of `plugin`.
+    ///
+    /// Fails if the preset belongs to a different plugin. Surplus parameter values are ignored.
+    pub fn apply<P: Plugin + ?Sized>(&self, plugin: &mut P) -> Result<(), PresetError> {
+        let info = plugin.get_info();
+        if info.unique_id != self.plugin_id {
+            return Err(PresetError::WrongPlugin {
+                expected: info.unique_id,
+                actual: self.plugin_id,
+            });
+        }
+
+        plugin.begin_set_preset();
+        match self.data {
+            PresetData::Parameters(ref values) => {
+                for (index, value) in values.iter().enumerate().take(info.parameters as usize) {
+                    plugin.set_parameter(index as i32, *value);
+                }
+            }
+            PresetData::Chunk { ref data, .. } => plugin.load_preset_data(data.clone()),
+        }
+        plugin.set_preset_name(self.name.clone());
+        plugin.end_set_preset();
+
+        Ok(())
+    }
+
+    /// Read a preset from an `.fxp` file.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Preset, PresetError> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data));
+        Preset::from_bytes(&data)
+    }
+
+    /// Write this preset as an `.fxp` file.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    /// Parse the contents of an `.fxp` file.
+    pub fn from_bytes(data: &[u8]) -> Result<Preset, PresetError> {
+        let mut cursor = Cursor { data: data, pos: 0 };
+        Preset::parse(&mut cursor)
+    }
+
+    /// Encode this preset as the contents of an `.fxp` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode(&mut out);
+        out
+    }
+
+    fn parse(cursor: &mut Cursor) -> Result<Preset, PresetError> {
+        let mut cursor = try!(cursor.file());
+        let magic = try!(cursor.bytes(4));
+        let _version = try!(cursor.i32());
+        let plugin_id = try!(cursor.i32());
+        let plugin_version = try!(cursor.i32());
+        let parameters = try!(cursor.count());
+        let name = ::read_fixed(try!(cursor.bytes(NAME_LEN)));
+
+        let data = if magic == PRESET_PARAMETERS_MAGIC {
+            PresetData::Parameters(try!(cursor.floats(parameters)))
+        } else if magic == PRESET_CHUNK_MAGIC {
+            let len = try!(cursor.count());
+            PresetData::Chunk {
+                parameters: parameters as i32,
+                data: try!(cursor.bytes(len)).to_vec(),
+            }
+        } else {
+            return Err(PresetError::InvalidFormat);
+        };
+
+        Ok(Preset {
+            plugin_id: plugin_id,
+            plugin_version: plugin_version,
+            name: name,
+            data: data,
+        })
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        let start = begin_file(out);
+
+        let mut name = [0; NAME_LEN];
+        ::write_fixed(&mut name, &self.name);
+
+        match self.data {
+            PresetData::Parameters(ref values) => {
+                out.extend_from_slice(PRESET_PARAMETERS_MAGIC);
+                self.encode_header(out, values.len() as i32, &name);
+                for value in values {
+                    put_i32(out, value.to_bits() as i32);
+                }
+            }
+            PresetData::Chunk { parameters, ref data } => {
+                out.extend_from_slice(PRESET_CHUNK_MAGIC);
+                self.encode_header(out, parameters, &name);
+                put_i32(out, data.len() as i32);
+                out.extend_from_slice(data);
+            }
+        }
+
+        end_file(out, start);
+    }
+
+    fn encode_header(&self, out: &mut Vec<u8>, parameters: i32, name: &[u8]) {
+        put_i32(out, PRESET_VERSION);
+        put_i32(out, self.plugin_id);
+        put_i32(out, self.plugin_version);
+        put_i32(out, parameters);
+        out.extend_from_slice(name);
+    }
+}
+
+/// Contents of a bank.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BankData {
+    /// All presets of the plugin.
+    Presets(Vec<Preset>),
+
+    /// An opaque chunk as returned by `Plugin::get_bank_data`.
+    Chunk {
+        /// Number of presets of the plugin, for information only.
+        presets: i32,
+        /// The chunk data.
+        data: Vec<u8>,
+    },
+}
+
+/// All presets of a plugin, as stored in an `.fxb` file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bank {
+    /// Unique id of the plugin the bank belongs to.
+    pub plugin_id: i32,
+
+    /// Version of the plugin which saved the bank.
+    pub plugin_version: i32,
+
+    /// Index of the preset selected when the bank was saved.
+    pub current_preset: i32,
+
+    /// Presets or chunk of the bank.
+    pub data: BankData,
+}
+
+impl Bank {
+    /// Take all presets of `plugin`.
+    ///
+    /// The bank stores a chunk if the plugin uses `preset_chunks`. Otherwise every preset is
+    /// selected in turn to read its parameters, and the current preset is selected again.
+    pub fn from_plugin<P: Plugin + ?Sized>(plugin: &mut P) -> Bank {
+        let info = plugin.get_info();
+        let current = plugin.get_preset_num();
+
+        let data = if info.preset_chunks {
+            BankData::Chunk {
+                presets: info.presets,
+                data: plugin.get_bank_data(),
+            }
+        } else {
+            let presets = (0..info.presets).map(|index| {
+                plugin.change_preset(index);
+                Preset::from_plugin(&mut *plugin)
+            }).collect();
+            plugin.change_preset(current);
+
+            BankData::Presets(presets)
+        };
+
+        Bank {
+            plugin_id: info.unique_id,
+            plugin_version: info.version,
+            current_preset: current,
+            data: data,
+        }
+    }
+
+    /// Load this bank into `plugin` and select the preset which was current when it was saved.
+    ///
+    /// Fails if the bank or one of its presets belongs to a different plugin. Surplus presets are
+    /// ignored.
+    pub fn apply<P: Plugin + ?Sized>(&self, plugin: &mut P) -> Result<(), PresetError> {
+        let info = plugin.get_info();
+        if info.unique_id != self.plugin_id {
+            return Err(PresetError::WrongPlugin {
+                expected: info.unique_id,
+                actual: self.plugin_id,
+            });
+        }
+
+        match self.data {
+            BankData::Presets(ref presets) => {
+                for (index, preset) in presets.iter().enumerate().take(info.presets as usize) {
+                    plugin.change_preset(index as i32);
+                    try!(preset.apply(plugin));
+                }
+            }
+            BankData::Chunk { ref data, .. } => plugin.load_bank_data(data.clone()),
+        }
+
+        if self.current_preset >= 0 && self.current_preset < info.presets {
+            plugin.change_preset(self.current_preset);
+        }
+
+        Ok(())
+    }
+
+    /// Read a bank from an `.fxb` file.
+    pub fn read<R: Read>(reader: &mut R) -> Result<Bank, PresetError> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data));
+        Bank::from_bytes(&data)
+    }
+
+    /// Write this bank as an `.fxb` file.
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    /// Parse the contents of an `.fxb` file.
+    pub fn from_bytes(data: &[u8]) -> Result<Bank, PresetError> {
+        let mut cursor = try!(Cursor { data: data, pos: 0 }.file());
+        let magic = try!(cursor.bytes(4));
+        let version = try!(cursor.i32());
+        let plugin_id = try!(cursor.i32());
+        let plugin_version = try!(cursor.i32());
+        let presets = try!(cursor.count());
+
+        let mut reserved = Cursor { data: try!(cursor.bytes(BANK_RESERVED_LEN)), pos: 0 };
+        let current_preset = if version >= 2 { try!(reserved.i32()) } else { 0 };
+
+        let data = if magic == BANK_PRESETS_MAGIC {
+            let mut list = Vec::new();
+            for _ in 0..presets {
+                list.push(try!(Preset::parse(&mut cursor)));
+            }
+            BankData::Presets(list)
+        } else if magic == BANK_CHUNK_MAGIC {
+            let len = try!(cursor.count());
+            BankData::Chunk {
+                presets: presets as i32,
+                data: try!(cursor.bytes(len)).to_vec(),
+            }
+        } else {
+            return Err(PresetError::InvalidFormat);
+        };
+
+        Ok(Bank {
+            plugin_id: plugin_id,
+            plugin_version: plugin_version,
+            current_preset: current_preset,
+            data: data,
+        })
+    }
+
+    /// Encode this bank as the contents of an `.fxb` file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let start = begin_file(&mut out);
+
+        let (magic, presets) = match self.data {
+            BankData::Presets(ref presets) => (BANK_PRESETS_MAGIC, presets.len() as i32),
+            BankData::Chunk { presets, .. } => (BANK_CHUNK_MAGIC, presets),
+        };
+        out.extend_from_slice(magic);
+        put_i32(&mut out, BANK_VERSION);
+        put_i32(&mut out, self.plugin_id);
+        put_i32(&mut out, self.plugin_version);
+        put_i32(&mut out, presets);
+        put_i32(&mut out, self.current_preset);
+        out.extend_from_slice(&[0; BANK_RESERVED_LEN - 4]);
+
+        match self.data {
+            BankData::Presets(ref presets) => {
+                for preset in presets {
+                    preset.encode(&mut out);
+                }
+            }
+            BankData::Chunk { ref data, .. } => {
+                put_i32(&mut out, data.len() as i32);
+                out.extend_from_slice(data);
+            }
+        }
+
+        end_file(&mut out, start);
+        out
+    }
+}
+
+/// Big endian reader over the bytes of a file.
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PresetError> {
+        if self.data.len() - self.pos < len {
+            return Err(PresetError::Truncated);
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn i32(&mut self) -> Result<i32, PresetError> {
+        let b = try!(self.bytes(4));
+        Ok((b[0] as i32) << 24 | (b[1] as i32) << 16 | (b[2] as i32) << 8 | b[3] as i32)
+    }
+
+    /// Read a count or length, which may not be negative.
+    fn count(&mut self) -> Result<usize, PresetError> {
+        match try!(self.i32()) {
+            count if count >= 0 => Ok(count as usize),
+            _ => Err(PresetError::InvalidFormat),
+        }
+    }
+
+    fn floats(&mut self, count: usize) -> Result<Vec<f32>, PresetError> {
+        let len = try!(count.checked_mul(4).ok_or(PresetError::Truncated));
+        let mut floats = Cursor { data: try!(self.bytes(len)), pos: 0 };
+        (0..count).map(|_| floats.i32().map(|bits| f32::from_bits(bits as u32))).collect()
+    }
+
+    /// Read the `CcnK` header of a file and return a cursor over its contents.
+    fn file(&mut self) -> Result<Cursor<'a>, PresetError> {
+        if try!(self.bytes(4)) != CHUNK_MAGIC {
+            return Err(PresetError::InvalidFormat);
+        }
+
+        let len = try!(self.count());
+        Ok(Cursor { data: try!(self.bytes(len)), pos: 0 })
+    }
+}
+
+fn put_i32(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&[(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8,
+                            value as u8]);
+}
+
+/// Write the `CcnK` header of a file, returning the position of its size field.
+fn begin_file(out: &mut Vec<u8>) -> usize {
+    out.extend_from_slice(CHUNK_MAGIC);
+    put_i32(out, 0);
+    out.len()
+}
+
+/// Fill in the size field of a file started at `start`.
+fn end_file(out: &mut Vec<u8>, start: usize) {
+    let mut size = Vec::new();
+    put_i32(&mut size, (out.len() - start) as i32);
+    out[start - 4..start].copy_from_slice(&size);
+}
+
+#[cfg(test)]
+mod tests {
+    use plugin::{Info, Plugin};
+    use preset::{Bank, BankData, Preset, PresetData, PresetError};
+
+    #[derive(Default)]
+    struct Synth {
+        current: i32,
+        presets: Vec<(String, [f32; 2])>,
+    }
+
+    impl Plugin for Synth {
+        fn get_info(&self) -> Info {
+            Info {
+                unique_id: 1234,
+                presets: 3,
+                parameters: 2,
+                ..Default::default()
+            }
+        }
+
+        fn change_preset(&mut self, preset: i32) { self.current = preset; }
+        fn get_preset_num(&self) -> i32 { self.current }
+        fn get_preset_name(&self, preset: i32) -> String { self.presets[preset as usize].0.clone() }
+
+        fn set_preset_name(&mut self, name: String) {
+            self.presets[self.current as usize].0 = name;
+        }
+
+        fn get_parameter(&self, index: i32) -> f32 {
+            self.presets[self.current as usize].1[index as usize]
+        }
+
+        fn set_parameter(&mut self, index: i32, value: f32) {
+            self.presets[self.current as usize].1[index as usize] = value;
+        }
+    }
+
+    fn synth() -> Synth {
+        Synth {
+            current: 1,
+            presets: vec![("Init".to_string(), [0.0, 0.5]),
+                          ("Bass".to_string(), [0.25, 1.0]),
+                          ("Lead".to_string(), [0.75, 0.125])],
+        }
+    }
+
+    /// Test that presets survive encoding and decoding.
+    #[test]
+    fn preset_round_trip() {
+        let preset = Preset::from_plugin(&mut synth());
+        assert_eq!(preset.name, "Bass");
+        assert_eq!(preset.data, PresetData::Parameters(vec![0.25, 1.0]));
+
+        let bytes = preset.to_bytes();
+        assert_eq!(&bytes[..4], b"CcnK");
+        assert_eq!(&bytes[8..12], b"FxCk");
+        assert_eq!(bytes.len(), 8 + 48 + 8);
+        assert_eq!(Preset::from_bytes(&bytes).unwrap(), preset);
+
+        let chunk = Preset {
+            plugin_id: 1,
+            plugin_version: 2,
+            name: "A very long name which is truncated".to_string(),
+            data: PresetData::Chunk { parameters: 4, data: vec![1, 2, 3] },
+        };
+        let read = Preset::from_bytes(&chunk.to_bytes()).unwrap();
+        assert_eq!(read.name, "A very long name which is t");
+        assert_eq!(read.data, chunk.data);
+
+        assert!(match Preset::from_bytes(&bytes[..30]) {
+            Err(PresetError::Truncated) => true,
+            _ => false,
+        });
+    }
+
+    /// Test taking a bank from a plugin and applying it to another one.
+    #[test]
+    fn bank_apply() {
+        let bank = Bank::from_plugin(&mut synth());
+        assert_eq!(bank.current_preset, 1);
+
+        let bytes = bank.to_bytes();
+        assert_eq!(&bytes[8..12], b"FxBk");
+        let bank = Bank::from_bytes(&bytes).unwrap();
+
+        let mut other = Synth {
+            current: 0,
+            presets: vec![(String::new(), [0.0; 2]); 3],
+        };
+        bank.apply(&mut other).unwrap();
+        assert_eq!(other.current, 1);
+        assert_eq!(other.presets, synth().presets);
+
+        let foreign = Bank { plugin_id: 1, ..bank };
+        assert!(match foreign.apply(&mut other) {
+            Err(PresetError::WrongPlugin { expected: 1234, actual: 1 }) => true,
+            _ => false,
+        });
+
+        let chunk = Bank {
+            plugin_id: 1,
+            plugin_version: 1,
+            current_preset: 0,
+            data: BankData::Chunk { presets: 8, data: vec![9; 10] },
+        };
+        assert_eq!(Bank::from_bytes(&chunk.to_bytes()).unwrap(), chunk);
+    }
+}
@@ -0,0 +1,279 @@
+//! Managing the programs of a plugin.
+//!
+//! Hosts treat the presets of a plugin as a bank of numbered slots, one of which is current. A
+//! [`ProgramBank`](struct.ProgramBank.html) holds a name and a state for every slot and takes care
+//! of the bookkeeping behind `Plugin::change_preset`, `get_preset_num`, `set_preset_name` and
+//! `get_preset_name`. The plugin edits the current state through the bank, which remembers whether
+//! anything changed since the bank was last saved or loaded, e.g. to mark unsaved changes in the
+//! editor.
+//!
+//! With the `chunk` feature, a bank of `chunk::State` states can also be saved to and loaded from
+//! preset and bank chunks, including the program names.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::program::ProgramBank;
+//!
+//! #[derive(Clone, Default)]
+//! struct SynthState {
+//!     cutoff: f32,
+//! }
+//!
+//! struct Synth {
+//!     programs: ProgramBank<SynthState>,
+//! }
+//!
+//! impl Default for Synth {
+//!     fn default() -> Synth {
+//!         Synth { programs: ProgramBank::new(16, SynthState::default()) }
+//!     }
+//! }
+//!
+//! impl Plugin for Synth {
+//!     fn get_info(&self) -> Info {
+//!         Info { presets: self.programs.count(), ..Default::default() }
+//!     }
+//!
+//!     fn change_preset(&mut self, preset: i32) { self.programs.set_program(preset); }
+//!     fn get_preset_num(&self) -> i32 { self.programs.program() }
+//!     fn set_preset_name(&mut self, name: String) { self.programs.set_name(name); }
+//!     fn get_preset_name(&self, preset: i32) -> String { self.programs.name(preset) }
+//!
+//!     fn set_parameter(&mut self, index: i32, value: f32) {
+//!         if index == 0 {
+//!             self.programs.state_mut().cutoff = value;
+//!         }
+//!     }
+//! }
+//! ```
+
+#[cfg(feature = "chunk")]
+use chunk::{self, ChunkError, State};
+
+/// Named program states with one current program. See the module documentation.
+///
+/// Out of range indices are ignored by setters and yield empty values from getters.
+#[derive(Clone, Debug)]
+pub struct ProgramBank<S> {
+    names: Vec<String>,
+    states: Vec<S>,
+    current: usize,
+    dirty: bool,
+}
+
+impl<S: Clone> ProgramBank<S> {
+    /// Create a bank of `count` programs named "Program 1" and so on, all set to `state`.
+    ///
+    /// Banks hold at least one program.
+    pub fn new(count: usize, state: S) -> ProgramBank<S> {
+        let count = count.max(1);
+
+        ProgramBank {
+            names: (1..count + 1).map(|i| format!("Program {}", i)).collect(),
+            states: vec![state; count],
+            current: 0,
+            dirty: false,
+        }
+    }
+
+    /// Create a bank of factory programs from their names and states.
+    ///
+    /// Panics if `programs` is empty.
+    pub fn from_programs(programs: Vec<(String, S)>) -> ProgramBank<S> {
+        assert!(!programs.is_empty(), "A program bank needs at least one program");
+        let (names, states) = programs.into_iter().unzip();
+
+        ProgramBank {
+            names: names,
+            states: states,
+            current: 0,
+            dirty: false,
+        }
+    }
+
+    /// Number of programs, for `Info::presets`.
+    pub fn count(&self) -> i32 {
+        self.states.len() as i32
+    }
+
+    /// Index of the current program, for `Plugin::get_preset_num`.
+    pub fn program(&self) -> i32 {
+        self.current as i32
+    }
+
+    /// Make the program at `index` current, for `Plugin::change_preset`.
+    ///
+    /// Returns `false` if there is no such program.
+    pub fn set_program(&mut self, index: i32) -> bool {
+        match self.index(index) {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// State of the current program.
+    pub fn state(&self) -> &S {
+        &self.states[self.current]
+    }
+
+    /// Mutable state of the current program. Marks the bank as dirty.
+    pub fn state_mut(&mut self) -> &mut S {
+        self.dirty = true;
+        &mut self.states[self.current]
+    }
+
+    /// State of the program at `index`.
+    pub fn get(&self, index: i32) -> Option<&S> {
+        self.index(index).map(|index| &self.states[index])
+    }
+
+    /// Name of the program at `index`, for `Plugin::get_preset_name`.
+    pub fn name(&self, index: i32) -> String {
+        self.index(index).map_or(String::new(), |index| self.names[index].clone())
+    }
+
+    /// Rename the current program, for `Plugin::set_preset_name`. Marks the bank as dirty.
+    pub fn set_name(&mut self, name: String) {
+        self.names[self.current] = name;
+        self.dirty = true;
+    }
+
+    /// Overwrite the program at `to` with the name and state of the program at `from`, e.g. for
+    /// a "copy to" menu in the editor. Marks the bank as dirty.
+    ///
+    /// Returns `false` if either program does not exist.
+    pub fn copy_program(&mut self, from: i32, to: i32) -> bool {
+        match (self.index(from), self.index(to)) {
+            (Some(from), Some(to)) => {
+                self.names[to] = self.names[from].clone();
+                self.states[to] = self.states[from].clone();
+                self.dirty = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether any program changed since the bank was created or last saved or loaded.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Mark all programs as unchanged, e.g. after storing them outside of a chunk.
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    fn index(&self, index: i32) -> Option<usize> {
+        if index >= 0 && index < self.count() {
+            Some(index as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "chunk")]
+impl<S: State + Clone> ProgramBank<S> {
+    /// Save the state of the current program, for `Plugin::get_preset_data`.
+    ///
+    /// Hosts store the program name next to preset chunks, so only the state is saved.
+    pub fn save_preset(&self) -> Result<Vec<u8>, ChunkError> {
+        chunk::save_preset(self.state())
+    }
+
+    /// Replace the state of the current program, for `Plugin::load_preset_data`. Marks the bank
+    /// as dirty.
+    pub fn load_preset(&mut self, data: &[u8]) -> Result<(), ChunkError> {
+        let state: S = try!(chunk::load_preset(data));
+        *self.state_mut() = state;
+        Ok(())
+    }
+
+    /// Save the names and states of all programs, for `Plugin::get_bank_data`. Marks the bank as
+    /// clean.
+    pub fn save_bank(&mut self) -> Result<Vec<u8>, ChunkError> {
+        let data = try!(chunk::save_named_bank(&self.names, &self.states));
+        self.dirty = false;
+        Ok(data)
+    }
+
+    /// Replace all programs, for `Plugin::load_bank_data`. Marks the bank as clean.
+    ///
+    /// The current program stays selected if it still exists. Banks without programs are
+    /// rejected with `ChunkError::InvalidHeader`.
+    pub fn load_bank(&mut self, data: &[u8]) -> Result<(), ChunkError> {
+        let (names, states): (Vec<String>, Vec<S>) = try!(chunk::load_named_bank(data));
+        if states.is_empty() || names.len() != states.len() {
+            return Err(ChunkError::InvalidHeader);
+        }
+
+        self.names = names;
+        self.states = states;
+        if self.current >= self.states.len() {
+            self.current = 0;
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use program::ProgramBank;
+
+    /// Test program selection, naming, copying and the dirty flag.
+    #[test]
+    fn programs() {
+        let mut bank = ProgramBank::new(3, 0.0);
+        assert_eq!(bank.count(), 3);
+        assert_eq!(bank.name(2), "Program 3");
+        assert_eq!(bank.name(3), "");
+        assert!(!bank.is_dirty());
+
+        assert!(bank.set_program(1));
+        assert!(!bank.set_program(-1));
+        assert_eq!(bank.program(), 1);
+
+        *bank.state_mut() = 0.5;
+        bank.set_name("Lead".to_string());
+        assert!(bank.is_dirty());
+        assert_eq!(bank.get(0), Some(&0.0));
+
+        assert!(bank.copy_program(1, 2));
+        assert!(!bank.copy_program(1, 3));
+        assert_eq!((bank.name(2), bank.get(2)), ("Lead".to_string(), Some(&0.5)));
+
+        bank.mark_clean();
+        assert!(!bank.is_dirty());
+    }
+
+    #[cfg(feature = "chunk")]
+    impl ::chunk::State for Vec<f32> {}
+
+    /// Test that banks survive a round trip through a chunk.
+    #[cfg(feature = "chunk")]
+    #[test]
+    fn chunks() {
+        let mut bank = ProgramBank::from_programs(vec![("A".to_string(), vec![0.25]),
+                                                       ("B".to_string(), vec![0.5])]);
+        bank.set_program(1);
+        let preset = bank.save_preset().unwrap();
+        let data = bank.save_bank().unwrap();
+
+        let mut other = ProgramBank::new(1, vec![0.0]);
+        other.load_preset(&preset).unwrap();
+        assert_eq!(other.state(), &vec![0.5]);
+        assert!(other.is_dirty());
+
+        other.load_bank(&data).unwrap();
+        assert_eq!(other.count(), 2);
+        assert_eq!((other.name(1), other.get(1)), ("B".to_string(), Some(&vec![0.5])));
+        assert!(!other.is_dirty());
+    }
+}
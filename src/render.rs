@@ -0,0 +1,595 @@
+//! Rendering audio files through plugins offline.
+//!
+//! A [`Render`](struct.Render.html) streams [`Audio`](struct.Audio.html) through a chain of plugins
+//! block by block, as fast as the plugins allow. MIDI events and parameter changes can be scheduled
+//! at sample positions; parameter changes are applied sample accurately. Since `PluginInstance`
+//! implements `Plugin`, this works for plugins loaded with a `PluginLoader` as well as for plugin
+//! types under test.
+//!
+//! Audio is read from and written to WAV files or headerless little endian `f32` data.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use vst2::plugin::Plugin;
+//! use vst2::render::{Audio, Render, RenderEvent, TimedEvent};
+//!
+//! # fn example(reverb: &mut Plugin, limiter: &mut Plugin) {
+//! let input = Audio::read_wav(&mut File::open("dry.wav").unwrap()).unwrap();
+//!
+//! let render = Render {
+//!     block_size: 256,
+//!     tail: input.sample_rate as usize * 2,
+//!     // Open up the reverb mix parameter after one second
+//!     events: vec![TimedEvent {
+//!         frame: input.sample_rate as usize,
+//!         plugin: 0,
+//!         event: RenderEvent::Parameter { index: 0, value: 1.0 },
+//!     }],
+//! };
+//!
+//! let output = render.run(&mut [reverb, limiter], &input);
+//! output.write_wav(&mut File::create("wet.wav").unwrap()).unwrap();
+//! # }
+//! ```
+
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::{cmp, fmt};
+
+use automation::{self, ParameterChange};
+use buffer::AudioBuffer;
+use event::{self, Event, MidiEvent};
+use plugin::Plugin;
+
+/// WAV format tag of integer samples.
+const FORMAT_PCM: u16 = 1;
+/// WAV format tag of floating point samples.
+const FORMAT_FLOAT: u16 = 3;
+/// WAV format tag of the extensible format, which stores the actual tag in its sub format.
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Error reading or writing audio.
+#[derive(Debug)]
+pub enum RenderError {
+    /// Reading or writing the underlying data failed.
+    Io(io::Error),
+
+    /// The data is not a WAV file or is truncated.
+    InvalidWav,
+
+    /// The WAV file uses a sample format which is not supported.
+    UnsupportedFormat {
+        /// Format tag of the file.
+        format: u16,
+        /// Bits per sample of the file.
+        bits: u16,
+    },
+}
+
+impl From<io::Error> for RenderError {
+    fn from(error: io::Error) -> RenderError {
+        RenderError::Io(error)
+    }
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RenderError::Io(ref error) => write!(f, "{}: {}", self.description(), error),
+            RenderError::UnsupportedFormat { format, bits } => {
+                write!(f, "{} (format {}, {} bits)", self.description(), format, bits)
+            }
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn description(&self) -> &str {
+        use self::RenderError::*;
+
+        match *self {
+            Io(_) => "Could not read or write the audio data",
+            InvalidWav => "Not a valid WAV file",
+            UnsupportedFormat { .. } => "Unsupported WAV sample format",
+        }
+    }
+}
+
+/// Audio held in memory, one `Vec` per channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Audio {
+    /// Sample rate in Hz.
+    pub sample_rate: u32,
+
+    /// Samples of every channel. All channels have the same length.
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Audio {
+    /// Number of samples in each channel.
+    pub fn frames(&self) -> usize {
+        self.channels.first().map_or(0, |c| c.len())
+    }
+
+    /// Read a WAV file with 8, 16, 24 or 32 bit integer or 32 or 64 bit floating point samples.
+    pub fn read_wav<R: Read>(reader: &mut R) -> Result<Audio, RenderError> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data));
+
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            return Err(RenderError::InvalidWav);
+        }
+
+        // Format tag, channels, sample rate and bits per sample
+        let mut format = None;
+        let mut samples = None;
+
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let id = &data[pos..pos + 4];
+            let len = read_u32(&data[pos + 4..]) as usize;
+            let body = match data.get(pos + 8..pos + 8 + len) {
+                Some(body) => body,
+                None => return Err(RenderError::InvalidWav),
+            };
+
+            if id == b"fmt " {
+                if body.len() < 16 {
+                    return Err(RenderError::InvalidWav);
+                }
+                let mut tag = read_u16(body);
+                if tag == FORMAT_EXTENSIBLE && body.len() >= 26 {
+                    tag = read_u16(&body[24..]);
+                }
+                let channels = read_u16(&body[2..]);
+                format = Some((tag, channels, read_u32(&body[4..]), read_u16(&body[14..])));
+            } else if id == b"data" {
+                samples = Some(body);
+            }
+
+            // Chunks are padded to an even length
+            pos += 8 + len + len % 2;
+        }
+
+        let ((tag, channels, sample_rate, bits), samples) = match (format, samples) {
+            (Some(format), Some(samples)) => (format, samples),
+            _ => return Err(RenderError::InvalidWav),
+        };
+
+        let convert: fn(&[u8]) -> f32 = match (tag, bits) {
+            (FORMAT_PCM, 8) => read_u8_sample,
+            (FORMAT_PCM, 16) => read_i16_sample,
+            (FORMAT_PCM, 24) => read_i24_sample,
+            (FORMAT_PCM, 32) => read_i32_sample,
+            (FORMAT_FLOAT, 32) => read_f32_sample,
+            (FORMAT_FLOAT, 64) => read_f64_sample,
+            _ => return Err(RenderError::UnsupportedFormat { format: tag, bits: bits }),
+        };
+
+        if channels == 0 {
+            return Err(RenderError::InvalidWav);
+        }
+        let sample_len = bits as usize / 8;
+        let channels = channels as usize;
+
+        let mut audio = Audio {
+            sample_rate: sample_rate,
+            channels: vec![Vec::with_capacity(samples.len() / sample_len / channels); channels],
+        };
+        for frame in samples.chunks(sample_len * channels) {
+            if frame.len() < sample_len * channels {
+                break;
+            }
+            for (channel, sample) in audio.channels.iter_mut().zip(frame.chunks(sample_len)) {
+                channel.push(convert(sample));
+            }
+        }
+
+        Ok(audio)
+    }
+
+    /// Write a WAV file with 32 bit floating point samples.
+    pub fn write_wav<W: Write>(&self, writer: &mut W) -> Result<(), RenderError> {
+        let channels = self.channels.len();
+        let data_len = (self.frames() * channels * 4) as u32;
+
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        write_u32(&mut header, 36 + data_len);
+        header.extend_from_slice(b"WAVE");
+
+        header.extend_from_slice(b"fmt ");
+        write_u32(&mut header, 16);
+        write_u16(&mut header, FORMAT_FLOAT);
+        write_u16(&mut header, channels as u16);
+        write_u32(&mut header, self.sample_rate);
+        write_u32(&mut header, self.sample_rate * channels as u32 * 4);
+        write_u16(&mut header, channels as u16 * 4);
+        write_u16(&mut header, 32);
+
+        header.extend_from_slice(b"data");
+        write_u32(&mut header, data_len);
+
+        try!(writer.write_all(&header));
+        self.write_raw(writer)
+    }
+
+    /// Read interleaved little endian `f32` samples without a header.
+    pub fn read_raw<R: Read>(reader: &mut R, channels: usize, sample_rate: u32)
+                             -> Result<Audio, RenderError> {
+        let mut data = Vec::new();
+        try!(reader.read_to_end(&mut data));
+
+        let mut audio = Audio {
+            sample_rate: sample_rate,
+            channels: vec![Vec::new(); channels],
+        };
+        if channels == 0 {
+            return Ok(audio);
+        }
+
+        for frame in data.chunks(channels * 4) {
+            if frame.len() < channels * 4 {
+                break;
+            }
+            for (channel, sample) in audio.channels.iter_mut().zip(frame.chunks(4)) {
+                channel.push(f32::from_bits(read_u32(sample)));
+            }
+        }
+
+        Ok(audio)
+    }
+
+    /// Write interleaved little endian `f32` samples without a header.
+    pub fn write_raw<W: Write>(&self, writer: &mut W) -> Result<(), RenderError> {
+        let mut data = Vec::with_capacity(self.frames() * self.channels.len() * 4);
+        for frame in 0..self.frames() {
+            for channel in &self.channels {
+                write_u32(&mut data, channel[frame].to_bits());
+            }
+        }
+
+        try!(writer.write_all(&data));
+        Ok(())
+    }
+}
+
+/// An event sent to a plugin during rendering.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderEvent {
+    /// A MIDI message.
+    Midi([u8; 3]),
+
+    /// A parameter change to a value between 0.0 and 1.0.
+    Parameter {
+        /// Index of the parameter.
+        index: i32,
+        /// New value of the parameter.
+        value: f32,
+    },
+}
+
+/// A `RenderEvent` scheduled for a plugin at a sample position.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimedEvent {
+    /// Sample position from the start of the rendered audio.
+    pub frame: usize,
+
+    /// Index of the plugin in the chain receiving the event.
+    pub plugin: usize,
+
+    /// The event.
+    pub event: RenderEvent,
+}
+
+/// Settings of an offline render.
+#[derive(Clone, Debug)]
+pub struct Render {
+    /// Maximum number of samples processed at once.
+    pub block_size: usize,
+
+    /// Number of samples rendered past the end of the input, e.g. to capture reverb tails.
+    pub tail: usize,
+
+    /// Events sent to the plugins while rendering.
+    pub events: Vec<TimedEvent>,
+}
+
+impl Default for Render {
+    fn default() -> Render {
+        Render {
+            block_size: 512,
+            tail: 0,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl Render {
+    /// Process `input` through `plugins` in order and return the output of the last plugin.
+    ///
+    /// Every plugin receives the output channels of the previous one; missing input channels are
+    /// silent and surplus ones are dropped. The sample rate and block size are set and the plugins
+    /// are resumed before rendering and suspended afterwards. Initialize the plugins first if
+    /// needed.
+    pub fn run(&self, plugins: &mut [&mut Plugin], input: &Audio) -> Audio {
+        let block_size = cmp::max(self.block_size, 1);
+        let frames = input.frames() + self.tail;
+
+        let mut buffers = Vec::with_capacity(plugins.len());
+        for plugin in plugins.iter_mut() {
+            let info = plugin.get_info();
+            buffers.push((vec![vec![0.0; block_size]; info.inputs.max(0) as usize],
+                          vec![vec![0.0; block_size]; info.outputs.max(0) as usize]));
+
+            plugin.sample_rate_changed(input.sample_rate as f32);
+            plugin.block_size_changed(block_size as i64);
+            plugin.on_resume();
+        }
+
+        let output_channels = buffers.last().map_or(input.channels.len(), |b| b.1.len());
+        let mut output = Audio {
+            sample_rate: input.sample_rate,
+            channels: vec![Vec::with_capacity(frames); output_channels],
+        };
+
+        let mut start = 0;
+        while start < frames {
+            let len = cmp::min(block_size, frames - start);
+
+            // Channels passed along the chain, starting with the input padded with silence
+            let mut signal: Vec<Vec<f32>> = input.channels.iter().map(|channel| {
+                let mut block = channel.get(start..cmp::min(start + len, channel.len()))
+                                       .unwrap_or(&[])
+                                       .to_vec();
+                block.resize(len, 0.0);
+                block
+            }).collect();
+
+            for (index, (plugin, &mut (ref mut inputs, ref mut outputs))) in
+                plugins.iter_mut().zip(buffers.iter_mut()).enumerate() {
+                for (channel, input) in inputs.iter_mut().enumerate() {
+                    match signal.get(channel) {
+                        Some(samples) => input[..len].copy_from_slice(samples),
+                        None => {
+                            for sample in &mut input[..len] {
+                                *sample = 0.0;
+                            }
+                        }
+                    }
+                }
+
+                let mut midi = Vec::new();
+                let mut changes = Vec::new();
+                for event in &self.events {
+                    if event.plugin != index || event.frame < start || event.frame >= start + len {
+                        continue;
+                    }
+
+                    let delta_frames = (event.frame - start) as i32;
+                    match event.event {
+                        RenderEvent::Midi(data) => {
+                            midi.push(Event::Midi(MidiEvent {
+                                data: data,
+                                delta_frames: delta_frames,
+                                ..Default::default()
+                            }));
+                        }
+                        RenderEvent::Parameter { index, value } => {
+                            changes.push(ParameterChange {
+                                index: index,
+                                value: value,
+                                delta_frames: delta_frames,
+                            });
+                        }
+                    }
+                }
+
+                if !midi.is_empty() {
+                    // The events of a render may be listed in any order
+                    event::sort_by_time(&mut midi);
+                    plugin.process_events(&midi);
+                }
+                automation::process_automated(&mut **plugin,
+                                              AudioBuffer::new(inputs.iter_mut()
+                                                                     .map(|c| &mut c[..len])
+                                                                     .collect(),
+                                                               outputs.iter_mut()
+                                                                      .map(|c| &mut c[..len])
+                                                                      .collect()),
+                                              &mut changes);
+
+                signal = outputs.iter().map(|c| c[..len].to_vec()).collect();
+            }
+
+            for (channel, samples) in output.channels.iter_mut().zip(signal) {
+                channel.extend_from_slice(&samples);
+            }
+            start += len;
+        }
+
+        for plugin in plugins.iter_mut() {
+            plugin.on_suspend();
+        }
+
+        output
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    bytes[0] as u16 | (bytes[1] as u16) << 8
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[0] as u32 | (bytes[1] as u32) << 8 | (bytes[2] as u32) << 16 | (bytes[3] as u32) << 24
+}
+
+fn read_u8_sample(bytes: &[u8]) -> f32 {
+    (bytes[0] as f32 - 128.0) / 128.0
+}
+
+fn read_i16_sample(bytes: &[u8]) -> f32 {
+    read_u16(bytes) as i16 as f32 / 32768.0
+}
+
+fn read_i24_sample(bytes: &[u8]) -> f32 {
+    // Shift into the upper bytes to sign extend
+    (read_u32(&[0, bytes[0], bytes[1], bytes[2]]) as i32 >> 8) as f32 / 8388608.0
+}
+
+fn read_i32_sample(bytes: &[u8]) -> f32 {
+    read_u32(bytes) as i32 as f32 / 2147483648.0
+}
+
+fn read_f32_sample(bytes: &[u8]) -> f32 {
+    f32::from_bits(read_u32(bytes))
+}
+
+fn read_f64_sample(bytes: &[u8]) -> f32 {
+    f64::from_bits(read_u32(bytes) as u64 | (read_u32(&bytes[4..]) as u64) << 32) as f32
+}
+
+fn write_u16(data: &mut Vec<u8>, value: u16) {
+    data.extend_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+fn write_u32(data: &mut Vec<u8>, value: u32) {
+    data.extend_from_slice(&[value as u8, (value >> 8) as u8, (value >> 16) as u8,
+                             (value >> 24) as u8]);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use buffer::AudioBuffer;
+    use event::Event;
+    use plugin::{Info, Plugin};
+    use render::{Audio, Render, RenderError, RenderEvent, TimedEvent};
+
+    /// Multiplies its mono input by a gain parameter and adds 1 for every note received.
+    #[derive(Default)]
+    struct GainPlugin {
+        gain: f32,
+        notes: f32,
+    }
+
+    impl Plugin for GainPlugin {
+        fn get_info(&self) -> Info {
+            Info { inputs: 1, outputs: 1, ..Default::default() }
+        }
+
+        fn set_parameter(&mut self, _: i32, value: f32) { self.gain = value; }
+
+        fn process_events(&mut self, events: &[Event]) {
+            self.notes += events.len() as f32;
+        }
+
+        fn process(&mut self, buffer: AudioBuffer<f32>) {
+            for (input, output) in buffer.zip() {
+                for (i, o) in input.iter().zip(output.iter_mut()) {
+                    *o = *i * self.gain + self.notes;
+                }
+            }
+        }
+    }
+
+    fn audio(samples: Vec<f32>) -> Audio {
+        Audio { sample_rate: 44100, channels: vec![samples] }
+    }
+
+    /// Test that WAV and raw data survive a round trip.
+    #[test]
+    fn round_trip() {
+        let audio = Audio { sample_rate: 48000, channels: vec![vec![0.0, 0.5], vec![-1.0, 0.25]] };
+
+        let mut wav = Vec::new();
+        audio.write_wav(&mut wav).unwrap();
+        assert_eq!(wav.len(), 44 + 16);
+        assert_eq!(Audio::read_wav(&mut Cursor::new(wav)).unwrap(), audio);
+
+        let mut raw = Vec::new();
+        audio.write_raw(&mut raw).unwrap();
+        assert_eq!(Audio::read_raw(&mut Cursor::new(raw), 2, 48000).unwrap(), audio);
+    }
+
+    /// Test reading 16 bit integer samples and rejecting other data.
+    #[test]
+    fn read_pcm() {
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF\x28\0\0\0WAVEfmt \x10\0\0\0\x01\0\x01\0\x44\xac\0\0");
+        wav.extend_from_slice(b"\x88\x58\x01\0\x02\0\x10\0data\x04\0\0\0\0\x40\0\xc0");
+
+        let audio = Audio::read_wav(&mut Cursor::new(wav)).unwrap();
+        assert_eq!(audio, Audio { sample_rate: 44100, channels: vec![vec![0.5, -0.5]] });
+
+        match Audio::read_wav(&mut Cursor::new(b"RIFF\0\0\0\0AVI ".to_vec())) {
+            Err(RenderError::InvalidWav) => (),
+            _ => panic!("Expected an invalid WAV file."),
+        }
+    }
+
+    /// Test that events and automation are applied at their positions and the tail is rendered.
+    #[test]
+    fn render() {
+        let mut first = GainPlugin::default();
+        let mut second = GainPlugin { gain: 1.0, notes: 0.0 };
+
+        let render = Render {
+            block_size: 2,
+            tail: 1,
+            events: vec![TimedEvent {
+                             frame: 1,
+                             plugin: 0,
+                             event: RenderEvent::Parameter { index: 0, value: 2.0 },
+                         },
+                         TimedEvent {
+                             frame: 2,
+                             plugin: 1,
+                             event: RenderEvent::Midi([0x90, 60, 100]),
+                         }],
+        };
+
+        let output = render.run(&mut [&mut first, &mut second], &audio(vec![1.0; 4]));
+        assert_eq!(output, audio(vec![0.0, 2.0, 3.0, 3.0, 1.0]));
+    }
+
+    /// Test that events listed out of order reach the plugin sorted by time.
+    #[test]
+    fn unsorted_events() {
+        #[derive(Default)]
+        struct NoteRecorder {
+            notes: Vec<(i32, u8)>,
+        }
+
+        impl Plugin for NoteRecorder {
+            fn get_info(&self) -> Info {
+                Info { inputs: 1, outputs: 1, ..Default::default() }
+            }
+
+            fn process_events(&mut self, events: &[Event]) {
+                for event in events {
+                    if let Event::Midi(midi) = *event {
+                        self.notes.push((midi.delta_frames, midi.data[1]));
+                    }
+                }
+            }
+        }
+
+        let note = |frame, note| {
+            TimedEvent { frame: frame, plugin: 0, event: RenderEvent::Midi([0x90, note, 100]) }
+        };
+        let render = Render {
+            block_size: 4,
+            tail: 0,
+            events: vec![note(3, 62), note(1, 60), note(3, 63), note(2, 61)],
+        };
+
+        let mut plugin = NoteRecorder::default();
+        render.run(&mut [&mut plugin], &audio(vec![0.0; 4]));
+        assert_eq!(plugin.notes, vec![(1, 60), (2, 61), (3, 62), (3, 63)]);
+    }
+}
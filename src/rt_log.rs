@@ -0,0 +1,383 @@
+//! Logging from the audio thread.
+//!
+//! Printing or calling into the `log` crate from `process` can allocate, lock or block on I/O,
+//! all of which cause dropouts. The `rt_*!` macros instead format the message into a fixed size
+//! buffer on the stack and push it into a lock-free ring. A background thread drains the ring and
+//! forwards the messages to the `log` crate, so they end up wherever the plugin's logger writes.
+//!
+//! Messages longer than 128 bytes are truncated, and messages are dropped if the ring is full. The
+//! number of dropped messages is logged as a warning. Nothing is logged before `start` is called.
+//!
+//! The macros can be used from any thread, including the dispatcher. Disabling the default
+//! `rt-log` feature compiles them out entirely.
+//!
+//! # Example
+//!
+//! ```
+//! # #[macro_use] extern crate vst2;
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::rt_log;
+//!
+//! #[derive(Default)]
+//! struct MyPlugin {
+//!     log: Option<rt_log::Drain>,
+//! }
+//!
+//! impl Plugin for MyPlugin {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn init(&mut self) {
+//!         self.log = Some(rt_log::start());
+//!     }
+//!
+//!     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//!         rt_debug!("Processing {} samples", buffer.samples());
+//!     }
+//! }
+//! # fn main() {}
+//! ```
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use std::{cmp, str};
+
+pub use log::Level;
+
+/// Log a message from the audio thread with the given `Level`. See the module documentation.
+#[cfg(feature = "rt-log")]
+#[macro_export]
+macro_rules! rt_log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::rt_log::log($level, format_args!($($arg)+))
+    }
+}
+
+/// Log a message from the audio thread with the given `Level`. See the module documentation.
+#[cfg(not(feature = "rt-log"))]
+#[macro_export]
+macro_rules! rt_log {
+    ($level:expr, $($arg:tt)+) => {
+        // Keep the arguments type checked and used
+        if false {
+            $crate::rt_log::log($level, format_args!($($arg)+))
+        }
+    }
+}
+
+/// Log an error from the audio thread.
+#[macro_export]
+macro_rules! rt_error {
+    ($($arg:tt)+) => { rt_log!($crate::rt_log::Level::Error, $($arg)+) }
+}
+
+/// Log a warning from the audio thread.
+#[macro_export]
+macro_rules! rt_warn {
+    ($($arg:tt)+) => { rt_log!($crate::rt_log::Level::Warn, $($arg)+) }
+}
+
+/// Log an informational message from the audio thread.
+#[macro_export]
+macro_rules! rt_info {
+    ($($arg:tt)+) => { rt_log!($crate::rt_log::Level::Info, $($arg)+) }
+}
+
+/// Log a debug message from the audio thread.
+#[macro_export]
+macro_rules! rt_debug {
+    ($($arg:tt)+) => { rt_log!($crate::rt_log::Level::Debug, $($arg)+) }
+}
+
+/// Log a trace message from the audio thread.
+#[macro_export]
+macro_rules! rt_trace {
+    ($($arg:tt)+) => { rt_log!($crate::rt_log::Level::Trace, $($arg)+) }
+}
+
+/// Maximum length of a message in bytes.
+const MESSAGE_LEN: usize = 128;
+/// Number of messages the ring holds, a power of two.
+const CAPACITY: usize = 1024;
+/// Time the drain thread sleeps between draining the ring.
+const DRAIN_INTERVAL_MS: u64 = 20;
+
+/// A formatted message.
+#[derive(Clone, Copy)]
+struct Message {
+    level: Level,
+    len: usize,
+    text: [u8; MESSAGE_LEN],
+}
+
+impl Message {
+    fn text(&self) -> &str {
+        // Only whole characters are written
+        str::from_utf8(&self.text[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut len = cmp::min(s.len(), MESSAGE_LEN - self.len);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.text[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+struct Slot {
+    /// Position in the ring this slot is ready for. Equal to the position when it may be written,
+    /// and one past it once the message has been written.
+    sequence: AtomicUsize,
+    message: UnsafeCell<Message>,
+}
+
+/// Bounded lock-free queue with multiple producers and a single consumer.
+struct Ring {
+    slots: Vec<Slot>,
+    write: AtomicUsize,
+    read: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn new(capacity: usize) -> Ring {
+        Ring {
+            slots: (0..capacity).map(|i| {
+                Slot {
+                    sequence: AtomicUsize::new(i),
+                    message: UnsafeCell::new(Message {
+                        level: Level::Trace,
+                        len: 0,
+                        text: [0; MESSAGE_LEN],
+                    }),
+                }
+            }).collect(),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Push a message, returning `false` and counting it as dropped if the ring is full.
+    fn push(&self, message: &Message) -> bool {
+        let mask = self.slots.len() - 1;
+        let mut pos = self.write.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[pos & mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+
+            if sequence == pos {
+                match self.write.compare_exchange_weak(pos, pos.wrapping_add(1),
+                                                       Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { *slot.message.get() = *message; }
+                        slot.sequence.store(pos.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(current) => pos = current,
+                }
+            } else if (sequence.wrapping_sub(pos) as isize) < 0 {
+                // The slot still holds a message from the previous round
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            } else {
+                pos = self.write.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Pop the oldest message. Must only be called from one thread at a time.
+    fn pop(&self) -> Option<Message> {
+        let mask = self.slots.len() - 1;
+        let pos = self.read.load(Ordering::Relaxed);
+        let slot = &self.slots[pos & mask];
+
+        if slot.sequence.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+
+        let message = unsafe { *slot.message.get() };
+        slot.sequence.store(pos.wrapping_add(self.slots.len()), Ordering::Release);
+        self.read.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(message)
+    }
+}
+
+/// State shared by all users of the logger, created once and never freed so that logging threads
+/// can never observe it being destroyed.
+struct Shared {
+    ring: Ring,
+    users: Mutex<usize>,
+    thread: Mutex<Option<JoinHandle<()>>>,
+    running: Arc<AtomicBool>,
+}
+
+static INIT: Once = Once::new();
+static SHARED: AtomicUsize = AtomicUsize::new(0);
+
+fn shared() -> Option<&'static Shared> {
+    match SHARED.load(Ordering::Acquire) {
+        0 => None,
+        ptr => Some(unsafe { &*(ptr as *const Shared) }),
+    }
+}
+
+/// Log a formatted message without allocating or blocking. Use the `rt_*!` macros instead.
+#[doc(hidden)]
+pub fn log(level: Level, args: fmt::Arguments) {
+    if !cfg!(feature = "rt-log") {
+        return;
+    }
+
+    if let Some(shared) = shared() {
+        let mut message = Message {
+            level: level,
+            len: 0,
+            text: [0; MESSAGE_LEN],
+        };
+        let _ = message.write_fmt(args);
+        shared.ring.push(&message);
+    }
+}
+
+/// Keeps the background thread forwarding messages running. See `start`.
+pub struct Drain {
+    _private: (),
+}
+
+/// Start forwarding logged messages to the `log` crate on a background thread.
+///
+/// All plugin instances in a process share one ring and thread. The thread runs until every
+/// returned `Drain` has been dropped, so keep it in the plugin and drop it when the plugin closes.
+/// This allocates and must not be called from the audio thread.
+pub fn start() -> Drain {
+    if !cfg!(feature = "rt-log") {
+        return Drain { _private: () };
+    }
+
+    INIT.call_once(|| {
+        let shared = Box::new(Shared {
+            ring: Ring::new(CAPACITY),
+            users: Mutex::new(0),
+            thread: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+        });
+        SHARED.store(Box::into_raw(shared) as usize, Ordering::Release);
+    });
+
+    let shared = shared().unwrap();
+    let mut users = shared.users.lock().unwrap();
+    *users += 1;
+
+    if *users == 1 {
+        shared.running.store(true, Ordering::SeqCst);
+        let running = shared.running.clone();
+
+        *shared.thread.lock().unwrap() = Some(thread::spawn(move || {
+            let shared = self::shared().unwrap();
+            loop {
+                // Drain once more after being stopped to flush the last messages
+                let stop = !running.load(Ordering::SeqCst);
+                drain(&shared.ring);
+                if stop {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(DRAIN_INTERVAL_MS));
+            }
+        }));
+    }
+
+    Drain { _private: () }
+}
+
+impl Drop for Drain {
+    fn drop(&mut self) {
+        let shared = match shared() {
+            Some(shared) => shared,
+            None => return,
+        };
+
+        let mut users = shared.users.lock().unwrap();
+        *users -= 1;
+
+        if *users == 0 {
+            shared.running.store(false, Ordering::SeqCst);
+            if let Some(thread) = shared.thread.lock().unwrap().take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// Forward all messages in the ring to the `log` crate.
+fn drain(ring: &Ring) {
+    while let Some(message) = ring.pop() {
+        log!(message.level, "{}", message.text());
+    }
+
+    let dropped = ring.dropped.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        warn!("{} real-time log messages were dropped", dropped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Write;
+
+    use log::Level;
+
+    use rt_log::{Message, Ring, MESSAGE_LEN};
+
+    fn message(text: &str) -> Message {
+        let mut message = Message {
+            level: Level::Info,
+            len: 0,
+            text: [0; MESSAGE_LEN],
+        };
+        write!(message, "{}", text).unwrap();
+        message
+    }
+
+    /// Test that long messages are truncated at character boundaries.
+    #[test]
+    fn truncate() {
+        let long: String = ::std::iter::repeat("é").take(100).collect();
+        let message = message(&long);
+
+        assert_eq!(message.len, MESSAGE_LEN);
+        assert_eq!(message.text().chars().count(), MESSAGE_LEN / 2);
+    }
+
+    /// Test that messages are received in order and dropped when the ring is full.
+    #[test]
+    fn ring() {
+        let ring = Ring::new(4);
+
+        for i in 0..4 {
+            assert!(ring.push(&message(&i.to_string())));
+        }
+        assert!(!ring.push(&message("full")));
+        assert_eq!(ring.dropped.load(::std::sync::atomic::Ordering::Relaxed), 1);
+
+        assert_eq!(ring.pop().unwrap().text(), "0");
+        assert!(ring.push(&message("4")));
+
+        let rest: Vec<String> = (0..4).map(|_| ring.pop().unwrap().text().to_string()).collect();
+        assert_eq!(rest, vec!["1", "2", "3", "4"]);
+        assert!(ring.pop().is_none());
+    }
+}
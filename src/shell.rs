@@ -0,0 +1,223 @@
+//! Shell plugins, exposing several plugins from a single library.
+//!
+//! When scanning a shell, the host first loads the shell itself and asks it for the unique IDs
+//! and names of the plugins it contains. To load one of them, the host loads the library again
+//! and answers `host::OpCode::CurrentId` with the unique ID of the requested plugin.
+//!
+//! All of this is handled by `plugin_shell_main!`, which takes the name and unique ID of the
+//! shell, and a list of [`ShellPlugin`](struct.ShellPlugin.html) entries.
+//!
+//! # Example
+//!
+//! ```no_run
+//! #[macro_use]
+//! extern crate vst2;
+//!
+//! use vst2::plugin::{Info, Plugin};
+//! use vst2::shell::ShellPlugin;
+//!
+//! #[derive(Default)]
+//! struct Delay;
+//!
+//! impl Plugin for Delay {
+//!     fn get_info(&self) -> Info {
+//!         Info { name: "Delay".to_string(), unique_id: 1001, ..Default::default() }
+//!     }
+//! }
+//!
+//! #[derive(Default)]
+//! struct Reverb;
+//!
+//! impl Plugin for Reverb {
+//!     fn get_info(&self) -> Info {
+//!         Info { name: "Reverb".to_string(), unique_id: 1002, ..Default::default() }
+//!     }
+//! }
+//!
+//! static PLUGINS: &'static [ShellPlugin] = &[
+//!     ShellPlugin { unique_id: 1001, name: "Delay", create: vst2::main::<Delay> },
+//!     ShellPlugin { unique_id: 1002, name: "Reverb", create: vst2::main::<Reverb> },
+//! ];
+//!
+//! plugin_shell_main!("Space Suite", 1000, PLUGINS);
+//! # fn main() {}
+//! ```
+
+use std::ptr;
+
+use api::{AEffect, HostCallbackProc};
+use host;
+use plugin::{Category, Info, Plugin};
+
+/// A plugin contained in a shell.
+#[derive(Clone, Copy)]
+pub struct ShellPlugin {
+    /// Unique ID of the plugin, which must match the one in its `Info`.
+    pub unique_id: i32,
+
+    /// Name of the plugin shown by the host, limited to `consts::MAX_PRODUCT_STR_LEN` bytes.
+    pub name: &'static str,
+
+    /// Creates the plugin, usually `vst2::main::<T>`.
+    pub create: fn(HostCallbackProc) -> *mut AEffect,
+}
+
+/// Exports the necessary symbols for a shell plugin to be used by a VST host.
+///
+/// This macro takes the name and unique ID of the shell, and a `&'static [shell::ShellPlugin]`
+/// listing the contained plugins. See the `shell` module for an example.
+#[macro_export]
+macro_rules! plugin_shell_main {
+    ($name:expr, $unique_id:expr, $plugins:expr) => {
+        #[cfg(target_os = "macos")]
+        #[no_mangle]
+        pub extern "system" fn main_macho(callback: $crate::api::HostCallbackProc) -> *mut $crate::api::AEffect {
+            VSTPluginMain(callback)
+        }
+
+        #[cfg(target_os = "windows")]
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "system" fn MAIN(callback: $crate::api::HostCallbackProc) -> *mut $crate::api::AEffect {
+            VSTPluginMain(callback)
+        }
+
+        #[allow(non_snake_case)]
+        #[no_mangle]
+        pub extern "system" fn VSTPluginMain(callback: $crate::api::HostCallbackProc) -> *mut $crate::api::AEffect {
+            $crate::shell::main(callback, $name, $unique_id, $plugins)
+        }
+    }
+}
+
+/// Create the plugin requested by the host, or the shell itself if none is requested.
+#[doc(hidden)]
+pub fn main(callback: HostCallbackProc,
+            name: &'static str,
+            unique_id: i32,
+            plugins: &'static [ShellPlugin])
+            -> *mut AEffect {
+    let requested = callback(ptr::null_mut(), host::OpCode::CurrentId.into(),
+                             0, 0, ptr::null_mut(), 0.0) as i32;
+
+    match plugins.iter().find(|p| requested != 0 && p.unique_id == requested) {
+        Some(plugin) => (plugin.create)(callback),
+        None => {
+            ::create_effect(callback, |_| {
+                Shell {
+                    name: name,
+                    unique_id: unique_id,
+                    plugins: plugins,
+                    next: 0,
+                }
+            })
+        }
+    }
+}
+
+/// The plugin reported to the host while scanning the shell.
+struct Shell {
+    name: &'static str,
+    unique_id: i32,
+    plugins: &'static [ShellPlugin],
+    next: usize,
+}
+
+impl Plugin for Shell {
+    fn get_info(&self) -> Info {
+        Info {
+            name: self.name.to_string(),
+            unique_id: self.unique_id,
+            category: Category::Shell,
+            presets: 0,
+            inputs: 0,
+            outputs: 0,
+            ..Default::default()
+        }
+    }
+
+    fn next_shell_plugin(&mut self) -> Option<(i32, String)> {
+        let plugin = self.plugins.get(self.next);
+        self.next += 1;
+        plugin.map(|p| (p.unique_id, p.name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use libc::c_void;
+
+    use api::AEffect;
+    use host;
+    use plugin::{self, Info, Plugin};
+    use shell::{self, ShellPlugin};
+
+    #[derive(Default)]
+    struct Delay;
+
+    impl Plugin for Delay {
+        fn get_info(&self) -> Info { Info { unique_id: 1001, ..Default::default() } }
+    }
+
+    #[derive(Default)]
+    struct Reverb;
+
+    impl Plugin for Reverb {
+        fn get_info(&self) -> Info { Info { unique_id: 1002, ..Default::default() } }
+    }
+
+    static PLUGINS: &'static [ShellPlugin] = &[
+        ShellPlugin { unique_id: 1001, name: "Delay", create: ::main::<Delay> },
+        ShellPlugin { unique_id: 1002, name: "Reverb", create: ::main::<Reverb> },
+    ];
+
+    fn scan_callback(_effect: *mut AEffect, opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
+        match host::OpCode::from(opcode) {
+            host::OpCode::CurrentId => 0,
+            host::OpCode::Version => 2400,
+            _ => 1,
+        }
+    }
+
+    fn load_callback(_effect: *mut AEffect, opcode: i32, _index: i32, _value: isize, _ptr: *mut c_void, _opt: f32) -> isize {
+        match host::OpCode::from(opcode) {
+            host::OpCode::CurrentId => 1002,
+            host::OpCode::Version => 2400,
+            _ => 1,
+        }
+    }
+
+    /// Test that the shell lists all plugins while scanning.
+    #[test]
+    fn scan() {
+        let aeffect = shell::main(scan_callback, "Suite", 1000, PLUGINS);
+        assert_eq!(unsafe { (*aeffect).uniqueId }, 1000);
+
+        let next = || {
+            let mut name = [0u8; 64];
+            let id = unsafe {
+                ((*aeffect).dispatcher)(aeffect, plugin::OpCode::ShellGetNextPlugin.into(), 0, 0,
+                                        name.as_mut_ptr() as *mut c_void, 0.0)
+            };
+            (id, ::read_fixed(&name))
+        };
+
+        assert_eq!(next(), (1001, "Delay".to_string()));
+        assert_eq!(next(), (1002, "Reverb".to_string()));
+        assert_eq!(next(), (0, String::new()));
+    }
+
+    /// Test that the plugin requested by the host is created.
+    #[test]
+    fn load() {
+        let aeffect = shell::main(load_callback, "Suite", 1000, PLUGINS);
+        assert_eq!(unsafe { (*aeffect).uniqueId }, 1002);
+
+        unsafe {
+            ((*aeffect).dispatcher)(aeffect, plugin::OpCode::Shutdown.into(), 0, 0,
+                                    ptr::null_mut(), 0.0);
+        }
+    }
+}
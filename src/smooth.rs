@@ -0,0 +1,254 @@
+//! Parameter smoothing to avoid zipper noise.
+//!
+//! A [`Smoother`](struct.Smoother.html) receives new target values from `Plugin::set_parameter`
+//! and glides towards them while processing, either sample by sample or a whole block at a time.
+//! It never allocates, so it can be used on the audio thread.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! # use vst2::smooth::{Smoother, SmoothingStyle};
+//! struct Gain {
+//!     gain: Smoother,
+//! }
+//!
+//! impl Plugin for Gain {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn sample_rate_changed(&mut self, rate: f32) {
+//!         self.gain.set_sample_rate(rate);
+//!     }
+//!
+//!     fn get_parameter(&self, _: i32) -> f32 { self.gain.target() }
+//!
+//!     fn set_parameter(&mut self, _: i32, value: f32) {
+//!         self.gain.set_target(value);
+//!     }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         let (inputs, mut outputs) = buffer.split_mut();
+//!
+//!         for i in 0..outputs[0].len() {
+//!             let gain = self.gain.next_value();
+//!             for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+//!                 output[i] = input[i] * gain;
+//!             }
+//!         }
+//!     }
+//! }
+//! # impl Default for Gain {
+//! #     fn default() -> Gain { Gain { gain: Smoother::new(SmoothingStyle::Linear(0.01), 1.0) } }
+//! # }
+//! ```
+
+/// How a `Smoother` moves towards its target. Each style takes the smoothing time in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SmoothingStyle {
+    /// Move at a constant rate, reaching the target after exactly the given time.
+    Linear(f32),
+
+    /// Move by a constant factor, reaching the target after exactly the given time. Suited to
+    /// frequencies and other values perceived logarithmically.
+    ///
+    /// Only works for positive values; other changes are applied immediately.
+    Exponential(f32),
+
+    /// Approach the target asymptotically like a one-pole lowpass filter, covering about two
+    /// thirds of the distance within the given time.
+    OnePole(f32),
+}
+
+/// A parameter value gliding towards a target.
+#[derive(Clone, Debug)]
+pub struct Smoother {
+    style: SmoothingStyle,
+    sample_rate: f32,
+
+    value: f32,
+    target: f32,
+
+    /// Samples left until the target is reached, for the linear and exponential styles.
+    steps_left: u32,
+    /// Increment for the linear style, factor for the exponential style or filter coefficient for
+    /// the one-pole style.
+    step: f32,
+}
+
+/// Distance to the target at which a one-pole smoother snaps to the target.
+const ONE_POLE_EPSILON: f32 = 1e-6;
+
+impl Smoother {
+    /// Create a smoother at `value`, using a sample rate of 44.1 kHz until `set_sample_rate` is
+    /// called.
+    pub fn new(style: SmoothingStyle, value: f32) -> Smoother {
+        Smoother {
+            style: style,
+            sample_rate: 44100.0,
+            value: value,
+            target: value,
+            steps_left: 0,
+            step: 0.0,
+        }
+    }
+
+    /// Set the sample rate used to convert the smoothing time. Any ongoing glide is finished.
+    pub fn set_sample_rate(&mut self, sample_rate: f32) {
+        self.sample_rate = sample_rate;
+        let target = self.target;
+        self.reset(target);
+    }
+
+    /// Set the smoothing style. Any ongoing glide is finished.
+    pub fn set_style(&mut self, style: SmoothingStyle) {
+        self.style = style;
+        let target = self.target;
+        self.reset(target);
+    }
+
+    /// Jump to `value` immediately, e.g. when loading a preset or resuming.
+    pub fn reset(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+        self.steps_left = 0;
+    }
+
+    /// Start gliding from the current value towards `target`.
+    pub fn set_target(&mut self, target: f32) {
+        self.target = target;
+
+        let (time, linear) = match self.style {
+            SmoothingStyle::Linear(time) => (time, true),
+            SmoothingStyle::Exponential(time) => (time, false),
+            SmoothingStyle::OnePole(time) => {
+                self.step = if time > 0.0 && self.sample_rate > 0.0 {
+                    (-1.0 / (time * self.sample_rate)).exp()
+                } else {
+                    0.0
+                };
+                return;
+            }
+        };
+
+        let steps = (time * self.sample_rate).round();
+        if steps < 1.0 || (!linear && (self.value <= 0.0 || target <= 0.0)) {
+            self.reset(target);
+            return;
+        }
+
+        self.steps_left = steps as u32;
+        self.step = if linear {
+            (target - self.value) / steps
+        } else {
+            (target / self.value).powf(1.0 / steps)
+        };
+    }
+
+    /// The value being glided towards.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    /// The current value, without advancing.
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    /// Whether the value is still moving towards the target.
+    pub fn is_smoothing(&self) -> bool {
+        self.value != self.target
+    }
+
+    /// Advance by one sample and return the new value.
+    pub fn next_value(&mut self) -> f32 {
+        self.advance(1)
+    }
+
+    /// Advance by `samples` samples at once and return the new value.
+    ///
+    /// Useful to update a value once per block instead of every sample.
+    pub fn advance(&mut self, samples: u32) -> f32 {
+        if !self.is_smoothing() || samples == 0 {
+            return self.value;
+        }
+
+        match self.style {
+            SmoothingStyle::Linear(_) | SmoothingStyle::Exponential(_) => {
+                if samples >= self.steps_left {
+                    self.value = self.target;
+                    self.steps_left = 0;
+                } else {
+                    self.steps_left -= samples;
+                    self.value = match self.style {
+                        SmoothingStyle::Linear(_) => self.value + self.step * samples as f32,
+                        _ => self.value * self.step.powi(samples as i32),
+                    };
+                }
+            }
+            SmoothingStyle::OnePole(_) => {
+                let decay = self.step.powi(samples as i32);
+                self.value = self.target + (self.value - self.target) * decay;
+
+                if (self.value - self.target).abs() < ONE_POLE_EPSILON {
+                    self.value = self.target;
+                }
+            }
+        }
+
+        self.value
+    }
+
+    /// Fill `values` with the values of the next samples, advancing past them.
+    pub fn fill(&mut self, values: &mut [f32]) {
+        for value in values {
+            *value = self.next_value();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use smooth::{Smoother, SmoothingStyle};
+
+    /// Test that linear and exponential smoothers reach the target after exactly the given time.
+    #[test]
+    fn ramps() {
+        let mut linear = Smoother::new(SmoothingStyle::Linear(0.01), 0.0);
+        linear.set_sample_rate(1000.0);
+        linear.set_target(1.0);
+
+        let mut values = [0.0; 4];
+        linear.fill(&mut values);
+        assert!((values[0] - 0.1).abs() < 1e-6 && (values[3] - 0.4).abs() < 1e-6);
+        assert!((linear.advance(5) - 0.9).abs() < 1e-6);
+        assert_eq!(linear.advance(5), 1.0);
+        assert!(!linear.is_smoothing());
+
+        let mut exponential = Smoother::new(SmoothingStyle::Exponential(0.002), 100.0);
+        exponential.set_sample_rate(1000.0);
+        exponential.set_target(400.0);
+        assert!((exponential.next_value() - 200.0).abs() < 1e-3);
+        assert_eq!(exponential.next_value(), 400.0);
+
+        // Non-positive values jump immediately
+        exponential.set_target(0.0);
+        assert_eq!(exponential.value(), 0.0);
+    }
+
+    /// Test that one-pole smoothers approach and finally settle on the target.
+    #[test]
+    fn one_pole() {
+        let mut smoother = Smoother::new(SmoothingStyle::OnePole(0.01), 0.0);
+        smoother.set_sample_rate(1000.0);
+        smoother.set_target(1.0);
+
+        let value = smoother.advance(10);
+        assert!(value > 0.6 && value < 0.7);
+        assert!(smoother.is_smoothing());
+
+        smoother.advance(1000);
+        assert_eq!(smoother.value(), 1.0);
+        assert!(!smoother.is_smoothing());
+    }
+}
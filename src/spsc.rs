@@ -0,0 +1,265 @@
+//! Wait-free queue between exactly one sending and one receiving thread.
+//!
+//! This is the building block for talking between the editor or host thread and `process`: the
+//! editor pushes parameter changes which `process` pops at the start of every block, and `process`
+//! pushes meter values or MIDI events which the editor pops when it redraws. Neither side ever
+//! blocks or allocates after the queue has been created, so it is safe to use on the audio thread.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::spsc::{self, Consumer, Producer};
+//!
+//! /// A parameter changed in the editor.
+//! struct ParameterChange {
+//!     index: i32,
+//!     value: f32,
+//! }
+//!
+//! struct Gain {
+//!     gain: f32,
+//!     // Given to the editor when it is created
+//!     editor_changes: Option<Producer<ParameterChange>>,
+//!     changes: Consumer<ParameterChange>,
+//! }
+//!
+//! impl Default for Gain {
+//!     fn default() -> Gain {
+//!         let (producer, consumer) = spsc::channel(64);
+//!         Gain { gain: 1.0, editor_changes: Some(producer), changes: consumer }
+//!     }
+//! }
+//!
+//! impl Plugin for Gain {
+//!     fn get_info(&self) -> Info { Default::default() }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         while let Some(change) = self.changes.pop() {
+//!             if change.index == 0 {
+//!                 self.gain = change.value;
+//!             }
+//!         }
+//!
+//!         buffer.copy_inputs_to_outputs();
+//!         // ...
+//!     }
+//! }
+//! ```
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Storage shared by both ends of the queue.
+struct Inner<T> {
+    slots: Vec<UnsafeCell<Option<T>>>,
+
+    /// Number of elements pushed so far, wrapping. Only written by the producer.
+    write: AtomicUsize,
+    /// Number of elements popped so far, wrapping. Only written by the consumer.
+    read: AtomicUsize,
+}
+
+// Each slot is only accessed by one side at a time, as decided by the positions.
+unsafe impl<T: Send> Sync for Inner<T> {}
+
+impl<T> Inner<T> {
+    fn len(&self) -> usize {
+        self.write.load(Ordering::Acquire).wrapping_sub(self.read.load(Ordering::Acquire))
+    }
+
+    fn slot(&self, pos: usize) -> *mut Option<T> {
+        self.slots[pos % self.slots.len()].get()
+    }
+}
+
+/// Create a queue holding up to `capacity` elements, returning its sending and receiving end.
+///
+/// # Panics
+///
+/// Panics if `capacity` is 0.
+pub fn channel<T: Send>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+    assert!(capacity > 0, "Queue capacity must not be 0");
+
+    let inner = Arc::new(Inner {
+        slots: (0..capacity).map(|_| UnsafeCell::new(None)).collect(),
+        write: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+    });
+
+    (Producer { inner: inner.clone() }, Consumer { inner: inner })
+}
+
+/// The sending end of a queue, see `channel`.
+pub struct Producer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T: Send> Producer<T> {
+    /// Push an element, or hand it back if the queue is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        let write = self.inner.write.load(Ordering::Relaxed);
+        if write.wrapping_sub(self.inner.read.load(Ordering::Acquire)) == self.capacity() {
+            return Err(value);
+        }
+
+        unsafe { *self.inner.slot(write) = Some(value); }
+        self.inner.write.store(write.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+
+    /// Number of elements waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the queue is full, so the next `push` fails unless the consumer pops first.
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    /// Maximum number of elements in the queue.
+    pub fn capacity(&self) -> usize {
+        self.inner.slots.len()
+    }
+}
+
+impl<T> fmt::Debug for Producer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Producer {{ len: {} }}", self.inner.len())
+    }
+}
+
+/// The receiving end of a queue, see `channel`.
+pub struct Consumer<T> {
+    inner: Arc<Inner<T>>,
+}
+
+impl<T: Send> Consumer<T> {
+    /// Pop the oldest element, or `None` if the queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let read = self.inner.read.load(Ordering::Relaxed);
+        if self.inner.write.load(Ordering::Acquire) == read {
+            return None;
+        }
+
+        let value = unsafe { (*self.inner.slot(read)).take() };
+        self.inner.read.store(read.wrapping_add(1), Ordering::Release);
+        value
+    }
+
+    /// Pop all waiting elements and return only the newest, e.g. to show the latest meter value.
+    pub fn pop_latest(&mut self) -> Option<T> {
+        let mut latest = None;
+        while let Some(value) = self.pop() {
+            latest = Some(value);
+        }
+        latest
+    }
+
+    /// Iterate over the waiting elements, popping them.
+    ///
+    /// Elements pushed while iterating are included.
+    pub fn drain(&mut self) -> Drain<T> {
+        Drain { consumer: self }
+    }
+
+    /// Number of elements waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Maximum number of elements in the queue.
+    pub fn capacity(&self) -> usize {
+        self.inner.slots.len()
+    }
+}
+
+impl<T> fmt::Debug for Consumer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Consumer {{ len: {} }}", self.inner.len())
+    }
+}
+
+/// Iterator popping the elements of a queue, see `Consumer::drain`.
+pub struct Drain<'a, T: 'a> {
+    consumer: &'a mut Consumer<T>,
+}
+
+impl<'a, T: Send> Iterator for Drain<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.consumer.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use spsc;
+
+    /// Test that elements are received in order and that a full queue rejects elements.
+    #[test]
+    fn push_pop() {
+        let (mut producer, mut consumer) = spsc::channel(2);
+
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert_eq!(producer.push(3), Err(3));
+        assert!(producer.is_full());
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(consumer.drain().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(consumer.pop(), None);
+
+        producer.push(4).unwrap();
+        producer.push(5).unwrap();
+        assert_eq!(consumer.pop_latest(), Some(5));
+        assert!(consumer.is_empty());
+    }
+
+    /// Test passing elements between threads.
+    #[test]
+    fn threads() {
+        let (mut producer, mut consumer) = spsc::channel(16);
+
+        let sender = thread::spawn(move || {
+            for i in 0..10000 {
+                let mut value = i;
+                while let Err(rejected) = producer.push(value) {
+                    value = rejected;
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 10000 {
+            match consumer.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+
+        sender.join().unwrap();
+    }
+}
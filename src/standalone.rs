@@ -0,0 +1,420 @@
+//! Running plugins as standalone applications.
+//!
+//! This module is only available with the `standalone` feature. [`run`](fn.run.html) hosts a
+//! plugin type directly on the system's default audio devices through CPAL, routes a MIDI input
+//! port to it through midir and provides a playing transport, so any plugin written with this
+//! crate can be tested or played live without a DAW.
+//!
+//! The plugin is created through the same entry point a host uses, so it sees a host answering
+//! `HostCallback` requests such as the sample rate, block size and time info.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # extern crate vst2;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::standalone::{self, Settings};
+//!
+//! # #[derive(Default)]
+//! # struct MySynth;
+//! # impl Plugin for MySynth {
+//! #     fn get_info(&self) -> Info { Default::default() }
+//! # }
+//! fn main() {
+//!     let settings = Settings { tempo: 128.0, ..Default::default() };
+//!
+//!     if let Err(error) = standalone::run::<MySynth>(settings) {
+//!         println!("Could not start: {}", error);
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::sync::mpsc;
+use std::{cmp, fmt, mem, ptr};
+
+use cpal;
+use libc::c_void;
+use midir::{MidiInput, MidiInputConnection};
+
+use api::{self, AEffect};
+use event::{Event, MidiEvent, SendEventBuffer};
+use host::{Host, SharedTimeInfo, Transport};
+use interfaces;
+use plugin::{OpCode, Plugin};
+
+/// Settings of a standalone run.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Sample rate to open the audio devices with, or `None` for the output device default.
+    pub sample_rate: Option<u32>,
+
+    /// Maximum number of samples passed to the plugin at once. Larger device buffers are split.
+    ///
+    /// A block size of 0 is treated as 1.
+    pub block_size: usize,
+
+    /// Index of the MIDI input port to connect, or `None` for the first port if there is one.
+    pub midi_port: Option<usize>,
+
+    /// Tempo of the transport in beats per minute.
+    pub tempo: f64,
+
+    /// Time signature of the transport as numerator and denominator.
+    pub time_signature: (i32, i32),
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            sample_rate: None,
+            block_size: 512,
+            midi_port: None,
+            tempo: 120.0,
+            time_signature: (4, 4),
+        }
+    }
+}
+
+/// Error setting up the audio or MIDI devices.
+#[derive(Debug)]
+pub enum StandaloneError {
+    /// There is no default audio output device.
+    NoOutputDevice,
+
+    /// The audio devices could not be opened.
+    Audio(String),
+
+    /// The MIDI input could not be opened.
+    Midi(String),
+}
+
+impl fmt::Display for StandaloneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StandaloneError::Audio(ref message) | StandaloneError::Midi(ref message) => {
+                write!(f, "{}: {}", self.description(), message)
+            }
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for StandaloneError {
+    fn description(&self) -> &str {
+        use self::StandaloneError::*;
+
+        match *self {
+            NoOutputDevice => "No audio output device found",
+            Audio(_) => "Could not open the audio devices",
+            Midi(_) => "Could not open the MIDI input",
+        }
+    }
+}
+
+/// State of the host, shared by the audio callback and the host callbacks of the plugin.
+struct HostState {
+    sample_rate: f32,
+    block_size: i64,
+    /// Set by the audio callback for every block.
+    time_info: SharedTimeInfo,
+}
+
+/// Host answering the callbacks of the standalone plugin.
+///
+/// It only reads the `HostState`, so each callback gets its own host and no lock is taken, even
+/// when the plugin asks for the time info while processing.
+struct StandaloneHost<'a> {
+    state: &'a HostState,
+}
+
+impl<'a> Host for StandaloneHost<'a> {
+    fn get_sample_rate(&self) -> f32 { self.state.sample_rate }
+
+    fn get_block_size(&self) -> i64 { self.state.block_size }
+
+    fn get_time_info(&self, _: i32) -> Option<api::TimeInfo> { Some(self.state.time_info.get()) }
+
+    fn get_product_string(&self) -> String { "rust-vst2 standalone".to_string() }
+}
+
+/// Run plugin `P` on the default audio devices until the process exits.
+///
+/// Only returns if the devices could not be opened. The plugin receives audio from the default
+/// input device if it has inputs, and MIDI from the selected port if there is one.
+pub fn run<P: Plugin + Default>(settings: Settings) -> Result<(), StandaloneError> {
+    let event_loop = cpal::EventLoop::new();
+
+    let output = try!(cpal::default_output_device().ok_or(StandaloneError::NoOutputDevice));
+    let mut format = try!(output.default_output_format()
+                                .map_err(|e| StandaloneError::Audio(format!("{:?}", e))));
+    if let Some(rate) = settings.sample_rate {
+        format.sample_rate = cpal::SampleRate(rate);
+    }
+    let output_stream = try!(event_loop.build_output_stream(&output, &format)
+                                       .map_err(|e| StandaloneError::Audio(format!("{:?}", e))));
+
+    let (sender, receiver) = mpsc::channel();
+    let _connection = try!(connect_midi(settings.midi_port, sender));
+
+    let sample_rate = format.sample_rate.0 as f32;
+    let block_size = cmp::max(settings.block_size, 1);
+
+    let mut transport = Transport::new(sample_rate as f64);
+    transport.set_tempo(settings.tempo);
+    transport.set_time_signature(settings.time_signature.0, settings.time_signature.1);
+    transport.play();
+
+    // Lives as long as the plugin, which is never freed
+    let state: &'static HostState = unsafe {
+        &*Box::into_raw(Box::new(HostState {
+            sample_rate: sample_rate,
+            block_size: block_size as i64,
+            time_info: SharedTimeInfo::new(transport.time_info()),
+        }))
+    };
+
+    // Create the plugin like a host would, then make the host available to its callbacks
+    let effect = ::main::<P>(standalone_callback);
+    unsafe {
+        (*effect).reserved1 = state as *const HostState as isize;
+        // Remember the initial latency so that only changes are reported to the host
+        (*effect).reserved2 = (*effect).initialDelay as isize;
+    }
+    let info = unsafe { (*effect).get_plugin() }.get_info();
+
+    // Only feed the input device to plugins which use it
+    let mut input_channels = 0;
+    if info.inputs > 0 {
+        if let Some(input) = cpal::default_input_device() {
+            let mut input_format = format.clone();
+            input_format.channels = try!(input.default_input_format()
+                                              .map_err(|e| {
+                                                  StandaloneError::Audio(format!("{:?}", e))
+                                              })).channels;
+
+            let stream = try!(event_loop.build_input_stream(&input, &input_format)
+                                        .map_err(|e| StandaloneError::Audio(format!("{:?}", e))));
+            event_loop.play_stream(stream);
+            input_channels = input_format.channels as usize;
+        }
+    }
+
+    // Set up the plugin through its dispatcher, so the wrapper reserves its buffers for the
+    // block size like it does in a host
+    let dispatch = |opcode: OpCode, value: isize, opt: f32| unsafe {
+        ((*effect).dispatcher)(effect, opcode.into(), 0, value, ptr::null_mut(), opt)
+    };
+    dispatch(OpCode::Initialize, 0, 0.0);
+    dispatch(OpCode::SetSampleRate, 0, sample_rate);
+    dispatch(OpCode::SetBlockSize, block_size as isize, 0.0);
+    dispatch(OpCode::StateChanged, 1, 0.0);
+    dispatch(OpCode::StartProcess, 0, 0.0);
+
+    let (num_inputs, num_outputs) = unsafe {
+        ((*effect).numInputs.max(0) as usize, (*effect).numOutputs.max(0) as usize)
+    };
+    let mut inputs = vec![vec![0.0; block_size]; num_inputs];
+    let mut outputs = vec![vec![0.0; block_size]; num_outputs];
+    // The channels are never resized, so the pointers stay valid
+    let input_ptrs = inputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+    let output_ptrs = outputs.iter_mut().map(|c| c.as_mut_ptr()).collect();
+
+    let mut processor = Processor {
+        effect: effect,
+        state: state,
+        transport: transport,
+        midi: receiver,
+        events: SendEventBuffer::new(MIDI_CAPACITY, 0),
+        input_fifo: VecDeque::with_capacity(block_size * input_channels * INPUT_BLOCKS),
+        input_channels: input_channels,
+        output_channels: format.channels as usize,
+        inputs: inputs,
+        outputs: outputs,
+        input_ptrs: input_ptrs,
+        output_ptrs: output_ptrs,
+        block_size: block_size,
+    };
+
+    event_loop.play_stream(output_stream);
+    event_loop.run(move |_, data| {
+        use cpal::StreamData::{Input, Output};
+        use cpal::UnknownTypeInputBuffer as In;
+        use cpal::UnknownTypeOutputBuffer as Out;
+
+        match data {
+            Input { buffer: In::F32(buffer) } => processor.push_input(buffer.iter().cloned()),
+            Input { buffer: In::I16(buffer) } => {
+                processor.push_input(buffer.iter().map(|&s| s as f32 / 32768.0));
+            }
+            Input { buffer: In::U16(buffer) } => {
+                processor.push_input(buffer.iter().map(|&s| s as f32 / 32768.0 - 1.0));
+            }
+            Output { buffer: Out::F32(mut buffer) } => processor.process(&mut buffer[..], |s| s),
+            Output { buffer: Out::I16(mut buffer) } => {
+                processor.process(&mut buffer[..], |s| (s.max(-1.0).min(1.0) * 32767.0) as i16);
+            }
+            Output { buffer: Out::U16(mut buffer) } => {
+                processor.process(&mut buffer[..], |s| {
+                    ((s.max(-1.0).min(1.0) + 1.0) * 32767.5) as u16
+                });
+            }
+        }
+    });
+}
+
+/// Connect the MIDI input port `port`, or the first port if `None`, forwarding its messages.
+fn connect_midi(port: Option<usize>, sender: mpsc::Sender<[u8; 3]>)
+                -> Result<Option<MidiInputConnection<()>>, StandaloneError> {
+    let input = try!(MidiInput::new("rust-vst2 standalone")
+                         .map_err(|e| StandaloneError::Midi(format!("{:?}", e))));
+
+    let port = match port {
+        Some(port) => port,
+        None if input.port_count() > 0 => 0,
+        None => return Ok(None),
+    };
+
+    input.connect(port, "input", move |_, message, _| {
+        if !message.is_empty() && message.len() <= 3 {
+            let mut data = [0; 3];
+            data[..message.len()].copy_from_slice(message);
+            let _ = sender.send(data);
+        }
+    }, ())
+         .map(Some)
+         .map_err(|e| StandaloneError::Midi(format!("{:?}", e)))
+}
+
+/// Number of MIDI messages passed to the plugin per device buffer. Later ones wait for the next.
+const MIDI_CAPACITY: usize = 256;
+
+/// Number of blocks of input kept when the output falls behind.
+const INPUT_BLOCKS: usize = 4;
+
+/// State of the audio callback.
+///
+/// Everything is allocated up front, and the plugin is called through its `AEffect` like a host
+/// would, so events are sorted and denormals flushed by the wrapper.
+struct Processor {
+    effect: *mut AEffect,
+    state: &'static HostState,
+    transport: Transport,
+    midi: mpsc::Receiver<[u8; 3]>,
+    events: SendEventBuffer,
+
+    /// Interleaved samples received from the input device and not processed yet.
+    input_fifo: VecDeque<f32>,
+    input_channels: usize,
+    output_channels: usize,
+
+    inputs: Vec<Vec<f32>>,
+    outputs: Vec<Vec<f32>>,
+    /// Pointers to the channels of `inputs` and `outputs`, passed to the plugin.
+    input_ptrs: Vec<*mut f32>,
+    output_ptrs: Vec<*mut f32>,
+    block_size: usize,
+}
+
+// The plugin is only used from the audio callback after setup.
+unsafe impl Send for Processor {}
+
+impl Processor {
+    fn push_input<I: Iterator<Item = f32>>(&mut self, samples: I) {
+        // Drop the oldest input if the output fell behind, to keep the latency bounded and stay
+        // within the reserved capacity
+        let limit = self.block_size * self.input_channels * INPUT_BLOCKS;
+        if limit == 0 {
+            return;
+        }
+        for sample in samples {
+            if self.input_fifo.len() == limit {
+                self.input_fifo.pop_front();
+            }
+            self.input_fifo.push_back(sample);
+        }
+    }
+
+    /// Fill an interleaved output buffer, converting samples with `convert`.
+    fn process<T, F: Fn(f32) -> T>(&mut self, buffer: &mut [T], convert: F) {
+        let frames = buffer.len() / cmp::max(self.output_channels, 1);
+
+        self.events.clear();
+        while self.events.len() < MIDI_CAPACITY {
+            match self.midi.try_recv() {
+                Ok(data) => {
+                    self.events.push(Event::Midi(MidiEvent { data: data, ..Default::default() }));
+                }
+                Err(_) => break,
+            }
+        }
+        if !self.events.is_empty() {
+            unsafe {
+                ((*self.effect).dispatcher)(self.effect, OpCode::ProcessEvents.into(), 0, 0,
+                                            self.events.as_raw() as *mut c_void, 0.0);
+            }
+        }
+
+        let mut done = 0;
+        while done < frames {
+            let len = cmp::min(self.block_size, frames - done);
+
+            for i in 0..len {
+                for channel in 0..self.input_channels {
+                    let sample = self.input_fifo.pop_front().unwrap_or(0.0);
+                    if let Some(input) = self.inputs.get_mut(channel) {
+                        input[i] = sample;
+                    }
+                }
+            }
+            for input in self.inputs.iter_mut().skip(self.input_channels) {
+                for sample in &mut input[..len] {
+                    *sample = 0.0;
+                }
+            }
+
+            self.state.time_info.set(self.transport.time_info());
+            unsafe {
+                ((*self.effect).processReplacing)(self.effect,
+                                                  self.input_ptrs.as_mut_ptr(),
+                                                  self.output_ptrs.as_mut_ptr(),
+                                                  len as i32);
+            }
+            self.transport.advance(len);
+
+            // A mono plugin is played on all channels, otherwise channels are matched by index
+            for i in 0..len {
+                for channel in 0..self.output_channels {
+                    let source = if self.outputs.len() == 1 { 0 } else { channel };
+                    let sample = self.outputs.get(source).map_or(0.0, |output| output[i]);
+                    buffer[(done + i) * self.output_channels + channel] = convert(sample);
+                }
+            }
+
+            done += len;
+        }
+    }
+}
+
+/// Function passed to the plugin to handle host opcodes.
+fn standalone_callback(effect: *mut AEffect, opcode: i32, index: i32, value: isize,
+                       ptr: *mut c_void, opt: f32) -> isize {
+    unsafe {
+        // The host is only set once the plugin has been created
+        if !effect.is_null() && (*effect).reserved1 != 0 {
+            let mut host = StandaloneHost { state: &*((*effect).reserved1 as *const HostState) };
+
+            interfaces::host_dispatch(&mut host, effect, opcode, index, value, ptr, opt)
+        } else {
+            let state = HostState {
+                sample_rate: 0.0,
+                block_size: 0,
+                time_info: SharedTimeInfo::new(mem::zeroed()),
+            };
+
+            interfaces::host_dispatch(&mut StandaloneHost { state: &state },
+                                      effect, opcode, index, value, ptr, opt)
+        }
+    }
+}
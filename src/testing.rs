@@ -0,0 +1,583 @@
+//! Unit testing plugins with a scripted host.
+//!
+//! [`TestHost::load`](struct.TestHost.html#method.load) creates a plugin through the same entry
+//! point a real host uses, so the plugin's `HostCallback` talks to the `TestHost`. The returned
+//! [`TestInstance`](struct.TestInstance.html) drives the plugin through its lifecycle: it is
+//! initialized, given the sample rate and block size and resumed when loaded, and suspended and
+//! shut down when dropped. In between, tests schedule MIDI events and parameter automation at
+//! sample positions, process audio and assert on the output and on everything the plugin asked the
+//! host to do.
+//!
+//! All calls go through the dispatcher, parameter and process functions of the plugin's
+//! `AEffect`, as in a real host, so the wrapper's handling of opcodes, events, denormals, panics
+//! and `f64` processing is tested along with the plugin.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::testing::TestHost;
+//!
+//! # #[derive(Default)]
+//! # struct Gain { gain: f32 }
+//! # impl Plugin for Gain {
+//! #     fn get_info(&self) -> Info { Info { inputs: 1, outputs: 1, ..Default::default() } }
+//! #     fn set_parameter(&mut self, _: i32, value: f32) { self.gain = value; }
+//! #     fn process(&mut self, buffer: AudioBuffer<f32>) {
+//! #         for (input, output) in buffer.zip() {
+//! #             for (i, o) in input.iter().zip(output.iter_mut()) { *o = *i * self.gain; }
+//! #         }
+//! #     }
+//! # }
+//! let mut instance = TestHost::new(44100.0, 64).load::<Gain>();
+//!
+//! // Turn the gain up halfway through the next call
+//! instance.automate(2, 0, 0.5);
+//! let output = instance.process(&[&[1.0; 4]]);
+//!
+//! assert_eq!(output, vec![vec![0.0, 0.0, 0.5, 0.5]]);
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{cmp, mem, ptr};
+
+use libc::c_void;
+use num::Float;
+
+use api::{self, AEffect};
+use automation::{self, ParameterChange};
+use buffer::AudioBuffer;
+use event::{Event, MidiEvent, SendEventBuffer};
+use host::{Host, Transport};
+use interfaces;
+use midi_file::MidiFile;
+use plugin::{Info, OpCode, Plugin};
+use render::RenderEvent;
+
+/// A host recording the requests of a plugin under test.
+#[derive(Clone, Debug)]
+pub struct TestHost {
+    /// Sample rate reported to the plugin.
+    pub sample_rate: f32,
+
+    /// Maximum block size reported to the plugin.
+    pub block_size: i64,
+
+    /// Time info reported to the plugin, updated before every processed block.
+    pub time_info: api::TimeInfo,
+
+    /// Parameter index and value of every automation request.
+    pub automated: Vec<(i32, f32)>,
+
+    /// Parameter index of every edit gesture, and whether the gesture began or ended.
+    pub edits: Vec<(i32, bool)>,
+
+    /// Number of display update requests.
+    pub display_updates: usize,
+
+    /// Every latency reported through `io_changed`.
+    pub latencies: Vec<i32>,
+
+    /// MIDI events sent from the plugin to the host.
+    pub midi_out: Vec<MidiEvent>,
+}
+
+impl TestHost {
+    /// Create a host with the given sample rate and maximum block size.
+    pub fn new(sample_rate: f32, block_size: usize) -> TestHost {
+        TestHost {
+            sample_rate: sample_rate,
+            block_size: block_size as i64,
+            time_info: Transport::new(sample_rate as f64).time_info(),
+            automated: Vec::new(),
+            edits: Vec::new(),
+            display_updates: 0,
+            latencies: Vec::new(),
+            midi_out: Vec::new(),
+        }
+    }
+
+    /// Create and start plugin `P` hosted by this host.
+    ///
+    /// The plugin is initialized, given the sample rate and block size and resumed.
+    pub fn load<P: Plugin + Default>(self) -> TestInstance {
+        let sample_rate = self.sample_rate;
+        let block_size = cmp::max(self.block_size, 1) as usize;
+        let host = Arc::new(Mutex::new(self));
+
+        // Create the plugin like a host would, then make the host available to its callbacks
+        let effect = ::main::<P>(test_callback);
+        unsafe {
+            (*effect).reserved1 = Box::into_raw(Box::new(host.clone())) as isize;
+            // Remember the initial latency so that only changes are reported to the host
+            (*effect).reserved2 = (*effect).initialDelay as isize;
+        }
+
+        let mut instance = TestInstance {
+            effect: effect,
+            host: host,
+            info: Default::default(),
+            transport: Transport::new(sample_rate as f64),
+            block_size: block_size,
+            position: 0,
+            pending: Vec::new(),
+            tempo_changes: Vec::new(),
+        };
+
+        instance.info = instance.plugin().get_info();
+        instance.transport.play();
+
+        instance.dispatch(OpCode::Initialize, 0, ptr::null_mut(), 0.0);
+        instance.dispatch(OpCode::SetSampleRate, 0, ptr::null_mut(), sample_rate);
+        instance.dispatch(OpCode::SetBlockSize, block_size as isize, ptr::null_mut(), 0.0);
+        instance.dispatch(OpCode::StateChanged, 1, ptr::null_mut(), 0.0);
+
+        instance
+    }
+}
+
+impl Default for TestHost {
+    fn default() -> TestHost {
+        TestHost::new(44100.0, 512)
+    }
+}
+
+impl Host for TestHost {
+    fn automate(&mut self, index: i32, value: f32) {
+        self.automated.push((index, value));
+    }
+
+    fn begin_edit(&mut self, index: i32) -> bool {
+        self.edits.push((index, true));
+        true
+    }
+
+    fn end_edit(&mut self, index: i32) -> bool {
+        self.edits.push((index, false));
+        true
+    }
+
+    fn get_sample_rate(&self) -> f32 { self.sample_rate }
+
+    fn get_block_size(&self) -> i64 { self.block_size }
+
+    fn update_display(&mut self) -> bool {
+        self.display_updates += 1;
+        true
+    }
+
+    fn get_time_info(&self, _: i32) -> Option<api::TimeInfo> { Some(self.time_info) }
+
+    fn get_product_string(&self) -> String { "rust-vst2 test host".to_string() }
+
+    fn io_changed(&mut self) -> bool { true }
+
+    fn latency_changed(&mut self, latency: i32) {
+        self.latencies.push(latency);
+    }
+
+    fn process_events(&mut self, events: &[Event]) {
+        for event in events {
+            if let Event::Midi(event) = *event {
+                self.midi_out.push(event);
+            }
+        }
+    }
+}
+
+/// A plugin running in a `TestHost`, see `TestHost::load`.
+pub struct TestInstance {
+    effect: *mut AEffect,
+    host: Arc<Mutex<TestHost>>,
+    info: Info,
+    transport: Transport,
+    block_size: usize,
+
+    /// Number of samples processed so far.
+    position: usize,
+    /// Scheduled events with their frame relative to the next processed sample.
+    pending: Vec<(usize, RenderEvent)>,
+    /// Scheduled tempo changes in time order, relative to the next processed sample.
+    tempo_changes: Vec<(usize, f64)>,
+}
+
+impl TestInstance {
+    /// The plugin under test, e.g. to call `Plugin` methods directly, bypassing the wrapper.
+    pub fn plugin(&mut self) -> &mut Plugin {
+        unsafe { &mut **(*self.effect).get_plugin() }
+    }
+
+    /// Send an opcode to the plugin's dispatcher and return its result.
+    pub fn dispatch(&mut self, opcode: OpCode, value: isize, ptr: *mut c_void, opt: f32) -> isize {
+        unsafe { ((*self.effect).dispatcher)(self.effect, opcode.into(), 0, value, ptr, opt) }
+    }
+
+    /// The host, to check the requests of the plugin.
+    ///
+    /// The host is locked while the guard is alive, so drop it before calling into the plugin.
+    pub fn host(&self) -> MutexGuard<TestHost> {
+        self.host.lock().unwrap()
+    }
+
+    /// Info of the plugin as returned when it was loaded.
+    pub fn info(&self) -> &Info {
+        &self.info
+    }
+
+    /// The transport providing the time info, playing from the start at 120 bpm by default.
+    pub fn transport(&mut self) -> &mut Transport {
+        &mut self.transport
+    }
+
+    /// Number of samples processed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Send a MIDI message `frame` samples into the next `process` call.
+    ///
+    /// Frames past the end of that call are delivered in later calls.
+    pub fn send_midi(&mut self, frame: usize, data: [u8; 3]) {
+        self.pending.push((frame, RenderEvent::Midi(data)));
+    }
+
+    /// Set the parameter at `index` to `value` exactly `frame` samples into the next `process`
+    /// call.
+    ///
+    /// Frames past the end of that call are applied in later calls.
+    pub fn automate(&mut self, frame: usize, index: i32, value: f32) {
+        self.pending.push((frame, RenderEvent::Parameter { index: index, value: value }));
+    }
+
+    /// Play the messages of a MIDI file from the start of the next `process` call.
+    ///
+    /// The transport follows the tempo map of the file: each tempo change takes effect from the
+    /// first block starting at or after it. Messages at the very end of the file are delivered
+    /// once more than `file.frames(sample_rate)` samples have been processed.
+    pub fn play_midi_file(&mut self, file: &MidiFile) {
+        let sample_rate = self.host().sample_rate as f64;
+
+        self.transport.set_tempo(file.initial_tempo());
+        self.tempo_changes = file.tempo_changes
+                                 .iter()
+                                 .map(|change| {
+                                     ((change.time * sample_rate).round() as usize, change.tempo)
+                                 })
+                                 .collect();
+        for event in file.timed_events(0, sample_rate) {
+            self.pending.push((event.frame, event.event));
+        }
+    }
+
+    /// Process the given input channels and return the output channels.
+    ///
+    /// The number of samples processed is the length of the longest input channel. Missing input
+    /// channels are silent and surplus ones are ignored. The samples are processed in blocks no
+    /// larger than the host's block size, through the plugin's `processReplacing`.
+    pub fn process(&mut self, inputs: &[&[f32]]) -> Vec<Vec<f32>> {
+        self.process_blocks(inputs, automation::process_automated::<RawPlugin>)
+    }
+
+    /// Process `f64` input channels through the plugin's `processDoubleReplacing`. See `process`.
+    pub fn process_f64(&mut self, inputs: &[&[f64]]) -> Vec<Vec<f64>> {
+        self.process_blocks(inputs, automation::process_automated_f64::<RawPlugin>)
+    }
+
+    fn process_blocks<T, F>(&mut self, inputs: &[&[T]], mut process: F) -> Vec<Vec<T>>
+        where T: Float,
+              F: FnMut(&mut RawPlugin, AudioBuffer<T>, &mut [ParameterChange])
+    {
+        // The channel counts may have changed since loading, e.g. through a speaker arrangement
+        let (num_inputs, num_outputs) = unsafe {
+            ((*self.effect).numInputs.max(0) as usize, (*self.effect).numOutputs.max(0) as usize)
+        };
+        let frames = inputs.iter().map(|c| c.len()).max().unwrap_or(0);
+        let mut outputs = vec![vec![T::zero(); frames]; num_outputs];
+
+        let mut input_block = vec![vec![T::zero(); self.block_size]; num_inputs];
+        let mut output_block = vec![vec![T::zero(); self.block_size]; outputs.len()];
+
+        let mut start = 0;
+        while start < frames {
+            let len = cmp::min(self.block_size, frames - start);
+
+            for (channel, block) in input_block.iter_mut().enumerate() {
+                for (i, sample) in block[..len].iter_mut().enumerate() {
+                    *sample = inputs.get(channel)
+                                    .and_then(|input| input.get(start + i))
+                                    .cloned()
+                                    .unwrap_or(T::zero());
+                }
+            }
+
+            let mut midi = Vec::new();
+            let mut changes = Vec::new();
+            for &(frame, event) in &self.pending {
+                if frame < start || frame >= start + len {
+                    continue;
+                }
+
+                let delta_frames = (frame - start) as i32;
+                match event {
+                    RenderEvent::Midi(data) => {
+                        midi.push(Event::Midi(MidiEvent {
+                            data: data,
+                            delta_frames: delta_frames,
+                            ..Default::default()
+                        }));
+                    }
+                    RenderEvent::Parameter { index, value } => {
+                        changes.push(ParameterChange {
+                            index: index,
+                            value: value,
+                            delta_frames: delta_frames,
+                        });
+                    }
+                }
+            }
+
+            while self.tempo_changes.first().map_or(false, |&(frame, _)| frame <= start) {
+                let (_, tempo) = self.tempo_changes.remove(0);
+                self.transport.set_tempo(tempo);
+            }
+            self.host.lock().unwrap().time_info = self.transport.time_info();
+
+            // The events must stay valid until the block has been processed
+            let mut events = SendEventBuffer::new(midi.len(), 0);
+            if !midi.is_empty() {
+                for event in midi {
+                    events.push(event);
+                }
+                let raw = events.as_raw() as *mut c_void;
+                self.dispatch(OpCode::ProcessEvents, 0, raw, 0.0);
+            }
+            let buffer = AudioBuffer::new(input_block.iter_mut().map(|c| &mut c[..len]).collect(),
+                                          output_block.iter_mut().map(|c| &mut c[..len]).collect());
+            process(&mut RawPlugin { effect: self.effect }, buffer.with_samples(len), &mut changes);
+
+            for (output, block) in outputs.iter_mut().zip(output_block.iter()) {
+                output[start..start + len].copy_from_slice(&block[..len]);
+            }
+
+            self.transport.advance(len);
+            start += len;
+        }
+
+        // Keep the events scheduled after this call, relative to the next one
+        self.pending.retain(|&(frame, _)| frame >= frames);
+        for event in &mut self.pending {
+            event.0 -= frames;
+        }
+        for change in &mut self.tempo_changes {
+            // Changes within the last block take effect at the start of the next call
+            change.0 = change.0.saturating_sub(frames);
+        }
+        self.position += frames;
+
+        outputs
+    }
+
+    /// Process `frames` samples of silence and return the output channels, e.g. for instruments.
+    pub fn process_silence(&mut self, frames: usize) -> Vec<Vec<f32>> {
+        let silence = vec![0.0; frames];
+        self.process(&[&silence])
+    }
+}
+
+impl Drop for TestInstance {
+    fn drop(&mut self) {
+        self.dispatch(OpCode::StateChanged, 0, ptr::null_mut(), 0.0);
+
+        unsafe {
+            let host = (*self.effect).reserved1;
+            self.dispatch(OpCode::Shutdown, 0, ptr::null_mut(), 0.0);
+            drop(Box::from_raw(host as *mut Arc<Mutex<TestHost>>));
+        }
+    }
+}
+
+/// Forwards the calls made by `automation` to the function pointers of a plugin.
+struct RawPlugin {
+    effect: *mut AEffect,
+}
+
+impl RawPlugin {
+    fn pointers<T>(buffer: AudioBuffer<T>) -> (Vec<*mut T>, Vec<*mut T>, i32) where T: Float {
+        let samples = buffer.samples() as i32;
+        let (inputs, outputs) = buffer.split();
+        (inputs.into_iter().map(|c| c.as_mut_ptr()).collect(),
+         outputs.into_iter().map(|c| c.as_mut_ptr()).collect(),
+         samples)
+    }
+}
+
+impl Plugin for RawPlugin {
+    fn get_info(&self) -> Info { Default::default() }
+
+    fn set_parameter(&mut self, index: i32, value: f32) {
+        unsafe { ((*self.effect).setParameter)(self.effect, index, value) }
+    }
+
+    fn process(&mut self, buffer: AudioBuffer<f32>) {
+        let (mut inputs, mut outputs, samples) = RawPlugin::pointers(buffer);
+        unsafe {
+            ((*self.effect).processReplacing)(self.effect, inputs.as_mut_ptr(),
+                                              outputs.as_mut_ptr(), samples)
+        }
+    }
+
+    fn process_f64(&mut self, buffer: AudioBuffer<f64>) {
+        let (mut inputs, mut outputs, samples) = RawPlugin::pointers(buffer);
+        unsafe {
+            ((*self.effect).processReplacingF64)(self.effect, inputs.as_mut_ptr(),
+                                                 outputs.as_mut_ptr(), samples)
+        }
+    }
+}
+
+/// Function passed to the plugin to handle host opcodes.
+fn test_callback(effect: *mut AEffect, opcode: i32, index: i32, value: isize, ptr: *mut c_void,
+                 opt: f32) -> isize {
+    unsafe {
+        // The host is only set once the plugin has been created
+        if !effect.is_null() && (*effect).reserved1 != 0 {
+            let host: &Arc<Mutex<TestHost>> = mem::transmute((*effect).reserved1);
+            let host = &mut *host.lock().unwrap();
+
+            interfaces::host_dispatch(host, effect, opcode, index, value, ptr, opt)
+        } else {
+            interfaces::host_dispatch(&mut TestHost::default(), effect, opcode, index, value, ptr,
+                                      opt)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffer::AudioBuffer;
+    use event::Event;
+    use host::Host;
+    use midi_file::MidiFile;
+    use plugin::{HostCallback, Info, Plugin};
+    use testing::TestHost;
+
+    /// Outputs the number of notes received and reports parameter changes to the host.
+    #[derive(Default)]
+    struct NoteCounter {
+        host: HostCallback,
+        notes: f32,
+    }
+
+    impl Plugin for NoteCounter {
+        fn new(host: HostCallback) -> NoteCounter {
+            NoteCounter { host: host, notes: 0.0 }
+        }
+
+        fn get_info(&self) -> Info {
+            Info { outputs: 1, ..Default::default() }
+        }
+
+        fn set_parameter(&mut self, index: i32, value: f32) {
+            self.host.begin_edit(index);
+            self.host.automate(index, value);
+            self.host.end_edit(index);
+        }
+
+        fn process_events(&mut self, events: &[Event]) {
+            self.notes += events.len() as f32;
+        }
+
+        fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+            let (_, mut outputs) = buffer.split_mut();
+            for sample in outputs[0].iter_mut() {
+                *sample = self.notes;
+            }
+        }
+    }
+
+    /// Test that events are delivered across blocks and calls.
+    #[test]
+    fn events() {
+        let mut instance = TestHost::new(44100.0, 2).load::<NoteCounter>();
+
+        instance.send_midi(3, [0x90, 60, 100]);
+        instance.send_midi(6, [0x80, 60, 0]);
+        assert_eq!(instance.process_silence(4), vec![vec![0.0, 0.0, 1.0, 1.0]]);
+        assert_eq!(instance.process_silence(4), vec![vec![1.0, 1.0, 2.0, 2.0]]);
+        assert_eq!(instance.position(), 8);
+    }
+
+    /// Test that the `f64` path of the wrapper processes plugins without `f64` support.
+    #[test]
+    fn process_f64() {
+        let mut instance = TestHost::new(44100.0, 2).load::<NoteCounter>();
+
+        instance.send_midi(2, [0x90, 60, 100]);
+        assert_eq!(instance.process_f64(&[&[0.0; 4]]), vec![vec![0.0, 0.0, 1.0, 1.0]]);
+    }
+
+    /// Test that the latency the plugin was loaded with is not reported as a change.
+    #[test]
+    fn latency() {
+        #[derive(Default)]
+        struct Delayed {
+            host: HostCallback,
+        }
+
+        impl Plugin for Delayed {
+            fn new(host: HostCallback) -> Delayed {
+                Delayed { host: host }
+            }
+
+            fn get_info(&self) -> Info {
+                Info { initial_delay: 64, ..Default::default() }
+            }
+
+            fn on_resume(&mut self) {
+                self.host.io_changed();
+            }
+        }
+
+        let instance = TestHost::default().load::<Delayed>();
+        assert!(instance.host().latencies.is_empty());
+    }
+
+    /// Test that requests of the plugin reach the host.
+    #[test]
+    fn host() {
+        let mut instance = TestHost::default().load::<NoteCounter>();
+
+        instance.automate(0, 1, 0.5);
+        instance.process_silence(1);
+        instance.plugin().set_parameter(2, 1.0);
+
+        let host = instance.host();
+        assert_eq!(host.automated, vec![(1, 0.5), (2, 1.0)]);
+        assert_eq!(host.edits, vec![(1, true), (1, false), (2, true), (2, false)]);
+        assert!(host.time_info.tempo == 120.0);
+    }
+
+    /// Test that MIDI file messages arrive at their frames and the tempo map is followed.
+    #[test]
+    fn midi_file() {
+        // 100 ticks per quarter note at 60 bpm, then 120 bpm after a quarter note
+        let mut data = b"MThd\0\0\0\x06\0\0\0\x01\0\x64MTrk\0\0\0\x1A".to_vec();
+        data.extend_from_slice(&[0x00, 0xFF, 0x51, 0x03, 0x0F, 0x42, 0x40,
+                                 0x00, 0x90, 0x3C, 0x64,
+                                 0x64, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20,
+                                 0x32, 0x80, 0x3C, 0x00,
+                                 0x00, 0xFF, 0x2F, 0x00]);
+        let file = MidiFile::from_bytes(&data).unwrap();
+
+        let mut instance = TestHost::new(8.0, 4).load::<NoteCounter>();
+        instance.play_midi_file(&file);
+        assert!(instance.transport().samples_per_quarter() == 8.0);
+
+        // The note off is at frame 10, in the third block
+        assert_eq!(instance.process_silence(12),
+                   vec![vec![1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 2.0, 2.0, 2.0, 2.0]]);
+        assert!(instance.host().time_info.tempo == 120.0);
+        assert!(instance.transport().samples_per_quarter() == 4.0);
+    }
+}
@@ -0,0 +1,421 @@
+//! Human readable presets and banks in JSON or TOML.
+//!
+//! This module is only available with the `text-presets` feature. It stores the same
+//! `preset::Preset` and `preset::Bank` values as `.fxp` and `.fxb` files, but as text which can
+//! be diffed, kept in version control and edited by hand. Parameter values are listed with the
+//! names of the parameters, and chunks are stored as base64.
+//!
+//! A preset of a plugin with two parameters looks like this in TOML:
+//!
+//! ```toml
+//! name = "Bass"
+//! plugin_id = 1234
+//! plugin_version = 1
+//!
+//! [[parameters]]
+//! name = "Cutoff"
+//! value = 0.25
+//!
+//! [[parameters]]
+//! name = "Resonance"
+//! value = 1.0
+//! ```
+//!
+//! Values are applied in order, the names are only there for the reader. A parameter can also be
+//! given as a plain number. Banks hold a list of `presets` without their own `plugin_id` and
+//! `plugin_version`, and the index of the `current_preset`.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # use std::fs::File;
+//! # use std::io::{Read, Write};
+//! # use vst2::plugin::Plugin;
+//! use vst2::text_preset::{self, Format};
+//!
+//! # fn example<P: Plugin>(plugin: &mut P) {
+//! let text = text_preset::save_preset(plugin, Format::Toml);
+//! File::create("preset.toml").unwrap().write_all(text.as_bytes()).unwrap();
+//!
+//! let mut text = String::new();
+//! File::open("preset.toml").unwrap().read_to_string(&mut text).unwrap();
+//! text_preset::load_preset(plugin, &text, Format::Toml).unwrap();
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+use base64;
+use serde_json::{self, Map, Number, Value};
+use toml;
+
+use plugin::Plugin;
+use preset::{Bank, BankData, Preset, PresetData, PresetError};
+
+/// Text format of a preset or bank.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// JSON, pretty printed.
+    Json,
+
+    /// TOML.
+    Toml,
+}
+
+/// Error reading or applying a text preset or bank.
+#[derive(Debug)]
+pub enum TextPresetError {
+    /// The text is not valid JSON or TOML. Contains the message of the parser.
+    Syntax(String),
+
+    /// The document is not a table.
+    InvalidFormat,
+
+    /// A required field is missing or has the wrong type.
+    InvalidField(&'static str),
+
+    /// A chunk is not valid base64.
+    InvalidChunk,
+
+    /// Applying the preset or bank failed, e.g. because it belongs to a different plugin.
+    Preset(PresetError),
+}
+
+impl From<PresetError> for TextPresetError {
+    fn from(error: PresetError) -> TextPresetError {
+        TextPresetError::Preset(error)
+    }
+}
+
+impl fmt::Display for TextPresetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TextPresetError::Syntax(ref message) => {
+                write!(f, "{}: {}", self.description(), message)
+            }
+            TextPresetError::InvalidField(field) => write!(f, "{}: {}", self.description(), field),
+            TextPresetError::Preset(ref error) => write!(f, "{}", error),
+            _ => write!(f, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for TextPresetError {
+    fn description(&self) -> &str {
+        use self::TextPresetError::*;
+
+        match *self {
+            Syntax(_) => "Could not parse the text",
+            InvalidFormat => "Not a preset or bank",
+            InvalidField(_) => "Missing or invalid field",
+            InvalidChunk => "The chunk is not valid base64",
+            Preset(ref error) => error.description(),
+        }
+    }
+}
+
+/// Names of the parameters of `plugin`, to label the values of written presets.
+pub fn parameter_names<P: Plugin + ?Sized>(plugin: &P) -> Vec<String> {
+    (0..plugin.get_info().parameters).map(|index| plugin.get_parameter_name(index)).collect()
+}
+
+/// Write `preset`, labeling its parameter values with `names`.
+///
+/// Non-finite parameter values are written as 0.0.
+pub fn write_preset(preset: &Preset, names: &[String], format: Format) -> String {
+    let mut map = header(preset.plugin_id, preset.plugin_version);
+    write_preset_fields(preset, names, &mut map);
+    write(Value::Object(map), format)
+}
+
+/// Read a preset written by `write_preset` or by hand.
+pub fn read_preset(text: &str, format: Format) -> Result<Preset, TextPresetError> {
+    let value = try!(read(text, format));
+    let map = try!(value.as_object().ok_or(TextPresetError::InvalidFormat));
+    read_preset_fields(map, try!(int(map, "plugin_id")), try!(int(map, "plugin_version")))
+}
+
+/// Write `bank`, labeling the parameter values of its presets with `names`.
+pub fn write_bank(bank: &Bank, names: &[String], format: Format) -> String {
+    let mut map = header(bank.plugin_id, bank.plugin_version);
+    map.insert("current_preset".to_string(), Value::from(bank.current_preset));
+
+    match bank.data {
+        BankData::Presets(ref presets) => {
+            let presets = presets.iter().map(|preset| {
+                let mut fields = Map::new();
+                write_preset_fields(preset, names, &mut fields);
+                Value::Object(fields)
+            }).collect();
+            map.insert("presets".to_string(), Value::Array(presets));
+        }
+        BankData::Chunk { presets, ref data } => {
+            map.insert("preset_count".to_string(), Value::from(presets));
+            map.insert("chunk".to_string(), Value::from(base64::encode(data)));
+        }
+    }
+
+    write(Value::Object(map), format)
+}
+
+/// Read a bank written by `write_bank` or by hand.
+pub fn read_bank(text: &str, format: Format) -> Result<Bank, TextPresetError> {
+    let value = try!(read(text, format));
+    let map = try!(value.as_object().ok_or(TextPresetError::InvalidFormat));
+    let plugin_id = try!(int(map, "plugin_id"));
+    let plugin_version = try!(int(map, "plugin_version"));
+
+    let data = match map.get("chunk") {
+        Some(chunk) => {
+            BankData::Chunk {
+                presets: try!(optional_int(map, "preset_count")),
+                data: try!(decode_chunk(chunk)),
+            }
+        }
+        None => {
+            let list = try!(map.get("presets").and_then(Value::as_array)
+                               .ok_or(TextPresetError::InvalidField("presets")));
+            let mut presets = Vec::with_capacity(list.len());
+            for preset in list {
+                let fields = try!(preset.as_object()
+                                        .ok_or(TextPresetError::InvalidField("presets")));
+                presets.push(try!(read_preset_fields(fields, plugin_id, plugin_version)));
+            }
+            BankData::Presets(presets)
+        }
+    };
+
+    Ok(Bank {
+        plugin_id: plugin_id,
+        plugin_version: plugin_version,
+        current_preset: try!(optional_int(map, "current_preset")),
+        data: data,
+    })
+}
+
+/// Write the current preset of `plugin`, like `preset::Preset::from_plugin`.
+pub fn save_preset<P: Plugin + ?Sized>(plugin: &mut P, format: Format) -> String {
+    let preset = Preset::from_plugin(plugin);
+    write_preset(&preset, &parameter_names(plugin), format)
+}
+
+/// Read a preset and load it into the current preset of `plugin`, like `preset::Preset::apply`.
+pub fn load_preset<P: Plugin + ?Sized>(plugin: &mut P, text: &str, format: Format)
+                                       -> Result<(), TextPresetError> {
+    let preset = try!(read_preset(text, format));
+    preset.apply(plugin).map_err(TextPresetError::from)
+}
+
+/// Write all presets of `plugin`, like `preset::Bank::from_plugin`.
+pub fn save_bank<P: Plugin + ?Sized>(plugin: &mut P, format: Format) -> String {
+    let bank = Bank::from_plugin(plugin);
+    write_bank(&bank, &parameter_names(plugin), format)
+}
+
+/// Read a bank and load it into `plugin`, like `preset::Bank::apply`.
+pub fn load_bank<P: Plugin + ?Sized>(plugin: &mut P, text: &str, format: Format)
+                                     -> Result<(), TextPresetError> {
+    let bank = try!(read_bank(text, format));
+    bank.apply(plugin).map_err(TextPresetError::from)
+}
+
+fn header(plugin_id: i32, plugin_version: i32) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("plugin_id".to_string(), Value::from(plugin_id));
+    map.insert("plugin_version".to_string(), Value::from(plugin_version));
+    map
+}
+
+fn write_preset_fields(preset: &Preset, names: &[String], map: &mut Map<String, Value>) {
+    map.insert("name".to_string(), Value::from(preset.name.clone()));
+
+    match preset.data {
+        PresetData::Parameters(ref values) => {
+            let parameters = values.iter().enumerate().map(|(index, value)| {
+                let mut parameter = Map::new();
+                if let Some(name) = names.get(index) {
+                    parameter.insert("name".to_string(), Value::from(name.clone()));
+                }
+                parameter.insert("value".to_string(), float(*value));
+                Value::Object(parameter)
+            }).collect();
+            map.insert("parameters".to_string(), Value::Array(parameters));
+        }
+        PresetData::Chunk { parameters, ref data } => {
+            map.insert("parameter_count".to_string(), Value::from(parameters));
+            map.insert("chunk".to_string(), Value::from(base64::encode(data)));
+        }
+    }
+}
+
+fn read_preset_fields(map: &Map<String, Value>, plugin_id: i32, plugin_version: i32)
+                      -> Result<Preset, TextPresetError> {
+    let name = match map.get("name") {
+        Some(name) => try!(name.as_str().ok_or(TextPresetError::InvalidField("name"))).to_string(),
+        None => String::new(),
+    };
+
+    let data = match map.get("chunk") {
+        Some(chunk) => {
+            PresetData::Chunk {
+                parameters: try!(optional_int(map, "parameter_count")),
+                data: try!(decode_chunk(chunk)),
+            }
+        }
+        None => {
+            let list = try!(map.get("parameters").and_then(Value::as_array)
+                               .ok_or(TextPresetError::InvalidField("parameters")));
+            let mut values = Vec::with_capacity(list.len());
+            for parameter in list {
+                let value = parameter.as_f64()
+                                     .or_else(|| parameter.get("value").and_then(Value::as_f64));
+                values.push(try!(value.ok_or(TextPresetError::InvalidField("value"))) as f32);
+            }
+            PresetData::Parameters(values)
+        }
+    };
+
+    Ok(Preset {
+        plugin_id: plugin_id,
+        plugin_version: plugin_version,
+        name: name,
+        data: data,
+    })
+}
+
+/// Convert a parameter value through its shortest decimal representation, so that 0.1 is
+/// written as 0.1 rather than 0.10000000149011612 and still reads back as the same `f32`.
+fn float(value: f32) -> Value {
+    let value = if value.is_finite() { value } else { 0.0 };
+    let value = value.to_string().parse().unwrap_or(0.0);
+    Number::from_f64(value).map_or(Value::from(0.0), Value::Number)
+}
+
+fn int(map: &Map<String, Value>, field: &'static str) -> Result<i32, TextPresetError> {
+    map.get(field)
+       .and_then(Value::as_i64)
+       .and_then(|value| if value as i32 as i64 == value { Some(value as i32) } else { None })
+       .ok_or(TextPresetError::InvalidField(field))
+}
+
+/// Read an integer which defaults to 0 if missing.
+fn optional_int(map: &Map<String, Value>, field: &'static str) -> Result<i32, TextPresetError> {
+    if map.contains_key(field) { int(map, field) } else { Ok(0) }
+}
+
+fn decode_chunk(chunk: &Value) -> Result<Vec<u8>, TextPresetError> {
+    let chunk = try!(chunk.as_str().ok_or(TextPresetError::InvalidField("chunk")));
+    base64::decode(chunk).map_err(|_| TextPresetError::InvalidChunk)
+}
+
+fn write(value: Value, format: Format) -> String {
+    // The documents only contain tables, arrays, strings and finite numbers, which both formats
+    // can represent
+    match format {
+        Format::Json => serde_json::to_string_pretty(&value).expect("Could not write JSON"),
+        Format::Toml => {
+            toml::Value::try_from(value).and_then(|value| toml::to_string_pretty(&value))
+                                        .expect("Could not write TOML")
+        }
+    }
+}
+
+fn read(text: &str, format: Format) -> Result<Value, TextPresetError> {
+    match format {
+        Format::Json => {
+            serde_json::from_str(text).map_err(|e| TextPresetError::Syntax(e.to_string()))
+        }
+        Format::Toml => toml::from_str(text).map_err(|e| TextPresetError::Syntax(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plugin::{Info, Plugin};
+    use preset::{Bank, BankData, Preset, PresetData, PresetError};
+    use text_preset::{self, Format, TextPresetError};
+
+    #[derive(Default)]
+    struct Synth {
+        values: [f32; 2],
+    }
+
+    impl Plugin for Synth {
+        fn get_info(&self) -> Info {
+            Info { unique_id: 1234, version: 3, parameters: 2, presets: 1, ..Default::default() }
+        }
+
+        fn get_parameter_name(&self, index: i32) -> String {
+            ["Cutoff", "Resonance"][index as usize].to_string()
+        }
+
+        fn get_parameter(&self, index: i32) -> f32 { self.values[index as usize] }
+        fn set_parameter(&mut self, index: i32, value: f32) { self.values[index as usize] = value; }
+    }
+
+    /// Test that presets and banks survive a round trip through both formats.
+    #[test]
+    fn round_trip() {
+        let mut synth = Synth { values: [0.1, 1.0] };
+
+        for &format in &[Format::Json, Format::Toml] {
+            let text = text_preset::save_preset(&mut synth, format);
+            assert!(text.contains("Resonance"));
+            assert!(text.contains("0.1") && !text.contains("0.1000"));
+
+            let mut other = Synth::default();
+            text_preset::load_preset(&mut other, &text, format).unwrap();
+            assert_eq!(other.values, synth.values);
+
+            let bank = Bank::from_plugin(&mut synth);
+            let text = text_preset::write_bank(&bank, &[], format);
+            assert_eq!(text_preset::read_bank(&text, format).unwrap(), bank);
+
+            let chunk = Bank {
+                plugin_id: 1,
+                plugin_version: 2,
+                current_preset: 0,
+                data: BankData::Chunk { presets: 4, data: vec![0, 1, 254, 255] },
+            };
+            let text = text_preset::write_bank(&chunk, &[], format);
+            assert_eq!(text_preset::read_bank(&text, format).unwrap(), chunk);
+        }
+    }
+
+    /// Test reading hand written presets and rejecting invalid ones.
+    #[test]
+    fn hand_written() {
+        let text = "plugin_id = 1234\nplugin_version = 1\nparameters = [0.5, 1.0]\n";
+        let preset = text_preset::read_preset(text, Format::Toml).unwrap();
+        assert_eq!(preset.data, PresetData::Parameters(vec![0.5, 1.0]));
+        assert_eq!(preset.name, "");
+
+        let text = r#"{ "plugin_id": 1, "plugin_version": 1, "chunk": "AQID" }"#;
+        let preset = text_preset::read_preset(text, Format::Json).unwrap();
+        assert_eq!(preset.data, PresetData::Chunk { parameters: 0, data: vec![1, 2, 3] });
+
+        let errors = [
+            text_preset::read_preset("{", Format::Json).err(),
+            text_preset::read_preset("[]", Format::Json).err(),
+            text_preset::read_preset(r#"{ "plugin_id": 1 }"#, Format::Json).err(),
+            text_preset::read_preset("plugin_id = 1\nplugin_version = 1\nchunk = \"!\"",
+                                     Format::Toml).err(),
+        ];
+        assert!(match errors {
+            [Some(TextPresetError::Syntax(_)), Some(TextPresetError::InvalidFormat),
+             Some(TextPresetError::InvalidField("plugin_version")),
+             Some(TextPresetError::InvalidChunk)] => true,
+            _ => false,
+        });
+
+        let foreign = Preset { plugin_id: 1, ..preset };
+        let text = text_preset::write_preset(&foreign, &[], Format::Json);
+        assert!(match text_preset::load_preset(&mut Synth::default(), &text, Format::Json) {
+            Err(TextPresetError::Preset(PresetError::WrongPlugin { expected, actual })) => {
+                (expected, actual) == (1234, 1)
+            }
+            _ => false,
+        });
+    }
+}
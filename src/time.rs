@@ -0,0 +1,414 @@
+//! Conversion between sample positions and musical time.
+//!
+//! A [`MusicalTime`](struct.MusicalTime.html) is created from the `api::TimeInfo` of the current
+//! block and converts event delta frames to positions in quarter notes (PPQ) and back. It also
+//! finds the sample offsets of beat, bar or grid boundaries within the block, as needed by
+//! arpeggiators, synced LFOs and step sequencers.
+//!
+//! A [`NoteLength`](struct.NoteLength.html) such as a dotted eighth or a quarter triplet converts
+//! to seconds, samples or a rate in Hz at a given tempo, e.g. for synced delays. A
+//! [`SyncedPhase`](struct.SyncedPhase.html) accumulates the phase of an LFO or gate over a note
+//! length and stays locked to the host's PPQ position, even when the transport jumps or loops.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::api::TimeInfo;
+//! # use vst2::time::MusicalTime;
+//! # fn trigger(_delta_frames: usize) {}
+//! # fn example(info: &TimeInfo, block_size: usize) {
+//! if let Some(time) = MusicalTime::from_time_info(info) {
+//!     // Trigger a step on every sixteenth note in this block
+//!     let mut from = 0;
+//!     while let Some(step) = time.next_grid(0.25, from, block_size) {
+//!         trigger(step);
+//!         from = step + 1;
+//!     }
+//! }
+//! # }
+//! ```
+
+use std::fmt;
+
+use api;
+
+/// Musical position and tempo at the start of a block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MusicalTime {
+    /// Position of the first sample of the block in quarter notes.
+    pub ppq_position: f64,
+
+    /// Tempo in beats (quarter notes) per minute.
+    pub tempo: f64,
+
+    /// Sample rate in Hz.
+    pub sample_rate: f64,
+
+    /// Position of the last bar start in quarter notes.
+    pub bar_start: f64,
+
+    /// Time signature as numerator and denominator, e.g. `(6, 8)`.
+    pub time_signature: (i32, i32),
+}
+
+impl MusicalTime {
+    /// Get the musical time from the time info of the current block.
+    ///
+    /// Returns `None` unless the host provided a valid tempo, PPQ position and sample rate. The
+    /// bar start and time signature default to the start of the song and 4/4 if missing.
+    pub fn from_time_info(info: &api::TimeInfo) -> Option<MusicalTime> {
+        let (ppq_position, tempo) = match (info.ppq_position(), info.bpm()) {
+            (Some(ppq_position), Some(tempo)) if tempo > 0.0 && info.sample_rate > 0.0 => {
+                (ppq_position, tempo)
+            }
+            _ => return None,
+        };
+
+        let time_signature = match info.time_signature() {
+            Some((numerator, denominator)) if numerator > 0 && denominator > 0 => {
+                (numerator, denominator)
+            }
+            _ => (4, 4),
+        };
+
+        Some(MusicalTime {
+            ppq_position: ppq_position,
+            tempo: tempo,
+            sample_rate: info.sample_rate,
+            bar_start: info.bar_start().unwrap_or(0.0),
+            time_signature: time_signature,
+        })
+    }
+
+    /// Number of samples per quarter note.
+    pub fn samples_per_beat(&self) -> f64 {
+        self.sample_rate * 60.0 / self.tempo
+    }
+
+    /// Length of a bar in quarter notes.
+    pub fn bar_length(&self) -> f64 {
+        let (numerator, denominator) = self.time_signature;
+        numerator as f64 * 4.0 / denominator as f64
+    }
+
+    /// Position in quarter notes of the sample `delta_frames` into the block.
+    pub fn ppq_at(&self, delta_frames: i32) -> f64 {
+        self.ppq_position + delta_frames as f64 / self.samples_per_beat()
+    }
+
+    /// Offset in samples from the start of the block of the position `ppq` in quarter notes.
+    ///
+    /// Positions before the block give negative offsets.
+    pub fn delta_frames_at(&self, ppq: f64) -> f64 {
+        (ppq - self.ppq_position) * self.samples_per_beat()
+    }
+
+    /// Offset of the first multiple of `step` quarter notes at or after sample `from` of the
+    /// block, if it falls before `block_size`.
+    ///
+    /// E.g. a `step` of `1.0` finds beats and `0.25` finds sixteenth notes.
+    pub fn next_grid(&self, step: f64, from: usize, block_size: usize) -> Option<usize> {
+        self.next_boundary(0.0, step, from, block_size)
+    }
+
+    /// Offset of the next beat (quarter note) at or after sample `from` of the block, if it falls
+    /// before `block_size`.
+    pub fn next_beat(&self, from: usize, block_size: usize) -> Option<usize> {
+        self.next_grid(1.0, from, block_size)
+    }
+
+    /// Offset of the next bar start at or after sample `from` of the block, if it falls before
+    /// `block_size`.
+    pub fn next_bar(&self, from: usize, block_size: usize) -> Option<usize> {
+        let bar_length = self.bar_length();
+        self.next_boundary(self.bar_start, bar_length, from, block_size)
+    }
+
+    /// Offset of the first position `origin + n * step` at or after sample `from`.
+    fn next_boundary(&self, origin: f64, step: f64, from: usize, block_size: usize)
+                     -> Option<usize> {
+        if step <= 0.0 || from >= block_size {
+            return None;
+        }
+
+        let from_ppq = self.ppq_at(from as i32);
+        // Tolerate rounding errors, so a boundary exactly on `from` is not skipped
+        let steps = ((from_ppq - origin) / step - 1e-9).ceil();
+        let offset = self.delta_frames_at(origin + steps * step).round();
+
+        let offset = if offset < from as f64 { from } else { offset as usize };
+        if offset < block_size { Some(offset) } else { None }
+    }
+}
+
+/// Modifier of a note length.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoteModifier {
+    /// The plain note length.
+    Straight,
+
+    /// One and a half times the note length.
+    Dotted,
+
+    /// Two thirds of the note length, i.e. three notes in the time of two.
+    Triplet,
+}
+
+/// A musical note length, such as a quarter note, a dotted eighth or a sixteenth triplet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteLength {
+    /// Fraction of a whole note, e.g. 4 for quarter notes and 16 for sixteenth notes. Values
+    /// below 1 are treated as 1.
+    pub division: u32,
+
+    /// Dotted or triplet modifier.
+    pub modifier: NoteModifier,
+
+    /// Number of notes, e.g. 2 for two quarter notes. Values below 1 are treated as 1.
+    pub count: u32,
+}
+
+impl NoteLength {
+    /// A note of 1/`division` of a whole note.
+    pub fn straight(division: u32) -> NoteLength {
+        NoteLength { division: division, modifier: NoteModifier::Straight, count: 1 }
+    }
+
+    /// A dotted note of 1/`division` of a whole note.
+    pub fn dotted(division: u32) -> NoteLength {
+        NoteLength { modifier: NoteModifier::Dotted, ..NoteLength::straight(division) }
+    }
+
+    /// A triplet note of 1/`division` of a whole note.
+    pub fn triplet(division: u32) -> NoteLength {
+        NoteLength { modifier: NoteModifier::Triplet, ..NoteLength::straight(division) }
+    }
+
+    /// `count` whole notes, i.e. bars in 4/4.
+    pub fn bars(count: u32) -> NoteLength {
+        NoteLength { count: count, ..NoteLength::straight(1) }
+    }
+
+    /// Length in quarter notes, the unit of PPQ positions.
+    pub fn quarters(&self) -> f64 {
+        let modifier = match self.modifier {
+            NoteModifier::Straight => 1.0,
+            NoteModifier::Dotted => 1.5,
+            NoteModifier::Triplet => 2.0 / 3.0,
+        };
+        4.0 / self.division.max(1) as f64 * modifier * self.count.max(1) as f64
+    }
+
+    /// Length in seconds at `tempo` in beats (quarter notes) per minute.
+    pub fn seconds(&self, tempo: f64) -> f64 {
+        self.quarters() * 60.0 / tempo
+    }
+
+    /// Length in samples at `tempo` and `sample_rate`.
+    pub fn samples(&self, tempo: f64, sample_rate: f64) -> f64 {
+        self.seconds(tempo) * sample_rate
+    }
+
+    /// Rate in Hz of something repeating once per note at `tempo`, e.g. an LFO.
+    pub fn hertz(&self, tempo: f64) -> f64 {
+        1.0 / self.seconds(tempo)
+    }
+}
+
+/// Formats note lengths the way they are usually shown in synced parameters, e.g. "1/8",
+/// "1/8." for dotted and "1/8T" for triplet notes, or "2 bars".
+impl fmt::Display for NoteLength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let division = self.division.max(1);
+        let count = self.count.max(1);
+
+        if division == 1 && self.modifier == NoteModifier::Straight {
+            return match count {
+                1 => write!(f, "1 bar"),
+                _ => write!(f, "{} bars", count),
+            };
+        }
+
+        try!(write!(f, "{}/{}", count, division));
+        match self.modifier {
+            NoteModifier::Straight => Ok(()),
+            NoteModifier::Dotted => write!(f, "."),
+            NoteModifier::Triplet => write!(f, "T"),
+        }
+    }
+}
+
+impl MusicalTime {
+    /// Length of `note` in samples at the current tempo.
+    pub fn note_samples(&self, note: NoteLength) -> f64 {
+        note.samples(self.tempo, self.sample_rate)
+    }
+}
+
+/// A phase between 0.0 and 1.0 cycling once per note length, locked to the host's transport.
+///
+/// Call `sync` at the start of every block. While the host provides a PPQ position, the phase is
+/// derived from it, so it follows transport jumps, loops and tempo changes and an LFO restarts in
+/// the same place on every playback. Without musical time, e.g. while the transport is stopped in
+/// some hosts, the phase keeps running at the last known tempo.
+///
+/// # Example
+///
+/// ```
+/// # use vst2::api::TimeInfo;
+/// use vst2::time::{NoteLength, SyncedPhase};
+///
+/// # fn example(info: &TimeInfo, output: &mut [f32]) {
+/// let mut lfo = SyncedPhase::new(NoteLength::dotted(8), 44100.0);
+///
+/// lfo.sync(info.musical_time().as_ref());
+/// for sample in output.iter_mut() {
+///     let phase = lfo.next_phase();
+///     *sample *= (phase * 2.0 * std::f64::consts::PI).sin() as f32 * 0.5 + 0.5;
+/// }
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct SyncedPhase {
+    quarters: f64,
+    phase: f64,
+    samples_per_beat: f64,
+}
+
+impl SyncedPhase {
+    /// Create a phase cycling once per `length`, running at 120 bpm and `sample_rate` until
+    /// synced.
+    pub fn new(length: NoteLength, sample_rate: f64) -> SyncedPhase {
+        SyncedPhase {
+            quarters: length.quarters(),
+            phase: 0.0,
+            samples_per_beat: sample_rate * 60.0 / 120.0,
+        }
+    }
+
+    /// Change the length of a cycle. The phase is updated on the next `sync`.
+    pub fn set_length(&mut self, length: NoteLength) {
+        self.quarters = length.quarters();
+    }
+
+    /// Lock the phase to the start of a block, or keep running if `time` is `None`.
+    pub fn sync(&mut self, time: Option<&MusicalTime>) {
+        if let Some(time) = time {
+            let cycles = time.ppq_position / self.quarters;
+            // Positions before the song start give negative cycles
+            self.phase = cycles - cycles.floor();
+            self.samples_per_beat = time.samples_per_beat();
+        }
+    }
+
+    /// Set the phase, e.g. to retrigger on a note while the transport is stopped.
+    pub fn reset(&mut self, phase: f64) {
+        self.phase = phase - phase.floor();
+    }
+
+    /// The current phase, between 0.0 and 1.0.
+    pub fn phase(&self) -> f64 {
+        self.phase
+    }
+
+    /// Return the current phase and advance by one sample.
+    pub fn next_phase(&mut self) -> f64 {
+        let phase = self.phase;
+        self.advance(1);
+        phase
+    }
+
+    /// Advance by `samples` samples.
+    pub fn advance(&mut self, samples: usize) {
+        let phase = self.phase + samples as f64 / (self.quarters * self.samples_per_beat);
+        self.phase = phase - phase.floor();
+    }
+}
+
+impl api::TimeInfo {
+    /// Get the musical time of this time info, see `MusicalTime::from_time_info`.
+    pub fn musical_time(&self) -> Option<MusicalTime> {
+        MusicalTime::from_time_info(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use host::TimeInfoBuilder;
+    use time::{MusicalTime, NoteLength, SyncedPhase};
+
+    fn time(ppq: f64) -> MusicalTime {
+        let info = TimeInfoBuilder::new(48000.0, 0.0)
+                                   .tempo(120.0)
+                                   .ppq_position(ppq)
+                                   .time_signature(3, 4)
+                                   .bar_start(0.0)
+                                   .build();
+        MusicalTime::from_time_info(&info).unwrap()
+    }
+
+    /// Test conversion between delta frames and quarter notes.
+    #[test]
+    fn conversion() {
+        let time = time(1.0);
+
+        // 120 bpm at 48 kHz is 24000 samples per quarter note
+        assert_eq!(time.samples_per_beat(), 24000.0);
+        assert_eq!(time.ppq_at(12000), 1.5);
+        assert_eq!(time.delta_frames_at(2.0), 24000.0);
+
+        let info = TimeInfoBuilder::new(48000.0, 0.0).build();
+        assert!(MusicalTime::from_time_info(&info).is_none());
+    }
+
+    /// Test finding grid, beat and bar boundaries within a block.
+    #[test]
+    fn boundaries() {
+        let time = time(2.5);
+
+        assert_eq!(time.next_beat(0, 48000), Some(12000));
+        assert_eq!(time.next_beat(12001, 48000), Some(36000));
+        assert_eq!(time.next_grid(0.25, 0, 48000), Some(0));
+        assert_eq!(time.next_bar(0, 48000), Some(12000));
+        assert_eq!(time.next_bar(0, 12000), None);
+        assert_eq!(time.next_bar(12001, 100000), Some(84000));
+    }
+
+    /// Test converting note lengths to time.
+    #[test]
+    fn note_lengths() {
+        assert_eq!(NoteLength::straight(4).quarters(), 1.0);
+        assert_eq!(NoteLength::dotted(8).quarters(), 0.75);
+        assert_eq!(NoteLength::triplet(4).samples(120.0, 48000.0).round(), 16000.0);
+        assert_eq!(NoteLength::bars(2).seconds(120.0), 4.0);
+        assert_eq!(NoteLength::straight(16).hertz(120.0), 8.0);
+        assert_eq!(time(0.0).note_samples(NoteLength::straight(8)), 12000.0);
+
+        let names: Vec<String> = vec![NoteLength::straight(4), NoteLength::dotted(8),
+                                      NoteLength::triplet(16), NoteLength::bars(1),
+                                      NoteLength::bars(4)]
+            .iter().map(|note| note.to_string()).collect();
+        assert_eq!(names, vec!["1/4", "1/8.", "1/16T", "1 bar", "4 bars"]);
+    }
+
+    /// Test that the synced phase follows the transport.
+    #[test]
+    fn synced_phase() {
+        let mut phase = SyncedPhase::new(NoteLength::straight(2), 48000.0);
+
+        phase.sync(Some(&time(2.5)));
+        assert_eq!(phase.phase(), 0.25);
+        assert_eq!(phase.next_phase(), 0.25);
+        phase.advance(23999);
+        assert!((phase.phase() - 0.75).abs() < 1e-9);
+
+        // Jumping back puts the phase where it would be when playing from there
+        phase.sync(Some(&time(-0.5)));
+        assert_eq!(phase.phase(), 0.75);
+
+        // Without time info the phase keeps running at the last tempo
+        phase.sync(None);
+        phase.advance(24000);
+        assert_eq!(phase.phase(), 0.25);
+    }
+}
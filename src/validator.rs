@@ -0,0 +1,412 @@
+//! Automated checks of plugin behaviour, similar to pluginval.
+//!
+//! A [`Validator`](struct.Validator.html) drives a plugin through the calls a host makes and
+//! reports everything that looks wrong: invalid info, lifecycle calls in the usual host orders,
+//! parameter values which do not survive being set and read back, presets and banks which change
+//! when saved and restored, and non-finite output when processing silence, denormals or a sine
+//! at various block sizes and sample rates. Inputs containing NaN and infinity are processed as
+//! well, where only crashing counts as a failure.
+//!
+//! Plugin authors can run it against their `Plugin` implementation in a test. Hosts can run it
+//! against a `PluginInstance` to qualify a third-party plugin; check `PluginInstance::crashed`
+//! afterwards, as the calls into a crashed instance are silently skipped.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::plugin::{Info, Plugin};
+//! # use vst2::validator::Validator;
+//! # #[derive(Default)]
+//! # struct MyPlugin;
+//! # impl Plugin for MyPlugin {
+//! #     fn get_info(&self) -> Info { Info { unique_id: 1234, ..Default::default() } }
+//! # }
+//! let report = Validator::default().validate(&mut MyPlugin::default());
+//! assert!(report.is_ok(), "{}", report);
+//! ```
+
+use std::any::Any;
+use std::f64::consts::PI;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use num::Float;
+
+use buffer::AudioBuffer;
+use plugin::{Info, Plugin};
+use preset::{Bank, Preset};
+
+/// The group of checks a failure was found by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Check {
+    /// Plugin information.
+    Info,
+    /// Initialization, resuming and suspending.
+    Lifecycle,
+    /// Setting and reading parameters.
+    Parameters,
+    /// Saving and restoring presets and banks.
+    State,
+    /// Processing audio.
+    Processing,
+}
+
+/// A problem found by the validator.
+#[derive(Clone, Debug)]
+pub struct Failure {
+    /// The group of checks which found the problem.
+    pub check: Check,
+
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}: {}", self.check, self.message)
+    }
+}
+
+/// Result of validating a plugin.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// All problems found, in the order they were found.
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn fail(&mut self, check: Check, message: String) {
+        self.failures.push(Failure {
+            check: check,
+            message: message,
+        });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "All checks passed");
+        }
+
+        try!(write!(f, "{} checks failed:", self.failures.len()));
+        for failure in &self.failures {
+            try!(write!(f, "\n  {}", failure));
+        }
+        Ok(())
+    }
+}
+
+/// Input signals processed by the validator.
+#[derive(Clone, Copy, Debug)]
+enum Signal {
+    Silence,
+    Denormals,
+    Sine,
+    NaN,
+    Infinity,
+}
+
+impl Signal {
+    /// Whether the plugin is expected to produce finite output for this signal.
+    fn is_finite(&self) -> bool {
+        match *self {
+            Signal::NaN | Signal::Infinity => false,
+            _ => true,
+        }
+    }
+
+    fn sample<T: Float>(&self, index: usize, sample_rate: f32) -> T {
+        match *self {
+            Signal::Silence => T::zero(),
+            Signal::Denormals => T::min_positive_value() / T::from(2.0).unwrap(),
+            Signal::Sine => {
+                let phase = 2.0 * PI * 440.0 * index as f64 / sample_rate as f64;
+                T::from(phase.sin() * 0.5).unwrap()
+            }
+            Signal::NaN => T::nan(),
+            Signal::Infinity => T::infinity(),
+        }
+    }
+}
+
+/// Settings of a validation run.
+#[derive(Clone, Debug)]
+pub struct Validator {
+    /// Sample rates to process at.
+    pub sample_rates: Vec<f32>,
+
+    /// Maximum block sizes to process with. Smaller blocks are processed as well.
+    pub block_sizes: Vec<usize>,
+
+    /// Largest difference allowed between a parameter value set and the value read back.
+    pub tolerance: f32,
+}
+
+impl Default for Validator {
+    fn default() -> Validator {
+        Validator {
+            sample_rates: vec![44100.0, 48000.0, 96000.0],
+            block_sizes: vec![1, 64, 512, 1000],
+            tolerance: 0.01,
+        }
+    }
+}
+
+impl Validator {
+    /// Run all checks on `plugin`, which must not have been initialized yet.
+    ///
+    /// The plugin is left suspended. If it panics, the panic is reported as a failure and no
+    /// further checks are run.
+    pub fn validate<P: Plugin + ?Sized>(&self, plugin: &mut P) -> Report {
+        let mut report = Report::default();
+
+        let checks: [(Check, fn(&Validator, &mut P, &Info, &mut Report)); 5] = [
+            (Check::Info, Validator::check_info),
+            (Check::Lifecycle, Validator::check_lifecycle),
+            (Check::Parameters, Validator::check_parameters),
+            (Check::State, Validator::check_state),
+            (Check::Processing, Validator::check_processing),
+        ];
+
+        let info = plugin.get_info();
+        for &(check, run) in checks.iter() {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                run(self, plugin, &info, &mut report)
+            }));
+
+            if let Err(payload) = result {
+                report.fail(check, format!("Panicked: {}", panic_message(&*payload)));
+                break;
+            }
+        }
+
+        report
+    }
+
+    fn check_info<P: Plugin + ?Sized>(&self, _: &mut P, info: &Info, report: &mut Report) {
+        let counts = [("presets", info.presets), ("parameters", info.parameters),
+                      ("inputs", info.inputs), ("outputs", info.outputs)];
+        for &(name, count) in counts.iter() {
+            if count < 0 {
+                report.fail(Check::Info, format!("Negative number of {}: {}", name, count));
+            }
+        }
+
+        if info.unique_id == 0 {
+            report.fail(Check::Info, "Unique id is 0".to_string());
+        }
+    }
+
+    fn check_lifecycle<P: Plugin + ?Sized>(&self, plugin: &mut P, info: &Info, _: &mut Report) {
+        plugin.open();
+        plugin.sample_rate_changed(44100.0);
+        plugin.block_size_changed(512);
+        plugin.on_resume();
+        self.process_signal::<P, f32>(plugin, info, Signal::Silence, 44100.0, 512);
+        plugin.on_suspend();
+
+        // Changing settings while suspended and resuming again
+        plugin.sample_rate_changed(48000.0);
+        plugin.on_resume();
+        plugin.on_suspend();
+    }
+
+    fn check_parameters<P: Plugin + ?Sized>(&self, plugin: &mut P, info: &Info,
+                                            report: &mut Report) {
+        for index in 0..info.parameters {
+            plugin.get_parameter_name(index);
+            plugin.get_parameter_label(index);
+            plugin.get_parameter_properties(index);
+
+            let original = plugin.get_parameter(index);
+            for &value in [0.0, 0.25, 0.5, 0.75, 1.0].iter() {
+                plugin.set_parameter(index, value);
+                plugin.get_parameter_text(index);
+
+                let actual = plugin.get_parameter(index);
+                if !(actual >= 0.0 && actual <= 1.0) {
+                    report.fail(Check::Parameters,
+                                format!("Parameter {} is {} after setting it to {}",
+                                        index, actual, value));
+                } else if (actual - value).abs() > self.tolerance {
+                    report.fail(Check::Parameters,
+                                format!("Parameter {} set to {} reads back as {}",
+                                        index, value, actual));
+                }
+            }
+            plugin.set_parameter(index, original);
+        }
+    }
+
+    fn check_state<P: Plugin + ?Sized>(&self, plugin: &mut P, _: &Info, report: &mut Report) {
+        let preset = Preset::from_plugin(plugin);
+        if let Err(error) = preset.apply(plugin) {
+            report.fail(Check::State, format!("Could not restore preset: {}", error));
+        } else if Preset::from_plugin(plugin).data != preset.data {
+            report.fail(Check::State, "Preset changed after restoring it".to_string());
+        }
+
+        let bank = Bank::from_plugin(plugin);
+        if let Err(error) = bank.apply(plugin) {
+            report.fail(Check::State, format!("Could not restore bank: {}", error));
+        } else if Bank::from_plugin(plugin).data != bank.data {
+            report.fail(Check::State, "Bank changed after restoring it".to_string());
+        }
+    }
+
+    fn check_processing<P: Plugin + ?Sized>(&self, plugin: &mut P, info: &Info,
+                                            report: &mut Report) {
+        let signals = [Signal::Silence, Signal::Denormals, Signal::Sine, Signal::NaN,
+                       Signal::Infinity];
+
+        for &sample_rate in &self.sample_rates {
+            for &block_size in &self.block_sizes {
+                plugin.sample_rate_changed(sample_rate);
+                plugin.block_size_changed(block_size as i64);
+                plugin.on_resume();
+
+                for &signal in signals.iter() {
+                    // Hosts may pass fewer samples than the maximum block size
+                    for &len in [block_size, block_size / 2 + 1, 1].iter() {
+                        let finite = self.process_signal::<P, f32>(plugin, info, signal,
+                                                                   sample_rate, len) &&
+                                     (!info.f64_precision ||
+                                      self.process_signal::<P, f64>(plugin, info, signal,
+                                                                    sample_rate, len));
+
+                        if signal.is_finite() && !finite {
+                            report.fail(Check::Processing,
+                                        format!("Non-finite output for {:?} input at {} Hz \
+                                                 with {} of {} samples",
+                                                signal, sample_rate, len, block_size));
+                        }
+                    }
+                }
+
+                plugin.on_suspend();
+            }
+        }
+    }
+
+    /// Process a block of `signal` and return whether all outputs are finite.
+    fn process_signal<P, T>(&self, plugin: &mut P, info: &Info, signal: Signal,
+                            sample_rate: f32, len: usize)
+                            -> bool
+        where P: Plugin + ?Sized,
+              T: Float + ProcessSample
+    {
+        let input: Vec<T> = (0..len).map(|i| signal.sample(i, sample_rate)).collect();
+        let mut inputs = vec![input; info.inputs.max(0) as usize];
+        let mut outputs = vec![vec![T::zero(); len]; info.outputs.max(0) as usize];
+
+        {
+            let buffer = AudioBuffer::new(inputs.iter_mut().map(|c| &mut c[..]).collect(),
+                                          outputs.iter_mut().map(|c| &mut c[..]).collect());
+            T::process(plugin, buffer);
+        }
+
+        outputs.iter().all(|output| output.iter().all(|sample| sample.is_finite()))
+    }
+}
+
+/// Sample types which can be processed by a plugin.
+trait ProcessSample: Float {
+    fn process<P: Plugin + ?Sized>(plugin: &mut P, buffer: AudioBuffer<Self>);
+}
+
+impl ProcessSample for f32 {
+    fn process<P: Plugin + ?Sized>(plugin: &mut P, buffer: AudioBuffer<f32>) {
+        plugin.process(buffer);
+    }
+}
+
+impl ProcessSample for f64 {
+    fn process<P: Plugin + ?Sized>(plugin: &mut P, buffer: AudioBuffer<f64>) {
+        plugin.process_f64(buffer);
+    }
+}
+
+/// The message of a caught panic.
+fn panic_message(payload: &(Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use buffer::AudioBuffer;
+    use plugin::{Info, Plugin};
+    use validator::{Check, Validator};
+
+    /// A gain plugin quantizing its parameter to steps of 0.5.
+    #[derive(Default)]
+    struct Gain {
+        gain: f32,
+    }
+
+    impl Plugin for Gain {
+        fn get_info(&self) -> Info {
+            Info {
+                unique_id: 42,
+                parameters: 1,
+                inputs: 1,
+                outputs: 1,
+                ..Default::default()
+            }
+        }
+
+        fn get_parameter(&self, _: i32) -> f32 { self.gain }
+
+        fn set_parameter(&mut self, _: i32, value: f32) {
+            self.gain = (value * 2.0).round() / 2.0;
+        }
+
+        fn process(&mut self, buffer: AudioBuffer<f32>) {
+            let (inputs, mut outputs) = buffer.split();
+            for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                for (x, y) in input.iter().zip(output.iter_mut()) {
+                    *y = x * self.gain;
+                }
+            }
+        }
+    }
+
+    /// A plugin which panics when processing.
+    #[derive(Default)]
+    struct Broken;
+
+    impl Plugin for Broken {
+        fn get_info(&self) -> Info { Info { unique_id: 1, ..Default::default() } }
+
+        fn process(&mut self, _: AudioBuffer<f32>) {
+            panic!("Broken");
+        }
+    }
+
+    /// Test that problems are reported.
+    #[test]
+    fn validate() {
+        let report = Validator::default().validate(&mut Gain::default());
+        assert!(report.failures.iter().all(|f| f.check == Check::Parameters));
+        assert_eq!(report.failures.len(), 2);
+
+        let lenient = Validator { tolerance: 0.25, ..Default::default() };
+        assert!(lenient.validate(&mut Gain::default()).is_ok());
+
+        let report = Validator::default().validate(&mut Broken);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].check, Check::Lifecycle);
+        assert_eq!(report.failures[0].message, "Panicked: Broken");
+    }
+}
@@ -0,0 +1,444 @@
+//! Polyphonic voice management for synthesizers.
+//!
+//! A [`VoiceAllocator`](struct.VoiceAllocator.html) owns a fixed set of voices implementing
+//! [`Voice`](trait.Voice.html). It assigns incoming notes to free voices, steals a voice according
+//! to a [`StealPolicy`](enum.StealPolicy.html) when all are busy, and holds released notes while
+//! the sustain pedal is down. In `process` the plugin only iterates over the sounding voices.
+//!
+//! Nothing is allocated after `VoiceAllocator::new`, so all methods can be used on the audio
+//! thread.
+//!
+//! # Example
+//!
+//! ```
+//! # use vst2::buffer::AudioBuffer;
+//! # use vst2::event::Event;
+//! # use vst2::plugin::{Info, Plugin};
+//! use vst2::voice::{StealPolicy, Voice, VoiceAllocator};
+//!
+//! #[derive(Default)]
+//! struct Sine {
+//!     phase: f32,
+//!     frequency: f32,
+//!     gain: f32,
+//!     releasing: bool,
+//! }
+//!
+//! impl Voice for Sine {
+//!     fn note_on(&mut self, note: u8, velocity: u8) {
+//!         self.frequency = 440.0 * 2f32.powf((note as f32 - 69.0) / 12.0);
+//!         self.gain = velocity as f32 / 127.0;
+//!         self.releasing = false;
+//!     }
+//!
+//!     fn note_off(&mut self) { self.releasing = true; }
+//!     fn is_active(&self) -> bool { self.gain > 0.0 }
+//!     fn level(&self) -> f32 { self.gain }
+//! }
+//!
+//! struct Synth {
+//!     voices: VoiceAllocator<Sine>,
+//! }
+//!
+//! impl Plugin for Synth {
+//!     fn get_info(&self) -> Info { Info { inputs: 0, outputs: 1, ..Default::default() } }
+//!
+//!     fn process_events(&mut self, events: &[Event]) {
+//!         self.voices.process_events(events);
+//!     }
+//!
+//!     fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+//!         let (_, mut outputs) = buffer.split_mut();
+//!         for voice in self.voices.voices_mut() {
+//!             for sample in outputs[0].iter_mut() {
+//!                 *sample += voice.phase.sin() * voice.gain;
+//!                 voice.phase += voice.frequency * 2.0 * std::f32::consts::PI / 44100.0;
+//!                 if voice.releasing {
+//!                     voice.gain = (voice.gain - 0.001).max(0.0);
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }
+//! # impl Default for Synth {
+//! #     fn default() -> Synth {
+//! #         let voices = (0..8).map(|_| Sine::default()).collect();
+//! #         Synth { voices: VoiceAllocator::new(voices, StealPolicy::Oldest) }
+//! #     }
+//! # }
+//! ```
+//!
+//! Events are applied as they arrive. For sample accurate timing, split `process` at the
+//! `delta_frames` of the events and call `note_on`, `note_off` and `set_sustain` in between.
+
+use std::slice;
+
+use event::Event;
+use midi::MidiMessage;
+
+/// Controller number of the sustain pedal.
+const SUSTAIN: u8 = 64;
+/// Controller number of the all sound off message.
+const ALL_SOUND_OFF: u8 = 120;
+/// Controller number of the all notes off message.
+const ALL_NOTES_OFF: u8 = 123;
+
+/// A single voice of a synthesizer.
+pub trait Voice {
+    /// Start playing `note` with `velocity`. Also called on a sounding voice when it is stolen.
+    fn note_on(&mut self, note: u8, velocity: u8);
+
+    /// Release the note, e.g. by entering the release stage of the envelope.
+    fn note_off(&mut self);
+
+    /// Whether the voice is still sounding, including its release. Inactive voices are free to
+    /// play new notes and are skipped by `VoiceAllocator::voices_mut`.
+    fn is_active(&self) -> bool;
+
+    /// Current level of the voice, used by `StealPolicy::Quietest`.
+    fn level(&self) -> f32 { 0.0 }
+
+    /// Silence the voice immediately, for the all sound off message. Defaults to `note_off`.
+    fn kill(&mut self) {
+        self.note_off();
+    }
+}
+
+/// Which voice to take over when a note arrives while all voices are active.
+///
+/// Voices whose notes were already released are always stolen before held ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StealPolicy {
+    /// Steal the voice which started playing first.
+    Oldest,
+
+    /// Steal the voice with the lowest `Voice::level`.
+    Quietest,
+
+    /// Retrigger the voice already playing the same note, even if other voices are free.
+    /// Otherwise steal the oldest voice.
+    SameNote,
+}
+
+/// Bookkeeping for a voice.
+#[derive(Clone, Debug)]
+struct Slot<V> {
+    voice: V,
+
+    /// Channel and note played by the voice, if it was started by the allocator.
+    note: Option<(u8, u8)>,
+
+    /// When the voice was started, increasing with every note.
+    age: u64,
+
+    /// Whether the key is held down.
+    held: bool,
+
+    /// Whether the key was released while the sustain pedal was down.
+    sustained: bool,
+}
+
+/// Assigns notes to a fixed set of voices. See the module documentation.
+#[derive(Clone, Debug)]
+pub struct VoiceAllocator<V> {
+    slots: Vec<Slot<V>>,
+    policy: StealPolicy,
+    sustain: bool,
+    notes: u64,
+}
+
+impl<V: Voice> VoiceAllocator<V> {
+    /// Manage `voices`, stealing according to `policy`. The number of voices is the polyphony.
+    pub fn new(voices: Vec<V>, policy: StealPolicy) -> VoiceAllocator<V> {
+        VoiceAllocator {
+            slots: voices.into_iter().map(|voice| {
+                Slot { voice: voice, note: None, age: 0, held: false, sustained: false }
+            }).collect(),
+            policy: policy,
+            sustain: false,
+            notes: 0,
+        }
+    }
+
+    /// Change the steal policy.
+    pub fn set_policy(&mut self, policy: StealPolicy) {
+        self.policy = policy;
+    }
+
+    /// Start playing `note` on `channel`, returning the index of the voice playing it, or `None`
+    /// if there are no voices.
+    pub fn note_on(&mut self, channel: u8, note: u8, velocity: u8) -> Option<usize> {
+        let index = match self.choose(channel, note) {
+            Some(index) => index,
+            None => return None,
+        };
+
+        self.notes += 1;
+        let slot = &mut self.slots[index];
+        slot.voice.note_on(note, velocity);
+        slot.note = Some((channel, note));
+        slot.age = self.notes;
+        slot.held = true;
+        slot.sustained = false;
+        Some(index)
+    }
+
+    /// Release `note` on `channel`, or keep it sounding until the sustain pedal is released.
+    pub fn note_off(&mut self, channel: u8, note: u8) {
+        let sustain = self.sustain;
+        for slot in &mut self.slots {
+            if slot.held && slot.note == Some((channel, note)) {
+                slot.held = false;
+                if sustain {
+                    slot.sustained = true;
+                } else {
+                    slot.voice.note_off();
+                }
+            }
+        }
+    }
+
+    /// Press or release the sustain pedal. Releasing it releases all notes whose keys are up.
+    pub fn set_sustain(&mut self, sustain: bool) {
+        self.sustain = sustain;
+        if !sustain {
+            for slot in &mut self.slots {
+                if slot.sustained {
+                    slot.sustained = false;
+                    slot.voice.note_off();
+                }
+            }
+        }
+    }
+
+    /// Whether the sustain pedal is down.
+    pub fn sustain(&self) -> bool {
+        self.sustain
+    }
+
+    /// Release all notes, ignoring the sustain pedal.
+    pub fn all_notes_off(&mut self) {
+        for slot in &mut self.slots {
+            if slot.held || slot.sustained {
+                slot.held = false;
+                slot.sustained = false;
+                slot.voice.note_off();
+            }
+        }
+    }
+
+    /// Silence all voices immediately, e.g. when the plugin is suspended.
+    pub fn kill_all(&mut self) {
+        for slot in &mut self.slots {
+            slot.held = false;
+            slot.sustained = false;
+            slot.note = None;
+            slot.voice.kill();
+        }
+    }
+
+    /// Handle a MIDI message: note on and off, the sustain pedal, all notes off and all sound
+    /// off. Other messages are ignored.
+    pub fn process_message(&mut self, message: MidiMessage) {
+        match message {
+            MidiMessage::NoteOn { channel, note, velocity } => {
+                self.note_on(channel, note, velocity);
+            }
+            MidiMessage::NoteOff { channel, note, .. } => self.note_off(channel, note),
+            MidiMessage::ControlChange { controller: SUSTAIN, value, .. } => {
+                self.set_sustain(value >= 64);
+            }
+            MidiMessage::ControlChange { controller: ALL_NOTES_OFF, .. } => self.all_notes_off(),
+            MidiMessage::ControlChange { controller: ALL_SOUND_OFF, .. } => self.kill_all(),
+            _ => {}
+        }
+    }
+
+    /// Handle all MIDI events of a block, e.g. from `Plugin::process_events`.
+    pub fn process_events(&mut self, events: &[Event]) {
+        for event in events {
+            if let Event::Midi(ref midi) = *event {
+                self.process_message(midi.message());
+            }
+        }
+    }
+
+    /// Iterate over the active voices, e.g. to render them in `process`.
+    pub fn voices_mut(&mut self) -> ActiveVoices<V> {
+        ActiveVoices { slots: self.slots.iter_mut() }
+    }
+
+    /// All voices, active or not, e.g. to change their settings.
+    pub fn all_voices_mut(&mut self) -> AllVoices<V> {
+        AllVoices { slots: self.slots.iter_mut() }
+    }
+
+    /// Number of active voices.
+    pub fn active(&self) -> usize {
+        self.slots.iter().filter(|slot| slot.voice.is_active()).count()
+    }
+
+    /// Number of voices, i.e. the polyphony.
+    pub fn polyphony(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Channel and note played by the voice at `index`, if it is active.
+    pub fn note(&self, index: usize) -> Option<(u8, u8)> {
+        self.slots.get(index).and_then(|slot| {
+            if slot.voice.is_active() { slot.note } else { None }
+        })
+    }
+
+    /// Pick the voice for a new note.
+    fn choose(&self, channel: u8, note: u8) -> Option<usize> {
+        if self.policy == StealPolicy::SameNote {
+            let same = self.slots.iter().position(|slot| {
+                slot.voice.is_active() && slot.note == Some((channel, note))
+            });
+            if same.is_some() {
+                return same;
+            }
+        }
+
+        if let Some(free) = self.slots.iter().position(|slot| !slot.voice.is_active()) {
+            return Some(free);
+        }
+
+        // Prefer voices which are already fading out
+        let released = self.slots.iter().any(|slot| !slot.held && !slot.sustained);
+        let candidates = self.slots.iter().enumerate().filter(|&(_, slot)| {
+            !released || (!slot.held && !slot.sustained)
+        });
+
+        match self.policy {
+            StealPolicy::Quietest => {
+                candidates.fold(None, |quietest: Option<(usize, f32)>, (index, slot)| {
+                    let level = slot.voice.level();
+                    match quietest {
+                        Some((_, lowest)) if lowest <= level => quietest,
+                        _ => Some((index, level)),
+                    }
+                }).map(|(index, _)| index)
+            }
+            StealPolicy::Oldest | StealPolicy::SameNote => {
+                candidates.min_by_key(|&(_, slot)| slot.age).map(|(index, _)| index)
+            }
+        }
+    }
+}
+
+/// Iterator over the active voices, see `VoiceAllocator::voices_mut`.
+pub struct ActiveVoices<'a, V: 'a> {
+    slots: slice::IterMut<'a, Slot<V>>,
+}
+
+impl<'a, V: Voice> Iterator for ActiveVoices<'a, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.slots.by_ref().find(|slot| slot.voice.is_active()).map(|slot| &mut slot.voice)
+    }
+}
+
+/// Iterator over all voices, see `VoiceAllocator::all_voices_mut`.
+pub struct AllVoices<'a, V: 'a> {
+    slots: slice::IterMut<'a, Slot<V>>,
+}
+
+impl<'a, V> Iterator for AllVoices<'a, V> {
+    type Item = &'a mut V;
+
+    fn next(&mut self) -> Option<&'a mut V> {
+        self.slots.next().map(|slot| &mut slot.voice)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use midi::MidiMessage;
+    use voice::{StealPolicy, Voice, VoiceAllocator};
+
+    /// A voice which is active until released and records its note.
+    #[derive(Default)]
+    struct TestVoice {
+        note: u8,
+        velocity: u8,
+        active: bool,
+        released: bool,
+    }
+
+    impl Voice for TestVoice {
+        fn note_on(&mut self, note: u8, velocity: u8) {
+            self.note = note;
+            self.velocity = velocity;
+            self.active = true;
+            self.released = false;
+        }
+
+        fn note_off(&mut self) {
+            self.released = true;
+        }
+
+        fn is_active(&self) -> bool {
+            self.active
+        }
+
+        fn level(&self) -> f32 {
+            self.velocity as f32
+        }
+    }
+
+    fn allocator(policy: StealPolicy) -> VoiceAllocator<TestVoice> {
+        VoiceAllocator::new(vec![TestVoice::default(), TestVoice::default()], policy)
+    }
+
+    fn notes(voices: &mut VoiceAllocator<TestVoice>) -> Vec<u8> {
+        voices.voices_mut().map(|voice| voice.note).collect()
+    }
+
+    /// Test the steal policies.
+    #[test]
+    fn stealing() {
+        let mut voices = allocator(StealPolicy::Oldest);
+        assert_eq!(voices.note_on(0, 60, 100), Some(0));
+        assert_eq!(voices.note_on(0, 62, 50), Some(1));
+        assert_eq!(voices.note_on(0, 64, 100), Some(0));
+        assert_eq!(notes(&mut voices), vec![64, 62]);
+
+        // Released voices are stolen first
+        voices.note_off(0, 64);
+        assert_eq!(voices.note_on(0, 65, 100), Some(0));
+
+        let mut voices = allocator(StealPolicy::Quietest);
+        voices.note_on(0, 60, 100);
+        voices.note_on(0, 62, 50);
+        assert_eq!(voices.note_on(0, 64, 100), Some(1));
+
+        let mut voices = allocator(StealPolicy::SameNote);
+        voices.note_on(0, 60, 100);
+        assert_eq!(voices.note_on(0, 60, 80), Some(0));
+        assert_eq!(voices.active(), 1);
+        assert_eq!(voices.note(0), Some((0, 60)));
+    }
+
+    /// Test that the sustain pedal holds released notes.
+    #[test]
+    fn sustain() {
+        let mut voices = allocator(StealPolicy::Oldest);
+        voices.process_message(MidiMessage::NoteOn { channel: 1, note: 60, velocity: 100 });
+        voices.process_message(MidiMessage::ControlChange { channel: 1, controller: 64,
+                                                            value: 127 });
+        voices.process_message(MidiMessage::NoteOff { channel: 1, note: 60, velocity: 0 });
+        voices.process_message(MidiMessage::NoteOff { channel: 0, note: 62, velocity: 0 });
+        assert!(voices.sustain());
+        assert!(voices.voices_mut().all(|voice| !voice.released));
+
+        voices.set_sustain(false);
+        assert!(voices.voices_mut().all(|voice| voice.released));
+
+        voices.process_message(MidiMessage::ControlChange { channel: 1, controller: 120,
+                                                            value: 0 });
+        assert_eq!(voices.note(0), None);
+    }
+}
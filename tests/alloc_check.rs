@@ -0,0 +1,161 @@
+//! Tests of the `alloc_check` module, which need the checking allocator installed for the whole
+//! test binary.
+
+extern crate libc;
+extern crate vst2;
+
+use vst2::alloc_check::{self, CheckingAllocator};
+
+#[global_allocator]
+static ALLOCATOR: CheckingAllocator = CheckingAllocator;
+
+/// Test that allocations inside sections are reported.
+#[test]
+fn sections() {
+    // Other tests may report allocations concurrently, so only check for an increase
+    let before = alloc_check::violations();
+    let mut values = Vec::with_capacity(1);
+
+    {
+        let _section = alloc_check::section("test");
+        values.extend_from_slice(&[1, 2, 3, 4]);
+    }
+    assert!(alloc_check::violations() > before);
+}
+
+/// Test that processing audio and events through the plugin interface does not allocate.
+#[test]
+fn plugin_interface() {
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use libc::c_void;
+
+    use vst2::api::AEffect;
+    use vst2::buffer::AudioBuffer;
+    use vst2::event::{Event, MidiEvent, SendEventBuffer};
+    use vst2::plugin::{Info, OpCode, Plugin};
+
+    /// Note of the first event received.
+    static FIRST_NOTE: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Default)]
+    struct Synth;
+
+    impl Plugin for Synth {
+        fn get_info(&self) -> Info {
+            Info { name: "Synth".to_string(), inputs: 2, outputs: 2, ..Default::default() }
+        }
+
+        fn process_events(&mut self, events: &[Event]) {
+            if let Some(&Event::Midi(midi)) = events.first() {
+                FIRST_NOTE.store(midi.data[1] as usize, Ordering::SeqCst);
+            }
+        }
+
+        fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+            let (inputs, mut outputs) = buffer.split_mut();
+            for (input, output) in inputs.iter().zip(outputs.iter_mut()) {
+                for (x, y) in input.iter().zip(output.iter_mut()) {
+                    *y = *x * 0.5;
+                }
+            }
+        }
+    }
+
+    fn callback(_: *mut AEffect, _: i32, _: i32, _: isize, _: *mut c_void, _: f32) -> isize {
+        1
+    }
+
+    let aeffect = vst2::main::<Synth>(callback);
+    let dispatch = |opcode: OpCode, value: isize, ptr: *mut c_void| unsafe {
+        ((*aeffect).dispatcher)(aeffect, opcode.into(), 0, value, ptr, 0.0)
+    };
+    dispatch(OpCode::SetBlockSize, 64, ptr::null_mut());
+    dispatch(OpCode::StateChanged, 1, ptr::null_mut());
+
+    let mut events = SendEventBuffer::new(2, 0);
+    events.push(Event::Midi(MidiEvent::note_on(0, 64, 100, 10)));
+    events.push(Event::Midi(MidiEvent::note_on(0, 60, 100, 5)));
+
+    // The events are out of order and the second channel is processed in place
+    let mut channels = vec![vec![1.0f32; 64]; 3];
+    let mut inputs = [channels[0].as_mut_ptr(), channels[1].as_mut_ptr()];
+    let mut outputs = [channels[2].as_mut_ptr(), channels[1].as_mut_ptr()];
+
+    // The default `process_f64` converts `f64` buffers in memory reserved on resume
+    let mut channels_f64 = vec![vec![1.0f64; 64]; 3];
+    let mut inputs_f64 = [channels_f64[0].as_mut_ptr(), channels_f64[1].as_mut_ptr()];
+    let mut outputs_f64 = [channels_f64[2].as_mut_ptr(), channels_f64[1].as_mut_ptr()];
+
+    let before = alloc_check::thread_violations();
+    dispatch(OpCode::ProcessEvents, 0, events.as_raw() as *mut c_void);
+    unsafe {
+        ((*aeffect).processReplacing)(aeffect, inputs.as_mut_ptr(), outputs.as_mut_ptr(), 64);
+        ((*aeffect).processReplacingF64)(aeffect, inputs_f64.as_mut_ptr(),
+                                         outputs_f64.as_mut_ptr(), 64);
+    }
+    assert_eq!(alloc_check::thread_violations(), before);
+
+    assert_eq!(FIRST_NOTE.load(Ordering::SeqCst), 60);
+    assert_eq!(channels, vec![vec![1.0; 64], vec![0.5; 64], vec![0.5; 64]]);
+    assert_eq!(channels_f64, vec![vec![1.0; 64], vec![0.5; 64], vec![0.5; 64]]);
+}
+
+/// Test that the deprecated accumulating process function does not allocate once resumed.
+#[test]
+fn accumulating() {
+    use std::ptr;
+
+    use libc::c_void;
+
+    use vst2::api::AEffect;
+    use vst2::buffer::AudioBuffer;
+    use vst2::plugin::{Info, OpCode, Plugin};
+
+    #[derive(Default)]
+    struct Legacy;
+
+    impl Plugin for Legacy {
+        fn get_info(&self) -> Info {
+            Info {
+                name: "Legacy".to_string(),
+                inputs: 0,
+                outputs: 2,
+                legacy_host_support: true,
+                ..Default::default()
+            }
+        }
+
+        fn process(&mut self, mut buffer: AudioBuffer<f32>) {
+            let (_, mut outputs) = buffer.split_mut();
+            for output in outputs.iter_mut() {
+                for sample in output.iter_mut() {
+                    *sample = 1.0;
+                }
+            }
+        }
+    }
+
+    fn callback(_: *mut AEffect, _: i32, _: i32, _: isize, _: *mut c_void, _: f32) -> isize {
+        1
+    }
+
+    let aeffect = vst2::main::<Legacy>(callback);
+    let dispatch = |opcode: OpCode, value: isize| unsafe {
+        ((*aeffect).dispatcher)(aeffect, opcode.into(), 0, value, ptr::null_mut(), 0.0)
+    };
+    dispatch(OpCode::SetBlockSize, 64);
+    dispatch(OpCode::StateChanged, 1);
+
+    let mut channels = vec![vec![0.5f32; 64]; 2];
+    let mut inputs: [*mut f32; 0] = [];
+    let mut outputs = [channels[0].as_mut_ptr(), channels[1].as_mut_ptr()];
+
+    let before = alloc_check::thread_violations();
+    unsafe {
+        ((*aeffect)._process)(aeffect, inputs.as_mut_ptr(), outputs.as_mut_ptr(), 64);
+    }
+    assert_eq!(alloc_check::thread_violations(), before);
+    assert_eq!(channels, vec![vec![1.5; 64]; 2]);
+}